@@ -1,9 +1,16 @@
+use metalforge_lib::library::loudness::DEFAULT_TARGET_LUFS;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub debug: DebugConfig,
-    pub library: LibraryConfig
+    pub library: LibraryConfig,
+    #[serde(default)]
+    pub practice: PracticeConfig,
+    #[serde(default)]
+    pub setlists: SetlistConfig,
+    #[serde(default)]
+    pub loudness: LoudnessConfig
 }
 
 #[derive(Serialize, Deserialize)]
@@ -13,5 +20,78 @@ pub struct DebugConfig {
 
 #[derive(Serialize, Deserialize)]
 pub struct LibraryConfig {
-    pub paths: Vec<String>
+    pub paths: Vec<String>,
+    /// The file the favorites, tags, ratings and notes of every song are kept in
+    #[serde(default = "default_annotations_path")]
+    pub annotations_path: String,
+    /// The file the ID of the song in each song directory is kept in, to find songs that changed
+    /// since the last scan
+    #[serde(default = "default_song_dirs_path")]
+    pub song_dirs_path: String,
+    /// The directory songs exported as song packages are written to
+    #[serde(default = "default_export_path")]
+    pub export_path: String
+}
+
+fn default_annotations_path() -> String {
+    "data/annotations.json".to_string()
+}
+
+fn default_song_dirs_path() -> String {
+    "data/song_dirs.json".to_string()
+}
+
+fn default_export_path() -> String {
+    "exports".to_string()
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PracticeConfig {
+    /// The file the practice history of every song is kept in
+    pub history_path: String,
+    /// The file the settings every song was last played with are kept in
+    pub settings_path: String
+}
+
+impl Default for PracticeConfig {
+    fn default() -> Self {
+        Self {
+            history_path: "data/practice.jsonl".to_string(),
+            settings_path: "data/song_settings.json".to_string()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SetlistConfig {
+    /// The file every setlist is kept in
+    pub path: String
+}
+
+impl Default for SetlistConfig {
+    fn default() -> Self {
+        Self {
+            path: "data/setlists.json".to_string()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoudnessConfig {
+    /// The file the measured loudness of every song is kept in
+    pub index_path: String,
+    /// The loudness songs are played at, in LUFS. Leave empty to play songs at their own volume
+    pub target_lufs: Option<f32>
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        Self {
+            index_path: "data/loudness.json".to_string(),
+            target_lufs: Some(DEFAULT_TARGET_LUFS)
+        }
+    }
 }
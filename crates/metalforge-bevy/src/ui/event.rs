@@ -1,44 +1,167 @@
 use std::time::Duration;
-use crate::ui::menu::{populate_song_browser, MenuId, MenuState, MenuStructure, SongLibrary};
+use crate::ui::menu::browser::{populate_song_browser, update_song_browser, BrowserQuery, LibraryAnnotations};
+use crate::ui::menu::details::DetailsEvent;
+use crate::ui::menu::setlist::{refresh_setlists, SetlistEditor, Setlists};
+use crate::ui::menu::text_entry::TextEntry;
+use crate::ui::menu::{populate_library_problems, LibraryScanStatus, LibrarySongDirs, MenuId, MenuState, MenuStructure, SongLibrary};
+use crate::ui::player::practice::PracticeLog;
+use crate::ui::player::settings::SongPreferences;
 use crate::ui::player::song_player::SongPlayer;
 use crate::ui::{AppState, UIEngine};
-use bevy::prelude::{NextState, Res, ResMut};
-use log::info;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::{MessageWriter, NextState, Res, ResMut, State};
+use log::{error, info};
 use metalforge_lib::engine::EngineEvent;
+use metalforge_lib::library::songfile::SongId;
 
+#[allow(clippy::too_many_arguments)]
 pub fn handle_engine_event(
     engine_channel: Res<UIEngine>,
     mut song_player: ResMut<SongPlayer>,
     mut song_library: ResMut<SongLibrary>,
+    mut scan_status: ResMut<LibraryScanStatus>,
+    browser_query: Res<BrowserQuery>,
+    mut song_data: SongData,
+    setlist_editor: Res<SetlistEditor>,
+    text_entry: Res<TextEntry>,
+    menu_state: Res<State<MenuState>>,
     mut next_menu_state: ResMut<NextState<MenuState>>,
     mut next_app_state: ResMut<NextState<AppState>>,
-    mut menu: ResMut<MenuStructure>
+    mut menu: ResMut<MenuStructure>,
+    mut details_events: MessageWriter<DetailsEvent>
 ) {
     while let Some(event) = engine_channel.channel.try_receive() {
         match event {
-            EngineEvent::SongLoaded(song) => {
-                song_player.reset(song);
+            EngineEvent::SongLoaded(song_id, song) => {
+                song_player.reset(song_id, song);
                 next_app_state.set(AppState::Player);
                 next_menu_state.set(MenuState::HideMenu);
             }
+            EngineEvent::SongDetailsLoaded(song_id, song) => {
+                details_events.write(DetailsEvent::ChartLoaded(song_id, song));
+            }
+            EngineEvent::LibraryScanProgress { scanned, found, total, current_path } => {
+                *scan_status = LibraryScanStatus { scanned, found, total, current_path };
+            }
+            EngineEvent::LibrarySongFound(song_file) => {
+                song_library.0.songs.push(song_file);
+            }
             EngineEvent::LibraryUpdated(library) => {
                 info!("Updating library");
                 song_library.0 = library;
 
+                // Songs edited since the last scan have new IDs, their data is moved along
+                match song_data.song_dirs.0.reconcile(&song_library.0) {
+                    Ok(updated) => for (previous_id, song_file) in updated {
+                        song_data.rekey(&previous_id, &song_file.id);
+                    }
+                    Err(error) => error!("Failed to save the song directories: {:?}", error),
+                }
+
+                refresh_setlists(&mut menu, &song_data.setlists.0, &setlist_editor, &song_library.0, &text_entry);
+
                 if let Some(browser_menu) = menu.menus.get_mut(&MenuId::Browser) {
-                    populate_song_browser(browser_menu, &song_library.0.songs);
+                    populate_song_browser(browser_menu, &song_library.0, &browser_query.0, &song_data.annotations.0);
+                }
+
+                if let Some(problems_menu) = menu.menus.get_mut(&MenuId::LibraryProblems) {
+                    populate_library_problems(problems_menu, &song_library.0.report);
                 }
 
                 next_menu_state.set(MenuState::ShowMenu);
             }
+            EngineEvent::LibrarySongAdded(song_file) => {
+                if let Err(error) = song_data.song_dirs.0.record(&song_file) {
+                    error!("Failed to save the song directories: {:?}", error);
+                }
+
+                let redraw = update_song_browser(&mut menu, &mut song_library.0, &browser_query.0, &song_data.annotations.0, |library| {
+                    library.upsert(song_file);
+                });
+                redraw_menu(redraw, &menu_state, &mut next_menu_state);
+            }
+            EngineEvent::LibrarySongUpdated(previous_id, song_file) => {
+                // The ID changes with the contents of the song, everything kept about the song stays with it
+                song_data.rekey(&previous_id, &song_file.id);
+
+                if let Err(error) = song_data.song_dirs.0.record(&song_file) {
+                    error!("Failed to save the song directories: {:?}", error);
+                }
+
+                let redraw = update_song_browser(&mut menu, &mut song_library.0, &browser_query.0, &song_data.annotations.0, |library| {
+                    library.remove(&previous_id);
+                    library.upsert(song_file);
+                });
+                refresh_setlists(&mut menu, &song_data.setlists.0, &setlist_editor, &song_library.0, &text_entry);
+
+                let redraw = redraw || menu.current_menu_id() == Some(MenuId::SetlistEditor);
+                redraw_menu(redraw, &menu_state, &mut next_menu_state);
+            }
+            EngineEvent::LibrarySongRemoved(song_id) => {
+                let redraw = update_song_browser(&mut menu, &mut song_library.0, &browser_query.0, &song_data.annotations.0, |library| {
+                    library.remove(&song_id);
+                });
+                redraw_menu(redraw, &menu_state, &mut next_menu_state);
+            }
+            EngineEvent::LibraryReportUpdated(report) => {
+                if let Some(problems_menu) = menu.menus.get_mut(&MenuId::LibraryProblems) {
+                    populate_library_problems(problems_menu, &report);
+                }
+                song_library.0.report = report;
+
+                let redraw = menu.current_menu_id() == Some(MenuId::LibraryProblems);
+                redraw_menu(redraw, &menu_state, &mut next_menu_state);
+            }
             EngineEvent::SongUnloaded => {
                 next_menu_state.set(MenuState::ShowMenu);
                 next_app_state.set(AppState::MainMenu);
                 song_player.current_song = None;
                 song_player.playing = false;
-                menu.pop_menu();
+
+                // Songs of a setlist end without the player menu being open
+                if menu.current_menu_id() == Some(MenuId::PlayerMenu) {
+                    menu.pop_menu();
+                }
             }
         }
     }
 }
 
+/// Everything kept about songs under their ID, which has to move along when the ID of a song changes
+#[derive(SystemParam)]
+pub(crate) struct SongData<'w> {
+    annotations: ResMut<'w, LibraryAnnotations>,
+    practice_log: ResMut<'w, PracticeLog>,
+    preferences: ResMut<'w, SongPreferences>,
+    setlists: ResMut<'w, Setlists>,
+    song_dirs: ResMut<'w, LibrarySongDirs>,
+}
+
+impl SongData<'_> {
+
+    /// Moves the annotations, practice history, settings and setlist entries of a song to its new ID
+    fn rekey(&mut self, previous_id: &SongId, song_id: &SongId) {
+        if let Err(error) = self.practice_log.0.rekey(previous_id, song_id) {
+            error!("Failed to move the practice history of {:?}: {:?}", previous_id, error);
+        }
+
+        if let Err(error) = self.annotations.0.rekey(previous_id, song_id) {
+            error!("Failed to move the annotations of {:?}: {:?}", previous_id, error);
+        }
+
+        if let Err(error) = self.preferences.0.rekey(previous_id, song_id) {
+            error!("Failed to move the settings of {:?}: {:?}", previous_id, error);
+        }
+
+        if let Err(error) = self.setlists.0.rekey(previous_id, song_id) {
+            error!("Failed to move the setlist entries of {:?}: {:?}", previous_id, error);
+        }
+    }
+}
+
+/// Re-enters the menu state so the menu currently shown is rebuilt with its new items
+fn redraw_menu(redraw: bool, menu_state: &Res<State<MenuState>>, next_menu_state: &mut ResMut<NextState<MenuState>>) {
+    if redraw && menu_state.get() == &MenuState::ShowMenu {
+        next_menu_state.set(MenuState::ShowMenu);
+    }
+}
@@ -1,19 +1,31 @@
 use crate::ui::debug::event::DebugEvent;
 use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::annotations::handle_annotation_keys;
+use crate::ui::menu::setlist::handle_setlist_keys;
+use crate::ui::menu::text_entry::handle_text_entry;
 use crate::ui::menu::{MenuId, MenuState, MenuStructure};
-use crate::ui::player::event::{PlayerEvent, SeekLocation, FINE_SCROLL_DISTANCE_MILLIS, JUMP_DISTANCE_MILLIS, SCROLL_DISTANCE_MILLIS};
+use crate::ui::player::loops::{handle_loop_keys, LoopEvent};
+use crate::ui::player::event::{PlayerEvent, SeekLocation, AUDIO_OFFSET_STEP_MILLIS, FINE_SCROLL_DISTANCE_MILLIS, JUMP_DISTANCE_MILLIS, SCROLL_DISTANCE_MILLIS};
 use crate::ui::player::song_player::PlayerState;
-use crate::ui::AppState;
+use crate::ui::{AppState, UIEngine};
 use bevy::input::ButtonInput;
 use bevy::prelude::{in_state, App, Commands, IntoScheduleConfigs, KeyCode, MessageWriter, NextState, Res, ResMut, State, Update};
 use std::time::Duration;
 use log::{info, trace, warn};
+use metalforge_lib::engine::EngineCommand;
 
 pub fn handle_key_input(app: &mut App) {
     app
         .add_systems(Update, handle_debug_keys)
         .add_systems(Update, handle_player_keys.run_if(in_state(AppState::Player)))
-        .add_systems(Update, handle_menu_keys.run_if(in_state(MenuState::ShowMenu)));
+        .add_systems(Update, handle_menu_keys.run_if(in_state(MenuState::ShowMenu)))
+        .add_systems(Update, handle_loading_keys.run_if(in_state(MenuState::LoadData)))
+        // Text entry runs last, so the other handlers still see that text was being entered when it's
+        // accepted, and the menus that start entering text ignore the keys typed meanwhile
+        .add_systems(Update, (handle_loop_keys, handle_setlist_keys, handle_annotation_keys, handle_text_entry).chain()
+            .after(handle_menu_keys)
+            .after(handle_player_keys)
+            .run_if(in_state(MenuState::ShowMenu)));
 }
 
 pub fn handle_debug_keys(
@@ -31,34 +43,64 @@ pub(crate) fn handle_menu_keys(
     mut menu_events: MessageWriter<MenuEvent>,
     menu: Res<MenuStructure>,
 ) {
-    if input.just_pressed(KeyCode::ArrowDown) {
+    if menu.text_entry {
+        return;
+    }
+
+    // Shift with the up and down keys moves items in menus that can be reordered
+    let reordering = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if input.just_pressed(KeyCode::ArrowDown) && !reordering {
         menu_events.write(MenuEvent::NextItemSelected);
 
-    } else if input.just_pressed(KeyCode::ArrowUp) {
+    } else if input.just_pressed(KeyCode::ArrowUp) && !reordering {
         menu_events.write(MenuEvent::PrevItemSelected);
 
-    } else if input.pressed(KeyCode::ArrowDown) {
+    } else if input.pressed(KeyCode::ArrowDown) && !reordering {
         // Down key has been pressed for a while
         menu_events.write(MenuEvent::NextItemSelected);
 
-    } else if input.pressed(KeyCode::ArrowUp) {
+    } else if input.pressed(KeyCode::ArrowUp) && !reordering {
         // Up key has been pressed for a while
         menu_events.write(MenuEvent::PrevItemSelected);
 
+    } else if input.just_pressed(KeyCode::PageDown) {
+        menu_events.write(MenuEvent::NextPageSelected);
+
+    } else if input.just_pressed(KeyCode::PageUp) {
+        menu_events.write(MenuEvent::PrevPageSelected);
+
+    } else if input.just_pressed(KeyCode::Home) {
+        menu_events.write(MenuEvent::FirstItemSelected);
+
+    } else if input.just_pressed(KeyCode::End) {
+        menu_events.write(MenuEvent::LastItemSelected);
+
     } else if input.just_pressed(KeyCode::Enter) {
         if let Some(item) = menu.current_item() {
-            menu_events.write(item.action);
+            menu_events.write(item.action.clone());
         }
 
     } else if input.just_pressed(KeyCode::Escape) {
         if let Some(menu) = menu.current_menu() {
-            menu_events.write(menu.pop_action);
+            menu_events.write(menu.pop_action.clone());
         } else {
             menu_events.write(MenuEvent::PopMenu);
         }
     }
 }
 
+fn handle_loading_keys(
+    input: Res<ButtonInput<KeyCode>>,
+    engine: Res<UIEngine>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        // Stop scanning, the songs found so far will still be shown
+        engine.send(EngineCommand::CancelScan);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_player_keys(
     input: Res<ButtonInput<KeyCode>>,
     player_state: Res<State<PlayerState>>,
@@ -66,10 +108,11 @@ fn handle_player_keys(
     mut next_player_state: ResMut<NextState<PlayerState>>,
     mut player_events: MessageWriter<PlayerEvent>,
     mut menu_events: MessageWriter<MenuEvent>,
+    mut loop_events: MessageWriter<LoopEvent>,
     mut menu_structure: ResMut<MenuStructure>
 ) {
     if player_state.get() == &PlayerState::Menu {
-        if input.just_pressed(KeyCode::Escape) {
+        if input.just_pressed(KeyCode::Escape) && !menu_structure.text_entry {
             // Release menu and resume playing
             trace!("Release player menu");
             menu_structure.pop_menu();
@@ -153,6 +196,24 @@ fn handle_player_keys(
             player_events.write(PlayerEvent::MarkLoopEnd);
         }
 
+        // Handle saved loop events
+        if input.just_pressed(KeyCode::KeyL) {
+            loop_events.write(LoopEvent::SaveCurrent);
+        } else if input.just_pressed(KeyCode::Tab) {
+            if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                loop_events.write(LoopEvent::Previous);
+            } else {
+                loop_events.write(LoopEvent::Next);
+            }
+        }
+
+        // Handle audio offset events
+        if input.just_pressed(KeyCode::Comma) {
+            player_events.write(PlayerEvent::AdjustAudioOffset(-AUDIO_OFFSET_STEP_MILLIS));
+        } else if input.just_pressed(KeyCode::Period) {
+            player_events.write(PlayerEvent::AdjustAudioOffset(AUDIO_OFFSET_STEP_MILLIS));
+        }
+
         if input.just_pressed(KeyCode::Escape) {
             trace!("Show player menu");
             menu_structure.push_menu(MenuId::PlayerMenu);
@@ -0,0 +1,195 @@
+use crate::ui::menu::browser::{update_song_browser, BrowserQuery, LibraryAnnotations};
+use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::text_entry::{TextEntry, TextEntryEvent, TextField};
+use crate::ui::menu::{Menu, MenuId, MenuItem, MenuState, MenuStructure, SongLibrary};
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ButtonState;
+use bevy::prelude::{KeyCode, Message, MessageReader, MessageWriter, NextState, Res, ResMut, Resource};
+use log::{error, info};
+use metalforge_lib::library::annotations::{SongAnnotations, MAX_RATING};
+use metalforge_lib::library::songfile::{SongFile, SongId};
+
+/// The position of the tags in the annotations menu
+const TAGS_ITEM: usize = 2;
+
+/// The position of the notes in the annotations menu
+const NOTES_ITEM: usize = 3;
+
+#[derive(Message, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+pub(crate) enum AnnotationEvent {
+    /// Show the annotations of a song
+    Open(SongId),
+    ToggleFavorite(SongId),
+    /// Move on to the next personal difficulty rating of a song
+    CycleRating(SongId),
+    /// Replace the tags of a song with the ones listed in the text, separated by commas
+    SetTags(SongId, String),
+    SetNotes(SongId, String),
+}
+
+/// The song shown in the annotations menu
+#[derive(Resource, Default)]
+pub(crate) struct AnnotationEditor {
+    song_id: Option<SongId>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_annotation_events(
+    mut events: MessageReader<AnnotationEvent>,
+    mut annotations: ResMut<LibraryAnnotations>,
+    mut editor: ResMut<AnnotationEditor>,
+    mut menu: ResMut<MenuStructure>,
+    mut library: ResMut<SongLibrary>,
+    browser_query: Res<BrowserQuery>,
+    text_entry: Res<TextEntry>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    let mut changed = false;
+
+    for event in events.read() {
+        changed = true;
+
+        let result = match event {
+            AnnotationEvent::Open(song_id) => {
+                editor.song_id = Some(song_id.clone());
+                menu.push_menu(MenuId::SongAnnotations);
+                Ok(())
+            }
+            AnnotationEvent::ToggleFavorite(song_id) => {
+                annotations.0.edit(song_id, |song_annotations| {
+                    song_annotations.favorite = !song_annotations.favorite;
+                    info!("Marked song {:?} as favorite: {}", song_id, song_annotations.favorite);
+                })
+            }
+            AnnotationEvent::CycleRating(song_id) => {
+                annotations.0.edit(song_id, |song_annotations| {
+                    song_annotations.rating = match song_annotations.rating {
+                        None => Some(1),
+                        Some(rating) if rating < MAX_RATING => Some(rating + 1),
+                        Some(_) => None,
+                    };
+                })
+            }
+            AnnotationEvent::SetTags(song_id, text) => {
+                annotations.0.edit(song_id, |song_annotations| song_annotations.set_tags(text))
+            }
+            AnnotationEvent::SetNotes(song_id, text) => {
+                annotations.0.edit(song_id, |song_annotations| song_annotations.notes = text.trim().to_string())
+            }
+        };
+
+        if let Err(error) = result {
+            error!("Failed to save song annotations: {:?}", error);
+        }
+    }
+
+    if changed {
+        // Favorites, tags and ratings are shown in the browser and may change which songs are listed
+        update_song_browser(&mut menu, &mut library.0, &browser_query.0, &annotations.0, |_library| {});
+        refresh_annotations(&mut menu, &library, &annotations, &editor, &text_entry);
+        next_state.set(MenuState::ShowMenu);
+    }
+}
+
+fn refresh_annotations(menu: &mut MenuStructure, library: &SongLibrary, annotations: &LibraryAnnotations, editor: &AnnotationEditor, text_entry: &TextEntry) {
+    if let Some(annotations_menu) = menu.menus.get_mut(&MenuId::SongAnnotations)
+        && let Some(song_file) = editor.song_id.as_ref().and_then(|song_id| library.0.get(song_id)) {
+        let song_annotations = annotations.0.get(&song_file.id).cloned().unwrap_or_default();
+        populate_song_annotations(annotations_menu, song_file, &song_annotations, text_entry);
+    }
+}
+
+/// Lists the annotations of a song. Selecting the favorite flag or the rating changes it, the tags
+/// and notes are edited as text.
+fn populate_song_annotations(annotations_menu: &mut Menu, song_file: &SongFile, annotations: &SongAnnotations, text_entry: &TextEntry) {
+    annotations_menu.title = format!("{} - {} (Enter to change, F2 to edit tags and notes)", song_file.metadata.artist, song_file.metadata.title);
+
+    let rating = annotations.rating
+        .map(|rating| format!("{}/{}", rating, MAX_RATING))
+        .unwrap_or("Not rated".to_string());
+
+    let text_value = |field: TextField, value: String| match text_entry.text(&field) {
+        Some(text) => format!("{}_", text),
+        None if value.is_empty() => "None".to_string(),
+        None => value,
+    };
+
+    annotations_menu.items = vec![
+        MenuItem {
+            label: format!("Favorite: {}", if annotations.favorite { "Yes" } else { "No" }),
+            action: MenuEvent::Annotation(AnnotationEvent::ToggleFavorite(song_file.id.clone())),
+        },
+        MenuItem {
+            label: format!("Rating: {}", rating),
+            action: MenuEvent::Annotation(AnnotationEvent::CycleRating(song_file.id.clone())),
+        },
+        MenuItem {
+            label: format!("Tags: {}", text_value(TextField::SongTags(song_file.id.clone()), annotations.tags.join(", "))),
+            action: MenuEvent::Noop,
+        },
+        MenuItem {
+            label: format!("Notes: {}", text_value(TextField::SongNotes(song_file.id.clone()), annotations.notes.clone())),
+            action: MenuEvent::Noop,
+        },
+    ];
+}
+
+/// While the annotations of a song are shown, F2 edits the selected tags or notes, which are typed
+/// through `TextEntry`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_annotation_keys(
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut text_events: MessageReader<TextEntryEvent>,
+    mut annotation_events: MessageWriter<AnnotationEvent>,
+    mut text_entry: ResMut<TextEntry>,
+    editor: Res<AnnotationEditor>,
+    annotations: Res<LibraryAnnotations>,
+    library: Res<SongLibrary>,
+    mut menu: ResMut<MenuStructure>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    let mut changed = false;
+
+    for event in text_events.read() {
+        match event {
+            TextEntryEvent::Accepted(TextField::SongTags(song_id), text) => {
+                annotation_events.write(AnnotationEvent::SetTags(song_id.clone(), text.clone()));
+            }
+            TextEntryEvent::Accepted(TextField::SongNotes(song_id), text) => {
+                annotation_events.write(AnnotationEvent::SetNotes(song_id.clone(), text.clone()));
+            }
+            TextEntryEvent::Changed(TextField::SongTags(_) | TextField::SongNotes(_))
+            | TextEntryEvent::Cancelled(TextField::SongTags(_) | TextField::SongNotes(_)) => changed = true,
+            _ => {}
+        }
+    }
+
+    let song_id = editor.song_id.clone()
+        .filter(|_| menu.current_menu_id() == Some(MenuId::SongAnnotations) && !text_entry.is_active());
+
+    match song_id {
+        Some(song_id) => {
+            for event in keyboard_events.read() {
+                if event.state != ButtonState::Pressed || event.key_code != KeyCode::F2 {
+                    continue;
+                }
+
+                let song_annotations = annotations.0.get(&song_id).cloned().unwrap_or_default();
+
+                match menu.selected_idx {
+                    TAGS_ITEM => text_entry.start(MenuId::SongAnnotations, TextField::SongTags(song_id.clone()), song_annotations.tags.join(", ")),
+                    NOTES_ITEM => text_entry.start(MenuId::SongAnnotations, TextField::SongNotes(song_id.clone()), song_annotations.notes),
+                    _ => continue,
+                }
+
+                changed = true;
+            }
+        }
+        None => keyboard_events.clear(),
+    }
+
+    if changed {
+        refresh_annotations(&mut menu, &library, &annotations, &editor, &text_entry);
+        next_state.set(MenuState::ShowMenu);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::ui::menu::annotations::AnnotationEvent;
+use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::{Menu, MenuId, MenuItem, MenuState, MenuStructure, SongLibrary};
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ButtonState;
+use bevy::prelude::{KeyCode, MessageReader, MessageWriter, NextState, Res, ResMut, Resource};
+use metalforge_lib::library::annotations::{AnnotationStore, SongAnnotations, MAX_RATING};
+use metalforge_lib::library::query::{LibraryQuery, SortKey};
+use metalforge_lib::library::songfile::{SongFile, SongId};
+use metalforge_lib::library::Library;
+use metalforge_lib::song::instrument_part::InstrumentKind;
+use std::collections::HashMap;
+
+/// The search text, filters and sort order currently applied to the song browser
+#[derive(Resource, Default)]
+pub(crate) struct BrowserQuery(pub(crate) LibraryQuery);
+
+/// The favorites, tags, ratings and notes the user gave to songs
+#[derive(Resource)]
+pub(crate) struct LibraryAnnotations(pub(crate) AnnotationStore);
+
+/// A filter or sort setting that can be changed from the browser filters menu
+#[derive(Hash, Ord, PartialOrd, PartialEq, Eq, Copy, Clone, Debug)]
+pub(crate) enum BrowserFilter {
+    Instrument,
+    Tuning,
+    Capo,
+    Year,
+    Difficulty,
+    Favorites,
+    Tag,
+    Rating,
+    Sort,
+    Order,
+}
+
+const ALL_INSTRUMENTS: [InstrumentKind; 6] = [
+    InstrumentKind::LeadGuitar,
+    InstrumentKind::RhythmGuitar,
+    InstrumentKind::BassGuitar,
+    InstrumentKind::Keyboard,
+    InstrumentKind::Drums,
+    InstrumentKind::Vocals
+];
+
+/// Lists the songs matching the query in the browser menu. The title shows the search text and
+/// how many songs are listed. Songs with several versions in the library are numbered, favorites
+/// are starred and followed by their tags and rating.
+pub fn populate_song_browser(browser_menu: &mut Menu, library: &Library, query: &LibraryQuery, annotations: &AnnotationStore) {
+    let versions = library.versions();
+    let version_numbers: HashMap<&SongId, (usize, usize)> = versions.iter()
+        .flat_map(|group| group.iter()
+            .enumerate()
+            .map(|(idx, song_file)| (&song_file.id, (idx + 1, group.len()))))
+        .collect();
+
+    let songs = library.query(query, annotations);
+
+    browser_menu.title = if query.text.is_empty() {
+        format!("Browser ({} songs, type to search, Tab for filters, F3 for statistics, F4 to toggle favorite, F5 to annotate)", songs.len())
+    } else {
+        format!("Browser - search: {}_ ({} of {} songs)", query.text, songs.len(), library.songs.len())
+    };
+
+    browser_menu.items.clear();
+
+    for song_file in songs {
+        browser_menu.items.push(song_to_menu(song_file, version_numbers.get(&song_file.id).copied(), annotations.get(&song_file.id)));
+    }
+
+    if browser_menu.items.is_empty() {
+        let label = if library.songs.is_empty() { "[No songs found]" } else { "[No songs match the search]" };
+
+        browser_menu.items.push(MenuItem {
+            label: label.to_string(),
+            action: MenuEvent::Noop,
+        });
+    }
+}
+
+/// Applies a change to the song library and rebuilds the browser menu, keeping the selected song
+/// selected. Returns `true` if the browser is the menu currently shown and needs to be redrawn.
+pub fn update_song_browser<F: FnOnce(&mut Library)>(
+    menu: &mut MenuStructure,
+    library: &mut Library,
+    query: &LibraryQuery,
+    annotations: &AnnotationStore,
+    change: F
+) -> bool {
+    let selection = menu.menu_selection(MenuId::Browser);
+    let selected_id = selection
+        .and_then(|idx| selected_song(menu, library, idx))
+        .map(|song_file| song_file.id.clone());
+
+    change(library);
+
+    if let Some(browser_menu) = menu.menus.get_mut(&MenuId::Browser) {
+        populate_song_browser(browser_menu, library, query, annotations);
+    }
+
+    if let Some(old_idx) = selection {
+        let item_count = menu.menus.get(&MenuId::Browser).map(|browser_menu| browser_menu.items.len()).unwrap_or(0);
+        let new_idx = selected_id
+            .and_then(|id| (0..item_count).find(|idx| selected_song(menu, library, *idx).is_some_and(|song_file| song_file.id == id)))
+            .unwrap_or_else(|| old_idx.min(item_count.saturating_sub(1)));
+
+        menu.set_menu_selection(MenuId::Browser, new_idx);
+    }
+
+    menu.current_menu_id() == Some(MenuId::Browser)
+}
+
+/// Rebuilds the browser and the filters menu after the query has changed and selects the first song
+pub(crate) fn refresh_song_browser(menu: &mut MenuStructure, library: &Library, query: &LibraryQuery, annotations: &AnnotationStore) {
+    if let Some(browser_menu) = menu.menus.get_mut(&MenuId::Browser) {
+        populate_song_browser(browser_menu, library, query, annotations);
+    }
+
+    if let Some(filters_menu) = menu.menus.get_mut(&MenuId::BrowserFilters) {
+        populate_browser_filters(filters_menu, library, query, annotations);
+    }
+
+    menu.set_menu_selection(MenuId::Browser, 0);
+}
+
+pub(crate) fn selected_song<'a>(menu: &MenuStructure, library: &'a Library, item_idx: usize) -> Option<&'a SongFile> {
+    match &menu.menus.get(&MenuId::Browser)?.items.get(item_idx)?.action {
+        MenuEvent::SongSelected(song_id) => library.get(song_id),
+        _ => None
+    }
+}
+
+fn song_to_menu(song_file: &SongFile, version: Option<(usize, usize)>, annotations: Option<&SongAnnotations>) -> MenuItem {
+    let mut label = match version {
+        Some((number, count)) => format!("{} - {} (version {} of {})", song_file.metadata.artist, song_file.metadata.title, number, count),
+        None => format!("{} - {}", song_file.metadata.artist, song_file.metadata.title),
+    };
+
+    if let Some(annotations) = annotations {
+        if annotations.favorite {
+            label.insert_str(0, "* ");
+        }
+
+        if !annotations.tags.is_empty() {
+            label.push_str(format!(" [{}]", annotations.tags.join(", ")).as_str());
+        }
+
+        if let Some(rating) = annotations.rating {
+            label.push_str(format!(" (rated {}/{})", rating, MAX_RATING).as_str());
+        }
+    }
+
+    MenuItem {
+        label,
+        action: MenuEvent::SongSelected(song_file.id.clone()),
+    }
+}
+
+/// Lists the current value of every filter. Selecting a filter moves on to its next value.
+pub fn populate_browser_filters(filters_menu: &mut Menu, library: &Library, query: &LibraryQuery, annotations: &AnnotationStore) {
+    let instrument = query.instrument.map(|instrument| instrument.to_string());
+    let tuning = query.tuning.as_ref().map(|tuning| tuning.to_string());
+    let capo = query.capo.map(|capo| if capo == 0 { "None".to_string() } else { format!("Fret {}", capo) });
+    let year = query.year.map(|(from, to)| format!("{}-{}", from, to));
+    let difficulty = query.difficulty.map(|(_from, to)| format!("Up to {}", to));
+    let favorites = query.favorites.then(|| "Only favorites".to_string());
+    let rating = query.rating.map(|rating| format!("{}/{}", rating, MAX_RATING));
+    let order = if query.descending { "Descending" } else { "Ascending" };
+
+    filters_menu.items = vec![
+        filter_item("Instrument", instrument, BrowserFilter::Instrument),
+        filter_item("Tuning", tuning, BrowserFilter::Tuning),
+        filter_item("Capo", capo, BrowserFilter::Capo),
+        filter_item("Year", year, BrowserFilter::Year),
+        filter_item("Difficulty", difficulty, BrowserFilter::Difficulty),
+        filter_item("Favorites", favorites, BrowserFilter::Favorites),
+        filter_item("Tag", query.tag.clone(), BrowserFilter::Tag),
+        filter_item("Rating", rating, BrowserFilter::Rating),
+        MenuItem {
+            label: format!("Sort: {:?}", query.sort),
+            action: MenuEvent::CycleFilter(BrowserFilter::Sort),
+        },
+        MenuItem {
+            label: format!("Order: {}", order),
+            action: MenuEvent::CycleFilter(BrowserFilter::Order),
+        },
+        MenuItem {
+            label: "Clear filters".to_string(),
+            action: MenuEvent::ClearFilters,
+        },
+    ];
+
+    let matching = library.query(query, annotations).len();
+    filters_menu.title = format!("Browser Filters ({} of {} songs)", matching, library.songs.len());
+}
+
+fn filter_item(name: &str, value: Option<String>, filter: BrowserFilter) -> MenuItem {
+    MenuItem {
+        label: format!("{}: {}", name, value.unwrap_or("Any".to_string())),
+        action: MenuEvent::CycleFilter(filter),
+    }
+}
+
+/// Moves the filter on to its next value. Only values that occur in the library are offered,
+/// followed by "Any".
+pub(crate) fn cycle_filter(query: &mut LibraryQuery, library: &Library, annotations: &AnnotationStore, filter: BrowserFilter) {
+    let parts = || library.songs.iter().flat_map(|song_file| song_file.parts.iter());
+
+    match filter {
+        BrowserFilter::Instrument => {
+            let options: Vec<InstrumentKind> = ALL_INSTRUMENTS.into_iter()
+                .filter(|instrument| parts().any(|part| part.kind == *instrument))
+                .collect();
+            query.instrument = next_option(&options, &query.instrument);
+        }
+        BrowserFilter::Tuning => {
+            query.tuning = next_option(&library.tunings(), &query.tuning);
+        }
+        BrowserFilter::Capo => {
+            let mut options: Vec<u8> = parts().map(|part| part.capo).collect();
+            options.sort();
+            options.dedup();
+            query.capo = next_option(&options, &query.capo);
+        }
+        BrowserFilter::Year => {
+            let mut options: Vec<(u16, u16)> = library.songs.iter()
+                .map(|song_file| song_file.metadata.year / 10 * 10)
+                .map(|decade| (decade, decade + 9))
+                .collect();
+            options.sort();
+            options.dedup();
+            query.year = next_option(&options, &query.year);
+        }
+        BrowserFilter::Difficulty => {
+            let mut options: Vec<(f32, f32)> = parts()
+                .map(|part| (0.0, part.difficulty.ceil()))
+                .collect();
+            options.sort_by(|a, b| a.1.total_cmp(&b.1));
+            options.dedup();
+            query.difficulty = next_option(&options, &query.difficulty);
+        }
+        BrowserFilter::Favorites => {
+            query.favorites = !query.favorites;
+        }
+        BrowserFilter::Tag => {
+            query.tag = next_option(&annotations.tags(), &query.tag);
+        }
+        BrowserFilter::Rating => {
+            let mut options: Vec<u8> = annotations.songs.values()
+                .filter_map(|song_annotations| song_annotations.rating)
+                .collect();
+            options.sort();
+            options.dedup();
+            query.rating = next_option(&options, &query.rating);
+        }
+        BrowserFilter::Sort => {
+            let current = SortKey::ALL.iter().position(|sort| *sort == query.sort).unwrap_or(0);
+            query.sort = SortKey::ALL[(current + 1) % SortKey::ALL.len()];
+        }
+        BrowserFilter::Order => {
+            query.descending = !query.descending;
+        }
+    }
+}
+
+/// Returns the option after the current one, or `None` ("Any") after the last option
+fn next_option<T: Clone + PartialEq>(options: &[T], current: &Option<T>) -> Option<T> {
+    match current {
+        None => options.first().cloned(),
+        Some(value) => options.iter()
+            .position(|option| option == value)
+            .and_then(|idx| options.get(idx + 1))
+            .cloned()
+    }
+}
+
+/// Typing while the browser is shown searches for songs. Backspace removes the last character,
+/// Delete clears the search, Tab opens the filters menu and F3 shows the statistics of the selected
+/// song. F4 toggles whether the selected song is a favorite and F5 opens its annotations.
+pub(crate) fn handle_browser_keys(
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut menu_events: MessageWriter<MenuEvent>,
+    mut menu: ResMut<MenuStructure>,
+    mut query: ResMut<BrowserQuery>,
+    library: Res<SongLibrary>,
+    annotations: Res<LibraryAnnotations>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    if menu.current_menu_id() != Some(MenuId::Browser) {
+        keyboard_events.clear();
+        return;
+    }
+
+    let mut search_changed = false;
+
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        match event.key_code {
+            KeyCode::Backspace => {
+                search_changed |= query.0.text.pop().is_some();
+            }
+            KeyCode::Delete => {
+                search_changed |= !query.0.text.is_empty();
+                query.0.text.clear();
+            }
+            KeyCode::F3 => {
+                if let Some(song_file) = selected_song(&menu, &library.0, menu.selected_idx) {
+                    menu_events.write(MenuEvent::ShowSongStats(song_file.id.clone()));
+                }
+            }
+            KeyCode::F4 => {
+                if let Some(song_file) = selected_song(&menu, &library.0, menu.selected_idx) {
+                    menu_events.write(MenuEvent::Annotation(AnnotationEvent::ToggleFavorite(song_file.id.clone())));
+                }
+            }
+            KeyCode::F5 => {
+                if let Some(song_file) = selected_song(&menu, &library.0, menu.selected_idx) {
+                    menu_events.write(MenuEvent::Annotation(AnnotationEvent::Open(song_file.id.clone())));
+                }
+            }
+            KeyCode::Tab => {
+                if let Some(filters_menu) = menu.menus.get_mut(&MenuId::BrowserFilters) {
+                    populate_browser_filters(filters_menu, &library.0, &query.0, &annotations.0);
+                }
+
+                menu.push_menu(MenuId::BrowserFilters);
+                next_state.set(MenuState::ShowMenu);
+                return;
+            }
+            _ => {
+                let typed = event.text.iter()
+                    .flat_map(|text| text.chars())
+                    .filter(|c| !c.is_control());
+
+                for c in typed {
+                    query.0.text.push(c);
+                    search_changed = true;
+                }
+            }
+        }
+    }
+
+    if search_changed {
+        refresh_song_browser(&mut menu, &library.0, &query.0, &annotations.0);
+        next_state.set(MenuState::ShowMenu);
+    }
+}
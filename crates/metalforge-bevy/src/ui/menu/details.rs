@@ -0,0 +1,288 @@
+use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::stats::{format_ago, format_duration};
+use crate::ui::menu::{Menu, MenuId, MenuItem, MenuState, MenuStructure, OnMenu, SongLibrary};
+use crate::ui::player::practice::PracticeLog;
+use crate::ui::player::song_player::SongPlayer;
+use crate::ui::UIEngine;
+use bevy::asset::{Assets, Handle, RenderAssetUsages};
+use bevy::image::{CompressedImageFormats, Image, ImageSampler, ImageType};
+use bevy::prelude::{default, Commands, ImageNode, Message, MessageReader, NextState, Res, ResMut, Resource};
+use bevy::ui::{px, Node, PositionType};
+use log::{error, info};
+use metalforge_lib::engine::EngineCommand;
+use metalforge_lib::format::package::{export_package, PACKAGE_EXTENSION};
+use metalforge_lib::format::registry::FormatRegistry;
+use metalforge_lib::format::LoadError;
+use metalforge_lib::library::songfile::{SongFile, SongId};
+use metalforge_lib::practice::PracticeStore;
+use metalforge_lib::song::instrument_part::InstrumentPartType;
+use metalforge_lib::song::Song;
+use std::path::{Path, PathBuf};
+
+/// The size the album art is shown at, in pixels
+const ALBUM_ART_SIZE: f32 = 300.0;
+
+/// The song shown on the song detail screen, its chart once it has been parsed and its album art
+#[derive(Resource, Default)]
+pub(crate) struct SongDetails {
+    song_id: Option<SongId>,
+    song: Option<Song>,
+    album_art: Option<Handle<Image>>,
+    /// The outcome of exporting the song as a song package, if it has been exported
+    export_status: Option<String>,
+}
+
+#[derive(Message, Clone)]
+pub(crate) enum DetailsEvent {
+    /// Show the details of a song
+    Open(SongId),
+    /// The chart of a song was parsed by the engine
+    ChartLoaded(SongId, Song),
+    /// Play the song shown with the part with the specified name
+    Play(String),
+    /// Pack the song shown into a song package in the export directory
+    Export,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_details_events(
+    mut events: MessageReader<DetailsEvent>,
+    mut details: ResMut<SongDetails>,
+    mut menu: ResMut<MenuStructure>,
+    mut images: ResMut<Assets<Image>>,
+    mut player: ResMut<SongPlayer>,
+    library: Res<SongLibrary>,
+    practice_log: Res<PracticeLog>,
+    engine: Res<UIEngine>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    for event in events.read() {
+        match event {
+            DetailsEvent::Open(song_id) => {
+                let Some(song_file) = library.0.get(song_id) else {
+                    continue;
+                };
+
+                details.song_id = Some(song_id.clone());
+                details.song = None;
+                details.album_art = song_file.album_art()
+                    .and_then(|path| load_image(path.as_path()))
+                    .map(|image| images.add(image));
+                details.export_status = None;
+
+                // The chart is parsed by the engine, the note counts are filled in when it arrives
+                engine.send(EngineCommand::LoadSongDetails(song_id.clone()));
+
+                if let Some(details_menu) = menu.menus.get_mut(&MenuId::SongDetails) {
+                    populate_song_details(details_menu, song_file, None, None, &practice_log.0);
+                }
+
+                let first_part = first_part_item(&menu);
+                menu.push_menu(MenuId::SongDetails);
+                menu.set_menu_selection(MenuId::SongDetails, first_part);
+                next_state.set(MenuState::ShowMenu);
+            }
+            DetailsEvent::ChartLoaded(song_id, song) => {
+                if details.song_id.as_ref() != Some(song_id) {
+                    continue;
+                }
+
+                details.song = Some(song.clone());
+
+                if let Some(song_file) = library.0.get(song_id)
+                    && let Some(details_menu) = menu.menus.get_mut(&MenuId::SongDetails) {
+                    populate_song_details(details_menu, song_file, details.song.as_ref(), details.export_status.as_deref(), &practice_log.0);
+                }
+
+                if menu.current_menu_id() == Some(MenuId::SongDetails) {
+                    next_state.set(MenuState::ShowMenu);
+                }
+            }
+            DetailsEvent::Export => {
+                let Some(song_file) = details.song_id.as_ref().and_then(|song_id| library.0.get(song_id)) else {
+                    continue;
+                };
+
+                let status = match export_song(song_file, engine.config.library.export_path.as_str()) {
+                    Ok(path) => format!("Exported to {}", path.display()),
+                    Err(error) => {
+                        error!("Failed to export song {:?}: {}", song_file.song_dir, error);
+                        format!("Export failed: {}", error)
+                    }
+                };
+
+                details.export_status = Some(status);
+
+                if let Some(details_menu) = menu.menus.get_mut(&MenuId::SongDetails) {
+                    populate_song_details(details_menu, song_file, details.song.as_ref(), details.export_status.as_deref(), &practice_log.0);
+                }
+
+                next_state.set(MenuState::ShowMenu);
+            }
+            DetailsEvent::Play(part) => {
+                if let Some(song_id) = details.song_id.clone() {
+                    info!("Playing song (id: {}) with part {:?}", song_id.0, part);
+                    player.requested_arrangement = Some((song_id.clone(), part.clone()));
+                    engine.send(EngineCommand::LoadSong(song_id));
+                }
+            }
+        }
+    }
+}
+
+/// Writes a song package named after the artist and title of the song into the export directory
+fn export_song(song_file: &SongFile, export_dir: &str) -> Result<PathBuf, LoadError> {
+    let name: String = format!("{} - {}", song_file.metadata.artist, song_file.metadata.title).chars()
+        .map(|c| if c.is_alphanumeric() || " -_.()".contains(c) { c } else { '_' })
+        .collect();
+
+    std::fs::create_dir_all(export_dir)?;
+
+    let path = Path::new(export_dir).join(format!("{}.{}", name.trim(), PACKAGE_EXTENSION));
+    export_package(song_file, &FormatRegistry::default(), path.as_path())?;
+
+    Ok(path)
+}
+
+/// Decodes an image file into an image that can be shown in the UI
+fn load_image(path: &Path) -> Option<Image> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+
+    let bytes = std::fs::read(path)
+        .inspect_err(|error| error!("Failed to read album art {:?}: {:?}", path, error))
+        .ok()?;
+
+    Image::from_buffer(
+        bytes.as_slice(),
+        ImageType::Extension(extension.as_str()),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::RENDER_WORLD
+    )
+        .inspect_err(|error| error!("Failed to decode album art {:?}: {:?}", path, error))
+        .ok()
+}
+
+/// The index of the first part listed on the song detail screen, so the first part can be played
+/// by pressing Enter
+fn first_part_item(menu: &MenuStructure) -> usize {
+    menu.menus.get(&MenuId::SongDetails)
+        .and_then(|details_menu| details_menu.items.iter().position(|item| matches!(item.action, MenuEvent::PlayPart(_))))
+        .unwrap_or(0)
+}
+
+/// Lists the metadata of a song, how much it has been practiced, then every part with its tuning,
+/// capo and difficulty, guitar and bass parts being playable from the list. The note and technique
+/// counts of each part are shown once the chart is parsed.
+/// The song can be exported as a song package from the end of the list.
+fn populate_song_details(details_menu: &mut Menu, song_file: &SongFile, song: Option<&Song>, export_status: Option<&str>, practice: &PracticeStore) {
+    let metadata = &song_file.metadata;
+    let length = metadata.length.as_secs();
+
+    details_menu.title = format!("{} - {}", metadata.artist, metadata.title);
+    details_menu.items.clear();
+
+    push_line(details_menu, format!("Album: {} ({})", metadata.album, metadata.year));
+    push_line(details_menu, format!("Length: {}:{:02}", length / 60, length % 60));
+
+    let stats = practice.stats(&song_file.id, None);
+
+    match stats.last_practiced {
+        Some(last_practiced) if stats.sessions > 0 => {
+            push_line(details_menu, format!("Practiced: {} sessions, {}, last practiced {}",
+                                            stats.sessions,
+                                            format_duration(stats.practiced),
+                                            format_ago(last_practiced)));
+        }
+        _ => push_line(details_menu, "Practiced: Not yet".to_string()),
+    }
+
+    for part in &song_file.parts {
+        let tuning = part.tuning.as_ref().map(|tuning| format!(", {}", tuning)).unwrap_or_default();
+        let capo = if part.capo > 0 { format!(", capo on fret {}", part.capo) } else { String::new() };
+
+        let description = format!("{} ({}{}{}, difficulty {:.1})", part.name, part.kind, tuning, capo, part.difficulty);
+
+        // Only guitar and bass parts can be played, the others are listed for information
+        if part.kind.is_guitar() {
+            details_menu.items.push(MenuItem {
+                label: format!("Play {}", description),
+                action: MenuEvent::PlayPart(part.name.clone()),
+            });
+        } else {
+            push_line(details_menu, description);
+        }
+
+        let part_stats = practice.stats(&song_file.id, Some(part.name.as_str()));
+        let practiced = if part_stats.sessions > 0 {
+            format!(" - practiced {} times, {}", part_stats.sessions, format_duration(part_stats.practiced))
+        } else {
+            String::new()
+        };
+
+        let contents = match song {
+            None => "Loading chart...".to_string(),
+            Some(song) => song.instrument_parts.iter()
+                .find(|instrument_part| instrument_part.name == part.name)
+                .map(|instrument_part| describe_part(&instrument_part.instrument_part_type))
+                .unwrap_or("Missing from the chart".to_string()),
+        };
+
+        push_line(details_menu, format!("    {}{}", contents, practiced));
+    }
+
+    details_menu.items.push(MenuItem {
+        label: export_status.unwrap_or("Export as Song Package").to_string(),
+        action: MenuEvent::ExportSong,
+    });
+}
+
+fn describe_part(part_type: &InstrumentPartType) -> String {
+    let part = match part_type {
+        InstrumentPartType::LeadGuitar(part) |
+        InstrumentPartType::RhythmGuitar(part) |
+        InstrumentPartType::BassGuitar(part) => part,
+        InstrumentPartType::Keyboard |
+        InstrumentPartType::Drums |
+        InstrumentPartType::Vocals => return "No notes".to_string(),
+    };
+
+    let statistics = part.statistics();
+    let mut description = format!("{} notes, {} chords", statistics.notes, statistics.chords);
+
+    for (technique, count) in statistics.techniques {
+        description.push_str(format!(", {} {}", count, technique).as_str());
+    }
+
+    description
+}
+
+fn push_line(details_menu: &mut Menu, label: String) {
+    details_menu.items.push(MenuItem {
+        label,
+        action: MenuEvent::Noop,
+    });
+}
+
+/// Shows the album art next to the song details, if the song has any
+pub(crate) fn show_album_art(mut commands: Commands, menu: Res<MenuStructure>, details: Res<SongDetails>) {
+    if menu.current_menu_id() != Some(MenuId::SongDetails) {
+        return;
+    }
+
+    if let Some(album_art) = details.album_art.clone() {
+        commands.spawn((
+            ImageNode::new(album_art),
+            Node {
+                position_type: PositionType::Absolute,
+                top: px(50),
+                right: px(25),
+                width: px(ALBUM_ART_SIZE),
+                height: px(ALBUM_ART_SIZE),
+                ..default()
+            },
+            OnMenu
+        ));
+    }
+}
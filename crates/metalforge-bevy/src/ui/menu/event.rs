@@ -1,17 +1,44 @@
+use crate::ui::menu::annotations::AnnotationEvent;
+use crate::ui::menu::browser::{cycle_filter, refresh_song_browser, BrowserFilter, BrowserQuery, LibraryAnnotations};
+use crate::ui::menu::details::DetailsEvent;
+use crate::ui::menu::setlist::SetlistEvent;
+use crate::ui::menu::stats::populate_song_stats;
 use crate::ui::menu::{Menu, MenuId, MenuState, MenuStructure, SongLibrary};
+use crate::ui::player::event::PlayerEvent;
+use crate::ui::player::loops::LoopEvent;
+use crate::ui::player::practice::PracticeLog;
 use crate::ui::UIEngine;
 use bevy::app::AppExit;
 use bevy::prelude::{Message, MessageReader, MessageWriter, NextState, Res, ResMut};
 use log::info;
 use metalforge_lib::engine::EngineCommand;
+use metalforge_lib::library::query::LibraryQuery;
+use metalforge_lib::library::songfile::SongId;
 
-#[derive(Message, Hash, Ord, PartialOrd, PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(Message, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
 pub(crate) enum MenuEvent {
     PrevItemSelected,
     NextItemSelected,
+    PrevPageSelected,
+    NextPageSelected,
+    FirstItemSelected,
+    LastItemSelected,
     PushMenu(MenuId),
     PopMenu,
-    PlaySong(usize),
+    /// A song was picked in the browser, its details are shown before it's played
+    SongSelected(SongId),
+    /// Play the song shown on the song detail screen with the part with the specified name
+    PlayPart(String),
+    /// Pack the song shown on the song detail screen into a song package
+    ExportSong,
+    /// Show the practice statistics of a song
+    ShowSongStats(SongId),
+    CycleFilter(BrowserFilter),
+    ClearFilters,
+    ResetSongSettings,
+    Loop(LoopEvent),
+    Setlist(SetlistEvent),
+    Annotation(AnnotationEvent),
     ExitSong,
     ExitApp,
     ShowMenu,
@@ -19,13 +46,22 @@ pub(crate) enum MenuEvent {
     Noop
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn handle_menu_events(
     mut events: MessageReader<MenuEvent>,
     mut app_exit_writer: MessageWriter<AppExit>,
     mut menu: ResMut<MenuStructure>,
     engine: Res<UIEngine>,
     mut next_state: ResMut<NextState<MenuState>>,
-    library: Res<SongLibrary>
+    library: Res<SongLibrary>,
+    mut browser_query: ResMut<BrowserQuery>,
+    practice_log: Res<PracticeLog>,
+    annotations: Res<LibraryAnnotations>,
+    mut player_events: MessageWriter<PlayerEvent>,
+    mut loop_events: MessageWriter<LoopEvent>,
+    mut setlist_events: MessageWriter<SetlistEvent>,
+    mut annotation_events: MessageWriter<AnnotationEvent>,
+    mut details_events: MessageWriter<DetailsEvent>
 ) {
     for event in events.read() {
         match event {
@@ -35,6 +71,18 @@ pub(crate) fn handle_menu_events(
             MenuEvent::NextItemSelected => {
                 menu.select_next();
             }
+            MenuEvent::PrevPageSelected => {
+                menu.select_prev_page();
+            }
+            MenuEvent::NextPageSelected => {
+                menu.select_next_page();
+            }
+            MenuEvent::FirstItemSelected => {
+                menu.select_first();
+            }
+            MenuEvent::LastItemSelected => {
+                menu.select_last();
+            }
             MenuEvent::PushMenu(menu_id) => {
                 menu.push_menu(*menu_id);
                 next_state.set(MenuState::ShowMenu);
@@ -44,12 +92,43 @@ pub(crate) fn handle_menu_events(
                     next_state.set(MenuState::ShowMenu);
                 }
             }
-            MenuEvent::PlaySong(song_idx) => {
-                info!("Playing song (idx: {})", *song_idx);
-                if let Some(song_file) = library.0.songs.get(*song_idx) {
-                    engine.send(EngineCommand::LoadSong(song_file.clone()))
+            MenuEvent::SongSelected(song_id) if menu.menu_selection(MenuId::SetlistEditor).is_some() => {
+                // Songs picked while browsing for a setlist are added to it instead of being played
+                setlist_events.write(SetlistEvent::AddSong(song_id.clone()));
+
+                if menu.pop_menu() {
+                    next_state.set(MenuState::ShowMenu);
                 }
             }
+            MenuEvent::SongSelected(song_id) => {
+                details_events.write(DetailsEvent::Open(song_id.clone()));
+            }
+            MenuEvent::PlayPart(part) => {
+                details_events.write(DetailsEvent::Play(part.clone()));
+            }
+            MenuEvent::ExportSong => {
+                details_events.write(DetailsEvent::Export);
+            }
+            MenuEvent::ShowSongStats(song_id) => {
+                if let Some(song_file) = library.0.get(song_id)
+                    && let Some(stats_menu) = menu.menus.get_mut(&MenuId::SongStats) {
+                    populate_song_stats(stats_menu, song_file, &practice_log.0);
+                    menu.push_menu(MenuId::SongStats);
+                    next_state.set(MenuState::ShowMenu);
+                }
+            }
+            MenuEvent::CycleFilter(filter) => {
+                cycle_filter(&mut browser_query.0, &library.0, &annotations.0, *filter);
+                refresh_song_browser(&mut menu, &library.0, &browser_query.0, &annotations.0);
+                next_state.set(MenuState::ShowMenu);
+            }
+            MenuEvent::ClearFilters => {
+                // The search text is kept, only the filters and sort order are reset
+                let text = std::mem::take(&mut browser_query.0.text);
+                browser_query.0 = LibraryQuery { text, ..LibraryQuery::default() };
+                refresh_song_browser(&mut menu, &library.0, &browser_query.0, &annotations.0);
+                next_state.set(MenuState::ShowMenu);
+            }
             MenuEvent::ShowMenu => {
                 next_state.set(MenuState::ShowMenu);
             }
@@ -61,6 +140,18 @@ pub(crate) fn handle_menu_events(
                 app_exit_writer.write(AppExit::Success);
                 engine.send(EngineCommand::Quit);
             }
+            MenuEvent::ResetSongSettings => {
+                player_events.write(PlayerEvent::ResetSongSettings);
+            }
+            MenuEvent::Loop(loop_event) => {
+                loop_events.write(*loop_event);
+            }
+            MenuEvent::Setlist(setlist_event) => {
+                setlist_events.write(setlist_event.clone());
+            }
+            MenuEvent::Annotation(annotation_event) => {
+                annotation_events.write(annotation_event.clone());
+            }
             MenuEvent::ExitSong => {
                 info!("Exiting song");
                 engine.send(EngineCommand::UnloadSong);
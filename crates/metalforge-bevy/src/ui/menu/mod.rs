@@ -1,24 +1,41 @@
+pub(crate) mod annotations;
+pub(crate) mod browser;
+pub(crate) mod details;
 pub(crate) mod event;
-
+pub(crate) mod preview;
+pub(crate) mod setlist;
+pub(crate) mod stats;
+pub(crate) mod text_entry;
+
+use crate::ui::menu::annotations::{handle_annotation_events, AnnotationEditor, AnnotationEvent};
+use crate::ui::menu::browser::{handle_browser_keys, BrowserQuery};
+use crate::ui::menu::details::{handle_details_events, show_album_art, DetailsEvent, SongDetails};
 use crate::ui::menu::event::{handle_menu_events, MenuEvent};
+use crate::ui::menu::preview::{preview_highlighted_song, reset_preview, SongPreview};
+use crate::ui::menu::setlist::{handle_setlist_events, init_setlists, SetlistEditor, SetlistEvent};
+use crate::ui::menu::text_entry::{TextEntry, TextEntryEvent};
 use crate::ui::{despawn_screen, exit_menu, AppState, UIEngine};
-use bevy::app::App;
+use bevy::app::{App, Startup};
 use bevy::color::Color;
 use bevy::math::Vec2;
-use bevy::prelude::{in_state, AppExtStates, Commands, Component, IntoScheduleConfigs, OnEnter, OnExit, Query, Res, ResMut, Resource, States, Text, Transform, Update};
-use bevy::sprite::{Sprite, Text2d};
+use bevy::prelude::{in_state, percent, AppExtStates, BackgroundColor, Commands, Component, IntoScheduleConfigs, OnEnter, OnExit, Query, Res, ResMut, Resource, States, Text, Transform, Update, With};
+use bevy::sprite::Sprite;
 use bevy::text::TextColor;
-use bevy::ui::{px, Node};
+use bevy::ui::{px, AlignItems, FlexDirection, JustifyContent, Node};
 use bevy::utils::default;
 use log::info;
 use metalforge_lib::engine::{EngineCommand};
-use metalforge_lib::library::songfile::SongFile;
+use metalforge_lib::library::report::{ScanReport, ScanStatus};
+use metalforge_lib::library::song_dirs::SongDirStore;
 use metalforge_lib::library::Library;
 use std::collections::HashMap;
 use std::time::Instant;
 
 const KEYSTEP_MILLIS: u32 = 100;
 
+/// The number of items skipped by the Page Up and Page Down keys
+const PAGE_ITEMS: usize = 10;
+
 /// Marker component to indicate what components are visible on the main menu screen
 #[derive(Component)]
 pub(crate) struct OnMenu;
@@ -27,9 +44,30 @@ pub(crate) struct OnMenu;
 #[derive(Component)]
 struct OnLoading;
 
+/// Marker component for the text describing the progress of the library scan
+#[derive(Component)]
+struct LoadingLabel;
+
+/// Marker component for the filled part of the library scan progress bar
+#[derive(Component)]
+struct LoadingBar;
+
 #[derive(Resource)]
 pub(crate) struct SongLibrary(pub(crate) Library);
 
+/// The ID of the song in each song directory when the library was last scanned or watched
+#[derive(Resource)]
+pub(crate) struct LibrarySongDirs(pub(crate) SongDirStore);
+
+/// The latest progress reported by the engine while scanning the library
+#[derive(Resource, Default)]
+pub(crate) struct LibraryScanStatus {
+    pub scanned: usize,
+    pub found: usize,
+    pub total: usize,
+    pub current_path: String,
+}
+
 #[derive(States, Copy, Clone, Hash, Ord, PartialOrd, PartialEq, Eq, Debug)]
 pub(crate) enum MenuState {
     // Preparation phase, data loading, etc.
@@ -43,19 +81,32 @@ pub(crate) enum MenuState {
 pub fn main_menu(app: &mut App) {
     app
         .add_message::<MenuEvent>()
+        .add_message::<SetlistEvent>()
+        .add_message::<AnnotationEvent>()
+        .add_message::<DetailsEvent>()
+        .add_message::<TextEntryEvent>()
         .insert_state(MenuState::LoadData)
         .insert_resource(SongLibrary(Library::empty()))
+        .insert_resource(LibraryScanStatus::default())
+        .insert_resource(BrowserQuery::default())
+        .insert_resource(SetlistEditor::default())
+        .insert_resource(AnnotationEditor::default())
+        .insert_resource(TextEntry::default())
+        .insert_resource(SongDetails::default())
+        .insert_resource(SongPreview::default())
+        .add_systems(Startup, init_setlists)
 
         // Main menu systems
-        .add_systems(OnExit(AppState::MainMenu), despawn_screen::<OnMenu>)
+        .add_systems(OnExit(AppState::MainMenu), (despawn_screen::<OnMenu>, reset_preview))
 
         // Loading screen systems
         .add_systems(OnEnter(MenuState::LoadData), refresh_library)
         .add_systems(OnExit(MenuState::LoadData), despawn_screen::<OnLoading>)
+        .add_systems(Update, update_loading_screen.run_if(in_state(MenuState::LoadData)))
 
-        .add_systems(OnEnter(MenuState::ShowMenu), show_menu)
+        .add_systems(OnEnter(MenuState::ShowMenu), (show_menu, show_album_art))
         .add_systems(OnExit(MenuState::ShowMenu), exit_menu::<OnMenu>)
-        .add_systems(Update, (handle_menu_events, highlight_selection).chain()
+        .add_systems(Update, (handle_browser_keys, handle_menu_events, handle_setlist_events, handle_annotation_events, handle_details_events, highlight_selection, preview_highlighted_song).chain()
             .run_if(in_state(MenuState::ShowMenu)));
 }
 
@@ -75,6 +126,9 @@ pub(crate) struct MenuStructure {
 
     // The last time the previous/next menu item was selected. Used for rate limiting keyboard actions
     last_update: Instant,
+
+    // Set while the text of a menu item is being edited, the usual menu keys are ignored meanwhile
+    pub text_entry: bool,
 }
 
 impl Default for MenuStructure {
@@ -84,6 +138,7 @@ impl Default for MenuStructure {
             selected_idx: 0,
             requested_idx: Some(0),
             last_update: Instant::now(),
+            text_entry: false,
             menus: HashMap::from([
                 (MenuId::MainMenu, Menu {
                     title: "Main Menu".to_string(),
@@ -92,10 +147,18 @@ impl Default for MenuStructure {
                             label: "Browser".to_string(),
                             action: MenuEvent::PushMenu(MenuId::Browser),
                         },
+                        MenuItem {
+                            label: "Setlists".to_string(),
+                            action: MenuEvent::PushMenu(MenuId::Setlists),
+                        },
                         MenuItem {
                             label: "Settings".to_string(),
                             action: MenuEvent::PushMenu(MenuId::Settings),
                         },
+                        MenuItem {
+                            label: "Library Problems".to_string(),
+                            action: MenuEvent::PushMenu(MenuId::LibraryProblems),
+                        },
                         MenuItem {
                             label: "Exit".to_string(),
                             action: MenuEvent::ExitApp,
@@ -118,9 +181,57 @@ impl Default for MenuStructure {
                     items: vec![],
                     pop_action: MenuEvent::PopMenu,
                 }),
+                (MenuId::BrowserFilters, Menu {
+                    title: "Browser Filters".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
+                (MenuId::SongDetails, Menu {
+                    title: "Song Details".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
+                (MenuId::SongAnnotations, Menu {
+                    title: "Annotations".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
+                (MenuId::Setlists, Menu {
+                    title: "Setlists".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
+                (MenuId::SetlistEditor, Menu {
+                    title: "Setlist".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
+                (MenuId::SavedLoops, Menu {
+                    title: "Saved Loops (Enter to loop, F2 to rename, Delete to remove)".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
+                (MenuId::SongStats, Menu {
+                    title: "Statistics".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
+                (MenuId::LibraryProblems, Menu {
+                    title: "Library Problems".to_string(),
+                    items: vec![],
+                    pop_action: MenuEvent::PopMenu,
+                }),
                 (MenuId::PlayerMenu, Menu {
                     title: "Song Player".to_string(),
                     items: vec![
+                        MenuItem {
+                            label: "Saved Loops".to_string(),
+                            action: MenuEvent::PushMenu(MenuId::SavedLoops),
+                        },
+                        MenuItem {
+                            label: "Reset Song Settings".to_string(),
+                            action: MenuEvent::ResetSongSettings,
+                        },
                         MenuItem {
                             label: "Exit Song".to_string(),
                             action: MenuEvent::ExitSong,
@@ -171,6 +282,34 @@ impl MenuStructure {
         }
     }
 
+    /// Moves the selection a page of items down, stopping at the last item
+    pub fn select_next_page(&mut self) {
+        let item_count = self.current_menu()
+            .map(|menu| menu.items.len())
+            .unwrap_or(0);
+
+        let new_idx = self.requested_idx.unwrap_or(self.selected_idx) + PAGE_ITEMS;
+        self.requested_idx = Some(new_idx.min(item_count.saturating_sub(1)));
+    }
+
+    /// Moves the selection a page of items up, stopping at the first item
+    pub fn select_prev_page(&mut self) {
+        let new_idx = self.requested_idx.unwrap_or(self.selected_idx).saturating_sub(PAGE_ITEMS);
+        self.requested_idx = Some(new_idx);
+    }
+
+    pub fn select_first(&mut self) {
+        self.requested_idx = Some(0);
+    }
+
+    pub fn select_last(&mut self) {
+        let item_count = self.current_menu()
+            .map(|menu| menu.items.len())
+            .unwrap_or(0);
+
+        self.requested_idx = Some(item_count.saturating_sub(1));
+    }
+
     pub fn current_menu_id(&self) -> Option<MenuId> {
         self.menu_stack.last().map(|(id, _idx)| *id)
     }
@@ -191,6 +330,29 @@ impl MenuStructure {
         self.requested_menu = Some(menu_id);
     }
 
+    /// Returns the index of the item selected in the specified menu, if the menu is open
+    pub fn menu_selection(&self, menu_id: MenuId) -> Option<usize> {
+        let position = self.menu_stack.iter().position(|(id, _idx)| *id == menu_id)?;
+
+        if position + 1 == self.menu_stack.len() {
+            Some(self.requested_idx.unwrap_or(self.selected_idx))
+        } else {
+            // The selection of a menu is saved with the menu opened from it
+            self.menu_stack.get(position + 1).map(|(_id, idx)| *idx)
+        }
+    }
+
+    /// Changes the item selected in the specified menu, if the menu is open
+    pub fn set_menu_selection(&mut self, menu_id: MenuId, new_idx: usize) {
+        if let Some(position) = self.menu_stack.iter().position(|(id, _idx)| *id == menu_id) {
+            if position + 1 == self.menu_stack.len() {
+                self.requested_idx = Some(new_idx);
+            } else if let Some((_id, idx)) = self.menu_stack.get_mut(position + 1) {
+                *idx = new_idx;
+            }
+        }
+    }
+
     pub fn pop_menu(&mut self) -> bool {
         if self.menu_stack.len() > 1 {
             if let Some((menu_id, last_idx)) = self.menu_stack.pop() {
@@ -239,17 +401,83 @@ pub(crate) fn show_menu(
     }
 }
 
-fn refresh_library(mut commands: Commands, engine: Res<UIEngine>) {
+fn refresh_library(
+    mut commands: Commands,
+    engine: Res<UIEngine>,
+    mut song_library: ResMut<SongLibrary>,
+    mut scan_status: ResMut<LibraryScanStatus>
+) {
     info!("Loading song library");
 
     let paths = engine.config.library.paths.clone();
     engine.send(EngineCommand::ScanLibrary(paths));
 
+    // Songs found by the scan are added as they arrive
+    song_library.0 = Library::empty();
+    *scan_status = LibraryScanStatus::default();
+
     commands.spawn((
-        Text2d::new("Loading..."),
+        Node {
+            width: percent(100.0),
+            height: percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: px(10),
+            ..default()
+        },
         OnMenu,
         OnLoading
-    ));
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new("Loading..."),
+            LoadingLabel
+        ));
+
+        parent.spawn((
+            Node {
+                width: px(400),
+                height: px(12),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.25)),
+        )).with_children(|bar| {
+            bar.spawn((
+                Node {
+                    width: percent(0.0),
+                    height: percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.3, 0.3, 1.0, 1.0)),
+                LoadingBar
+            ));
+        });
+    });
+}
+
+fn update_loading_screen(
+    scan_status: Res<LibraryScanStatus>,
+    mut label_q: Query<&mut Text, With<LoadingLabel>>,
+    mut bar_q: Query<&mut Node, With<LoadingBar>>
+) {
+    for mut text in label_q.iter_mut() {
+        text.0 = format!("Loading... {} songs found ({}/{} directories)\n{}",
+                         scan_status.found,
+                         scan_status.scanned,
+                         scan_status.total,
+                         scan_status.current_path);
+    }
+
+    // The total grows as sub-directories are discovered, so the bar may occasionally move backwards
+    let progress = if scan_status.total > 0 {
+        scan_status.scanned as f32 / scan_status.total as f32
+    } else {
+        0.0
+    };
+
+    for mut node in bar_q.iter_mut() {
+        node.width = percent(100.0 * progress);
+    }
 }
 
 pub(crate) fn highlight_selection(
@@ -273,38 +501,47 @@ pub(crate) fn highlight_selection(
     }
 }
 
-pub fn populate_song_browser(browser_menu: &mut Menu, songs: &Vec<SongFile>) {
-    browser_menu.items.clear();
-
-    if songs.is_empty() {
-        reset_songs(browser_menu);
-    } else {
-        for (song_idx, song_file) in songs.iter().enumerate() {
-            browser_menu.items.push(song_to_menu(song_idx, song_file));
-        }
+/// Lists every song directory in the scan report that couldn't be loaded
+pub fn populate_library_problems(problems_menu: &mut Menu, report: &ScanReport) {
+    problems_menu.items.clear();
+
+    for entry in report.problems() {
+        let label = match &entry.status {
+            ScanStatus::Loaded => continue,
+            ScanStatus::Skipped(reason) => format!("[Skipped] {}: {}", entry.path, reason),
+            ScanStatus::Duplicate(original_dir) => format!("[Duplicate] {}: same as {}", entry.path, original_dir),
+            ScanStatus::Failed { error, json_path: Some(json_path), .. } => format!("[Failed] {}: {} (at {})", entry.path, error, json_path),
+            ScanStatus::Failed { error, json_path: None, .. } => format!("[Failed] {}: {}", entry.path, error),
+        };
+
+        problems_menu.items.push(MenuItem {
+            label,
+            action: MenuEvent::Noop,
+        });
     }
-}
 
-fn song_to_menu(song_idx: usize, song_file: &SongFile) -> MenuItem {
-    MenuItem {
-        label: format!("{} - {}", song_file.song.metadata.artist, song_file.song.metadata.title),
-        action: MenuEvent::PlaySong(song_idx),
+    if problems_menu.items.is_empty() {
+        problems_menu.items.push(MenuItem {
+            label: "[No problems found]".to_string(),
+            action: MenuEvent::Noop,
+        });
     }
 }
 
-fn reset_songs(browser_menu: &mut Menu) {
-    browser_menu.items.push(MenuItem {
-        label: "[No songs found]".to_string(),
-        action: MenuEvent::Noop,
-    });
-}
-
 #[derive(Hash, PartialEq, Eq, Copy, Clone, PartialOrd, Ord, Debug)]
 pub enum MenuId {
     MainMenu,
     PlayerMenu,
+    SavedLoops,
     Browser,
+    BrowserFilters,
+    SongStats,
+    SongDetails,
+    SongAnnotations,
+    Setlists,
+    SetlistEditor,
     Settings,
+    LibraryProblems,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,60 @@
+use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::{MenuId, MenuStructure};
+use crate::ui::UIEngine;
+use bevy::prelude::{Res, ResMut, Resource};
+use metalforge_lib::engine::EngineCommand;
+use metalforge_lib::library::songfile::SongId;
+use std::time::{Duration, Instant};
+
+/// How long a song has to stay highlighted before its preview starts, so scrolling through the
+/// library doesn't start a preview for every song passed
+const PREVIEW_DELAY: Duration = Duration::from_millis(500);
+
+/// The song highlighted in the song browser and whether its preview has been started yet
+#[derive(Resource, Default)]
+pub(crate) struct SongPreview {
+    song_id: Option<SongId>,
+    highlighted_at: Option<Instant>,
+    playing: bool,
+}
+
+/// Plays a preview of the song highlighted in the song browser. The preview is stopped as soon as
+/// the selection changes, and the next one starts once the selection has settled.
+pub(crate) fn preview_highlighted_song(
+    mut preview: ResMut<SongPreview>,
+    menu: Res<MenuStructure>,
+    engine: Res<UIEngine>,
+) {
+    let highlighted = match menu.current_item().map(|item| &item.action) {
+        Some(MenuEvent::SongSelected(song_id)) if menu.current_menu_id() == Some(MenuId::Browser) => Some(song_id),
+        _ => None,
+    };
+
+    if preview.song_id.as_ref() != highlighted {
+        if preview.playing {
+            engine.send(EngineCommand::StopPreview);
+        }
+
+        preview.song_id = highlighted.cloned();
+        preview.highlighted_at = Some(Instant::now());
+        preview.playing = false;
+        return;
+    }
+
+    if let Some(song_id) = preview.song_id.clone()
+        && !preview.playing
+        && preview.highlighted_at.is_some_and(|highlighted_at| highlighted_at.elapsed() >= PREVIEW_DELAY) {
+        engine.send(EngineCommand::PreviewSong(song_id));
+        preview.playing = true;
+    }
+}
+
+/// Forgets the previewed song when the menu is left, the engine stops the preview itself when the
+/// song is loaded
+pub(crate) fn reset_preview(mut preview: ResMut<SongPreview>, engine: Res<UIEngine>) {
+    if preview.playing {
+        engine.send(EngineCommand::StopPreview);
+    }
+
+    *preview = SongPreview::default();
+}
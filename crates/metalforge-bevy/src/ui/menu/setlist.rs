@@ -0,0 +1,338 @@
+use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::text_entry::{TextEntry, TextEntryEvent, TextField};
+use crate::ui::menu::{Menu, MenuId, MenuItem, MenuState, MenuStructure, SongLibrary};
+use crate::ui::player::setlist::SetlistPlayback;
+use crate::ui::UIEngine;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::{ButtonInput, ButtonState};
+use bevy::prelude::{KeyCode, Message, MessageReader, MessageWriter, NextState, Res, ResMut, Resource};
+use log::{error, info};
+use metalforge_lib::library::songfile::SongId;
+use metalforge_lib::library::Library;
+use metalforge_lib::playlist::{Playlist, PlaylistStore};
+
+/// The gaps between songs the setlist editor cycles through, in seconds
+const GAP_OPTIONS: [u64; 6] = [0, 2, 5, 10, 20, 30];
+
+/// The count-in lengths the setlist editor cycles through, in beats
+const COUNT_IN_OPTIONS: [u8; 4] = [0, 2, 4, 8];
+
+/// The number of items in the setlist editor before the first song of the setlist
+const ENTRY_ITEMS_OFFSET: usize = 4;
+
+/// Every setlist created by the user
+#[derive(Resource)]
+pub(crate) struct Setlists(pub(crate) PlaylistStore);
+
+/// The setlist open in the setlist editor
+#[derive(Resource, Default)]
+pub(crate) struct SetlistEditor {
+    playlist_idx: usize,
+}
+
+#[derive(Message, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+pub(crate) enum SetlistEvent {
+    /// Create an empty setlist and open it in the editor
+    Create,
+    /// Open the setlist with the specified index in the editor
+    Open(usize),
+    /// Start playing the setlist open in the editor
+    Play,
+    Delete,
+    Rename(String),
+    /// Move on to the next gap between songs
+    CycleGap,
+    /// Move on to the next count-in length
+    CycleCountIn,
+    /// Move on to the next instrument part of the song with the specified index
+    CyclePart(usize),
+    AddSong(SongId),
+    RemoveSong(usize),
+    /// Move the song with the specified index to a new position in the setlist
+    MoveSong { from: usize, to: usize },
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_setlist_events(
+    mut events: MessageReader<SetlistEvent>,
+    mut setlists: ResMut<Setlists>,
+    mut editor: ResMut<SetlistEditor>,
+    mut playback: ResMut<SetlistPlayback>,
+    mut menu: ResMut<MenuStructure>,
+    library: Res<SongLibrary>,
+    engine: Res<UIEngine>,
+    text_entry: Res<TextEntry>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    let mut changed = false;
+
+    for event in events.read() {
+        changed = true;
+        let idx = editor.playlist_idx;
+
+        let result = match event {
+            SetlistEvent::Create => {
+                let name = format!("Setlist {}", setlists.0.playlists.len() + 1);
+
+                setlists.0.create(name).map(|new_idx| {
+                    open_setlist(&mut menu, &mut editor, new_idx);
+                })
+            }
+            SetlistEvent::Open(new_idx) => {
+                open_setlist(&mut menu, &mut editor, *new_idx);
+                Ok(())
+            }
+            SetlistEvent::Play => {
+                if let Some(playlist) = setlists.0.playlists.get(idx) {
+                    playback.start(playlist.clone(), &library.0, &engine);
+                }
+                Ok(())
+            }
+            SetlistEvent::Delete => {
+                if menu.current_menu_id() == Some(MenuId::SetlistEditor) {
+                    menu.pop_menu();
+                }
+
+                setlists.0.delete(idx).map(|deleted| {
+                    if let Some(playlist) = deleted {
+                        info!("Deleted setlist {:?}", playlist.name);
+                    }
+                })
+            }
+            SetlistEvent::Rename(name) => {
+                setlists.0.edit(idx, |playlist| playlist.name = name.clone())
+            }
+            SetlistEvent::CycleGap => {
+                setlists.0.edit(idx, |playlist| {
+                    playlist.gap_millis = 1000 * next_option(&GAP_OPTIONS, playlist.gap_millis / 1000);
+                })
+            }
+            SetlistEvent::CycleCountIn => {
+                setlists.0.edit(idx, |playlist| {
+                    playlist.count_in_beats = next_option(&COUNT_IN_OPTIONS, playlist.count_in_beats);
+                })
+            }
+            SetlistEvent::CyclePart(entry_idx) => {
+                setlists.0.edit(idx, |playlist| {
+                    if let Some(entry) = playlist.entries.get_mut(*entry_idx)
+                        && let Some(song_file) = library.0.get(&entry.song_id) {
+                        // Cycles through every part of the song, then back to the part the song was last played with
+                        let parts: Vec<Option<String>> = std::iter::once(None)
+                            .chain(song_file.parts.iter().map(|part| Some(part.name.clone())))
+                            .collect();
+                        entry.part = next_option(&parts, entry.part.clone());
+                    }
+                })
+            }
+            SetlistEvent::AddSong(song_id) => {
+                setlists.0.edit(idx, |playlist| {
+                    info!("Adding song {:?} to setlist {:?}", song_id, playlist.name);
+                    playlist.add(song_id.clone(), None);
+                })
+            }
+            SetlistEvent::RemoveSong(entry_idx) => {
+                setlists.0.edit(idx, |playlist| {
+                    playlist.remove(*entry_idx);
+                })
+            }
+            SetlistEvent::MoveSong { from, to } => {
+                let mut moved = false;
+                let result = setlists.0.edit(idx, |playlist| moved = playlist.move_entry(*from, *to));
+
+                if moved {
+                    menu.set_menu_selection(MenuId::SetlistEditor, ENTRY_ITEMS_OFFSET + to);
+                }
+                result
+            }
+        };
+
+        if let Err(error) = result {
+            error!("Failed to save setlists: {:?}", error);
+        }
+    }
+
+    if changed {
+        refresh_setlists(&mut menu, &setlists.0, &editor, &library.0, &text_entry);
+        next_state.set(MenuState::ShowMenu);
+    }
+}
+
+fn open_setlist(menu: &mut MenuStructure, editor: &mut SetlistEditor, idx: usize) {
+    editor.playlist_idx = idx;
+
+    menu.push_menu(MenuId::SetlistEditor);
+}
+
+/// Returns the option following the current one, or the first option if the current one isn't listed
+fn next_option<T: PartialEq + Clone>(options: &[T], current: T) -> T {
+    options.iter()
+        .position(|option| *option == current)
+        .and_then(|idx| options.get(idx + 1))
+        .or(options.first())
+        .cloned()
+        .unwrap_or(current)
+}
+
+/// Rebuilds the setlist menus after a setlist or the library changed
+pub(crate) fn refresh_setlists(menu: &mut MenuStructure, store: &PlaylistStore, editor: &SetlistEditor, library: &Library, text_entry: &TextEntry) {
+    if let Some(setlists_menu) = menu.menus.get_mut(&MenuId::Setlists) {
+        populate_setlists(setlists_menu, store);
+    }
+
+    if let Some(editor_menu) = menu.menus.get_mut(&MenuId::SetlistEditor)
+        && let Some(playlist) = store.playlists.get(editor.playlist_idx) {
+        let rename = text_entry.text(&TextField::SetlistName(editor.playlist_idx));
+        populate_setlist_editor(editor_menu, playlist, library, rename);
+    }
+}
+
+pub(crate) fn init_setlists(
+    mut menu: ResMut<MenuStructure>,
+    setlists: Res<Setlists>,
+    editor: Res<SetlistEditor>,
+    library: Res<SongLibrary>,
+    text_entry: Res<TextEntry>,
+) {
+    refresh_setlists(&mut menu, &setlists.0, &editor, &library.0, &text_entry);
+}
+
+pub(crate) fn populate_setlists(setlists_menu: &mut Menu, store: &PlaylistStore) {
+    setlists_menu.items = vec![
+        MenuItem {
+            label: "New Setlist".to_string(),
+            action: MenuEvent::Setlist(SetlistEvent::Create),
+        }
+    ];
+
+    for (idx, playlist) in store.playlists.iter().enumerate() {
+        setlists_menu.items.push(MenuItem {
+            label: format!("{} ({} songs)", playlist.name, playlist.entries.len()),
+            action: MenuEvent::Setlist(SetlistEvent::Open(idx)),
+        });
+    }
+}
+
+/// Lists the settings and the songs of a setlist. Songs that are no longer in the library are kept
+/// in the setlist, but skipped when it's played.
+fn populate_setlist_editor(editor_menu: &mut Menu, playlist: &Playlist, library: &Library, rename: Option<&str>) {
+    editor_menu.title = match rename {
+        Some(new_name) => format!("Rename setlist: {}_", new_name),
+        None => format!("Setlist: {} (Shift+Up/Down to reorder, Delete to remove, F2 to rename)", playlist.name),
+    };
+
+    let count_in = match playlist.count_in_beats {
+        0 => "Off".to_string(),
+        beats => format!("{} beats", beats),
+    };
+
+    editor_menu.items = vec![
+        MenuItem {
+            label: "Play Setlist".to_string(),
+            action: MenuEvent::Setlist(SetlistEvent::Play),
+        },
+        MenuItem {
+            label: "Add Songs".to_string(),
+            action: MenuEvent::PushMenu(MenuId::Browser),
+        },
+        MenuItem {
+            label: format!("Gap Between Songs: {}s", playlist.gap().as_secs()),
+            action: MenuEvent::Setlist(SetlistEvent::CycleGap),
+        },
+        MenuItem {
+            label: format!("Count-in: {}", count_in),
+            action: MenuEvent::Setlist(SetlistEvent::CycleCountIn),
+        },
+    ];
+
+    for (idx, entry) in playlist.entries.iter().enumerate() {
+        let song = library.get(&entry.song_id)
+            .map(|song_file| format!("{} - {}", song_file.metadata.artist, song_file.metadata.title))
+            .unwrap_or_else(|| "[Missing song]".to_string());
+        let part = entry.part.as_deref().unwrap_or("Last played part");
+
+        editor_menu.items.push(MenuItem {
+            label: format!("{}. {} [{}]", idx + 1, song, part),
+            action: MenuEvent::Setlist(SetlistEvent::CyclePart(idx)),
+        });
+    }
+
+    editor_menu.items.push(MenuItem {
+        label: "Delete Setlist".to_string(),
+        action: MenuEvent::Setlist(SetlistEvent::Delete),
+    });
+}
+
+/// While the setlist editor is shown, Shift+Up and Shift+Down move the selected song, Delete
+/// removes it and F2 renames the setlist. The new name is typed through `TextEntry`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_setlist_keys(
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut text_events: MessageReader<TextEntryEvent>,
+    input: Res<ButtonInput<KeyCode>>,
+    mut setlist_events: MessageWriter<SetlistEvent>,
+    mut text_entry: ResMut<TextEntry>,
+    editor: Res<SetlistEditor>,
+    setlists: Res<Setlists>,
+    library: Res<SongLibrary>,
+    mut menu: ResMut<MenuStructure>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    let mut changed = false;
+
+    for event in text_events.read() {
+        match event {
+            TextEntryEvent::Accepted(TextField::SetlistName(idx), new_name) => {
+                // The setlist that was renamed is still the one open in the editor, as leaving the
+                // editor cancels renaming
+                if *idx == editor.playlist_idx && !new_name.is_empty() {
+                    setlist_events.write(SetlistEvent::Rename(new_name.clone()));
+                }
+
+                changed = true;
+            }
+            TextEntryEvent::Changed(TextField::SetlistName(_)) | TextEntryEvent::Cancelled(TextField::SetlistName(_)) => changed = true,
+            _ => {}
+        }
+    }
+
+    if menu.current_menu_id() != Some(MenuId::SetlistEditor) || text_entry.is_active() {
+        keyboard_events.clear();
+    }
+
+    let selected_entry = match menu.current_item().map(|item| &item.action) {
+        Some(MenuEvent::Setlist(SetlistEvent::CyclePart(idx))) => Some(*idx),
+        _ => None,
+    };
+
+    let shift = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        match (event.key_code, selected_entry) {
+            (KeyCode::F2, _) => {
+                if let Some(playlist) = setlists.0.playlists.get(editor.playlist_idx) {
+                    text_entry.start(MenuId::SetlistEditor, TextField::SetlistName(editor.playlist_idx), playlist.name.clone());
+                    changed = true;
+                }
+            }
+            (KeyCode::Delete, Some(idx)) => {
+                setlist_events.write(SetlistEvent::RemoveSong(idx));
+            }
+            (KeyCode::ArrowUp, Some(idx)) if shift && idx > 0 => {
+                setlist_events.write(SetlistEvent::MoveSong { from: idx, to: idx - 1 });
+            }
+            (KeyCode::ArrowDown, Some(idx)) if shift => {
+                setlist_events.write(SetlistEvent::MoveSong { from: idx, to: idx + 1 });
+            }
+            _ => {}
+        }
+    }
+
+    if changed {
+        refresh_setlists(&mut menu, &setlists.0, &editor, &library.0, &text_entry);
+        next_state.set(MenuState::ShowMenu);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::{Menu, MenuItem};
+use metalforge_lib::library::songfile::SongFile;
+use metalforge_lib::practice::stats::PracticeStats;
+use metalforge_lib::practice::PracticeStore;
+use std::time::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The number of most recent weeks listed in the practice trend
+const TREND_WEEKS: usize = 8;
+
+/// Lists the practice statistics of a song: totals for the whole song and each part practiced, then
+/// how much the song was practiced in each of the recent weeks
+pub fn populate_song_stats(stats_menu: &mut Menu, song_file: &SongFile, practice: &PracticeStore) {
+    stats_menu.title = format!("Statistics: {} - {}", song_file.metadata.artist, song_file.metadata.title);
+    stats_menu.items.clear();
+
+    let stats = practice.stats(&song_file.id, None);
+
+    if stats.sessions == 0 {
+        push_line(stats_menu, "[Not practiced yet]".to_string());
+        return;
+    }
+
+    push_line(stats_menu, format!("Sessions: {}", stats.sessions));
+    push_line(stats_menu, format!("Time practiced: {}", format_duration(stats.practiced)));
+
+    if let Some(last_practiced) = stats.last_practiced {
+        push_line(stats_menu, format!("Last practiced: {}", format_ago(last_practiced)));
+    }
+
+    if let Some(top_speed) = stats.top_speed {
+        push_line(stats_menu, format!("Top speed: {:.0}%", top_speed * 100.0));
+    }
+
+    if let Some(best_score) = stats.best_score {
+        push_line(stats_menu, format!("Best score: {:.1}", best_score));
+    }
+
+    for part in practice.practiced_parts(&song_file.id) {
+        let part_stats = practice.stats(&song_file.id, Some(part.as_str()));
+        push_line(stats_menu, format!("{}: {}", part, summarise(&part_stats)));
+    }
+
+    let trend = practice.trend(&song_file.id, None, Duration::from_secs(7 * SECONDS_PER_DAY));
+    push_line(stats_menu, "Recent weeks:".to_string());
+
+    for point in trend.iter().rev().take(TREND_WEEKS) {
+        let speed = point.average_speed
+            .map(|speed| format!(", average speed {:.0}%", speed * 100.0))
+            .unwrap_or_default();
+
+        push_line(stats_menu, format!("  {}: {} in {} sessions{}",
+                                      format_weeks_ago(point.start),
+                                      format_duration(point.practiced),
+                                      point.sessions,
+                                      speed));
+    }
+}
+
+fn summarise(stats: &PracticeStats) -> String {
+    let speed = stats.top_speed
+        .map(|speed| format!(", top speed {:.0}%", speed * 100.0))
+        .unwrap_or_default();
+
+    format!("{} sessions, {}{}", stats.sessions, format_duration(stats.practiced), speed)
+}
+
+fn push_line(stats_menu: &mut Menu, label: String) {
+    stats_menu.items.push(MenuItem {
+        label,
+        action: MenuEvent::Noop,
+    });
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+pub(crate) fn format_ago(time: SystemTime) -> String {
+    let days = SystemTime::now()
+        .duration_since(time)
+        .map(|elapsed| elapsed.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0);
+
+    match days {
+        0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        days => format!("{} days ago", days),
+    }
+}
+
+fn format_weeks_ago(time: SystemTime) -> String {
+    let weeks = SystemTime::now()
+        .duration_since(time)
+        .map(|elapsed| elapsed.as_secs() / (7 * SECONDS_PER_DAY))
+        .unwrap_or(0);
+
+    match weeks {
+        0 => "This week".to_string(),
+        1 => "Last week".to_string(),
+        weeks => format!("{} weeks ago", weeks),
+    }
+}
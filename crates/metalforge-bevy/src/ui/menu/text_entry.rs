@@ -0,0 +1,148 @@
+use crate::ui::menu::{MenuId, MenuStructure};
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ButtonState;
+use bevy::prelude::{KeyCode, Message, MessageReader, MessageWriter, ResMut, Resource};
+use metalforge_lib::library::songfile::SongId;
+
+/// The menu item text can be entered for
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum TextField {
+    /// The name of the saved loop with the specified index
+    LoopName(usize),
+    /// The name of the setlist with the specified index
+    SetlistName(usize),
+    SongTags(SongId),
+    SongNotes(SongId),
+}
+
+/// Sent when the text being entered changes and when entering it ends. Leaving the menu the text
+/// was entered in cancels it.
+#[derive(Message, Clone, Debug)]
+pub(crate) enum TextEntryEvent {
+    Changed(TextField),
+    Accepted(TextField, String),
+    Cancelled(TextField),
+}
+
+/// The text being typed into a menu item, e.g. the new name of a saved loop. Menus start entering
+/// text, after which `handle_text_entry` takes over the keyboard until the text is accepted or
+/// cancelled.
+#[derive(Resource, Default)]
+pub(crate) struct TextEntry(Option<ActiveEntry>);
+
+struct ActiveEntry {
+    menu_id: MenuId,
+    field: TextField,
+    text: String,
+}
+
+impl TextEntry {
+
+    /// Starts entering text for a field of the specified menu, beginning with the field's current text
+    pub(crate) fn start(&mut self, menu_id: MenuId, field: TextField, text: String) {
+        self.0 = Some(ActiveEntry { menu_id, field, text });
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// The text entered so far, if text is being entered for the field
+    pub(crate) fn text(&self, field: &TextField) -> Option<&str> {
+        self.0.as_ref()
+            .filter(|entry| entry.field == *field)
+            .map(|entry| entry.text.as_str())
+    }
+}
+
+/// The outcome of a key press while text is being entered
+enum TextEdit {
+    Accepted,
+    Cancelled,
+    Changed,
+    Ignored,
+}
+
+/// Applies key presses to the text being entered: typed characters are appended, Backspace removes
+/// the last character, Enter accepts the text and Escape cancels entering it. This is the only
+/// system that sets `MenuStructure::text_entry`, which the other key handlers check to ignore the
+/// keys used for typing.
+pub(crate) fn handle_text_entry(
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut text_entry: ResMut<TextEntry>,
+    mut text_events: MessageWriter<TextEntryEvent>,
+    mut menu: ResMut<MenuStructure>,
+) {
+    let Some(entry) = text_entry.0.as_mut() else {
+        keyboard_events.clear();
+
+        if menu.text_entry {
+            menu.text_entry = false;
+        }
+        return;
+    };
+
+    let mut finished = menu.current_menu_id() != Some(entry.menu_id);
+
+    if finished {
+        keyboard_events.clear();
+        text_events.write(TextEntryEvent::Cancelled(entry.field.clone()));
+    }
+
+    for event in keyboard_events.read() {
+        match edit_text(event, &mut entry.text) {
+            TextEdit::Accepted => {
+                let text = entry.text.trim().to_string();
+                text_events.write(TextEntryEvent::Accepted(entry.field.clone(), text));
+                finished = true;
+            }
+            TextEdit::Cancelled => {
+                text_events.write(TextEntryEvent::Cancelled(entry.field.clone()));
+                finished = true;
+            }
+            TextEdit::Changed => {
+                text_events.write(TextEntryEvent::Changed(entry.field.clone()));
+            }
+            TextEdit::Ignored => {}
+        }
+
+        if finished {
+            keyboard_events.clear();
+            break;
+        }
+    }
+
+    if finished {
+        text_entry.0 = None;
+    }
+
+    menu.text_entry = !finished;
+}
+
+fn edit_text(event: &KeyboardInput, text: &mut String) -> TextEdit {
+    if event.state != ButtonState::Pressed {
+        return TextEdit::Ignored;
+    }
+
+    match event.key_code {
+        KeyCode::Enter => TextEdit::Accepted,
+        KeyCode::Escape => TextEdit::Cancelled,
+        KeyCode::Backspace => {
+            text.pop();
+            TextEdit::Changed
+        }
+        _ => {
+            let typed: String = event.text.iter()
+                .flat_map(|text| text.chars())
+                .filter(|c| !c.is_control())
+                .collect();
+
+            if typed.is_empty() {
+                TextEdit::Ignored
+            } else {
+                text.push_str(typed.as_str());
+                TextEdit::Changed
+            }
+        }
+    }
+}
@@ -16,9 +16,19 @@ use bevy::window::{PrimaryWindow, Window, WindowCloseRequested, WindowPlugin, Wi
 use bevy::winit::WinitSettings;
 use bevy::DefaultPlugins;
 use bevy_dev_tools::fps_overlay::{FpsOverlayConfig, FpsOverlayPlugin, FrameTimeGraphConfig};
-use log::info;
+use log::{error, info};
 use metalforge_lib::engine::{EngineChannel, EngineCommand};
 use crate::ui::event::handle_engine_event;
+use crate::ui::menu::browser::LibraryAnnotations;
+use crate::ui::menu::LibrarySongDirs;
+use crate::ui::menu::setlist::Setlists;
+use crate::ui::player::practice::PracticeLog;
+use crate::ui::player::settings::SongPreferences;
+use metalforge_lib::library::annotations::AnnotationStore;
+use metalforge_lib::library::song_dirs::SongDirStore;
+use metalforge_lib::playlist::PlaylistStore;
+use metalforge_lib::practice::settings::SongSettingsStore;
+use metalforge_lib::practice::PracticeStore;
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AppState {
@@ -66,8 +76,50 @@ impl UI {
 
         // engine.send(EngineCommand::LoadSong(unimplemented!()));
 
+        // The stores move files they can't read aside, so falling back to an empty store never
+        // overwrites saved data. Opening only fails if the file couldn't be moved either.
+        let practice_store = PracticeStore::open(config.practice.history_path.as_str())
+            .unwrap_or_else(|error| {
+                error!("Failed to read practice history: {:?}", error);
+                PracticeStore::empty(config.practice.history_path.as_str())
+            });
+
+        let settings_store = SongSettingsStore::open(config.practice.settings_path.as_str())
+            .unwrap_or_else(|error| {
+                error!("Failed to read song settings: {:?}", error);
+                SongSettingsStore::empty(config.practice.settings_path.as_str())
+            });
+
+        let annotation_store = AnnotationStore::open(config.library.annotations_path.as_str())
+            .unwrap_or_else(|error| {
+                error!("Failed to read song annotations: {:?}", error);
+                AnnotationStore::empty(config.library.annotations_path.as_str())
+            });
+
+        let song_dir_store = SongDirStore::open(config.library.song_dirs_path.as_str())
+            .unwrap_or_else(|error| {
+                error!("Failed to read song directories: {:?}", error);
+                SongDirStore::empty(config.library.song_dirs_path.as_str())
+            });
+
+        engine.send(EngineCommand::ConfigureLoudness {
+            index_path: config.loudness.index_path.clone(),
+            target_lufs: config.loudness.target_lufs,
+        });
+
+        let setlist_store = PlaylistStore::open(config.setlists.path.as_str())
+            .unwrap_or_else(|error| {
+                error!("Failed to read setlists: {:?}", error);
+                PlaylistStore::empty(config.setlists.path.as_str())
+            });
+
         app
             .insert_state(AppState::MainMenu)
+            .insert_resource(PracticeLog(practice_store))
+            .insert_resource(SongPreferences(settings_store))
+            .insert_resource(Setlists(setlist_store))
+            .insert_resource(LibraryAnnotations(annotation_store))
+            .insert_resource(LibrarySongDirs(song_dir_store))
             .insert_resource(WinitSettings::game())
             .insert_resource(MenuStructure::default())
             .insert_resource(UIEngine {
@@ -1,4 +1,6 @@
 use crate::ui::player::song_player::{PlayerState, SongPlayer};
+use crate::ui::player::loops::snap_position;
+use crate::ui::player::settings::{reset_song_settings, SongPreferences};
 use crate::ui::player::CameraPosition;
 use crate::ui::UIEngine;
 use bevy::prelude::{Message, MessageReader, NextState, ResMut};
@@ -18,6 +20,8 @@ pub(crate) const FINE_SCROLL_DISTANCE_MILLIS: u64 = 10;
 pub(crate) const SCROLL_DISTANCE_MILLIS: u64 = 250;
 pub(crate) const JUMP_DISTANCE_MILLIS: u64 = 1000;
 
+pub(crate) const AUDIO_OFFSET_STEP_MILLIS: i32 = 10;
+
 #[derive(Copy, Clone)]
 pub enum SeekLocation {
     Start,
@@ -54,6 +58,10 @@ pub enum PlayerEvent {
     MarkLoopStart,
     /// Create a new marker indicating where playback should end next
     MarkLoopEnd,
+    /// Shift the chart relative to the audio by the specified number of milliseconds
+    AdjustAudioOffset(i32),
+    /// Forget the saved settings of the song and return to the default settings
+    ResetSongSettings,
 }
 
 pub(crate) fn handle_events(
@@ -62,6 +70,7 @@ pub(crate) fn handle_events(
     mut player: ResMut<SongPlayer>,
     mut camera: ResMut<CameraPosition>,
     mut player_state: ResMut<NextState<PlayerState>>,
+    mut preferences: ResMut<SongPreferences>,
 ) {
     for event in events.read() {
         match *event {
@@ -97,10 +106,18 @@ pub(crate) fn handle_events(
                 reset_speed(&mut engine, &mut player);
             }
             PlayerEvent::MarkLoopStart => {
-                player.start_position = player.song_position;
+                player.start_position = snap_position(&player, player.song_position);
+                player.current_loop = None;
             }
             PlayerEvent::MarkLoopEnd => {
-                player.loop_position = player.song_position;
+                player.loop_position = snap_position(&player, player.song_position);
+                player.current_loop = None;
+            }
+            PlayerEvent::AdjustAudioOffset(diff) => {
+                player.audio_offset_millis += diff;
+            }
+            PlayerEvent::ResetSongSettings => {
+                reset_song_settings(&engine, &mut preferences, &mut player, &mut camera);
             }
         }
     }
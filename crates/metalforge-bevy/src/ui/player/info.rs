@@ -24,13 +24,17 @@ pub fn update_info(player: Res<SongPlayer>, mut query: Query<&mut Text, With<UIL
     let time = player.song_position.as_secs_f32();
     let speed = 100.0 * player.player_speed;
 
-    let time_label = format!("{:.2}% {:01}:{:02}:{:02}.{:03}",
+    let mut time_label = format!("{:.2}% {:01}:{:02}:{:02}.{:03}",
                              speed,
                              (time / 3600.0) as u8,
                              (time % 3600.0 / 60.0) as u8,
                              (time % 60.0) as u8,
                              (time.fract() * 1000.0) as u16);
 
+    if player.audio_offset_millis != 0 {
+        time_label.push_str(format!(" (offset {:+}ms)", player.audio_offset_millis).as_str());
+    }
+
     for mut text in query.iter_mut() {
         text.0.clear();
         text.0.push_str(&time_label);
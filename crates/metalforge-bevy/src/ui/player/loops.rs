@@ -0,0 +1,293 @@
+use crate::ui::menu::event::MenuEvent;
+use crate::ui::menu::text_entry::{TextEntry, TextEntryEvent, TextField};
+use crate::ui::menu::{Menu, MenuId, MenuItem, MenuState, MenuStructure};
+use crate::ui::player::event::{PlayerEvent, SeekLocation};
+use crate::ui::player::settings::SongPreferences;
+use crate::ui::player::song_player::SongPlayer;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ButtonState;
+use bevy::prelude::{KeyCode, Message, MessageReader, MessageWriter, NextState, Res, ResMut, State};
+use log::{error, info};
+use metalforge_lib::practice::settings::{LoopSnap, NamedLoop};
+use metalforge_lib::song::Song;
+use std::time::Duration;
+
+#[derive(Message, Hash, Ord, PartialOrd, PartialEq, Eq, Copy, Clone, Debug)]
+pub(crate) enum LoopEvent {
+    /// Save the loop currently marked in the player under a generated name
+    SaveCurrent,
+    /// Loop the saved loop with the specified index and jump to its start
+    Select(usize),
+    /// Loop the saved loop after the current one
+    Next,
+    /// Loop the saved loop before the current one
+    Previous,
+    Delete(usize),
+    /// Move on to the next way of snapping loop edges
+    CycleSnap,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_loop_events(
+    mut events: MessageReader<LoopEvent>,
+    mut player: ResMut<SongPlayer>,
+    mut player_events: MessageWriter<PlayerEvent>,
+    mut preferences: ResMut<SongPreferences>,
+    mut menu: ResMut<MenuStructure>,
+    text_entry: Res<TextEntry>,
+    menu_state: Res<State<MenuState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+) {
+    let mut changed = false;
+    let mut loops_changed = false;
+
+    for event in events.read() {
+        changed = true;
+        loops_changed |= matches!(event, LoopEvent::SaveCurrent | LoopEvent::Delete(_) | LoopEvent::CycleSnap);
+
+        match *event {
+            LoopEvent::SaveCurrent => save_current_loop(&mut player),
+            LoopEvent::Select(idx) => select_loop(&mut player, &mut player_events, idx),
+            LoopEvent::Next => {
+                let count = player.saved_loops.len();
+
+                if count > 0 {
+                    let idx = player.current_loop.map(|idx| (idx + 1) % count).unwrap_or(0);
+                    select_loop(&mut player, &mut player_events, idx);
+                }
+            }
+            LoopEvent::Previous => {
+                let count = player.saved_loops.len();
+
+                if count > 0 {
+                    let idx = player.current_loop.map(|idx| (idx + count - 1) % count).unwrap_or(count - 1);
+                    select_loop(&mut player, &mut player_events, idx);
+                }
+            }
+            LoopEvent::Delete(idx) => {
+                if idx < player.saved_loops.len() {
+                    let removed = player.saved_loops.remove(idx);
+                    info!("Deleted loop {:?}", removed.name);
+
+                    player.current_loop = match player.current_loop {
+                        Some(current) if current == idx => None,
+                        Some(current) if current > idx => Some(current - 1),
+                        current => current,
+                    };
+                }
+            }
+            LoopEvent::CycleSnap => {
+                player.loop_snap = match player.loop_snap {
+                    LoopSnap::Off => LoopSnap::Beat,
+                    LoopSnap::Beat => LoopSnap::Measure,
+                    LoopSnap::Measure => LoopSnap::Off,
+                };
+            }
+        }
+    }
+
+    if loops_changed {
+        save_loops(&mut preferences, &player);
+    }
+
+    if changed {
+        refresh_saved_loops(&mut menu, &player, &text_entry, &menu_state, &mut next_menu_state);
+    }
+}
+
+/// Saves the loops of the current song right away, as the rest of the song's settings aren't saved
+/// when it's played as part of a setlist
+fn save_loops(preferences: &mut SongPreferences, player: &SongPlayer) {
+    if let Some(song_id) = player.song_id.as_ref()
+        && let Err(error) = preferences.0.set_loops(song_id, player.saved_loops.clone(), player.loop_snap) {
+        error!("Failed to save loops: {:?}", error);
+    }
+}
+
+fn save_current_loop(player: &mut SongPlayer) {
+    if player.start_position == Duration::ZERO && player.loop_position >= player.song_duration {
+        info!("No loop marked, nothing to save");
+        return;
+    }
+
+    let number = player.saved_loops.len() + 1;
+    let name = player.current_song.as_ref()
+        .map(|song| default_loop_name(song, player.start_position, player.loop_position, number))
+        .unwrap_or_else(|| format!("Loop {}", number));
+
+    info!("Saving loop {:?}", name);
+
+    player.saved_loops.push(NamedLoop {
+        name,
+        start_millis: player.start_position.as_millis() as u64,
+        end_millis: player.loop_position.as_millis() as u64,
+    });
+    player.current_loop = Some(player.saved_loops.len() - 1);
+}
+
+fn select_loop(player: &mut SongPlayer, player_events: &mut MessageWriter<PlayerEvent>, idx: usize) {
+    if let Some(named_loop) = player.saved_loops.get(idx) {
+        let start = Duration::from_millis(named_loop.start_millis);
+        let end = Duration::from_millis(named_loop.end_millis);
+
+        player.start_position = start;
+        player.loop_position = end;
+        player.current_loop = Some(idx);
+
+        player_events.write(PlayerEvent::Seek(SeekLocation::Location(start)));
+    }
+}
+
+/// Names a loop after the section and the measures it covers, e.g. "Solo, bars 33-40"
+fn default_loop_name(song: &Song, start: Duration, end: Duration, number: usize) -> String {
+    // A loop ending on the first beat of a measure doesn't include that measure
+    let last_measure = song.measure_at(end.saturating_sub(Duration::from_millis(1)));
+
+    let bars = match (song.measure_at(start), last_measure) {
+        (Some(first), Some(last)) if first < last => format!("bars {}-{}", first, last),
+        (Some(first), _) => format!("bar {}", first),
+        _ => return format!("Loop {}", number),
+    };
+
+    match song.section_at(start) {
+        Some(section) => format!("{}, {}", section.name, bars),
+        None => format!("Loop {}, {}", number, bars),
+    }
+}
+
+/// Moves a loop edge to the nearest beat or measure, depending on the snapping chosen
+pub(crate) fn snap_position(player: &SongPlayer, position: Duration) -> Duration {
+    let measures_only = match player.loop_snap {
+        LoopSnap::Off => return position,
+        LoopSnap::Beat => false,
+        LoopSnap::Measure => true,
+    };
+
+    player.current_song.as_ref()
+        .and_then(|song| song.nearest_beat(position, measures_only))
+        .map(|beat| beat.time)
+        .unwrap_or(position)
+}
+
+/// Rebuilds the saved loops menu, redrawing it if it's the menu currently shown
+fn refresh_saved_loops(
+    menu: &mut MenuStructure,
+    player: &SongPlayer,
+    text_entry: &TextEntry,
+    menu_state: &State<MenuState>,
+    next_menu_state: &mut NextState<MenuState>,
+) {
+    if let Some(loops_menu) = menu.menus.get_mut(&MenuId::SavedLoops) {
+        populate_saved_loops(loops_menu, player, text_entry);
+    }
+
+    if menu.current_menu_id() == Some(MenuId::SavedLoops) && menu_state.get() == &MenuState::ShowMenu {
+        next_menu_state.set(MenuState::ShowMenu);
+    }
+}
+
+pub(crate) fn populate_saved_loops(loops_menu: &mut Menu, player: &SongPlayer, text_entry: &TextEntry) {
+    loops_menu.items = vec![
+        MenuItem {
+            label: "Save Current Loop".to_string(),
+            action: MenuEvent::Loop(LoopEvent::SaveCurrent),
+        },
+        MenuItem {
+            label: format!("Snap Loop Edges: {:?}", player.loop_snap),
+            action: MenuEvent::Loop(LoopEvent::CycleSnap),
+        },
+    ];
+
+    for (idx, named_loop) in player.saved_loops.iter().enumerate() {
+        let name = match text_entry.text(&TextField::LoopName(idx)) {
+            Some(new_name) => format!("{}_", new_name),
+            None => named_loop.name.clone(),
+        };
+
+        let marker = if player.current_loop == Some(idx) { "> " } else { "" };
+
+        loops_menu.items.push(MenuItem {
+            label: format!("{}{} ({} - {})",
+                           marker,
+                           name,
+                           format_time(Duration::from_millis(named_loop.start_millis)),
+                           format_time(Duration::from_millis(named_loop.end_millis))),
+            action: MenuEvent::Loop(LoopEvent::Select(idx)),
+        });
+    }
+}
+
+pub(crate) fn init_saved_loops(mut menu: ResMut<MenuStructure>, player: Res<SongPlayer>, text_entry: Res<TextEntry>) {
+    if let Some(loops_menu) = menu.menus.get_mut(&MenuId::SavedLoops) {
+        populate_saved_loops(loops_menu, &player, &text_entry);
+    }
+}
+
+/// While the saved loops are shown, F2 renames the selected loop and Delete removes it. The new
+/// name is typed through `TextEntry`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_loop_keys(
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut text_events: MessageReader<TextEntryEvent>,
+    mut loop_events: MessageWriter<LoopEvent>,
+    mut text_entry: ResMut<TextEntry>,
+    mut player: ResMut<SongPlayer>,
+    mut preferences: ResMut<SongPreferences>,
+    mut menu: ResMut<MenuStructure>,
+    menu_state: Res<State<MenuState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+) {
+    let mut changed = false;
+
+    for event in text_events.read() {
+        match event {
+            TextEntryEvent::Accepted(TextField::LoopName(idx), new_name) => {
+                if let Some(named_loop) = player.saved_loops.get_mut(*idx).filter(|_| !new_name.is_empty()) {
+                    info!("Renamed loop {:?} to {:?}", named_loop.name, new_name);
+                    named_loop.name = new_name.clone();
+                    save_loops(&mut preferences, &player);
+                }
+
+                changed = true;
+            }
+            TextEntryEvent::Changed(TextField::LoopName(_)) | TextEntryEvent::Cancelled(TextField::LoopName(_)) => changed = true,
+            _ => {}
+        }
+    }
+
+    if menu.current_menu_id() != Some(MenuId::SavedLoops) || text_entry.is_active() {
+        keyboard_events.clear();
+    }
+
+    let selected_loop = match menu.current_item().map(|item| &item.action) {
+        Some(MenuEvent::Loop(LoopEvent::Select(idx))) => Some(*idx),
+        _ => None,
+    };
+
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        match (event.key_code, selected_loop) {
+            (KeyCode::F2, Some(idx)) => {
+                if let Some(named_loop) = player.saved_loops.get(idx) {
+                    text_entry.start(MenuId::SavedLoops, TextField::LoopName(idx), named_loop.name.clone());
+                    changed = true;
+                }
+            }
+            (KeyCode::Delete, Some(idx)) => {
+                loop_events.write(LoopEvent::Delete(idx));
+            }
+            _ => {}
+        }
+    }
+
+    if changed {
+        refresh_saved_loops(&mut menu, &player, &text_entry, &menu_state, &mut next_menu_state);
+    }
+}
+
+fn format_time(time: Duration) -> String {
+    format!("{}:{:02}.{:01}", time.as_secs() / 60, time.as_secs() % 60, time.subsec_millis() / 100)
+}
@@ -2,11 +2,19 @@ pub mod song_player;
 pub mod event;
 mod cursor;
 mod info;
+pub mod loops;
+pub mod practice;
+pub mod settings;
+pub mod setlist;
 
 use crate::ui::menu::show_menu;
 use crate::ui::player::cursor::{Cursor, CursorBundle};
 use crate::ui::player::event::{handle_events, PlayerEvent, SeekLocation};
 use crate::ui::player::info::{setup_info, update_info};
+use crate::ui::player::loops::{handle_loop_events, init_saved_loops, LoopEvent};
+use crate::ui::player::practice::{finish_practice, start_practice, track_practice, PracticeTracker};
+use crate::ui::player::settings::{restore_song_settings, save_song_settings};
+use crate::ui::player::setlist::{prepare_setlist_song, run_setlist, stop_setlist, SetlistPlayback};
 use crate::ui::player::song_player::{PlayerState, SongPlayer};
 use crate::ui::{despawn_screen, AppState};
 use bevy::app::{App, FixedUpdate, Update};
@@ -41,23 +49,27 @@ pub fn player_plugin(app: &mut App) {
         .insert_state(PlayerState::Paused)
         .insert_resource(CameraPosition::default())
         .insert_resource(SongPlayer::default())
+        .insert_resource(PracticeTracker::default())
+        .insert_resource(SetlistPlayback::default())
         .insert_resource(ClearColor(Color::srgb(0.06, 0.06, 0.10)))
-        .add_systems(OnEnter(AppState::Player), (setup_player, setup_info))
-        .add_systems(OnExit(AppState::Player), despawn_screen::<OnPlayer>)
+        .add_systems(OnEnter(AppState::Player), (restore_song_settings, prepare_setlist_song, init_saved_loops, setup_player, setup_info, start_practice).chain())
+        .add_systems(OnExit(AppState::Player), (despawn_screen::<OnPlayer>, finish_practice, save_song_settings))
+        .add_systems(OnEnter(AppState::MainMenu), stop_setlist)
 
         .add_systems(OnEnter(PlayerState::Menu), show_menu.chain())
         // .add_systems(OnExit(PlayerState::Menu), despawn_screen::<OnMenu>)
         // .add_systems(Update, (handle_menu_keyboard_events, highlight_selection, handle_menu_events)
         //     .run_if(in_state(PlayerState::Menu)))
-        .add_systems(Update, handle_events
+        .add_systems(Update, (handle_events, handle_loop_events, run_setlist)
             .run_if(in_state(AppState::Player)))
         .add_systems(FixedUpdate, (update_position, update_info, update_markers)
             .run_if(in_state(AppState::Player)))
-        .add_systems(FixedUpdate, check_loop
+        .add_systems(FixedUpdate, (check_loop, track_practice)
             .run_if(in_state(AppState::Player)))
         .add_systems(Update, update_camera
             .run_if(in_state(AppState::Player)))
         .add_message::<PlayerEvent>()
+        .add_message::<LoopEvent>()
     ;
 }
 
@@ -91,7 +103,7 @@ fn setup_player(
     mut player_state: ResMut<NextState<PlayerState>>
 ) {
     let song = player.current_song.as_ref().expect("No song selected");
-    let instrument = song.instrument_parts.first()
+    let instrument = player.current_part()
         .expect("Instrument part could not be found");
 
     let part = match &instrument.instrument_part_type {
@@ -301,7 +313,7 @@ fn update_position(time: Res<Time>, mut camera_position: ResMut<CameraPosition>,
     }
 
     position.velocity.x = player.player_speed;
-    position.current.x = player.song_position.as_secs_f32();
+    position.current.x = player.song_position.as_secs_f32() - player.audio_offset_millis as f32 / 1000.0;
 }
 
 /// Calculates and adjusts the position for the camera for each frame, interpolating and extrapolating
@@ -363,9 +375,10 @@ fn update_markers(player: Res<SongPlayer>, mut q_start_marker: Query<&mut Transf
     }
 }
 
-/// Verify if the current playback position is beyond the loop end marker and we need to wind back to the start of the loop
-fn check_loop(player: Res<SongPlayer>, mut event_queue: MessageWriter<PlayerEvent>) {
-    if player.playing && player.song_position >= player.loop_position {
+/// Verify if the current playback position is beyond the loop end marker and we need to wind back to the start of the loop.
+/// Songs of a setlist don't loop, the next song is played instead.
+fn check_loop(player: Res<SongPlayer>, setlist: Res<SetlistPlayback>, mut event_queue: MessageWriter<PlayerEvent>) {
+    if player.playing && !setlist.is_active() && player.song_position >= player.loop_position {
         let location = SeekLocation::Location(player.start_position);
         event_queue.write(PlayerEvent::Seek(location));
     }
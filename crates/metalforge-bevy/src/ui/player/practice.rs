@@ -0,0 +1,61 @@
+use crate::ui::player::song_player::SongPlayer;
+use bevy::prelude::{Res, ResMut, Resource};
+use log::{error, info};
+use metalforge_lib::practice::{PracticeSession, PracticeStore};
+use std::time::{Duration, Instant};
+
+/// The practice history of every song
+#[derive(Resource)]
+pub(crate) struct PracticeLog(pub(crate) PracticeStore);
+
+/// Collects what happens while a song is being practiced, the session is saved when the song is
+/// exited
+#[derive(Resource, Default)]
+pub(crate) struct PracticeTracker {
+    session: Option<PracticeSession>,
+    last_update: Option<Instant>,
+}
+
+pub(crate) fn start_practice(player: Res<SongPlayer>, mut tracker: ResMut<PracticeTracker>) {
+    let Some(song_id) = player.song_id.as_ref() else {
+        return;
+    };
+
+    let part = player.current_part()
+        .map(|part| part.name.clone())
+        .unwrap_or_default();
+
+    tracker.session = Some(PracticeSession::new(song_id.clone(), part));
+    tracker.last_update = Some(Instant::now());
+}
+
+pub(crate) fn track_practice(player: Res<SongPlayer>, mut tracker: ResMut<PracticeTracker>) {
+    let now = Instant::now();
+    let elapsed = tracker.last_update.map(|last_update| now.duration_since(last_update)).unwrap_or_default();
+    tracker.last_update = Some(now);
+
+    let Some(session) = tracker.session.as_mut() else {
+        return;
+    };
+
+    if player.playing() {
+        session.add_time(player.player_speed, elapsed);
+    }
+
+    if player.start_position > Duration::ZERO || player.loop_position < player.song_duration {
+        session.add_loop(player.start_position, player.loop_position);
+    }
+}
+
+pub(crate) fn finish_practice(mut tracker: ResMut<PracticeTracker>, mut practice_log: ResMut<PracticeLog>) {
+    tracker.last_update = None;
+
+    // Sessions where the song was never played are not worth keeping
+    if let Some(session) = tracker.session.take().filter(|session| session.practiced_millis > 0) {
+        info!("Recording practice session: {:?} {:?}", session.song_id, session.practiced());
+
+        if let Err(error) = practice_log.0.record(session) {
+            error!("Failed to record practice session: {:?}", error);
+        }
+    }
+}
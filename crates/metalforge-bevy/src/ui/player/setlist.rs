@@ -0,0 +1,228 @@
+use crate::ui::menu::SongLibrary;
+use crate::ui::player::event::PlayerEvent;
+use crate::ui::player::song_player::SongPlayer;
+use crate::ui::player::OnPlayer;
+use crate::ui::UIEngine;
+use bevy::prelude::{default, Commands, Component, MessageWriter, Query, Res, ResMut, Resource, Text, With};
+use bevy::ui::{px, Node, PositionType};
+use log::{info, warn};
+use metalforge_lib::engine::EngineCommand;
+use metalforge_lib::library::Library;
+use metalforge_lib::playlist::Playlist;
+use std::time::{Duration, Instant};
+
+/// The time between count-in clicks when the song has no beats to take the tempo from
+const DEFAULT_BEAT_MILLIS: u64 = 500;
+
+/// The setlist being played, if there is one. Songs of the setlist are played from the start to the
+/// end, then the next song is loaded after the gap and count-in of the setlist.
+#[derive(Resource, Default)]
+pub(crate) struct SetlistPlayback {
+    playlist: Option<Playlist>,
+    /// The index of the entry of the setlist currently loaded
+    position: usize,
+    /// The number of songs loaded so far, the gap is only waited for before songs following another
+    songs_played: usize,
+    phase: SetlistPhase,
+}
+
+#[derive(Default)]
+enum SetlistPhase {
+    /// Waiting for the engine to load the next song
+    #[default]
+    Loading,
+    /// Waiting for the gap between two songs to pass
+    Gap { until: Instant },
+    /// Counting in, the next click is played at the specified time
+    CountIn { next_click: Instant, remaining: u8 },
+    Playing,
+}
+
+/// Marker component for the label showing the progress through the setlist
+#[derive(Component)]
+pub(crate) struct SetlistLabel;
+
+impl SetlistPlayback {
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.playlist.is_some()
+    }
+
+    /// Starts playing a setlist from its first song that can be found in the library
+    pub(crate) fn start(&mut self, playlist: Playlist, library: &Library, engine: &UIEngine) {
+        info!("Playing setlist {:?}", playlist.name);
+
+        self.playlist = Some(playlist);
+        self.songs_played = 0;
+
+        if !self.load_next(0, library, engine) {
+            warn!("None of the songs of the setlist can be found in the library");
+            self.stop();
+        }
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.playlist = None;
+        self.phase = SetlistPhase::Loading;
+    }
+
+    /// Loads the first song at or after the specified entry that is in the library. Returns `false`
+    /// if there are no more songs to play.
+    fn load_next(&mut self, from: usize, library: &Library, engine: &UIEngine) -> bool {
+        let Some(playlist) = self.playlist.as_ref() else {
+            return false;
+        };
+
+        for (idx, entry) in playlist.entries.iter().enumerate().skip(from) {
+            if library.get(&entry.song_id).is_some() {
+                self.position = idx;
+                self.phase = SetlistPhase::Loading;
+                engine.send(EngineCommand::LoadSong(entry.song_id.clone()));
+
+                return true;
+            }
+
+            warn!("Skipping song missing from the library: {:?}", entry.song_id);
+        }
+
+        false
+    }
+}
+
+/// Plays the loaded song of the setlist with the part chosen for it, from the start to the end.
+/// Runs after the song's own settings are restored, so only the position, the loop and the part
+/// are changed.
+pub(crate) fn prepare_setlist_song(
+    mut commands: Commands,
+    mut setlist: ResMut<SetlistPlayback>,
+    mut player: ResMut<SongPlayer>,
+    engine: Res<UIEngine>,
+) {
+    let Some(playlist) = setlist.playlist.as_ref() else {
+        return;
+    };
+
+    if let Some(part) = playlist.entries.get(setlist.position).and_then(|entry| entry.part.clone()) {
+        player.arrangement = Some(part);
+    }
+
+    player.song_position = Duration::ZERO;
+    player.start_position = Duration::ZERO;
+    player.loop_position = player.song_duration;
+    player.current_loop = None;
+    engine.send(EngineCommand::Seek(Duration::ZERO));
+
+    let gap = if setlist.songs_played > 0 { playlist.gap() } else { Duration::ZERO };
+    setlist.songs_played += 1;
+    setlist.phase = SetlistPhase::Gap { until: Instant::now() + gap };
+
+    commands.spawn((
+        Text::new(String::new()),
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(0),
+            left: px(10),
+            ..default()
+        },
+        OnPlayer,
+        SetlistLabel
+    ));
+}
+
+/// Moves the setlist along: waits for the gap, counts in, starts the song and loads the next song
+/// when the current one ends
+pub(crate) fn run_setlist(
+    mut setlist: ResMut<SetlistPlayback>,
+    player: Res<SongPlayer>,
+    library: Res<SongLibrary>,
+    engine: Res<UIEngine>,
+    mut player_events: MessageWriter<PlayerEvent>,
+    mut label_q: Query<&mut Text, With<SetlistLabel>>,
+) {
+    let Some(playlist) = setlist.playlist.as_ref() else {
+        return;
+    };
+
+    let count_in_beats = playlist.count_in_beats;
+    let now = Instant::now();
+
+    let status = match setlist.phase {
+        SetlistPhase::Loading => String::new(),
+        SetlistPhase::Gap { until } if now < until => {
+            format!("Next song in {}s", (until - now).as_secs() + 1)
+        }
+        SetlistPhase::Gap { .. } => {
+            setlist.phase = if count_in_beats > 0 {
+                SetlistPhase::CountIn { next_click: now, remaining: count_in_beats }
+            } else {
+                player_events.write(PlayerEvent::ResumePlaying);
+                SetlistPhase::Playing
+            };
+            String::new()
+        }
+        SetlistPhase::CountIn { next_click, remaining } if now < next_click => {
+            format!("Count-in: {}", count_in_beats - remaining)
+        }
+        SetlistPhase::CountIn { remaining: 0, .. } => {
+            player_events.write(PlayerEvent::ResumePlaying);
+            setlist.phase = SetlistPhase::Playing;
+            String::new()
+        }
+        SetlistPhase::CountIn { remaining, .. } => {
+            // The first click of the count-in is accented
+            engine.send(EngineCommand::Click { accent: remaining == count_in_beats });
+
+            setlist.phase = SetlistPhase::CountIn {
+                next_click: now + beat_interval(&player),
+                remaining: remaining - 1,
+            };
+            format!("Count-in: {}", count_in_beats - remaining + 1)
+        }
+        SetlistPhase::Playing if player.song_position >= player.song_duration => {
+            player_events.write(PlayerEvent::PausePlaying);
+
+            let next = setlist.position + 1;
+
+            if !setlist.load_next(next, &library.0, &engine) {
+                info!("Setlist finished");
+                engine.send(EngineCommand::UnloadSong);
+                setlist.phase = SetlistPhase::Loading;
+            }
+            String::new()
+        }
+        SetlistPhase::Playing => String::new(),
+    };
+
+    let Some(playlist) = setlist.playlist.as_ref() else {
+        return;
+    };
+
+    let mut label = format!("{} ({}/{})", playlist.name, setlist.position + 1, playlist.entries.len());
+
+    if !status.is_empty() {
+        label.push_str(" - ");
+        label.push_str(status.as_str());
+    }
+
+    for mut text in label_q.iter_mut() {
+        text.0 = label.clone();
+    }
+}
+
+/// The time between two beats at the start of the song, at the current playing speed
+fn beat_interval(player: &SongPlayer) -> Duration {
+    let interval = player.current_song.as_ref()
+        .and_then(|song| match song.beats.as_slice() {
+            [first, second, ..] => Some(second.time.saturating_sub(first.time)),
+            _ => None,
+        })
+        .filter(|interval| !interval.is_zero())
+        .unwrap_or(Duration::from_millis(DEFAULT_BEAT_MILLIS));
+
+    interval.div_f32(player.player_speed)
+}
+
+/// Leaves the setlist when the player is exited
+pub(crate) fn stop_setlist(mut setlist: ResMut<SetlistPlayback>) {
+    setlist.stop();
+}
@@ -0,0 +1,120 @@
+use crate::ui::player::setlist::SetlistPlayback;
+use crate::ui::player::song_player::SongPlayer;
+use crate::ui::player::CameraPosition;
+use crate::ui::UIEngine;
+use bevy::prelude::{Res, ResMut, Resource};
+use log::{error, info};
+use metalforge_lib::engine::EngineCommand;
+use metalforge_lib::practice::settings::{SongSettings, SongSettingsStore};
+use std::time::Duration;
+
+/// The settings every song was last played with
+#[derive(Resource)]
+pub(crate) struct SongPreferences(pub(crate) SongSettingsStore);
+
+/// Puts the player back into the state the song was left in, or the default state if the song
+/// hasn't been played before
+pub(crate) fn restore_song_settings(
+    engine: Res<UIEngine>,
+    preferences: Res<SongPreferences>,
+    mut player: ResMut<SongPlayer>,
+    mut camera: ResMut<CameraPosition>,
+) {
+    let settings = player.song_id.as_ref()
+        .and_then(|song_id| preferences.0.get(song_id))
+        .cloned()
+        .unwrap_or_default();
+
+    apply_settings(&engine, &mut player, &mut camera, &settings);
+
+    if let Some((song_id, part)) = player.requested_arrangement.take()
+        && player.song_id.as_ref() == Some(&song_id) {
+        player.arrangement = Some(part);
+    }
+}
+
+/// Remembers the state of the player when the song is exited. Songs played as part of a setlist
+/// are played from start to end, which isn't worth remembering. Saved loops don't rely on this, as
+/// they're saved whenever they change.
+pub(crate) fn save_song_settings(
+    mut preferences: ResMut<SongPreferences>,
+    player: Res<SongPlayer>,
+    camera: Res<CameraPosition>,
+    setlist: Res<SetlistPlayback>,
+) {
+    let Some(song_id) = player.song_id.clone().filter(|_| !setlist.is_active()) else {
+        return;
+    };
+
+    let loop_end_millis = Some(player.loop_position)
+        .filter(|loop_position| *loop_position < player.song_duration)
+        .map(|loop_position| loop_position.as_millis() as u64);
+
+    let settings = SongSettings {
+        position_millis: player.song_position.as_millis() as u64,
+        speed: player.player_speed,
+        loop_start_millis: player.start_position.as_millis() as u64,
+        loop_end_millis,
+        arrangement: player.arrangement.clone(),
+        zoom: camera.zoom,
+        audio_offset_millis: player.audio_offset_millis,
+        loops: player.saved_loops.clone(),
+        loop_snap: player.loop_snap,
+    };
+
+    if let Err(error) = preferences.0.set(song_id, settings) {
+        error!("Failed to save song settings: {:?}", error);
+    }
+}
+
+/// Forgets the saved settings of the current song and returns the player to the default settings.
+/// Saved loops are kept, as they have to be deleted one by one.
+pub(crate) fn reset_song_settings(
+    engine: &UIEngine,
+    preferences: &mut SongPreferences,
+    player: &mut SongPlayer,
+    camera: &mut CameraPosition,
+) {
+    let settings = SongSettings {
+        loops: player.saved_loops.clone(),
+        ..SongSettings::default()
+    };
+
+    if let Some(song_id) = player.song_id.clone() {
+        info!("Resetting settings of song {:?}", song_id);
+
+        let result = if settings.loops.is_empty() {
+            preferences.0.remove(&song_id)
+        } else {
+            preferences.0.set(song_id, settings.clone())
+        };
+
+        if let Err(error) = result {
+            error!("Failed to reset song settings: {:?}", error);
+        }
+    }
+
+    apply_settings(engine, player, camera, &settings);
+}
+
+fn apply_settings(engine: &UIEngine, player: &mut SongPlayer, camera: &mut CameraPosition, settings: &SongSettings) {
+    let position = Duration::from_millis(settings.position_millis);
+
+    // Songs left at the very end start over from the beginning
+    player.song_position = if position < player.song_duration { position } else { Duration::ZERO };
+    player.start_position = Duration::from_millis(settings.loop_start_millis).min(player.song_duration);
+    player.loop_position = settings.loop_end_millis
+        .map(Duration::from_millis)
+        .unwrap_or(player.song_duration)
+        .min(player.song_duration);
+    player.player_speed = settings.speed;
+    player.arrangement = settings.arrangement.clone();
+    player.audio_offset_millis = settings.audio_offset_millis;
+    player.saved_loops = settings.loops.clone();
+    player.current_loop = None;
+    player.loop_snap = settings.loop_snap;
+    camera.zoom = settings.zoom;
+
+    engine.send(EngineCommand::Seek(player.song_position));
+    engine.send(EngineCommand::ChangeSpeed(player.player_speed));
+}
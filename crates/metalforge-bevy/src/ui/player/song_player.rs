@@ -1,5 +1,8 @@
 use std::time::{Duration, Instant};
 use bevy::prelude::{Resource, States};
+use metalforge_lib::library::songfile::SongId;
+use metalforge_lib::practice::settings::{LoopSnap, NamedLoop};
+use metalforge_lib::song::instrument_part::InstrumentPart;
 use metalforge_lib::song::Song;
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
@@ -11,6 +14,7 @@ pub enum PlayerState {
 
 #[derive(Resource)]
 pub struct SongPlayer {
+    pub song_id: Option<SongId>,
     pub current_song: Option<Song>,
     pub start_position: Duration,
     pub loop_position: Duration,
@@ -18,6 +22,18 @@ pub struct SongPlayer {
     pub song_position: Duration,
     pub song_duration: Duration,
     pub player_speed: f32,
+    /// The name of the instrument part shown, `None` means the first part of the song
+    pub arrangement: Option<String>,
+    /// Shifts the chart relative to the audio, positive values delay the chart
+    pub audio_offset_millis: i32,
+    /// The loops saved for the song
+    pub saved_loops: Vec<NamedLoop>,
+    /// The saved loop currently looping, if the loop hasn't been changed since it was selected
+    pub current_loop: Option<usize>,
+    pub loop_snap: LoopSnap,
+    /// The part picked on the song detail screen for the song being loaded, it replaces the part the
+    /// song was last played with
+    pub requested_arrangement: Option<(SongId, String)>,
     pub playing: bool
 }
 
@@ -31,6 +47,7 @@ impl SongPlayer {
         let song_duration = duration;
 
         Self {
+            song_id: None,
             current_song: Some(song),
             start_position: Duration::ZERO,
             loop_position,
@@ -38,18 +55,40 @@ impl SongPlayer {
             song_position: Duration::ZERO,
             song_duration,
             playing: false,
-            player_speed: 1.0 // Start with normal speed by default
+            player_speed: 1.0, // Start with normal speed by default
+            arrangement: None,
+            audio_offset_millis: 0,
+            saved_loops: vec![],
+            current_loop: None,
+            loop_snap: LoopSnap::Off,
+            requested_arrangement: None,
         }
     }
 
-    pub fn reset(&mut self, song: Song) {
+    pub fn reset(&mut self, song_id: SongId, song: Song) {
         let length = song.metadata.length;
+        self.song_id = Some(song_id);
         self.current_song = Some(song);
         self.start_position = Duration::ZERO;
         self.song_position = Duration::ZERO;
         self.song_duration = length;
         self.loop_position = length;
         self.last_start = Instant::now();
+        self.player_speed = 1.0;
+        self.arrangement = None;
+        self.audio_offset_millis = 0;
+        self.saved_loops.clear();
+        self.current_loop = None;
+        self.loop_snap = LoopSnap::Off;
+    }
+
+    /// The instrument part shown in the player
+    pub fn current_part(&self) -> Option<&InstrumentPart> {
+        let song = self.current_song.as_ref()?;
+
+        self.arrangement.as_ref()
+            .and_then(|arrangement| song.instrument_parts.iter().find(|part| &part.name == arrangement))
+            .or_else(|| song.instrument_parts.first())
     }
 
     pub fn playing(&self) -> bool {
@@ -87,6 +126,7 @@ impl SongPlayer {
 impl Default for SongPlayer {
     fn default() -> Self {
         Self {
+            song_id: None,
             current_song: None,
             start_position: Duration::ZERO,
             loop_position: Duration::ZERO,
@@ -94,7 +134,13 @@ impl Default for SongPlayer {
             song_position: Duration::ZERO,
             song_duration: Duration::ZERO,
             playing: false,
-            player_speed: 1.0 // Start with normal speed by default
+            player_speed: 1.0, // Start with normal speed by default
+            arrangement: None,
+            audio_offset_millis: 0,
+            saved_loops: vec![],
+            current_loop: None,
+            loop_snap: LoopSnap::Off,
+            requested_arrangement: None,
         }
     }
 }
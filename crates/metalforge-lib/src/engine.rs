@@ -1,22 +1,68 @@
-use std::fs::File;
-use std::path::Path;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 use std::time::Duration;
 use crossbeam_channel::{Receiver, Sender};
 use log::{debug, error, info};
 use rodio::{MixerDeviceSink, Player};
-use rodio::decoder::DecoderBuilder;
+use rodio::decoder::{DecoderBuilder, DecoderError};
+use rodio::source::{SineWave, Source};
+use crate::format::registry::FormatRegistry;
+use crate::format::SongAudio;
+use crate::library::loudness::{self, LoudnessStore};
+use crate::library::report::ScanReport;
+use crate::library::scanner::ScanEvent;
+use crate::library::watcher::{LibraryChange, LibraryWatcher};
 use crate::library::Library;
-use crate::library::songfile::SongFile;
+use crate::library::songfile::{SongFile, SongId};
 use crate::song::Song;
 
+/// The pitch of the clicks used for counting in. The first beat of a count-in is accented with a
+/// higher pitch.
+const CLICK_HZ: f32 = 880.0;
+const ACCENT_CLICK_HZ: f32 = 1760.0;
+const CLICK_MILLIS: u64 = 40;
+const CLICK_VOLUME: f32 = 0.3;
+
+/// How long the preview of a song plays for and how long it takes to fade in
+const PREVIEW_LENGTH: Duration = Duration::from_secs(30);
+const PREVIEW_FADE_IN: Duration = Duration::from_secs(2);
+
 /// `Engine` is responsible for handling input and output devices and managing playback.
 pub struct Engine {
     command_rx: Receiver<EngineCommand>,
     command_tx: Sender<EngineCommand>,
     event_rx: Receiver<EngineEvent>,
     event_tx: Sender<EngineEvent>,
-    _output_sink: MixerDeviceSink,
+    output_sink: MixerDeviceSink,
     output_player: Player,
+    /// Plays the preview of the song highlighted in the library, separately from the loaded song
+    preview_player: Arc<Player>,
+    /// Counts the previews started and stopped, so a preview still being prepared in the background
+    /// isn't played once another song is highlighted
+    preview_generation: Arc<Mutex<u64>>,
+    /// The most recently scanned library, used for looking up songs to load
+    library: Arc<Mutex<Library>>,
+    /// The formats the library is scanned for and songs are loaded with
+    formats: Arc<FormatRegistry>,
+    /// The library scan currently running in the background, if there is one
+    library_scan: RefCell<Option<LibraryScan>>,
+    /// Keeps the library up to date with changes made to the library paths after scanning
+    library_watcher: RefCell<Option<LibraryWatcher>>,
+    /// Evens out the volume of songs, if it's turned on
+    normalization: RefCell<Option<Normalization>>,
+}
+
+struct Normalization {
+    /// The loudness of every song analyzed so far, shared with the threads analyzing new songs
+    index: Arc<Mutex<LoudnessStore>>,
+    target_lufs: f32,
+}
+
+struct LibraryScan {
+    cancelled: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
 }
 
 impl Engine {
@@ -25,17 +71,32 @@ impl Engine {
             .expect("Cannot open audio stream");
 
         let player = Player::connect_new(output_sink.mixer());
+        let preview_player = Player::connect_new(output_sink.mixer());
 
         Self {
             command_rx,
             command_tx,
             event_rx,
             event_tx,
-            _output_sink: output_sink,
+            output_sink,
             output_player: player,
+            preview_player: Arc::new(preview_player),
+            preview_generation: Arc::new(Mutex::new(0)),
+            library: Arc::new(Mutex::new(Library::empty())),
+            formats: Arc::new(FormatRegistry::default()),
+            library_scan: RefCell::new(None),
+            library_watcher: RefCell::new(None),
+            normalization: RefCell::new(None),
         }
     }
 
+    /// Replaces the built-in formats with the specified ones, e.g. to add formats of the application's
+    /// own. Must be called before the library is scanned.
+    pub fn with_formats(mut self, formats: FormatRegistry) -> Self {
+        self.formats = Arc::new(formats);
+        self
+    }
+
     pub fn create_channel(&self) -> EngineChannel {
         EngineChannel::new(self.command_tx.clone(), self.event_rx.clone())
     }
@@ -56,12 +117,8 @@ impl Engine {
 
     fn handle_command(&self, command: &EngineCommand, event_tx: &Sender<EngineEvent>) -> bool {
         match command {
-            EngineCommand::ScanLibrary(paths) => {
-                let song_paths = paths.iter().map(|s| s.as_str()).collect();
-                let library = Library::scan_directories(song_paths);
-
-                let _ = event_tx.send(EngineEvent::LibraryUpdated(library));
-            }
+            EngineCommand::ScanLibrary(paths) => self.scan_library(paths.clone(), event_tx.clone()),
+            EngineCommand::CancelScan => self.cancel_scan(),
             EngineCommand::Quit => {
                 info!("Received quit command");
                 return self.quit()
@@ -70,41 +127,251 @@ impl Engine {
             EngineCommand::Resume => self.resume(),
             EngineCommand::Seek(duration) => self.seek(*duration),
             EngineCommand::ChangeSpeed(speed) => self.change_speed(*speed),
-            EngineCommand::LoadSong(songfile) => self.load_songfile(songfile),
-            EngineCommand::UnloadSong => self.unload_song()
+            EngineCommand::LoadSong(song_id) => self.load_songfile(song_id),
+            EngineCommand::LoadSongDetails(song_id) => self.load_song_details(song_id),
+            EngineCommand::UnloadSong => self.unload_song(),
+            EngineCommand::PreviewSong(song_id) => self.preview_song(song_id),
+            EngineCommand::StopPreview => self.stop_preview(),
+            EngineCommand::Click { accent } => self.click(*accent),
+            EngineCommand::ConfigureLoudness { index_path, target_lufs } => self.configure_loudness(index_path, *target_lufs),
         }
         true
     }
 
-    fn load_songfile(&self, songfile: &SongFile) {
-        self.load_song(songfile.song_path.as_str());
-        if let Err(error) = self.event_tx.send(EngineEvent::SongLoaded(songfile.song.clone())) {
+    /// Starts scanning the library paths on a background thread. Any scan still running is cancelled
+    /// first and its partial results are published before the new scan starts.
+    fn scan_library(&self, paths: Vec<String>, event_tx: Sender<EngineEvent>) {
+        self.cancel_scan();
+        if let Some(previous_scan) = self.library_scan.take() && previous_scan.thread.join().is_err() {
+            error!("Previous library scan panicked");
+        }
+
+        self.watch_library(&paths, event_tx.clone());
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let scan_cancelled = cancelled.clone();
+        let library = self.library.clone();
+        let loudness_index = self.loudness_index();
+        let formats = self.formats.clone();
+
+        let thread = std::thread::spawn(move || {
+            if let Ok(mut library) = library.lock() {
+                library.songs.clear();
+            }
+
+            let scanned_library = Library::scan_directories_with(paths, &formats, &scan_cancelled, |event| match event {
+                ScanEvent::Progress(progress) => {
+                    // Progress is only informational, so it's fine to drop updates if the UI falls behind
+                    let _ = event_tx.try_send(EngineEvent::LibraryScanProgress {
+                        scanned: progress.scanned,
+                        found: progress.found,
+                        total: progress.total,
+                        current_path: progress.current_path,
+                    });
+                }
+                ScanEvent::SongFound(songfile) => {
+                    if let Ok(mut library) = library.lock() {
+                        library.songs.push(songfile.clone());
+                    }
+                    let _ = event_tx.send(EngineEvent::LibrarySongFound(songfile));
+                }
+            });
+
+            if scan_cancelled.load(Ordering::Relaxed) {
+                info!("Library scan cancelled, {} songs found", scanned_library.songs.len());
+            }
+
+            if let Ok(mut library) = library.lock() {
+                *library = scanned_library.clone();
+            }
+            let _ = event_tx.send(EngineEvent::LibraryUpdated(scanned_library.clone()));
+
+            // Songs new to the library are analyzed after the scan, so the library can be browsed meanwhile
+            if let Some(index) = loudness_index {
+                loudness::analyze_missing(&scanned_library.songs, &index, &formats, &scan_cancelled);
+            }
+        });
+
+        self.library_scan.replace(Some(LibraryScan { cancelled, thread }));
+    }
+
+    fn watch_library(&self, paths: &[String], event_tx: Sender<EngineEvent>) {
+        // Stop watching the previous paths first, the new scan will pick up any changes anyway
+        self.library_watcher.replace(None);
+
+        // Changed songs are analyzed on their own thread, as decoding a song would hold up the
+        // watcher's later changes. The thread exits once the watcher and its sender are dropped.
+        let (analyze_tx, analyze_rx) = crossbeam_channel::unbounded::<SongFile>();
+
+        if let Some(index) = self.loudness_index() {
+            let formats = self.formats.clone();
+
+            std::thread::spawn(move || {
+                for songfile in analyze_rx {
+                    if index.lock().is_ok_and(|index| index.get(&songfile.id).is_none()) {
+                        loudness::analyze_into(&songfile, &index, &formats);
+                    }
+                }
+            });
+        }
+
+        let watcher = LibraryWatcher::watch(paths, self.library.clone(), self.formats.clone(), move |change| {
+            let changed_song = match &change {
+                LibraryChange::SongAdded(songfile) | LibraryChange::SongUpdated(_, songfile) => Some(songfile.clone()),
+                _ => None,
+            };
+
+            let event = match change {
+                LibraryChange::SongAdded(songfile) => EngineEvent::LibrarySongAdded(songfile),
+                LibraryChange::SongUpdated(previous_id, songfile) => EngineEvent::LibrarySongUpdated(previous_id, songfile),
+                LibraryChange::SongRemoved(song_id) => EngineEvent::LibrarySongRemoved(song_id),
+                LibraryChange::ReportUpdated(report) => EngineEvent::LibraryReportUpdated(report),
+            };
+
+            if let Err(error) = event_tx.send(event) {
+                error!("Error sending engine event: {}", error);
+            }
+
+            if let Some(songfile) = changed_song {
+                // The send fails when normalization is off, as there is no thread to analyze the song
+                let _ = analyze_tx.send(songfile);
+            }
+        });
+
+        match watcher {
+            Ok(watcher) => {
+                self.library_watcher.replace(Some(watcher));
+            }
+            Err(error) => error!("Failed to watch library paths: {:?}", error),
+        }
+    }
+
+    fn cancel_scan(&self) {
+        if let Some(scan) = self.library_scan.borrow().as_ref() && !scan.thread.is_finished() {
+            info!("Cancelling library scan");
+            scan.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn load_songfile(&self, song_id: &SongId) {
+        let Some(songfile) = self.library.lock().ok().and_then(|library| library.get(song_id).cloned()) else {
+            error!("Song not found in library: {:?}", song_id);
+            return;
+        };
+
+        let song = match self.formats.load_song(&songfile) {
+            Ok(song) => song,
+            Err(error) => {
+                error!("Failed to load song chart {:?}: {:?}", songfile.song_dir, error);
+                return;
+            }
+        };
+
+        let audio = match self.formats.open_audio(&songfile) {
+            Ok(audio) => audio,
+            Err(error) => {
+                error!("Failed to open song audio {:?}: {}", songfile.song_path, error);
+                return;
+            }
+        };
+
+        info!("Loading song: {}", songfile.song_path);
+
+        self.stop_preview();
+
+        if let Err(error) = self.load_song(audio) {
+            error!("Failed to create decoder for song audio {:?}: {:?}", songfile.song_path, error);
+            return;
+        }
+
+        self.output_player.set_volume(self.song_volume(&songfile.id));
+        if let Err(error) = self.event_tx.send(EngineEvent::SongLoaded(songfile.id.clone(), song)) {
             error!("Error sending engine event: {}", error);
         }
     }
 
-    fn load_song<P: AsRef<Path>>(&self, path: P) {
-        info!("Loading song: {}", path.as_ref().display());
+    /// Parses the chart of a song without loading its audio, so its parts can be described before
+    /// the song is played
+    fn load_song_details(&self, song_id: &SongId) {
+        let Some(songfile) = self.library.lock().ok().and_then(|library| library.get(song_id).cloned()) else {
+            error!("Song not found in library: {:?}", song_id);
+            return;
+        };
+
+        match self.formats.load_song(&songfile) {
+            Ok(song) => {
+                if let Err(error) = self.event_tx.send(EngineEvent::SongDetailsLoaded(songfile.id.clone(), song)) {
+                    error!("Error sending engine event: {}", error);
+                }
+            }
+            Err(error) => error!("Failed to load song chart {:?}: {:?}", songfile.song_dir, error),
+        }
+    }
+
+    /// Plays a snippet of a song fading in, starting from the preview start of its chart or from
+    /// its busiest part. Any preview already playing is stopped first. The preview is prepared on a
+    /// background thread, since finding the busiest part means parsing the whole chart.
+    fn preview_song(&self, song_id: &SongId) {
+        self.stop_preview();
+
+        let Some(songfile) = self.library.lock().ok().and_then(|library| library.get(song_id).cloned()) else {
+            error!("Song not found in library: {:?}", song_id);
+            return;
+        };
+
+        let Ok(generation) = self.preview_generation.lock().map(|generation| *generation) else {
+            return;
+        };
 
-        let file = File::open(path)
-            .expect("Failed to open OGG file");
+        let volume = self.song_volume(&songfile.id);
+        let formats = self.formats.clone();
+        let player = self.preview_player.clone();
+        let preview_generation = self.preview_generation.clone();
 
-        let len = file.metadata()
-            .expect("Failed to open file metadata")
-            .len();
+        std::thread::spawn(move || {
+            let Some(source) = preview_source(&songfile, &formats) else {
+                return;
+            };
 
+            // Holding the lock keeps the preview from being stopped between the check and playing it
+            let Ok(current_generation) = preview_generation.lock() else {
+                return;
+            };
+
+            if *current_generation != generation {
+                debug!("Dropping preview of {:?}, another song was highlighted meanwhile", songfile.song_path);
+                return;
+            }
+
+            player.set_volume(volume);
+            player.append(source);
+            player.play();
+        });
+    }
+
+    fn stop_preview(&self) {
+        if let Ok(mut generation) = self.preview_generation.lock() {
+            *generation += 1;
+        }
+
+        if !self.preview_player.empty() {
+            self.preview_player.clear();
+        }
+    }
+
+    fn load_song(&self, audio: SongAudio) -> Result<(), DecoderError> {
         let file_source = DecoderBuilder::new()
-            .with_data(file)
-            .with_byte_len(len)
+            .with_data(audio.data)
+            .with_byte_len(audio.byte_len)
             .with_gapless(true)
             .with_seekable(true)
-            .build()
-            .expect("Failed to create decoder for file");
+            .build()?;
 
         info!("Song loaded, appending to player");
 
         self.output_player.clear();
         self.output_player.append(file_source);
+        Ok(())
     }
 
     fn unload_song(&self) {
@@ -139,28 +406,151 @@ impl Engine {
         self.output_player.set_speed(speed);
     }
 
+    /// Plays a short click on top of the song, e.g. for counting in
+    fn click(&self, accent: bool) {
+        let frequency = if accent { ACCENT_CLICK_HZ } else { CLICK_HZ };
+
+        let click = SineWave::new(frequency)
+            .take_duration(Duration::from_millis(CLICK_MILLIS))
+            .amplify(CLICK_VOLUME);
+
+        self.output_sink.mixer().add(click);
+    }
+
+    /// Turns loudness normalization on with the specified target loudness, or off if there is no target
+    fn configure_loudness(&self, index_path: &str, target_lufs: Option<f32>) {
+        let Some(target_lufs) = target_lufs else {
+            info!("Loudness normalization is off");
+            self.normalization.replace(None);
+            return;
+        };
+
+        let index = LoudnessStore::open(index_path)
+            .unwrap_or_else(|error| {
+                error!("Failed to read loudness index: {:?}", error);
+                LoudnessStore::empty(index_path)
+            });
+
+        info!("Normalizing songs to {} LUFS", target_lufs);
+
+        self.normalization.replace(Some(Normalization {
+            index: Arc::new(Mutex::new(index)),
+            target_lufs,
+        }));
+    }
+
+    fn loudness_index(&self) -> Option<Arc<Mutex<LoudnessStore>>> {
+        self.normalization.borrow().as_ref().map(|normalization| normalization.index.clone())
+    }
+
+    /// The volume a song is played at. With normalization on, songs are played so they're all about
+    /// as loud as the target. Songs that haven't been analyzed yet are played as they are.
+    fn song_volume(&self, song_id: &SongId) -> f32 {
+        self.normalization.borrow().as_ref()
+            .and_then(|normalization| {
+                let loudness = normalization.index.lock().ok()?.get(song_id)?;
+                Some(loudness.gain(normalization.target_lufs))
+            })
+            .unwrap_or(1.0)
+    }
+
     fn quit(&self) -> bool {
         info!("Shutting down engine");
+        self.cancel_scan();
+        self.library_watcher.replace(None);
         self.output_player.stop();
+        self.preview_player.stop();
         info!("Shutdown engine, output player stopped, exiting main loop");
         false
     }
 }
 
+/// Decodes the preview of a song, starting from the preview start of its library entry. Charts that
+/// don't give one are parsed to find their busiest part.
+fn preview_source(songfile: &SongFile, formats: &FormatRegistry) -> Option<impl Source + Send + 'static> {
+    let start = match songfile.metadata.preview_start {
+        Some(start) => start,
+        None => match formats.load_song(songfile) {
+            Ok(song) => song.preview_start(PREVIEW_LENGTH),
+            Err(error) => {
+                error!("Failed to load song chart {:?}: {:?}", songfile.song_dir, error);
+                return None;
+            }
+        },
+    };
+
+    let audio = match formats.open_audio(songfile) {
+        Ok(audio) => audio,
+        Err(error) => {
+            error!("Failed to open song audio {:?}: {}", songfile.song_path, error);
+            return None;
+        }
+    };
+
+    let decoder = DecoderBuilder::new()
+        .with_data(audio.data)
+        .with_byte_len(audio.byte_len)
+        .with_seekable(true)
+        .build();
+
+    let mut source = match decoder {
+        Ok(source) => source,
+        Err(error) => {
+            error!("Failed to create decoder for song audio {:?}: {:?}", songfile.song_path, error);
+            return None;
+        }
+    };
+
+    if let Err(error) = source.try_seek(start) {
+        error!("Failed to seek to the preview of {:?}: {:?}", songfile.song_path, error);
+    }
+
+    debug!("Previewing song {:?} from {:?}", songfile.song_path, start);
+
+    Some(source.take_duration(PREVIEW_LENGTH).fade_in(PREVIEW_FADE_IN))
+}
+
 pub enum EngineCommand {
     ScanLibrary(Vec<String>),
-    LoadSong(SongFile),
+    /// Stop the library scan in progress. The songs found so far are sent as the updated library
+    CancelScan,
+    LoadSong(SongId),
+    /// Parse the chart of a song without playing it, the chart is sent back in `SongDetailsLoaded`
+    LoadSongDetails(SongId),
     UnloadSong,
+    /// Play a snippet of a song on its own, e.g. while it's highlighted in the library. The preview
+    /// is stopped when another song is previewed or loaded.
+    PreviewSong(SongId),
+    StopPreview,
     Seek(Duration),
     Pause,
     Resume,
     ChangeSpeed(f32),
+    /// Play a short click, accented clicks have a higher pitch
+    Click { accent: bool },
+    /// Turn loudness normalization on or off. Songs are analyzed after each library scan and the
+    /// results are kept in the loudness index at `index_path`. `None` turns normalization off.
+    ConfigureLoudness { index_path: String, target_lufs: Option<f32> },
     Quit
 }
 
 pub enum EngineEvent {
+    /// Sent after each directory scanned while a library scan is running
+    LibraryScanProgress { scanned: usize, found: usize, total: usize, current_path: String },
+    /// A song found by the library scan in progress, before the scan has finished
+    LibrarySongFound(SongFile),
     LibraryUpdated(Library),
-    SongLoaded(Song),
+    /// A song directory was added to one of the library paths after the library was scanned
+    LibrarySongAdded(SongFile),
+    /// A song already in the library was modified. Includes the ID the song had before the change
+    LibrarySongUpdated(SongId, SongFile),
+    /// A song directory was removed from the library paths
+    LibrarySongRemoved(SongId),
+    /// The scan report changed after the library paths were modified
+    LibraryReportUpdated(ScanReport),
+    SongLoaded(SongId, Song),
+    /// The chart of a song requested with `LoadSongDetails`
+    SongDetailsLoaded(SongId, Song),
     SongUnloaded,
 }
 
@@ -0,0 +1,291 @@
+use crate::format::asciitab::note_name;
+use crate::song::guitar::{GuitarNote, GuitarPart, GuitarTechnique};
+use crate::song::Beat;
+use std::time::Duration;
+
+/// Every beat is written as this many slots, each holding the notes nearest to it
+const SLOTS_PER_BEAT: u32 = 4;
+
+/// The length of the beats of measures written past the last beat of the song, if the song doesn't
+/// have two beats to tell it from
+const DEFAULT_BEAT_LENGTH: Duration = Duration::from_millis(500);
+
+/// The number of beats of measures written past the last beat of the song
+const DEFAULT_BEATS_PER_MEASURE: usize = 4;
+
+/// Renders a guitar part as ASCII tablature, in the notation read by `import_ascii_tab`. Every measure
+/// of the beats is written with four slots to a beat, and measures are wrapped into blocks no wider
+/// than `width` where they fit. Measures of four beats are added for notes past the last beat.
+pub fn export_ascii_tab(part: &GuitarPart, beats: &[Beat], width: usize) -> String {
+    let string_count = part.tuning.string_offsets.len();
+    let measures = measure_slots(beats, part.notes.iter().map(|note| note.time).max());
+    let slot_times: Vec<Duration> = measures.iter().flatten().copied().collect();
+
+    // The note played on each string in each slot, the first one if there are several
+    let mut slots: Vec<Vec<Option<&GuitarNote>>> = vec![vec![None; string_count]; slot_times.len()];
+
+    for note in &part.notes {
+        let next = slot_times.partition_point(|time| *time <= note.time);
+        let Some(slot) = [next.checked_sub(1), Some(next)].into_iter()
+            .flatten()
+            .filter(|slot| *slot < slot_times.len())
+            .min_by_key(|slot| slot_times[*slot].abs_diff(note.time)) else {
+            continue;
+        };
+
+        if let Some(cell) = slots[slot].get_mut(note.string as usize) {
+            cell.get_or_insert(note);
+        }
+    }
+
+    let names = string_names(part);
+    let name_width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+
+    let mut tab = String::new();
+
+    if part.capo > 0 {
+        tab.push_str(format!("Capo {}\n\n", part.capo).as_str());
+    }
+
+    let mut systems: Vec<Vec<MeasureText>> = vec![];
+    let mut system_width = 0;
+    let mut first_slot = 0;
+
+    for measure in &measures {
+        let text = MeasureText::new(&slots[first_slot..first_slot + measure.len()], string_count);
+        first_slot += measure.len();
+
+        let measure_width = text.width() + 1;
+
+        match systems.last_mut() {
+            Some(system) if name_width + 1 + system_width + measure_width <= width => system.push(text),
+            _ => {
+                systems.push(vec![text]);
+                system_width = 0;
+            }
+        }
+
+        system_width += measure_width;
+    }
+
+    let blocks: Vec<String> = systems.iter()
+        .map(|system| {
+            let mut lines = vec![];
+
+            for row in 0..ANNOTATION_ROWS {
+                let line: String = system.iter()
+                    .map(|measure| format!(" {}", measure.annotations[row]))
+                    .collect();
+
+                if !line.trim().is_empty() {
+                    lines.push(format!("{:name_width$}{}", "", line).trim_end().to_string());
+                }
+            }
+
+            for (idx, name) in names.iter().enumerate() {
+                let string = string_count - 1 - idx;
+                let line: String = system.iter()
+                    .map(|measure| format!("|{}", measure.strings[string]))
+                    .collect();
+
+                lines.push(format!("{:name_width$}{}|", name, line));
+            }
+
+            lines.join("\n")
+        })
+        .collect();
+
+    tab.push_str(blocks.join("\n\n").as_str());
+    tab.push('\n');
+    tab
+}
+
+/// The times of the slots of every measure. Beats are split into measures where they start a new
+/// one, and measures are added past the last beat up to the last note.
+fn measure_slots(beats: &[Beat], last_note: Option<Duration>) -> Vec<Vec<Duration>> {
+    let mut beat_times: Vec<(Duration, bool)> = beats.iter()
+        .enumerate()
+        .map(|(idx, beat)| (beat.time, idx == 0 || beat.beat_in_measure == 1 || beat.measure != beats[idx - 1].measure))
+        .collect();
+
+    let beat_length = match beats {
+        [.., previous, last] if last.time > previous.time => last.time - previous.time,
+        _ => DEFAULT_BEAT_LENGTH,
+    };
+
+    let mut added = 0;
+
+    while let Some(last_note) = last_note {
+        let next = beat_times.last().map_or(Duration::ZERO, |(time, _)| *time + beat_length);
+
+        if next > last_note && !beat_times.is_empty() {
+            break;
+        }
+
+        beat_times.push((next, added % DEFAULT_BEATS_PER_MEASURE == 0));
+        added += 1;
+    }
+
+    let mut measures: Vec<Vec<Duration>> = vec![];
+
+    for (idx, (time, new_measure)) in beat_times.iter().enumerate() {
+        let end = beat_times.get(idx + 1).map_or(*time + beat_length, |(next, _)| *next);
+        let slots = (0..SLOTS_PER_BEAT).map(|slot| *time + (end - *time) * slot / SLOTS_PER_BEAT);
+
+        match measures.last_mut() {
+            Some(measure) if !new_measure => measure.extend(slots),
+            _ => measures.push(slots.collect()),
+        }
+    }
+
+    measures
+}
+
+/// The names of the strings from the highest to the lowest. The highest string is written in lower
+/// case if another string has the same name, as in "e" for the high E of a guitar.
+fn string_names(part: &GuitarPart) -> Vec<String> {
+    let offsets = &part.tuning.string_offsets;
+
+    offsets.iter()
+        .enumerate()
+        .rev()
+        .map(|(idx, offset)| {
+            let name = note_name(*offset);
+            let repeated = offsets[..idx].iter().any(|other| note_name(*other) == name);
+
+            match idx == offsets.len() - 1 && repeated {
+                true => name.to_lowercase(),
+                false => name.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// The number of lines written above the strings: palm mutes, tremolo picking, slaps and pops
+const ANNOTATION_ROWS: usize = 3;
+
+/// A measure written out, without its bar lines
+struct MeasureText {
+    annotations: [String; ANNOTATION_ROWS],
+    /// From the lowest string to the highest
+    strings: Vec<String>,
+}
+
+impl MeasureText {
+
+    fn new(slots: &[Vec<Option<&GuitarNote>>], string_count: usize) -> Self {
+        let mut text = MeasureText {
+            annotations: Default::default(),
+            strings: vec![String::new(); string_count],
+        };
+
+        let mut palm_muted = false;
+        let mut tremolo_picked = false;
+
+        for slot in slots {
+            let tokens: Vec<String> = slot.iter()
+                .map(|note| note.map(note_token).unwrap_or_default())
+                .collect();
+
+            let slot_width = tokens.iter().map(|token| token.chars().count()).max().unwrap_or(0).max(1) + 1;
+
+            for (string, token) in text.strings.iter_mut().zip(&tokens) {
+                string.push_str(format!("{:-<slot_width$}", token).as_str());
+            }
+
+            let has = |technique: fn(&GuitarTechnique) -> bool| slot.iter()
+                .flatten()
+                .any(|note| note.technique.iter().any(technique));
+
+            let now_palm_muted = has(|technique| matches!(technique, GuitarTechnique::PalmMute));
+            let now_tremolo_picked = has(|technique| matches!(technique, GuitarTechnique::Tremolo));
+
+            text.annotations[0].push_str(range_text("PM", now_palm_muted, palm_muted, slot_width).as_str());
+            text.annotations[1].push_str(range_text("TP", now_tremolo_picked, tremolo_picked, slot_width).as_str());
+
+            let marker = if has(|technique| matches!(technique, GuitarTechnique::Slap)) {
+                "S"
+            } else if has(|technique| matches!(technique, GuitarTechnique::Pop)) {
+                "P"
+            } else {
+                ""
+            };
+
+            text.annotations[2].push_str(format!("{:slot_width$}", marker).as_str());
+
+            palm_muted = now_palm_muted;
+            tremolo_picked = now_tremolo_picked;
+        }
+
+        text
+    }
+
+    fn width(&self) -> usize {
+        self.strings.first().map_or(0, |string| string.chars().count())
+    }
+}
+
+/// Writes the columns of one slot of a technique that lasts over several notes, starting with its
+/// label and continued with dashes
+fn range_text(label: &str, applies: bool, continued: bool, slot_width: usize) -> String {
+    match (applies, continued) {
+        (false, _) => " ".repeat(slot_width),
+        (true, false) => format!("{:-<slot_width$}", label),
+        (true, true) => "-".repeat(slot_width),
+    }
+}
+
+/// Writes a note the way it's read by `import_ascii_tab`, e.g. "h7", "<12>" or "7b9r7~"
+fn note_token(note: &GuitarNote) -> String {
+    let mut token = String::new();
+
+    for technique in &note.technique {
+        match technique {
+            GuitarTechnique::HammerOn => token.push('h'),
+            GuitarTechnique::PullOff => token.push('p'),
+            GuitarTechnique::Tap => token.push('t'),
+            _ => {}
+        }
+    }
+
+    let has = |technique: fn(&GuitarTechnique) -> bool| note.technique.iter().any(technique);
+
+    if has(|technique| matches!(technique, GuitarTechnique::FretHandMute)) {
+        token.push('x');
+    } else if has(|technique| matches!(technique, GuitarTechnique::Harmonic)) {
+        token.push_str(format!("<{}>", note.fret).as_str());
+    } else if has(|technique| matches!(technique, GuitarTechnique::PinchHarmonic)) {
+        token.push_str(format!("[{}]", note.fret).as_str());
+    } else {
+        token.push_str(note.fret.to_string().as_str());
+    }
+
+    for technique in &note.technique {
+        match technique {
+            GuitarTechnique::Slide { to_fret } if *to_fret > note.fret => token.push_str(format!("/{}", to_fret).as_str()),
+            GuitarTechnique::Slide { to_fret } if *to_fret < note.fret => token.push_str(format!("\\{}", to_fret).as_str()),
+            GuitarTechnique::Bend { points } => token.push_str(bend_text(note.fret, points.iter().map(|point| point.cents)).as_str()),
+            _ => {}
+        }
+    }
+
+    if has(|technique| matches!(technique, GuitarTechnique::Vibrato)) {
+        token.push('~');
+    }
+
+    token
+}
+
+/// Writes a bend as the fret whose pitch it reaches, followed by the fret it's released to if it
+/// comes back down by at least a quarter tone
+fn bend_text(fret: u8, cents: impl Iterator<Item = i16> + Clone) -> String {
+    let highest = cents.clone().max().unwrap_or(0);
+    let last = cents.last().unwrap_or(0);
+    let to_fret = |cents: i16| (fret as i32 + (cents as f32 / 100.0).round() as i32).max(0);
+
+    match highest {
+        ..=0 => String::new(),
+        _ if last < highest - 50 => format!("b{}r{}", to_fret(highest), to_fret(last)),
+        _ => format!("b{}", to_fret(highest)),
+    }
+}
@@ -0,0 +1,505 @@
+use crate::format::asciitab::{parse_note_name, COLUMNS_PER_BEAT};
+use crate::format::Warnings;
+use crate::song::guitar::{BendPoint, GuitarNote, GuitarPart, GuitarTechnique, GuitarTuning};
+use crate::song::Beat;
+use std::ops::{Range, RangeInclusive};
+use std::time::Duration;
+
+/// The tempo evenly spaced columns are played at, in beats per minute
+const EVEN_TEMPO: f64 = 120.0;
+
+/// The tempos a tab can be read at, in beats per minute
+const TEMPOS: RangeInclusive<f64> = 1.0..=1000.0;
+
+/// Bends written without the fret they bend to are taken to be whole-tone bends
+const DEFAULT_BEND_CENTS: i16 = 200;
+
+/// The characters a line of tablature is made of after the name of its string
+const TAB_CHARACTERS: &str = "-0123456789|:hpbrtxX/\\~<>[]()*. ";
+
+/// Blocks of fewer or more lines than this aren't taken to be tablature
+const STRING_COUNTS: Range<usize> = 4..9;
+
+/// The pitch of each open string of the instruments usually written as tablature, for tabs that
+/// don't name their strings, in semitones from E2
+const DEFAULT_TUNINGS: [&[i8]; 5] = [
+    &[-12, -7, -2, 3],
+    &[-17, -12, -7, -2, 3],
+    &[0, 5, 10, 15, 19, 24],
+    &[-5, 0, 5, 10, 15, 19, 24],
+    &[-10, -5, 0, 5, 10, 15, 19, 24],
+];
+
+/// A guitar part read from ASCII tablature, along with the beats of the measures it's written in and
+/// a description of everything that couldn't be represented in the part
+pub struct AsciiTabImport {
+    pub part: GuitarPart,
+    pub beats: Vec<Beat>,
+    pub warnings: Vec<String>,
+}
+
+/// How the columns of a tab are turned into time. Tabs don't say how long their notes are, so notes
+/// are always played as far apart as they're written.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum TabTiming {
+    /// Every column takes the same time, with eight columns to a beat at 120 bpm
+    #[default]
+    Even,
+    /// Every measure takes the same number of beats at the tempo, in beats per minute, however wide
+    /// it's written. The tempo must be between 1 and 1000 bpm.
+    Tempo { bpm: f64, beats_per_measure: u8 },
+}
+
+/// Reads ASCII tablature into a guitar part. Tabs are read as blocks of lines, one for each string
+/// from the highest to the lowest, optionally starting with the name of the string and split into
+/// measures by bar lines. Notes are written as frets, with these techniques:
+///
+/// - `5h7`, `7p5`: hammer-ons and pull-offs
+/// - `5/7`, `7\5`: slides
+/// - `7b9`, `7b9r7`: bends to the pitch of a fret and releases
+/// - `7~`: vibrato
+/// - `x`: dead notes
+/// - `<12>`, `[12]`, `t12`: natural harmonics, pinch harmonics and taps
+///
+/// Palm mutes (`PM---`), tremolo picking (`TP---`), slaps (`S`) and pops (`P`) are written on a line
+/// above the strings, over the notes they apply to.
+pub fn import_ascii_tab(text: &str, timing: TabTiming) -> Result<AsciiTabImport, String> {
+    if let TabTiming::Tempo { bpm, beats_per_measure } = timing
+        && !(TEMPOS.contains(&bpm) && beats_per_measure > 0) {
+        return Err(format!("invalid tempo of {} bpm with {} beats per measure", bpm, beats_per_measure));
+    }
+
+    let mut warnings = Warnings::default();
+    let lines: Vec<&str> = text.lines().collect();
+    let blocks = read_blocks(lines.as_slice());
+
+    let Some(first_block) = blocks.first() else {
+        return Err("no tablature found".to_string());
+    };
+
+    let string_count = first_block.lines.len();
+    let tuning = named_tuning(first_block)
+        .unwrap_or_else(|| DEFAULT_TUNINGS.iter().find(|tuning| tuning.len() == string_count).unwrap().to_vec());
+
+    let mut notes: Vec<(GuitarNote, Option<TabBend>, Duration)> = vec![];
+    let mut beats = vec![];
+    let mut time = 0.0;
+
+    for block in &blocks {
+        if block.lines.len() != string_count {
+            warnings.add(format!("blocks of {} strings among tabs of {} strings were skipped", block.lines.len(), string_count));
+            continue;
+        }
+
+        let annotations = Annotations::read(block, &lines);
+        let block_notes: Vec<TabNote> = block.lines.iter()
+            .enumerate()
+            .flat_map(|(idx, line)| read_notes(line.content.as_slice(), (string_count - 1 - idx) as u8, &mut warnings))
+            .collect();
+
+        for measure in measures(block) {
+            let width = (measure.end - measure.start) as f64;
+            let (beat_length, beat_count) = match timing {
+                TabTiming::Even => (60.0 / EVEN_TEMPO, (measure.end - measure.start).div_ceil(COLUMNS_PER_BEAT).max(1)),
+                TabTiming::Tempo { bpm, beats_per_measure } => (60.0 / bpm, beats_per_measure as usize),
+            };
+
+            let length = match timing {
+                TabTiming::Even => width * beat_length / COLUMNS_PER_BEAT as f64,
+                TabTiming::Tempo { .. } => beat_count as f64 * beat_length,
+            };
+
+            let measure_number = beats.last().map_or(1, |beat: &Beat| beat.measure + 1);
+            beats.extend((0..beat_count).map(|idx| Beat {
+                time: seconds(time + beat_length * idx as f64),
+                measure: measure_number,
+                beat_in_measure: (idx + 1).min(u8::MAX as usize) as u8,
+            }));
+
+            let end = seconds(time + length);
+
+            for note in block_notes.iter().filter(|note| measure.contains(&note.column)) {
+                let mut technique = note.technique.clone();
+                annotations.apply(note.column, &mut technique);
+
+                let guitar_note = GuitarNote {
+                    string: note.string,
+                    fret: note.fret,
+                    finger: None,
+                    time: seconds(time + length * (note.column - measure.start) as f64 / width),
+                    length: Duration::ZERO,
+                    technique,
+                };
+
+                notes.push((guitar_note, note.bend, end));
+            }
+
+            time += length;
+        }
+    }
+
+    notes.sort_by_key(|(note, _, _)| (note.time, note.string));
+
+    // Notes ring until the next note on any string, but never past the end of their measure
+    let starts: Vec<Duration> = notes.iter().map(|(note, _, _)| note.time).collect();
+    let notes = notes.into_iter()
+        .map(|(mut note, bend, end)| {
+            let next = starts.iter().find(|start| **start > note.time).copied().unwrap_or(end);
+            note.length = next.min(end).saturating_sub(note.time);
+
+            if let Some(bend) = bend {
+                note.technique.push(bend.technique(note.length));
+            }
+
+            note
+        })
+        .collect();
+
+    let part = GuitarPart {
+        notes,
+        tuning: GuitarTuning::from(tuning),
+        capo: read_capo(lines.as_slice()).unwrap_or(0),
+        anchors: vec![],
+    };
+
+    Ok(AsciiTabImport { part, beats, warnings: warnings.into_messages() })
+}
+
+/// A line of tablature
+struct TabLine {
+    /// The number of semitones above E of the note the string is named after, if it's named
+    name: Option<i32>,
+    /// The column the string's notes start at
+    start: usize,
+    /// What's written after the name of the string
+    content: Vec<char>,
+}
+
+/// Consecutive lines of tablature, one for each string from the highest to the lowest
+struct Block {
+    lines: Vec<TabLine>,
+    /// The index of the first line of the block in the text
+    first_line: usize,
+}
+
+fn read_blocks(lines: &[&str]) -> Vec<Block> {
+    let mut blocks = vec![];
+    let mut block: Option<Block> = None;
+
+    for (idx, line) in lines.iter().enumerate() {
+        let Some(tab_line) = read_tab_line(line) else {
+            blocks.extend(block.take());
+            continue;
+        };
+
+        block.get_or_insert_with(|| Block { lines: vec![], first_line: idx }).lines.push(tab_line);
+    }
+
+    blocks.extend(block);
+    blocks.retain(|block| STRING_COUNTS.contains(&block.lines.len()));
+    blocks
+}
+
+fn read_tab_line(line: &str) -> Option<TabLine> {
+    let chars: Vec<char> = line.trim_end().chars().collect();
+    let indent = chars.iter().take_while(|c| c.is_whitespace()).count();
+    let name_length = chars[indent..].iter().take_while(|c| !matches!(c, '|' | ':' | '-')).count();
+
+    let name: String = chars[indent..indent + name_length].iter().collect();
+    let name = match name.trim() {
+        "" => None,
+        name => Some(parse_note_name(name)?),
+    };
+
+    let start = indent + name_length;
+    let content = chars[start..].to_vec();
+
+    if content.iter().filter(|c| **c == '-').count() < 3 || content.iter().any(|c| !TAB_CHARACTERS.contains(*c)) {
+        return None;
+    }
+
+    Some(TabLine { name, start, content })
+}
+
+/// The tuning of the strings of a block if all of them are named. Octaves aren't written, so blocks
+/// of four or five strings are taken to be bass, and every string to be tuned above the one below it.
+fn named_tuning(block: &Block) -> Option<Vec<i8>> {
+    let names: Vec<i32> = block.lines.iter().rev().map(|line| line.name).collect::<Option<_>>()?;
+
+    // The lowest string is within the octave below this note, so that it's near the lowest string
+    // of the default tuning for the number of strings: up to A#1 on a bass, A#2 on a guitar, C#2 on
+    // a seven-string and G#1 on an eight-string guitar
+    let highest_lowest = match names.len() {
+        ..=5 => -6,
+        6 => 6,
+        7 => 1,
+        _ => -4,
+    };
+
+    let mut offset = highest_lowest - (highest_lowest - names[0]).rem_euclid(12);
+    let mut tuning = vec![offset as i8];
+
+    for name in &names[1..] {
+        offset += 1 + (name - offset - 1).rem_euclid(12);
+        tuning.push(offset as i8);
+    }
+
+    Some(tuning)
+}
+
+/// Converts seconds to a duration. Times that don't fit in a duration can only come from tabs too long
+/// to play and are zero.
+fn seconds(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds).unwrap_or_default()
+}
+
+/// The columns of a block between two bar lines, relative to the start of its strings. Bar lines are
+/// the columns at least half of the strings have one in.
+fn measures(block: &Block) -> Vec<Range<usize>> {
+    let width = block.lines.iter().map(|line| line.content.len()).max().unwrap_or(0);
+
+    // Lines a column off from the others don't split their measures in two
+    let is_bar = |column: usize| 2 * block.lines.iter().filter(|line| line.content.get(column) == Some(&'|')).count() >= block.lines.len();
+
+    let mut measures = vec![];
+    let mut start = 0;
+
+    for column in 0..=width {
+        if column < width && !is_bar(column) {
+            continue;
+        }
+
+        // Repeat signs next to bar lines and the space after the last one aren't measures
+        let is_measure = block.lines.iter().any(|line| line.content.get(start..column.min(line.content.len()))
+            .is_some_and(|chars| chars.iter().any(|c| !matches!(c, ':' | '*' | ' ' | '|'))));
+
+        if is_measure {
+            measures.push(start..column);
+        }
+
+        start = column + 1;
+    }
+
+    measures
+}
+
+/// A note as it's written on the line of its string
+struct TabNote {
+    column: usize,
+    string: u8,
+    fret: u8,
+    technique: Vec<GuitarTechnique>,
+    bend: Option<TabBend>,
+}
+
+/// A bend as written in a tab, which doesn't say when it's bent or released
+#[derive(Copy, Clone)]
+struct TabBend {
+    cents: i16,
+    release_cents: Option<i16>,
+}
+
+impl TabBend {
+
+    /// Bends over the first half of the note, or bends and releases over its first two thirds
+    fn technique(&self, length: Duration) -> GuitarTechnique {
+        let point = |time_offset: Duration, cents: i16| BendPoint { time_offset, cents };
+
+        let points = match self.release_cents {
+            None => vec![point(Duration::ZERO, 0), point(length / 2, self.cents)],
+            Some(release_cents) => vec![
+                point(Duration::ZERO, 0),
+                point(length / 3, self.cents),
+                point(length * 2 / 3, release_cents),
+            ],
+        };
+
+        GuitarTechnique::Bend { points }
+    }
+}
+
+fn read_notes(content: &[char], string: u8, warnings: &mut Warnings) -> Vec<TabNote> {
+    let mut notes = vec![];
+    let mut idx = 0;
+
+    while idx < content.len() {
+        match read_note(content, idx, string, warnings) {
+            Some((note, end)) => {
+                notes.push(note);
+                idx = end;
+            }
+            None => idx += 1,
+        }
+    }
+
+    notes
+}
+
+/// Reads the note starting at a column, returning it along with the column after it
+fn read_note(content: &[char], start: usize, string: u8, warnings: &mut Warnings) -> Option<(TabNote, usize)> {
+    let mut idx = start;
+    let mut technique = vec![];
+    let mut slide_in = false;
+
+    while let Some(c) = content.get(idx) {
+        match c {
+            'h' => technique.push(GuitarTechnique::HammerOn),
+            'p' => technique.push(GuitarTechnique::PullOff),
+            't' => technique.push(GuitarTechnique::Tap),
+            '<' => technique.push(GuitarTechnique::Harmonic),
+            '[' => technique.push(GuitarTechnique::PinchHarmonic),
+            '/' | '\\' => slide_in = true,
+            _ => break,
+        }
+
+        idx += 1;
+    }
+
+    let fret = match content.get(idx) {
+        Some('x' | 'X') => {
+            idx += 1;
+            technique.push(GuitarTechnique::FretHandMute);
+            0
+        }
+        _ => read_fret(content, &mut idx)?,
+    };
+
+    if slide_in {
+        warnings.add("slides into notes were imported as plain notes".to_string());
+    }
+
+    let mut bend: Option<TabBend> = None;
+    let bend_cents = |to_fret: Option<u8>, default: i16| to_fret
+        .map_or(default, |to_fret| (to_fret as i16 - fret as i16) * 100);
+
+    while let Some(c) = content.get(idx) {
+        idx += 1;
+
+        match c {
+            '>' | ']' => {}
+            '~' => if !technique.iter().any(|technique| matches!(technique, GuitarTechnique::Vibrato)) {
+                technique.push(GuitarTechnique::Vibrato);
+            },
+            '/' | '\\' => match read_fret(content, &mut idx) {
+                Some(to_fret) => technique.push(GuitarTechnique::Slide { to_fret }),
+                None => warnings.add("slides out of notes were imported as plain notes".to_string()),
+            },
+            'b' => bend = Some(TabBend {
+                cents: bend_cents(read_fret(content, &mut idx), DEFAULT_BEND_CENTS),
+                release_cents: None,
+            }),
+            'r' => {
+                let release_cents = bend_cents(read_fret(content, &mut idx), 0);
+
+                match &mut bend {
+                    Some(bend) => bend.release_cents = Some(release_cents),
+                    None => warnings.add("releases without a bend were ignored".to_string()),
+                }
+            }
+            _ => {
+                idx -= 1;
+                break;
+            }
+        }
+    }
+
+    Some((TabNote { column: start, string, fret, technique, bend }, idx))
+}
+
+/// Reads a fret of one or two digits
+fn read_fret(content: &[char], idx: &mut usize) -> Option<u8> {
+    let digits = content.get(*idx..)?.iter().take(2).take_while(|c| c.is_ascii_digit()).count();
+    let fret = content[*idx..*idx + digits].iter().collect::<String>().parse().ok()?;
+    *idx += digits;
+
+    Some(fret)
+}
+
+/// Techniques written on lines of their own above the strings of a block, over the columns they
+/// apply to
+#[derive(Default)]
+struct Annotations {
+    palm_mutes: Vec<Range<usize>>,
+    tremolo_picking: Vec<Range<usize>>,
+    slaps: Vec<usize>,
+    pops: Vec<usize>,
+}
+
+impl Annotations {
+
+    /// Reads the lines right above a block, up to the previous blank line
+    fn read(block: &Block, lines: &[&str]) -> Self {
+        let mut annotations = Annotations::default();
+        let start = block.lines[0].start;
+
+        for line in lines[..block.first_line].iter().rev().take_while(|line| !line.trim().is_empty()) {
+            let chars: Vec<char> = line.chars().collect();
+            let mut idx = 0;
+
+            while idx < chars.len() {
+                if chars[idx].is_whitespace() {
+                    idx += 1;
+                    continue;
+                }
+
+                let word_start = idx;
+
+                while idx < chars.len() && !chars[idx].is_whitespace() {
+                    idx += 1;
+                }
+
+                let Some(column) = word_start.checked_sub(start) else {
+                    continue;
+                };
+
+                let word: String = chars[word_start..idx].iter().collect();
+                let word = word.trim_end_matches('|');
+                let columns = column..column + word.chars().count();
+
+                if word.starts_with("PM") || word.starts_with("P.M.") {
+                    annotations.palm_mutes.push(columns);
+                } else if word.starts_with("TP") || word.starts_with("T.P.") {
+                    annotations.tremolo_picking.push(columns);
+                } else if word == "S" {
+                    annotations.slaps.push(column);
+                } else if word == "P" {
+                    annotations.pops.push(column);
+                }
+            }
+        }
+
+        annotations
+    }
+
+    fn apply(&self, column: usize, technique: &mut Vec<GuitarTechnique>) {
+        if self.palm_mutes.iter().any(|columns| columns.contains(&column)) {
+            technique.push(GuitarTechnique::PalmMute);
+        }
+
+        if self.tremolo_picking.iter().any(|columns| columns.contains(&column)) {
+            technique.push(GuitarTechnique::Tremolo);
+        }
+
+        if self.slaps.contains(&column) {
+            technique.push(GuitarTechnique::Slap);
+        }
+
+        if self.pops.contains(&column) {
+            technique.push(GuitarTechnique::Pop);
+        }
+    }
+}
+
+/// Finds the fret of the capo in a line such as "Capo 2" or "capo: 3rd fret"
+fn read_capo(lines: &[&str]) -> Option<u8> {
+    lines.iter().find_map(|line| {
+        let line = line.to_lowercase();
+        let after = &line[line.find("capo")? + 4..];
+        let digits: String = after.trim_start_matches(|c: char| !c.is_ascii_digit())
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+
+        digits.parse().ok()
+    })
+}
@@ -0,0 +1,149 @@
+pub mod export;
+pub mod import;
+
+pub use export::export_ascii_tab;
+pub use import::{import_ascii_tab, AsciiTabImport, TabTiming};
+
+/// The names of the notes, from E up
+const NOTE_NAMES: [&str; 12] = ["E", "F", "F#", "G", "G#", "A", "A#", "B", "C", "C#", "D", "D#"];
+
+/// Tabs are usually written with every sixteenth note taking two columns, so every column is an
+/// eighth of a beat
+const COLUMNS_PER_BEAT: usize = 8;
+
+/// The name of the note a string is tuned to, given in semitones from E2
+fn note_name(offset: i8) -> &'static str {
+    NOTE_NAMES[(offset as i32).rem_euclid(12) as usize]
+}
+
+/// The number of semitones above E of a note name such as "e", "F#" or "Bb"
+fn parse_note_name(name: &str) -> Option<i32> {
+    let mut chars = name.chars();
+    let letter = chars.next()?.to_ascii_uppercase();
+    let mut semitones = NOTE_NAMES.iter().position(|note| note.len() == 1 && note.starts_with(letter))? as i32;
+
+    match chars.as_str() {
+        "" => {}
+        "#" => semitones += 1,
+        "b" => semitones -= 1,
+        _ => return None,
+    }
+
+    Some(semitones.rem_euclid(12))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::testing::{guitar_part, note};
+    use crate::song::guitar::GuitarTechnique;
+
+    const TAB: &str = "\
+Capo 2
+
+  PM------
+   S                           TP---
+e|----------t12----|-----------------|
+B|-----------------|-----x~----------|
+G|-----------------|--7b9r7----------|
+D|--5h7p5----------|----------<12>---|
+A|--------5/7------|-------------[5]-|
+E|-0---------------|--------------3\\1|
+";
+
+    const TEMPO: TabTiming = TabTiming::Tempo { bpm: 120.0, beats_per_measure: 4 };
+
+    /// The notes of a part as the measure, string and fret they're played at and their techniques,
+    /// which is all a tab keeps of them
+    fn notes(import: &AsciiTabImport) -> Vec<(usize, u8, u8, Vec<String>)> {
+        import.part.notes.iter()
+            .map(|note| {
+                let measure = import.beats.iter().take_while(|beat| beat.time <= note.time).last().map_or(0, |beat| beat.measure);
+                let mut techniques: Vec<String> = note.technique.iter().map(technique).collect();
+                techniques.sort();
+
+                (measure, note.string, note.fret, techniques)
+            })
+            .collect()
+    }
+
+    fn technique(technique: &GuitarTechnique) -> String {
+        match technique {
+            GuitarTechnique::Slide { to_fret } => format!("slide to {}", to_fret),
+            GuitarTechnique::Bend { points } => format!("bend {:?}", points.iter().map(|point| point.cents).collect::<Vec<_>>()),
+            technique => technique.name().to_string(),
+        }
+    }
+
+    fn strings(techniques: &[&str]) -> Vec<String> {
+        techniques.iter().map(|technique| technique.to_string()).collect()
+    }
+
+    #[test]
+    fn imports_techniques() {
+        let import = import_ascii_tab(TAB, TEMPO).unwrap();
+
+        assert_eq!(import.part.tuning.string_offsets, vec![0, 5, 10, 15, 19, 24]);
+        assert_eq!(import.part.capo, 2);
+        assert_eq!(import.beats.len(), 8);
+        assert_eq!(notes(&import), vec![
+            (1, 0, 0, strings(&["palm mutes", "slaps"])),
+            (1, 2, 5, strings(&["palm mutes"])),
+            (1, 2, 7, strings(&["hammer-ons", "palm mutes"])),
+            (1, 2, 5, strings(&["palm mutes", "pull-offs"])),
+            (1, 1, 5, strings(&["slide to 7"])),
+            (1, 5, 12, strings(&["taps"])),
+            (2, 3, 7, strings(&["bend [0, 200, 0]"])),
+            (2, 4, 0, strings(&["fret-hand mutes", "vibrato"])),
+            (2, 2, 12, strings(&["harmonics"])),
+            (2, 1, 5, strings(&["pinch harmonics", "tremolo"])),
+            (2, 0, 3, strings(&["slide to 1", "tremolo"])),
+        ]);
+    }
+
+    #[test]
+    fn round_trips_through_export() {
+        let import = import_ascii_tab(TAB, TEMPO).unwrap();
+        let tab = export_ascii_tab(&import.part, &import.beats, 80);
+        let reimport = import_ascii_tab(tab.as_str(), TEMPO).unwrap();
+
+        assert_eq!(reimport.part.tuning, import.part.tuning);
+        assert_eq!(reimport.part.capo, import.part.capo);
+        assert_eq!(reimport.beats.iter().map(|beat| beat.time).collect::<Vec<_>>(), import.beats.iter().map(|beat| beat.time).collect::<Vec<_>>());
+        assert_eq!(notes(&reimport), notes(&import));
+        assert!(reimport.warnings.is_empty(), "{:?}", reimport.warnings);
+    }
+
+    #[test]
+    fn round_trips_tunings() {
+        for offsets in [vec![-10, -5, 0, 5, 10, 15, 19, 24], vec![-7, -2, 3, 8, 12, 17, 22], vec![-2, 5, 10, 15, 19, 24], vec![-17, -12, -7, -2, 3]] {
+            let part = guitar_part(offsets.clone(), 0, vec![note(0, 0, 0, 3, None, vec![])]);
+            let tab = export_ascii_tab(&part, &[], 80);
+
+            assert_eq!(import_ascii_tab(tab.as_str(), TabTiming::Even).unwrap().part.tuning.string_offsets, offsets, "{}", tab);
+        }
+    }
+
+    #[test]
+    fn names_tunings_by_string_count() {
+        let tuning = |names: &[&str]| {
+            let tab: String = names.iter().map(|name| format!("{}|-----|\n", name)).collect();
+            import_ascii_tab(tab.as_str(), TabTiming::Even).unwrap().part.tuning.string_offsets
+        };
+
+        assert_eq!(tuning(&["G", "D", "A", "E"]), vec![-12, -7, -2, 3]);
+        assert_eq!(tuning(&["G", "D", "A", "E", "B"]), vec![-17, -12, -7, -2, 3]);
+        assert_eq!(tuning(&["e", "B", "G", "D", "A", "D"]), vec![-2, 5, 10, 15, 19, 24]);
+        assert_eq!(tuning(&["e", "B", "G", "D", "A", "E", "B"]), vec![-5, 0, 5, 10, 15, 19, 24]);
+        assert_eq!(tuning(&["e", "B", "G", "D", "A", "E", "B", "F#"]), vec![-10, -5, 0, 5, 10, 15, 19, 24]);
+    }
+
+    #[test]
+    fn rejects_tempos_out_of_range() {
+        for bpm in [0.0, -120.0, 1e-300, f64::INFINITY, f64::NAN, 1e300] {
+            assert!(import_ascii_tab(TAB, TabTiming::Tempo { bpm, beats_per_measure: 4 }).is_err(), "{}", bpm);
+        }
+
+        assert!(import_ascii_tab(TAB, TabTiming::Tempo { bpm: 1.0, beats_per_measure: 4 }).is_ok());
+    }
+}
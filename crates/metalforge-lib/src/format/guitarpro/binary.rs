@@ -0,0 +1,760 @@
+use crate::format::guitarpro::tab::{Harmonic, MeasureHeader, NoteEffects, TabBeat, TabNote, TabSong, Track, QUARTER_TICKS};
+
+/// Bends are stored in 1/25 of a semitone
+const CENTS_PER_BEND_UNIT: i32 = 4;
+
+/// The positions of bend points go from the start of the note at 0 to its end at 60
+const BEND_POSITIONS: f64 = 60.0;
+
+/// The channel drum tracks are played on
+const PERCUSSION_CHANNEL: i32 = 10;
+
+/// Reads a Guitar Pro 3, 4 or 5 file
+pub(crate) fn read_binary(data: &[u8]) -> Result<TabSong, String> {
+    let mut reader = Reader { data, position: 0, version: Version { major: 0, minor: 0 } };
+    read_song(&mut reader)
+}
+
+#[derive(Copy, Clone)]
+struct Version {
+    major: u8,
+    minor: u8,
+}
+
+/// Reads the little-endian values Guitar Pro files are made of. Strings are stored in the Windows
+/// encoding of the author, they're read as Latin-1.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    version: Version,
+}
+
+impl<'a> Reader<'a> {
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position.checked_add(count)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| format!("unexpected end of file at byte {}", self.position))?;
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), String> {
+        self.bytes(count).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8, String> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// The number of elements that follow. Every element takes at least a byte, so a count larger
+    /// than the rest of the file means the file is damaged.
+    fn count(&mut self) -> Result<usize, String> {
+        let position = self.position;
+        let count = self.i32()?;
+
+        usize::try_from(count).ok()
+            .filter(|count| *count <= self.data.len() - self.position)
+            .ok_or_else(|| format!("invalid count {} at byte {}", count, position))
+    }
+
+    /// A string in a field of a fixed size, preceded by its length as a byte
+    fn byte_size_string(&mut self, size: usize) -> Result<String, String> {
+        let length = self.u8()? as usize;
+        let bytes = self.bytes(size)?;
+
+        Ok(decode(&bytes[..length.min(size)]))
+    }
+
+    /// A string preceded by its length as an int
+    fn int_size_string(&mut self) -> Result<String, String> {
+        let length = self.count()?;
+        Ok(decode(self.bytes(length)?))
+    }
+
+    /// A string preceded by its size plus one as an int, and by its length as a byte
+    fn int_byte_size_string(&mut self) -> Result<String, String> {
+        let size = self.count()?;
+        self.byte_size_string(size.saturating_sub(1))
+    }
+
+    fn is_gp5(&self) -> bool {
+        self.version.major >= 5
+    }
+
+    /// Versions 5.1 and later added a few fields to version 5.0
+    fn is_gp51(&self) -> bool {
+        self.version.major >= 5 && self.version.minor > 0
+    }
+}
+
+fn decode(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|byte| char::from(*byte))
+        .collect::<String>()
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+fn read_song(reader: &mut Reader) -> Result<TabSong, String> {
+    reader.version = read_version(reader)?;
+
+    let title = reader.int_byte_size_string()?;
+    let _subtitle = reader.int_byte_size_string()?;
+    let artist = reader.int_byte_size_string()?;
+    let album = reader.int_byte_size_string()?;
+
+    // Words, music (only in version 5), copyright, tab author and instructions
+    for _ in 0..if reader.is_gp5() { 5 } else { 4 } {
+        reader.int_byte_size_string()?;
+    }
+
+    for _ in 0..reader.count()? {
+        reader.int_byte_size_string()?;
+    }
+
+    if !reader.is_gp5() {
+        // Triplet feel
+        reader.skip(1)?;
+    }
+
+    if reader.version.major >= 4 {
+        // Lyrics
+        reader.skip(4)?;
+
+        for _ in 0..5 {
+            reader.skip(4)?;
+            reader.int_size_string()?;
+        }
+    }
+
+    if reader.is_gp51() {
+        // Master volume and equalizer
+        reader.skip(19)?;
+    }
+
+    if reader.is_gp5() {
+        // Page setup
+        reader.skip(30)?;
+
+        for _ in 0..10 {
+            reader.int_byte_size_string()?;
+        }
+
+        // Tempo name
+        reader.int_byte_size_string()?;
+    }
+
+    let tempo = reader.i32()?;
+
+    if reader.is_gp51() {
+        // Hide tempo
+        reader.skip(1)?;
+    }
+
+    // Key and octave, then the 64 MIDI channels
+    reader.skip(if reader.version.major == 3 { 4 } else { 5 })?;
+    reader.skip(64 * 12)?;
+
+    if reader.is_gp5() {
+        // Directions like "Da Capo" and the master reverb
+        reader.skip(19 * 2 + 4)?;
+    }
+
+    let measure_count = reader.count()?;
+    let track_count = reader.count()?;
+
+    let mut headers: Vec<MeasureHeader> = vec![];
+
+    for _ in 0..measure_count {
+        let header = read_measure_header(reader, &headers)?;
+        headers.push(header);
+    }
+
+    let mut tracks = vec![];
+
+    for idx in 0..track_count {
+        tracks.push(read_track(reader, idx)?);
+    }
+
+    if reader.is_gp5() {
+        reader.skip(if reader.is_gp51() { 1 } else { 2 })?;
+    }
+
+    for header in &mut headers {
+        for track in &mut tracks {
+            let measure = read_measure(reader, track.tuning.len(), header)?;
+            track.measures.push(measure);
+        }
+    }
+
+    Ok(TabSong { title, artist, album, tempo: f64::from(tempo), headers, tracks })
+}
+
+fn read_version(reader: &mut Reader) -> Result<Version, String> {
+    let text = reader.byte_size_string(30)?;
+
+    if !text.starts_with("FICHIER GUITAR") {
+        return Err(format!("not a Guitar Pro file: {:?}", text));
+    }
+
+    let version = text.rsplit_once('v')
+        .and_then(|(_, number)| number.split_once('.'))
+        .and_then(|(major, minor)| Some(Version { major: major.parse().ok()?, minor: minor.parse().ok()? }))
+        .filter(|version| (3..=5).contains(&version.major))
+        .ok_or_else(|| format!("unsupported Guitar Pro version {:?}", text))?;
+
+    Ok(version)
+}
+
+fn read_measure_header(reader: &mut Reader, previous: &[MeasureHeader]) -> Result<MeasureHeader, String> {
+    if reader.is_gp5() && !previous.is_empty() {
+        reader.skip(1)?;
+    }
+
+    let flags = reader.u8()?;
+
+    let mut header = MeasureHeader {
+        numerator: previous.last().map(|header| header.numerator).unwrap_or(4),
+        denominator: previous.last().map(|header| header.denominator).unwrap_or(4),
+        repeat_open: flags & 0x04 != 0,
+        repeat_close: 0,
+        alternatives: 0,
+        marker: None,
+        tempo: vec![],
+    };
+
+    if flags & 0x01 != 0 {
+        header.numerator = u64::from(reader.u8()?.max(1));
+    }
+
+    if flags & 0x02 != 0 {
+        header.denominator = u64::from(reader.u8()?.max(1));
+    }
+
+    if flags & 0x08 != 0 {
+        // Version 5 stores how often the measures are played, older versions how often they're repeated
+        let count = i32::from(reader.i8()?) - if reader.is_gp5() { 1 } else { 0 };
+        header.repeat_close = count.max(0) as u32;
+    }
+
+    if reader.is_gp5() {
+        if flags & 0x20 != 0 {
+            header.marker = Some(read_marker(reader)?);
+        }
+
+        if flags & 0x40 != 0 {
+            // Key signature
+            reader.skip(2)?;
+        }
+
+        if flags & 0x10 != 0 {
+            header.alternatives = reader.u8()?;
+        }
+
+        // Beaming of the time signature, a byte used when there are no alternate endings, and the
+        // triplet feel
+        reader.skip(if flags & 0x03 != 0 { 4 } else { 0 } + if flags & 0x10 == 0 { 1 } else { 0 } + 1)?;
+    } else {
+        if flags & 0x10 != 0 {
+            // Older versions store the number of the ending, which is played in every pass that
+            // no earlier ending of the same repeat is played in
+            let number = u32::from(reader.u8()?.min(8));
+            let taken = previous.iter().rev()
+                .take_while(|header| !header.repeat_open)
+                .fold(0, |taken, header| taken | header.alternatives);
+
+            header.alternatives = ((1u32 << number) - 1) as u8 & !taken;
+        }
+
+        if flags & 0x20 != 0 {
+            header.marker = Some(read_marker(reader)?);
+        }
+
+        if flags & 0x40 != 0 {
+            reader.skip(2)?;
+        }
+    }
+
+    Ok(header)
+}
+
+fn read_marker(reader: &mut Reader) -> Result<String, String> {
+    let name = reader.int_byte_size_string()?;
+
+    // Color
+    reader.skip(4)?;
+
+    Ok(name)
+}
+
+fn read_track(reader: &mut Reader, idx: usize) -> Result<Track, String> {
+    if reader.is_gp5() && (idx == 0 || !reader.is_gp51()) {
+        reader.skip(1)?;
+    }
+
+    let flags = reader.u8()?;
+    let name = reader.byte_size_string(40)?;
+    let string_count = reader.count()?;
+
+    if !(1..=7).contains(&string_count) {
+        return Err(format!("{}: unsupported number of strings {}", name, string_count));
+    }
+
+    let mut tuning = vec![];
+
+    for string in 0..7 {
+        let note = reader.i32()?;
+
+        if string < string_count {
+            tuning.push(note);
+        }
+    }
+
+    // Port
+    reader.skip(4)?;
+
+    let channel = reader.i32()?;
+
+    // Effect channel and number of frets
+    reader.skip(8)?;
+
+    let capo = reader.i32()?.clamp(0, i32::from(u8::MAX)) as u8;
+
+    // Color
+    reader.skip(4)?;
+
+    if reader.is_gp5() {
+        // Display flags, auto accentuation, MIDI bank, humanizing and RSE settings
+        reader.skip(2 + 1 + 1 + 1 + 12 + 12)?;
+
+        // RSE instrument
+        reader.skip(if reader.is_gp51() { 16 } else { 15 })?;
+
+        if reader.is_gp51() {
+            // Equalizer, effect and effect category
+            reader.skip(4)?;
+            reader.int_byte_size_string()?;
+            reader.int_byte_size_string()?;
+        }
+    }
+
+    // The strings are stored from the highest one
+    tuning.reverse();
+
+    Ok(Track {
+        name,
+        percussion: flags & 0x01 != 0 || channel == PERCUSSION_CHANNEL,
+        tuning,
+        capo,
+        measures: vec![],
+    })
+}
+
+/// Reads the voices of a measure of a track. The tempo changes of its mix tables are added to the
+/// measure's header.
+fn read_measure(reader: &mut Reader, string_count: usize, header: &mut MeasureHeader) -> Result<Vec<Vec<TabBeat>>, String> {
+    let mut voices = vec![];
+
+    for _ in 0..if reader.is_gp5() { 2 } else { 1 } {
+        let beat_count = reader.count()?;
+        let mut beats = Vec::with_capacity(beat_count);
+        let mut position = 0;
+
+        for _ in 0..beat_count {
+            let (beat, tempo) = read_beat(reader, string_count)?;
+
+            if let Some(bpm) = tempo {
+                header.tempo.push((position, f64::from(bpm)));
+            }
+
+            position += beat.ticks;
+            beats.push(beat);
+        }
+
+        voices.push(beats);
+    }
+
+    if reader.is_gp5() {
+        // Line break
+        reader.skip(1)?;
+    }
+
+    Ok(voices)
+}
+
+/// Reads a beat along with the tempo its mix table changes to
+fn read_beat(reader: &mut Reader, string_count: usize) -> Result<(TabBeat, Option<i32>), String> {
+    let flags = reader.u8()?;
+
+    // Empty beats take no time, rests have no notes
+    let status = if flags & 0x40 != 0 { reader.u8()? } else { 1 };
+    let ticks = read_duration(reader, flags)?;
+
+    if flags & 0x02 != 0 {
+        skip_chord(reader)?;
+    }
+
+    if flags & 0x04 != 0 {
+        // Text
+        reader.int_byte_size_string()?;
+    }
+
+    let mut effects = NoteEffects::default();
+
+    if flags & 0x08 != 0 {
+        read_beat_effects(reader, &mut effects)?;
+    }
+
+    let tempo = if flags & 0x10 != 0 { read_mix_table(reader)? } else { None };
+
+    let string_flags = reader.u8()?;
+    let mut notes = vec![];
+
+    // The highest string is numbered 1
+    for number in 1..=string_count {
+        if string_flags & (1 << (7 - number)) != 0 {
+            notes.push(read_note(reader, string_count - number, &effects)?);
+        }
+    }
+
+    if reader.is_gp5() && reader.i16()? & 0x0800 != 0 {
+        reader.skip(1)?;
+    }
+
+    let beat = TabBeat {
+        ticks: if status == 0 { 0 } else { ticks },
+        notes,
+    };
+
+    Ok((beat, tempo))
+}
+
+fn read_duration(reader: &mut Reader, flags: u8) -> Result<u64, String> {
+    let value = reader.i8()?;
+
+    if !(-2..=6).contains(&value) {
+        return Err(format!("invalid duration {} at byte {}", value, reader.position - 1));
+    }
+
+    let mut ticks = (QUARTER_TICKS * 4) >> (value + 2);
+
+    if flags & 0x01 != 0 {
+        ticks = ticks * 3 / 2;
+    }
+
+    if flags & 0x20 != 0 {
+        let enters = reader.i32()?;
+
+        let times = match enters {
+            3 => 2,
+            5..=7 => 4,
+            9..=13 => 8,
+            _ => enters,
+        };
+
+        if enters > 0 {
+            ticks = ticks * times as u64 / enters as u64;
+        }
+    }
+
+    Ok(ticks)
+}
+
+fn skip_chord(reader: &mut Reader) -> Result<(), String> {
+    if reader.is_gp5() {
+        return reader.skip(107);
+    }
+
+    if reader.bool()? {
+        return reader.skip(if reader.version.major == 3 { 124 } else { 106 });
+    }
+
+    // Chords in the old format have a name and the frets, unless they don't show a diagram
+    reader.int_byte_size_string()?;
+
+    if reader.i32()? != 0 {
+        reader.skip(6 * 4)?;
+    }
+
+    Ok(())
+}
+
+fn read_beat_effects(reader: &mut Reader, effects: &mut NoteEffects) -> Result<(), String> {
+    let flags = reader.u8()?;
+    let flags2 = if reader.version.major >= 4 { reader.u8()? } else { 0 };
+
+    // Vibrato and wide vibrato
+    effects.vibrato = flags & 0x03 != 0;
+
+    if flags & 0x20 != 0 {
+        match reader.u8()? {
+            1 => effects.tap = true,
+            2 => effects.slap = true,
+            3 => effects.pop = true,
+            0 if reader.version.major == 3 => effects.tremolo_bar = true,
+            _ => {}
+        }
+
+        if reader.version.major == 3 {
+            // The value of the tremolo bar, or nothing
+            reader.skip(4)?;
+        }
+    }
+
+    if flags2 & 0x04 != 0 {
+        read_bend(reader)?;
+        effects.tremolo_bar = true;
+    }
+
+    if flags & 0x40 != 0 {
+        // Strum
+        reader.skip(2)?;
+    }
+
+    if reader.version.major == 3 {
+        if flags & 0x04 != 0 {
+            effects.harmonic = Some(Harmonic::Natural);
+        }
+
+        if flags & 0x08 != 0 {
+            effects.harmonic = Some(Harmonic::Artificial);
+        }
+    }
+
+    if flags2 & 0x02 != 0 {
+        // Pick stroke
+        reader.skip(1)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the mix table of a beat, which changes the instrument and the sound. Only the tempo is
+/// kept, in quarter notes per minute.
+fn read_mix_table(reader: &mut Reader) -> Result<Option<i32>, String> {
+    // Instrument
+    reader.skip(1)?;
+
+    if reader.is_gp5() {
+        // RSE instrument
+        reader.skip(16)?;
+    }
+
+    // Volume, balance, chorus, reverb, phaser and tremolo, each -1 if unchanged
+    let values = reader.bytes(6)?;
+    let changed_values = values.iter().filter(|value| (**value as i8) >= 0).count();
+
+    if reader.is_gp5() {
+        // Tempo name
+        reader.int_byte_size_string()?;
+    }
+
+    let tempo = reader.i32()?;
+
+    // The time each change takes
+    reader.skip(changed_values)?;
+
+    if tempo >= 0 {
+        reader.skip(if reader.is_gp51() { 2 } else { 1 })?;
+    }
+
+    if reader.version.major >= 4 {
+        // Which changes apply to all tracks
+        reader.skip(1)?;
+    }
+
+    if reader.is_gp5() {
+        // Wah-wah
+        reader.skip(1)?;
+
+        if reader.is_gp51() {
+            // RSE effect and effect category
+            reader.int_byte_size_string()?;
+            reader.int_byte_size_string()?;
+        }
+    }
+
+    Ok((tempo > 0).then_some(tempo))
+}
+
+fn read_note(reader: &mut Reader, string: usize, beat_effects: &NoteEffects) -> Result<TabNote, String> {
+    let flags = reader.u8()?;
+
+    let mut note = TabNote {
+        string,
+        fret: 0,
+        tie: false,
+        dead: false,
+        finger: None,
+        effects: beat_effects.clone(),
+    };
+
+    if flags & 0x20 != 0 {
+        match reader.u8()? {
+            2 => note.tie = true,
+            3 => note.dead = true,
+            _ => {}
+        }
+    }
+
+    if flags & 0x01 != 0 && !reader.is_gp5() {
+        // Duration and tuplet of a note that doesn't follow its beat
+        reader.skip(2)?;
+    }
+
+    if flags & 0x10 != 0 {
+        // Dynamics
+        reader.skip(1)?;
+    }
+
+    if flags & 0x20 != 0 {
+        note.fret = reader.i8()?.clamp(0, 99) as u8;
+    }
+
+    if flags & 0x80 != 0 {
+        let left_hand = reader.i8()?;
+        note.finger = (0..=4).contains(&left_hand).then_some(left_hand as u8);
+
+        // Right hand
+        reader.skip(1)?;
+    }
+
+    if reader.is_gp5() {
+        if flags & 0x01 != 0 {
+            // Duration as a fraction of the beat
+            reader.skip(8)?;
+        }
+
+        reader.skip(1)?;
+    }
+
+    if flags & 0x08 != 0 {
+        read_note_effects(reader, &mut note.effects)?;
+    }
+
+    Ok(note)
+}
+
+fn read_note_effects(reader: &mut Reader, effects: &mut NoteEffects) -> Result<(), String> {
+    let flags = reader.u8()?;
+    let flags2 = if reader.version.major >= 4 { reader.u8()? } else { 0 };
+
+    effects.hammer = flags & 0x02 != 0;
+    effects.let_ring = flags & 0x08 != 0;
+    effects.palm_mute = flags2 & 0x02 != 0;
+    effects.vibrato |= flags2 & 0x40 != 0;
+
+    if flags & 0x01 != 0 {
+        effects.bend = read_bend(reader)?;
+    }
+
+    if flags & 0x10 != 0 {
+        reader.skip(if reader.is_gp5() { 5 } else { 4 })?;
+        effects.grace = true;
+    }
+
+    if reader.version.major == 3 {
+        effects.slide = flags & 0x04 != 0;
+        return Ok(());
+    }
+
+    if flags2 & 0x04 != 0 {
+        // Speed of the tremolo picking
+        reader.skip(1)?;
+        effects.tremolo_picking = true;
+    }
+
+    if flags2 & 0x08 != 0 {
+        let slide = reader.i8()?;
+
+        if reader.is_gp5() {
+            // Shift and legato slides to the next note, the others slide into or out of the note
+            effects.slide = slide & 0x03 != 0;
+            effects.slide_in_out = slide & 0x3c != 0;
+        } else {
+            effects.slide = slide == 1 || slide == 2;
+            effects.slide_in_out = !effects.slide && slide != 0;
+        }
+    }
+
+    if flags2 & 0x10 != 0 {
+        let harmonic = reader.i8()?;
+
+        if reader.is_gp5() {
+            match harmonic {
+                2 => reader.skip(3)?,
+                3 => reader.skip(1)?,
+                _ => {}
+            }
+        }
+
+        effects.harmonic = match (harmonic, reader.is_gp5()) {
+            (1, _) => Some(Harmonic::Natural),
+            (2, true) | (15 | 17 | 22, false) => Some(Harmonic::Artificial),
+            (3, _) => Some(Harmonic::Tapped),
+            (4, _) => Some(Harmonic::Pinch),
+            (5, _) => Some(Harmonic::Semi),
+            _ => None,
+        };
+    }
+
+    if flags2 & 0x20 != 0 {
+        // Fret and speed of the trill
+        reader.skip(2)?;
+        effects.trill = true;
+    }
+
+    Ok(())
+}
+
+/// Reads a bend as its points. Bends without points are bent halfway through the note.
+fn read_bend(reader: &mut Reader) -> Result<Vec<(f64, i16)>, String> {
+    // Type
+    reader.skip(1)?;
+
+    let value = reader.i32()?;
+    let mut points = vec![];
+
+    for _ in 0..reader.count()? {
+        let position = reader.i32()?;
+        let point_value = reader.i32()?;
+
+        // Vibrato
+        reader.skip(1)?;
+
+        points.push(bend_point(position, point_value));
+    }
+
+    if points.is_empty() && value != 0 {
+        points.push(bend_point(BEND_POSITIONS as i32 / 2, value));
+    }
+
+    Ok(points)
+}
+
+fn bend_point(position: i32, value: i32) -> (f64, i16) {
+    let cents = value.saturating_mul(CENTS_PER_BEND_UNIT).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+    (f64::from(position) / BEND_POSITIONS, cents)
+}
@@ -0,0 +1,224 @@
+use std::io::{Cursor, Read};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// The name of the score in the file system of a `.gpx` file
+const GPX_SCORE_NAME: &str = "score.gpif";
+
+/// The path of the score in the zip archive of a `.gp` file
+pub(crate) const ZIP_SCORE_PATH: &str = "Content/score.gpif";
+
+/// The directory the paths of embedded files are relative to in a `.gp` file
+const ZIP_CONTENT_DIR: &str = "Content/";
+
+/// The file system of a `.gpx` file is made of sectors of this size
+const SECTOR_SIZE: usize = 0x1000;
+
+/// Files larger than this are assumed to be damaged rather than read into memory
+const MAX_FILE_SIZE: usize = 512 * 1024 * 1024;
+
+/// How the score of a Guitar Pro file is stored
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Container {
+    /// The binary format of Guitar Pro 3, 4 and 5
+    Binary,
+    /// A file system holding a GPIF score, compressed or not, as written by Guitar Pro 6
+    Gpx,
+    /// A zip archive holding a GPIF score and the embedded assets, as written by Guitar Pro 7 and 8
+    Zip,
+}
+
+/// Tells the containers apart by their first bytes, regardless of the extension of the file
+pub(crate) fn container(data: &[u8]) -> Container {
+    match data.get(..4) {
+        Some(b"BCFZ" | b"BCFS") => Container::Gpx,
+        Some(b"PK\x03\x04") => Container::Zip,
+        _ => Container::Binary,
+    }
+}
+
+/// Reads the GPIF score out of a `.gpx` file
+pub(crate) fn read_gpx_score(data: &[u8]) -> Result<Vec<u8>, String> {
+    let file_system = match data.get(..4) {
+        Some(b"BCFZ") => decompress(&data[4..])?,
+        Some(b"BCFS") => data.to_vec(),
+        _ => return Err("not a Guitar Pro 6 file".to_string()),
+    };
+
+    // Both start with the header of the file system
+    let file_system = file_system.get(4..).unwrap_or_default();
+
+    read_gpx_file(file_system, GPX_SCORE_NAME)?
+        .ok_or_else(|| format!("missing {}", GPX_SCORE_NAME))
+}
+
+/// Reads a file out of the zip archive of a `.gp` file. Paths of embedded files are given relative
+/// to the content directory in the score, so they're looked up both as they are and within it.
+pub(crate) fn read_zip_file(data: &[u8], path: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|error| error.to_string())?;
+
+    for name in [path.to_string(), format!("{}{}", ZIP_CONTENT_DIR, path)] {
+        let file = match archive.by_name(name.as_str()) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => continue,
+            Err(error) => return Err(error.to_string()),
+        };
+
+        if file.size() > MAX_FILE_SIZE as u64 {
+            return Err(format!("{} is too large", name));
+        }
+
+        let mut contents = Vec::with_capacity(file.size() as usize);
+        file.take(MAX_FILE_SIZE as u64).read_to_end(&mut contents).map_err(|error| error.to_string())?;
+
+        return Ok(Some(contents));
+    }
+
+    Ok(None)
+}
+
+/// Reads the bits of compressed data from the most significant bit of each byte
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// In bits
+    position: usize,
+}
+
+impl BitReader<'_> {
+
+    fn bit(&mut self) -> Option<usize> {
+        let byte = self.data.get(self.position / 8)?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+
+        Some(bit as usize)
+    }
+
+    /// Reads a number stored from its most significant bit
+    fn bits(&mut self, count: usize) -> Option<usize> {
+        (0..count).rev().try_fold(0, |value, shift| Some(value | self.bit()? << shift))
+    }
+
+    /// Reads a number stored from its least significant bit
+    fn bits_reversed(&mut self, count: usize) -> Option<usize> {
+        (0..count).try_fold(0, |value, shift| Some(value | self.bit()? << shift))
+    }
+}
+
+/// Decompresses the data following a "BCFZ" header. The data starts with its decompressed length,
+/// followed by chunks that are either copied from the data or repeat part of what was decompressed
+/// so far. Data that ends early is returned as far as it goes.
+fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let length = data.get(..4)
+        .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .and_then(|length| usize::try_from(length).ok())
+        .filter(|length| *length <= MAX_FILE_SIZE)
+        .ok_or_else(|| "invalid length of the compressed data".to_string())?;
+
+    let mut bits = BitReader { data, position: 32 };
+    let mut decompressed = Vec::with_capacity(length);
+
+    while decompressed.len() < length {
+        let Some(flag) = bits.bit() else {
+            break;
+        };
+
+        if flag == 1 {
+            let Some(word_size) = bits.bits(4) else {
+                break;
+            };
+
+            let (Some(offset), Some(size)) = (bits.bits_reversed(word_size), bits.bits_reversed(word_size)) else {
+                break;
+            };
+
+            if offset == 0 || offset > decompressed.len() {
+                return Err(format!("invalid reference at byte {} of the compressed data", bits.position / 8));
+            }
+
+            let start = decompressed.len() - offset;
+
+            for idx in start..start + size.min(offset) {
+                decompressed.push(decompressed[idx]);
+            }
+        } else {
+            let Some(size) = bits.bits_reversed(2) else {
+                break;
+            };
+
+            for _ in 0..size {
+                match bits.bits(8) {
+                    Some(byte) => decompressed.push(byte as u8),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    decompressed.truncate(length);
+    Ok(decompressed)
+}
+
+/// Looks up a file in the file system of a `.gpx` file. Every sector starting with 2 describes a
+/// file: its name, its size and the numbers of the sectors its contents are stored in, ending with 0.
+/// The sectors are scanned front to back only, and the data sectors of a file are skipped if they
+/// come after its header.
+fn read_gpx_file(file_system: &[u8], name: &str) -> Result<Option<Vec<u8>>, String> {
+    let int = |offset: usize| file_system.get(offset..offset + 4)
+        .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+
+    let mut offset = SECTOR_SIZE;
+
+    while offset + 3 < file_system.len() {
+        if int(offset) == Some(2) {
+            let name_bytes = file_system.get(offset + 0x04..offset + 0x04 + 127).unwrap_or_default();
+            let file_name: String = name_bytes.iter()
+                .take_while(|byte| **byte != 0)
+                .map(|byte| char::from(*byte))
+                .collect();
+
+            let size = int(offset + 0x8c)
+                .and_then(|size| usize::try_from(size).ok())
+                .filter(|size| *size <= MAX_FILE_SIZE)
+                .ok_or_else(|| format!("invalid size of {}", file_name))?;
+
+            let sectors = offset + 0x94;
+            let mut contents = Vec::with_capacity(size);
+            let mut last_sector = offset;
+
+            for idx in 0.. {
+                let Some(sector) = int(sectors + 4 * idx).filter(|sector| *sector > 0) else {
+                    break;
+                };
+
+                let sector_offset = sector as usize * SECTOR_SIZE;
+
+                let Some(sector_data) = file_system.get(sector_offset..(sector_offset + SECTOR_SIZE).min(file_system.len())) else {
+                    return Err(format!("{} is stored outside of the file", file_name));
+                };
+
+                contents.extend_from_slice(sector_data);
+                last_sector = last_sector.max(sector_offset);
+
+                if contents.len() >= size {
+                    break;
+                }
+            }
+
+            if file_name == name {
+                if contents.len() < size {
+                    return Err(format!("{} is truncated", file_name));
+                }
+
+                contents.truncate(size);
+                return Ok(Some(contents));
+            }
+
+            offset = last_sector;
+        }
+
+        offset += SECTOR_SIZE;
+    }
+
+    Ok(None)
+}
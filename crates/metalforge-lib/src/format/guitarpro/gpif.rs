@@ -0,0 +1,431 @@
+use crate::format::guitarpro::tab::{Harmonic, MeasureHeader, NoteEffects, TabBeat, TabNote, TabSong, Track, QUARTER_TICKS};
+use crate::format::Warnings;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+/// Bends are stored in 1/50 of a semitone
+const CENTS_PER_BEND_UNIT: f64 = 2.0;
+
+/// The positions of bend points are stored as percentages of the note
+const BEND_POSITIONS: f64 = 100.0;
+
+/// A GPIF score, the XML document Guitar Pro 6 and later store songs in
+pub(crate) struct Gpif {
+    pub(crate) tab: TabSong,
+    /// The path of the backing track embedded in the file, if it has one
+    pub(crate) backing_track: Option<String>,
+}
+
+/// Reads a GPIF score. Tracks without strings, like keyboards, are skipped.
+pub(crate) fn read_gpif(data: &[u8], warnings: &mut Warnings) -> Result<Gpif, String> {
+    let xml = std::str::from_utf8(data).map_err(|error| error.to_string())?;
+    let document = parse_gpif(xml)?;
+    let root = document.root_element();
+
+    let score = child(root, "Score");
+    let score_text = |name| score.and_then(|score| text(score, name)).unwrap_or_default().to_string();
+
+    let elements = Elements {
+        bars: by_id(root, "Bars"),
+        voices: by_id(root, "Voices"),
+        beats: by_id(root, "Beats"),
+        notes: by_id(root, "Notes"),
+        rhythms: by_id(root, "Rhythms"),
+    };
+
+    let mut headers = vec![];
+    let mut bars = vec![];
+
+    for master_bar in children(root, "MasterBars", "MasterBar") {
+        let header = read_master_bar(master_bar, headers.last());
+        bars.push(text(master_bar, "Bars").unwrap_or_default().split_whitespace().collect::<Vec<_>>());
+        headers.push(header);
+    }
+
+    read_tempo(root, &mut headers, warnings);
+
+    let mut tracks = vec![];
+
+    // Each staff of a track has its own bars, listed one after the other in every master bar
+    let mut first_staff = 0;
+
+    for track in children(root, "Tracks", "Track") {
+        let staves = child(track, "Staves").map_or(1, |staves| children_named(staves, "Staff").count().max(1));
+        let tab_track = read_track(track, first_staff, &bars, &elements, warnings);
+
+        first_staff += staves;
+
+        match tab_track {
+            Some(tab_track) => tracks.push(tab_track),
+            None => warnings.add(format!("{}: tracks without strings skipped", text(track, "Name").unwrap_or_default().trim())),
+        }
+    }
+
+    let backing_track = backing_track(root);
+
+    let tab = TabSong {
+        title: score_text("Title").trim().to_string(),
+        artist: score_text("Artist").trim().to_string(),
+        album: score_text("Album").trim().to_string(),
+        tempo: 120.0,
+        headers,
+        tracks,
+    };
+
+    Ok(Gpif { tab, backing_track })
+}
+
+/// Reads only the path of the backing track embedded in the file of a GPIF score, without reading
+/// the rest of the score
+pub(crate) fn read_backing_track_path(data: &[u8]) -> Result<Option<String>, String> {
+    let xml = std::str::from_utf8(data).map_err(|error| error.to_string())?;
+
+    Ok(backing_track(parse_gpif(xml)?.root_element()))
+}
+
+fn parse_gpif(xml: &str) -> Result<Document<'_>, String> {
+    let document = Document::parse(xml.trim_start_matches('\u{feff}')).map_err(|error| error.to_string())?;
+    let root = document.root_element();
+
+    if !root.has_tag_name("GPIF") {
+        return Err(format!("not a GPIF score: <{}>", root.tag_name().name()));
+    }
+
+    Ok(document)
+}
+
+/// The path of the backing track, which refers to one of the assets embedded in the file
+fn backing_track(root: Node) -> Option<String> {
+    child(root, "BackingTrack")
+        .and_then(|backing_track| text(backing_track, "AssetId"))
+        .and_then(|asset_id| children(root, "Assets", "Asset").find(|asset| asset.attribute("id") == Some(asset_id.trim())))
+        .and_then(|asset| text(asset, "EmbeddedFilePath"))
+        .map(|path| path.trim().to_string())
+}
+
+/// The elements of a score that are referred to by their ID
+struct Elements<'a, 'input> {
+    bars: HashMap<&'a str, Node<'a, 'input>>,
+    voices: HashMap<&'a str, Node<'a, 'input>>,
+    beats: HashMap<&'a str, Node<'a, 'input>>,
+    notes: HashMap<&'a str, Node<'a, 'input>>,
+    rhythms: HashMap<&'a str, Node<'a, 'input>>,
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn children_named<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+/// The elements with the specified name in the list with the specified name
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, list: &str, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    child(node, list).into_iter().flat_map(move |list| children_named(list, name))
+}
+
+fn text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|child| child.text())
+}
+
+fn by_id<'a, 'input>(root: Node<'a, 'input>, list: &str) -> HashMap<&'a str, Node<'a, 'input>> {
+    child(root, list).into_iter()
+        .flat_map(|list| list.children())
+        .filter_map(|element| Some((element.attribute("id")?, element)))
+        .collect()
+}
+
+/// A property of a track, staff, beat or note. Properties are listed in a `<Properties>` element
+/// and identified by their name.
+fn property<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    child(node, "Properties")?.children()
+        .find(|property| property.has_tag_name("Property") && property.attribute("name") == Some(name))
+}
+
+/// Checks if a property is set. Properties that are set have an `<Enable/>` element.
+fn enabled(node: Node, name: &str) -> bool {
+    property(node, name).is_some_and(|property| child(property, "Enable").is_some())
+}
+
+fn number_property<T: std::str::FromStr>(node: Node, name: &str, value: &str) -> Option<T> {
+    property(node, name).and_then(|property| text(property, value)).and_then(|text| text.trim().parse().ok())
+}
+
+fn read_master_bar(master_bar: Node, previous: Option<&MeasureHeader>) -> MeasureHeader {
+    let (numerator, denominator) = text(master_bar, "Time")
+        .and_then(|time| time.trim().split_once('/'))
+        .and_then(|(numerator, denominator)| Some((numerator.parse::<u64>().ok()?, denominator.parse::<u64>().ok()?)))
+        .filter(|(numerator, denominator)| *numerator > 0 && *denominator > 0)
+        .or(previous.map(|header| (header.numerator, header.denominator)))
+        .unwrap_or((4, 4));
+
+    let repeat = child(master_bar, "Repeat");
+    let repeat_attribute = |name| repeat.and_then(|repeat| repeat.attribute(name));
+
+    // The count is how often the measures are played
+    let repeat_close = match repeat_attribute("end") {
+        Some("true") => repeat_attribute("count").and_then(|count| count.parse::<u32>().ok()).unwrap_or(2).saturating_sub(1),
+        _ => 0,
+    };
+
+    let alternatives = text(master_bar, "AlternateEndings").unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|ending| ending.parse::<u32>().ok())
+        .filter(|ending| (1..=8).contains(ending))
+        .fold(0, |alternatives, ending| alternatives | 1 << (ending - 1));
+
+    let marker = child(master_bar, "Section")
+        .and_then(|section| text(section, "Text").filter(|name| !name.trim().is_empty()).or(text(section, "Letter")))
+        .map(|name| name.trim().to_string());
+
+    MeasureHeader {
+        numerator,
+        denominator,
+        repeat_open: repeat_attribute("start") == Some("true"),
+        repeat_close,
+        alternatives,
+        marker,
+        tempo: vec![],
+    }
+}
+
+/// Adds the tempo automations of the master track to the measures they change the tempo in. The
+/// tempo is stored along with the note value it's counted in, e.g. "120 2" for 120 quarter notes
+/// per minute.
+fn read_tempo(root: Node, headers: &mut [MeasureHeader], warnings: &mut Warnings) {
+    let Some(master_track) = child(root, "MasterTrack") else {
+        return;
+    };
+
+    for automation in children(master_track, "Automations", "Automation") {
+        if text(automation, "Type").map(str::trim) != Some("Tempo") {
+            continue;
+        }
+
+        let bar = text(automation, "Bar").and_then(|bar| bar.trim().parse::<usize>().ok());
+        let position = text(automation, "Position").and_then(|position| position.trim().parse::<f64>().ok()).unwrap_or(0.0);
+        let mut value = text(automation, "Value").unwrap_or_default().split_whitespace();
+        let bpm = value.next().and_then(|bpm| bpm.parse::<f64>().ok());
+
+        let (Some(header), Some(bpm)) = (bar.and_then(|bar| headers.get_mut(bar)), bpm) else {
+            continue;
+        };
+
+        let quarter_bpm = match value.next() {
+            Some("1") => bpm / 2.0,
+            Some("3") => bpm * 1.5,
+            Some("4") => bpm * 2.0,
+            Some("5") => bpm * 3.0,
+            _ => bpm,
+        };
+
+        if text(automation, "Linear").map(str::trim) == Some("true") {
+            warnings.add("gradual tempo changes made sudden".to_string());
+        }
+
+        let offset = (header.ticks() as f64 * position.clamp(0.0, 1.0)) as u64;
+        header.tempo.push((offset, quarter_bpm));
+    }
+}
+
+/// Reads a track with strings, taking the tuning and capo of its first staff
+fn read_track(track: Node, staff: usize, bars: &[Vec<&str>], elements: &Elements, warnings: &mut Warnings) -> Option<Track> {
+    let name = text(track, "Name").unwrap_or_default().trim().to_string();
+
+    let percussion = child(track, "InstrumentSet")
+        .and_then(|instrument_set| text(instrument_set, "Type"))
+        .is_some_and(|instrument| instrument.to_lowercase().contains("drum"))
+        || child(track, "GeneralMidi").is_some_and(|midi| midi.attribute("table") == Some("Percussion"));
+
+    let staff_properties = |name| track.descendants()
+        .find(|node| node.has_tag_name("Property") && node.attribute("name") == Some(name));
+
+    let tuning: Vec<i32> = staff_properties("Tuning")
+        .and_then(|tuning| text(tuning, "Pitches"))
+        .map(|pitches| pitches.split_whitespace().filter_map(|pitch| pitch.parse().ok()).collect())
+        .unwrap_or_default();
+
+    if percussion {
+        return Some(Track { name, percussion, tuning, capo: 0, measures: vec![] });
+    }
+
+    if tuning.is_empty() || tuning.len() > 7 {
+        return None;
+    }
+
+    let capo = staff_properties("CapoFret")
+        .and_then(|capo| text(capo, "Fret"))
+        .and_then(|fret| fret.trim().parse::<u8>().ok())
+        .unwrap_or(0);
+
+    let measures = bars.iter()
+        .map(|bar_ids| bar_ids.get(staff)
+            .and_then(|id| elements.bars.get(id))
+            .map(|bar| read_bar(*bar, tuning.len(), elements, name.as_str(), warnings))
+            .unwrap_or_default())
+        .collect();
+
+    Some(Track { name, percussion, tuning, capo, measures })
+}
+
+/// Reads the voices of a bar, of which -1 are unused
+fn read_bar(bar: Node, string_count: usize, elements: &Elements, track_name: &str, warnings: &mut Warnings) -> Vec<Vec<TabBeat>> {
+    text(bar, "Voices").unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|id| elements.voices.get(id))
+        .map(|voice| text(*voice, "Beats").unwrap_or_default()
+            .split_whitespace()
+            .filter_map(|id| elements.beats.get(id))
+            .filter_map(|beat| read_beat(*beat, string_count, elements, track_name, warnings))
+            .collect())
+        .collect()
+}
+
+/// Reads a beat, or returns `None` for grace notes, which take no time of their own
+fn read_beat(beat: Node, string_count: usize, elements: &Elements, track_name: &str, warnings: &mut Warnings) -> Option<TabBeat> {
+    if child(beat, "GraceNotes").is_some() {
+        warnings.add(format!("{}: grace notes dropped", track_name));
+        return None;
+    }
+
+    let ticks = child(beat, "Rhythm")
+        .and_then(|rhythm| rhythm.attribute("ref"))
+        .and_then(|id| elements.rhythms.get(id))
+        .map_or(QUARTER_TICKS, |rhythm| read_rhythm(*rhythm));
+
+    let effects = NoteEffects {
+        tremolo_picking: child(beat, "Tremolo").is_some(),
+        tremolo_bar: child(beat, "Whammy").is_some() || enabled(beat, "WhammyBar"),
+        tap: enabled(beat, "Tapped"),
+        slap: enabled(beat, "Slapped"),
+        pop: enabled(beat, "Popped"),
+        ..NoteEffects::default()
+    };
+
+    let notes = text(beat, "Notes").unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|id| elements.notes.get(id))
+        .filter_map(|note| {
+            let tab_note = read_note(*note, string_count, &effects);
+
+            if tab_note.is_none() {
+                warnings.add(format!("{}: notes without a string and fret dropped", track_name));
+            }
+
+            tab_note
+        })
+        .collect();
+
+    Some(TabBeat { ticks, notes })
+}
+
+/// The length of a rhythm in ticks
+fn read_rhythm(rhythm: Node) -> u64 {
+    let mut ticks = match text(rhythm, "NoteValue").map(str::trim) {
+        Some("Long") => QUARTER_TICKS * 16,
+        Some("DoubleWhole") => QUARTER_TICKS * 8,
+        Some("Whole") => QUARTER_TICKS * 4,
+        Some("Half") => QUARTER_TICKS * 2,
+        Some("Eighth") => QUARTER_TICKS / 2,
+        Some("16th") => QUARTER_TICKS / 4,
+        Some("32nd") => QUARTER_TICKS / 8,
+        Some("64th") => QUARTER_TICKS / 16,
+        Some("128th") => QUARTER_TICKS / 32,
+        Some("256th") => QUARTER_TICKS / 64,
+        _ => QUARTER_TICKS,
+    };
+
+    let dots = child(rhythm, "AugmentationDot")
+        .and_then(|dot| dot.attribute("count"))
+        .and_then(|count| count.parse::<u32>().ok())
+        .unwrap_or(0);
+
+    // Each dot adds half of what the previous one added
+    ticks = (0..dots.min(3)).fold((ticks, ticks), |(total, added), _| (total + added / 2, added / 2)).0;
+
+    let tuplet = child(rhythm, "PrimaryTuplet")
+        .and_then(|tuplet| Some((tuplet.attribute("num")?.parse::<u64>().ok()?, tuplet.attribute("den")?.parse::<u64>().ok()?)));
+
+    if let Some((num, den)) = tuplet.filter(|(num, den)| *num > 0 && *den > 0) {
+        ticks = ticks * den / num;
+    }
+
+    ticks
+}
+
+/// Reads a note, or returns `None` if it has no string or fret. Strings are numbered from 0 for the
+/// lowest string.
+fn read_note(note: Node, string_count: usize, beat_effects: &NoteEffects) -> Option<TabNote> {
+    let string = number_property::<usize>(note, "String", "String").filter(|string| *string < string_count)?;
+    let fret = number_property::<u8>(note, "Fret", "Fret")?;
+
+    let mut effects = beat_effects.clone();
+
+    effects.hammer = enabled(note, "HopoOrigin");
+    effects.palm_mute = enabled(note, "PalmMuted");
+    effects.vibrato = child(note, "Vibrato").is_some();
+    effects.let_ring = child(note, "LetRing").is_some();
+    effects.trill = child(note, "Trill").is_some();
+    effects.tap |= enabled(note, "Tapped") || enabled(note, "LeftHandTapped");
+
+    // Shift and legato slides go to the next note, the others into or out of the note
+    let slide = number_property::<u32>(note, "Slide", "Flags").unwrap_or(0);
+    effects.slide = slide & 0x03 != 0;
+    effects.slide_in_out = slide & 0x3c != 0;
+
+    effects.harmonic = match property(note, "HarmonicType").and_then(|harmonic| text(harmonic, "HType")).map(str::trim) {
+        Some("Natural") => Some(Harmonic::Natural),
+        Some("Artificial") => Some(Harmonic::Artificial),
+        Some("Tap") => Some(Harmonic::Tapped),
+        Some("Pinch") => Some(Harmonic::Pinch),
+        Some("Semi") => Some(Harmonic::Semi),
+        _ => None,
+    };
+
+    if enabled(note, "Bended") {
+        effects.bend = read_bend(note);
+    }
+
+    let finger = match text(note, "LeftFingering").map(str::trim) {
+        Some("P") => Some(0),
+        Some("I") => Some(1),
+        Some("M") => Some(2),
+        Some("A") => Some(3),
+        Some("C") => Some(4),
+        _ => None,
+    };
+
+    Some(TabNote {
+        string,
+        fret,
+        tie: child(note, "Tie").and_then(|tie| tie.attribute("destination")) == Some("true"),
+        dead: enabled(note, "Muted"),
+        finger,
+        effects,
+    })
+}
+
+/// Reads the points of a bend: where it starts, the value it may hold in the middle, and where it
+/// ends
+fn read_bend(note: Node) -> Vec<(f64, i16)> {
+    let float = |name| number_property::<f64>(note, name, "Float");
+    let point = |position: f64, value: f64| (position / BEND_POSITIONS, (value * CENTS_PER_BEND_UNIT).clamp(i16::MIN as f64, i16::MAX as f64) as i16);
+
+    let mut points = vec![point(float("BendOriginOffset").unwrap_or(0.0), float("BendOriginValue").unwrap_or(0.0))];
+
+    if let Some(middle) = float("BendMiddleValue") {
+        let first = float("BendMiddleOffset1").unwrap_or(BEND_POSITIONS / 2.0);
+        points.push(point(first, middle));
+
+        if let Some(second) = float("BendMiddleOffset2").filter(|second| *second != first) {
+            points.push(point(second, middle));
+        }
+    }
+
+    if let Some(destination) = float("BendDestinationValue") {
+        points.push(point(float("BendDestinationOffset").unwrap_or(BEND_POSITIONS), destination));
+    }
+
+    points
+}
@@ -0,0 +1,486 @@
+use crate::format::guitarpro::container::{container, read_gpx_score, read_zip_file, Container, ZIP_SCORE_PATH};
+use crate::format::registry::{Confidence, SongFormat};
+use crate::format::{has_extension, open_audio_file, path_string, silent_audio, LoadError, SongAudio, Warnings};
+use crate::library::songfile::{Format, PartSummary, SongFile, SongIdBuilder};
+use crate::song::Song;
+use log::{debug, warn};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+mod binary;
+mod container;
+mod gpif;
+mod tab;
+
+/// The extensions of Guitar Pro files, from the newest version of the format to the oldest
+pub const GUITAR_PRO_EXTENSIONS: [&str; 5] = ["gp", "gpx", "gp5", "gp4", "gp3"];
+
+/// The extension of the audio files played along with Guitar Pro files of the same name
+pub const AUDIO_EXTENSION: &str = "ogg";
+
+/// The name of the audio file played along with the only Guitar Pro file in a directory
+pub const AUDIO_FILE_NAME: &str = "song.ogg";
+
+/// A song read from a Guitar Pro file, along with a description of everything in the file that
+/// couldn't be represented in the song
+pub struct GuitarProImport {
+    pub song: Song,
+    pub warnings: Vec<String>,
+    /// The path of the backing track embedded in the file, if it has one
+    pub backing_track: Option<String>,
+}
+
+/// Reads a Guitar Pro file into a song. The binary files of Guitar Pro 3, 4 and 5 are read as well as
+/// the GPIF scores of Guitar Pro 6 (`.gpx`) and Guitar Pro 7 and later (`.gp`), whatever the
+/// extension of the file.
+pub fn import_guitar_pro(data: &[u8]) -> Result<GuitarProImport, String> {
+    let mut warnings = Warnings::default();
+
+    let (tab, backing_track) = match container(data) {
+        Container::Binary => (binary::read_binary(data)?, None),
+        Container::Gpx => {
+            let gpif = gpif::read_gpif(read_gpx_score(data)?.as_slice(), &mut warnings)?;
+            (gpif.tab, None)
+        }
+        Container::Zip => {
+            let score = read_zip_file(data, ZIP_SCORE_PATH)?
+                .ok_or_else(|| format!("missing {}", ZIP_SCORE_PATH))?;
+
+            let gpif = gpif::read_gpif(score.as_slice(), &mut warnings)?;
+            (gpif.tab, gpif.backing_track)
+        }
+    };
+
+    let song = tab.into_song(&mut warnings);
+
+    Ok(GuitarProImport { song, warnings: warnings.into_messages(), backing_track })
+}
+
+/// Reads the backing track embedded in a Guitar Pro file, if it has one. Only Guitar Pro 7 and later
+/// embed backing tracks.
+pub fn read_backing_track(data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let Container::Zip = container(data) else {
+        return Ok(None);
+    };
+
+    let score = read_zip_file(data, ZIP_SCORE_PATH)?
+        .ok_or_else(|| format!("missing {}", ZIP_SCORE_PATH))?;
+
+    match gpif::read_backing_track_path(score.as_slice())? {
+        Some(path) => read_zip_file(data, path.as_str()),
+        None => Ok(None),
+    }
+}
+
+/// A Guitar Pro file, each file in a directory being a song of its own. The song's audio is either
+/// an audio file next to it, or embedded in the file as its backing track. Songs without either are
+/// played along with silence.
+pub struct GuitarProFormat;
+
+impl SongFormat for GuitarProFormat {
+    fn format(&self) -> Format {
+        Format::GuitarPro
+    }
+
+    fn probe(&self, path: &Path) -> Confidence {
+        match is_tab(path) {
+            true => Confidence::Likely,
+            false => Confidence::None,
+        }
+    }
+
+    fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError> {
+        load_guitar_pro(path)
+    }
+
+    fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError> {
+        load_guitar_pro_song(song_file.song_dir.as_str())
+    }
+
+    fn open_audio(&self, song_file: &SongFile) -> Result<SongAudio, LoadError> {
+        open_guitar_pro_audio(song_file.song_dir.as_str(), song_file.metadata.length)
+    }
+}
+
+/// Imports a Guitar Pro file and creates a library entry for it. Returns `None` if the path isn't a
+/// Guitar Pro file, or the file has no guitar or bass tracks.
+pub fn load_guitar_pro<P: AsRef<Path>>(tab: P) -> Result<Option<SongFile>, LoadError> {
+    let tab = tab.as_ref();
+
+    if !is_tab(tab) {
+        return Ok(None);
+    }
+
+    let import = read_tab(tab)?;
+
+    if import.song.instrument_parts.is_empty() {
+        debug!("No guitar or bass tracks in {:?}", tab);
+        return Ok(None);
+    }
+
+    let mut id = SongIdBuilder::new();
+    id.add_file(file_name(tab), std::fs::File::open(tab)?)?;
+
+    // Audio next to the file is preferred over the backing track, which is only listed in the
+    // library as a path within the file
+    let song_path = match (audio_path(tab), &import.backing_track) {
+        (Some(audio), _) => {
+            id.add_file(file_name(audio.as_path()), std::fs::File::open(audio.as_path())?)?;
+            path_string(audio.as_path())?
+        }
+        (None, Some(backing_track)) => tab.join(backing_track).display().to_string(),
+        (None, None) => {
+            debug!("No audio for {:?}, playing it along with silence", tab);
+            String::new()
+        }
+    };
+
+    Ok(Some(SongFile {
+        id: id.build(),
+        format: Format::GuitarPro,
+        song_dir: path_string(tab)?,
+        song_path,
+        parts: import.song.instrument_parts.iter().map(PartSummary::from).collect(),
+        metadata: import.song.metadata,
+    }))
+}
+
+/// Imports a Guitar Pro file
+pub fn load_guitar_pro_song<P: AsRef<Path>>(tab: P) -> Result<Song, LoadError> {
+    Ok(read_tab(tab.as_ref())?.song)
+}
+
+/// Opens the audio of a Guitar Pro file. Without an audio file, the backing track embedded in the
+/// file is read into memory, and without a backing track, silence as long as the song is played.
+pub fn open_guitar_pro_audio<P: AsRef<Path>>(tab: P, length: Duration) -> Result<SongAudio, LoadError> {
+    if let Some(audio) = audio_path(tab.as_ref()) {
+        return open_audio_file(path_string(audio.as_path())?.as_str());
+    }
+
+    let backing_track = read_backing_track(read_file(tab.as_ref())?.as_slice())
+        .map_err(|message| parse_error(tab.as_ref(), message))?;
+
+    match backing_track {
+        Some(contents) => {
+            let byte_len = contents.len() as u64;
+            Ok(SongAudio { data: Box::new(Cursor::new(contents)), byte_len })
+        }
+        None => Ok(silent_audio(length)),
+    }
+}
+
+/// Imports a Guitar Pro file, logging everything that couldn't be imported
+fn read_tab(path: &Path) -> Result<GuitarProImport, LoadError> {
+    let import = import_guitar_pro(read_file(path)?.as_slice())
+        .map_err(|message| parse_error(path, message))?;
+
+    for warning in &import.warnings {
+        warn!("{:?}: {}", path, warning);
+    }
+
+    Ok(import)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, LoadError> {
+    let mut data = vec![];
+    std::fs::File::open(path)?.read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+fn parse_error(path: &Path, message: String) -> LoadError {
+    LoadError::Parse {
+        file: path.display().to_string(),
+        json_path: ".".to_string(),
+        message,
+    }
+}
+
+fn is_tab(path: &Path) -> bool {
+    path.is_file() && has_extension(path, &GUITAR_PRO_EXTENSIONS)
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name().and_then(|name| name.to_str()).unwrap_or_default()
+}
+
+/// The audio file played along with a Guitar Pro file: an audio file with the same name, or
+/// `song.ogg` if the file is the only Guitar Pro file in its directory
+fn audio_path(tab: &Path) -> Option<PathBuf> {
+    let dir = tab.parent()?;
+    let same_name = tab.with_extension(AUDIO_EXTENSION);
+
+    if same_name.is_file() {
+        return Some(same_name);
+    }
+
+    let song_audio = dir.join(AUDIO_FILE_NAME);
+    let tabs = std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_tab(entry.path().as_path()))
+        .count();
+
+    (tabs == 1 && song_audio.is_file()).then_some(song_audio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::registry::FormatRegistry;
+    use crate::format::testing::{describe_notes, fixture, guitar_parts, millis};
+    use crate::library::scanner::LibraryScanner;
+    use crate::library::testing::temp_dir;
+    use rodio::Source;
+    use std::sync::atomic::AtomicBool;
+
+    const BINARY_FIXTURES: [&str; 4] = ["gp3.gp3", "gp4.gp4", "gp5.gp5", "gp510.gp5"];
+    const GPIF_FIXTURES: [&str; 3] = ["gp6_bcfs.gpx", "gp6_bcfz.gpx", "gp7.gp"];
+
+    fn import(file: &str) -> GuitarProImport {
+        import_guitar_pro(fixture(format!("guitarpro/{}", file).as_str()).as_slice())
+            .unwrap_or_else(|error| panic!("{}: {}", file, error))
+    }
+
+    /// The notes of the first two measures, which are played twice
+    fn repeated_measures(start: u64, palm_mutes: bool) -> Vec<String> {
+        let palm_mute = if palm_mutes { " [palm mutes]" } else { "" };
+
+        vec![
+            format!("{}+500 0:0", start),
+            format!("{}+500 1:2 finger 1{}", start, palm_mute),
+            format!("{}+500 0:3 [hammer-ons]", start + 500),
+            format!("{}+250 3:5 [slide to 7]", start + 1000),
+            format!("{}+250 3:7", start + 1250),
+            format!("{}+500 4:8 [bend 0@0 200@250 200@500]", start + 1500),
+            format!("{}+1000 5:12 [vibrato, harmonics]", start + 2000),
+            format!("{}+500 2:5 [fret-hand mutes]", start + 3000),
+        ]
+    }
+
+    /// Asserts the parts, beats and sections every version of the fixture has in common
+    fn assert_common(file: &str, import: &GuitarProImport) {
+        let song = &import.song;
+        assert_eq!(song.metadata.title, "Test Song", "{}", file);
+        assert_eq!(song.metadata.artist, "Some Band", "{}", file);
+        assert_eq!(millis(song.metadata.length), 12000, "{}", file);
+
+        let parts = guitar_parts(song);
+        let names: Vec<&str> = parts.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["Lead Guitar", "Bass"], "{}", file);
+
+        let (_, lead) = parts[0];
+        assert_eq!(lead.tuning.string_offsets, vec![-2, 5, 10, 15, 19, 24], "{}", file);
+        assert_eq!(lead.capo, 2, "{}", file);
+
+        let (_, bass) = parts[1];
+        assert_eq!(bass.tuning.string_offsets, vec![-12, -7, -2, 3], "{}", file);
+        assert_eq!(bass.capo, 0, "{}", file);
+        assert_eq!(describe_notes(bass), vec![
+            "0+2000 0:0 [slaps]",
+            "2000+2000 1:2 [pops]",
+            "4000+2000 0:0 [slaps]",
+            "6000+2000 1:2 [pops]",
+        ], "{}", file);
+
+        // Five measures once the repeat is played out, the last one at half the tempo
+        let beats: Vec<(usize, u8, u64)> = song.beats.iter()
+            .map(|beat| (beat.measure, beat.beat_in_measure, millis(beat.time)))
+            .collect();
+        let expected: Vec<(usize, u8, u64)> = (0..20u64)
+            .map(|beat| (beat as usize / 4 + 1, (beat % 4) as u8 + 1, if beat < 16 { beat * 500 } else { 8000 + (beat - 16) * 1000 }))
+            .collect();
+        assert_eq!(beats, expected, "{}", file);
+
+        assert_eq!(song.tempo_at(Duration::from_secs(1)), Some(120.0), "{}", file);
+        assert_eq!(song.tempo_at(Duration::from_secs(9)), Some(60.0), "{}", file);
+
+        let sections: Vec<(&str, u64)> = song.sections.iter().map(|section| (section.name.as_str(), millis(section.time))).collect();
+        assert_eq!(sections[..2], [("Intro", 0), ("Intro", 4000)], "{}", file);
+        assert_eq!(sections[2].1, 8000, "{}", file);
+
+        assert!(import.warnings.contains(&"Drums: drum tracks skipped".to_string()), "{}", file);
+    }
+
+    #[test]
+    fn reads_binary_versions() {
+        for file in BINARY_FIXTURES {
+            let import = import(file);
+            assert_common(file, &import);
+            assert!(import.backing_track.is_none());
+
+            // Guitar Pro 3 has no palm mutes. The tied note in the last measure is merged into
+            // the note it's tied to.
+            let mut expected = repeated_measures(0, file != "gp3.gp3");
+            expected.extend(repeated_measures(4000, file != "gp3.gp3"));
+            expected.extend(["8000+2000 0:5".to_string(), "10000+2000 1:7 [taps]".to_string()]);
+
+            let (_, lead) = guitar_parts(&import.song)[0];
+            assert_eq!(describe_notes(lead), expected, "{}", file);
+            assert_eq!(import.song.sections[2].name, "Outro", "{}", file);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_binary_files() {
+        for file in BINARY_FIXTURES {
+            let data = fixture(format!("guitarpro/{}", file).as_str());
+
+            for len in 0..data.len() {
+                assert!(import_guitar_pro(&data[..len]).is_err(), "{} truncated to {} bytes", file, len);
+            }
+        }
+    }
+
+    #[test]
+    fn survives_corrupt_binary_files() {
+        for file in BINARY_FIXTURES {
+            let data = fixture(format!("guitarpro/{}", file).as_str());
+
+            for index in 0..data.len() {
+                for byte in [0x00, 0x7f, 0xff] {
+                    let mut corrupt = data.clone();
+                    corrupt[index] = byte;
+                    let _ = import_guitar_pro(corrupt.as_slice());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_gpif_versions() {
+        for file in GPIF_FIXTURES {
+            let import = import(file);
+            assert_common(file, &import);
+
+            // The tied note is merged, and the tuplet splits the second beat of the last measure
+            // into thirds
+            let mut expected = repeated_measures(0, true);
+            expected.extend(repeated_measures(4000, true));
+            expected.extend([
+                "8000+2000 0:5",
+                "10000+333 1:7 [slaps]",
+                "10333+333 1:7",
+                "10667+333 1:7 [tremolo]",
+                "11000+1000 1:9 [taps]",
+            ].map(String::from));
+
+            let (_, lead) = guitar_parts(&import.song)[0];
+            assert_eq!(describe_notes(lead), expected, "{}", file);
+            assert_eq!(import.song.sections[2].name, "B", "{}", file);
+
+            for warning in ["Piano: tracks without strings skipped", "Lead Guitar: grace notes dropped", "Lead Guitar: let ring dropped"] {
+                assert!(import.warnings.contains(&warning.to_string()), "{}: {}", file, warning);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_backing_tracks() {
+        let data = fixture("guitarpro/gp7.gp");
+        assert_eq!(import_guitar_pro(data.as_slice()).unwrap().backing_track.as_deref(), Some("Assets/backing.ogg"));
+        assert!(read_backing_track(data.as_slice()).unwrap().is_some_and(|audio| !audio.is_empty()));
+
+        for file in ["gp6_bcfz.gpx", "gp5.gp5"] {
+            assert!(read_backing_track(fixture(format!("guitarpro/{}", file).as_str()).as_slice()).unwrap().is_none(), "{}", file);
+        }
+    }
+
+    /// The padding at the end of a `.gpx` file system and the files other than the score can be cut
+    /// off without losing any of the song, so only the song that's read is checked
+    #[test]
+    fn rejects_truncated_gpif_files() {
+        for file in GPIF_FIXTURES {
+            let data = fixture(format!("guitarpro/{}", file).as_str());
+            let song = import(file).song;
+            let notes = describe_notes(guitar_parts(&song)[0].1);
+            let step = (data.len() / 500).max(1);
+
+            for len in (0..data.len()).step_by(step) {
+                if let Ok(truncated) = import_guitar_pro(&data[..len]) {
+                    let (_, lead) = guitar_parts(&truncated.song)[0];
+                    assert_eq!(describe_notes(lead), notes, "{} truncated to {} bytes", file, len);
+                }
+
+                let _ = read_backing_track(&data[..len]);
+            }
+        }
+    }
+
+    /// A damaged file system whose only file is stored in a sector before its header
+    #[test]
+    fn scans_gpx_sectors_forward_only() {
+        let data = fixture("guitarpro/gp6_backwards_sector.gpx");
+        assert_eq!(import_guitar_pro(data.as_slice()).err().as_deref(), Some("missing score.gpif"));
+    }
+
+    #[test]
+    fn survives_corrupt_gpif_files() {
+        for file in GPIF_FIXTURES {
+            let data = fixture(format!("guitarpro/{}", file).as_str());
+            let step = (data.len() / 1000).max(1);
+
+            for index in (0..data.len()).step_by(step) {
+                for byte in [0x00, 0x7f, 0xff] {
+                    let mut corrupt = data.clone();
+                    corrupt[index] = byte;
+                    let _ = import_guitar_pro(corrupt.as_slice());
+                    let _ = read_backing_track(corrupt.as_slice());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lists_every_file_as_a_song() {
+        let dir = temp_dir("guitarpro-entries");
+        for file in ["gp3.gp3", "gp5.gp5", "gp7.gp"] {
+            std::fs::write(dir.join(file), fixture(format!("guitarpro/{}", file).as_str())).unwrap();
+        }
+        std::fs::write(dir.join("gp5.ogg"), b"audio").unwrap();
+        std::fs::write(dir.join(AUDIO_FILE_NAME), b"audio").unwrap();
+
+        let formats = FormatRegistry::default();
+        let cancelled = AtomicBool::new(false);
+        let (songs, _) = LibraryScanner::new(&formats, &cancelled, |_| {}).scan(vec![dir.as_path()]);
+
+        // Audio with the same name comes first, then the backing track. With several files in the
+        // directory, song.ogg doesn't belong to any of them.
+        let entries: Vec<(String, String)> = songs.iter()
+            .map(|song| (file_name(Path::new(song.song_dir.as_str())).to_string(), song.song_path.replace(dir.to_str().unwrap(), "")))
+            .collect();
+        assert_eq!(entries, vec![
+            ("gp3.gp3".to_string(), "".to_string()),
+            ("gp5.gp5".to_string(), "/gp5.ogg".to_string()),
+            ("gp7.gp".to_string(), "/gp7.gp/Assets/backing.ogg".to_string()),
+        ]);
+
+        let song = formats.load_song(&songs[0]).unwrap();
+        assert_eq!(guitar_parts(&song).len(), 2);
+
+        // Without any audio, the song is played along with silence
+        let silence = formats.open_audio(&songs[0]).unwrap();
+        let decoder = rodio::Decoder::new(silence.data).unwrap();
+        assert_eq!(decoder.total_duration().map(millis), Some(12000));
+
+        let backing_track = formats.open_audio(&songs[2]).unwrap();
+        assert_eq!(backing_track.byte_len, read_backing_track(fixture("guitarpro/gp7.gp").as_slice()).unwrap().unwrap().len() as u64);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn plays_only_file_with_song_audio() {
+        let dir = temp_dir("guitarpro-song-audio");
+        std::fs::write(dir.join("gp5.gp5"), fixture("guitarpro/gp5.gp5")).unwrap();
+        std::fs::write(dir.join(AUDIO_FILE_NAME), b"audio").unwrap();
+
+        let song_file = load_guitar_pro(dir.join("gp5.gp5")).unwrap().unwrap();
+        assert_eq!(Path::new(song_file.song_path.as_str()), dir.join(AUDIO_FILE_NAME));
+
+        assert!(load_guitar_pro(dir.as_path()).unwrap().is_none());
+        assert!(load_guitar_pro(dir.join(AUDIO_FILE_NAME)).unwrap().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+use crate::format::{guitar_part_type, Warnings};
+use crate::song::guitar::{BendPoint, GuitarNote, GuitarPart, GuitarTechnique, GuitarTuning};
+use crate::song::instrument_part::{InstrumentPart, InstrumentPartType};
+use crate::song::metadata::Metadata;
+use crate::song::{Beat, Section, Song};
+use std::time::Duration;
+
+/// Durations are counted in ticks, with this many ticks per quarter note
+pub(crate) const QUARTER_TICKS: u64 = 960;
+
+/// Repeats are played out when a song is imported. This limits how long a song with broken repeats
+/// can get.
+const MAX_MEASURES: usize = 10_000;
+
+/// A song as written in a Guitar Pro file, which every version of the format is read into before
+/// it's turned into a `Song`
+pub(crate) struct TabSong {
+    pub(crate) title: String,
+    pub(crate) artist: String,
+    pub(crate) album: String,
+    /// In quarter notes per minute
+    pub(crate) tempo: f64,
+    pub(crate) headers: Vec<MeasureHeader>,
+    pub(crate) tracks: Vec<Track>,
+}
+
+/// The properties of a measure shared by all tracks
+pub(crate) struct MeasureHeader {
+    pub(crate) numerator: u64,
+    pub(crate) denominator: u64,
+    pub(crate) repeat_open: bool,
+    /// How many times the measures since the last repeat open are played again
+    pub(crate) repeat_close: u32,
+    /// The passes through the repeat this measure is played in, as a bit per pass
+    pub(crate) alternatives: u8,
+    pub(crate) marker: Option<String>,
+    /// Tempo changes, as ticks from the start of the measure and quarter notes per minute
+    pub(crate) tempo: Vec<(u64, f64)>,
+}
+
+impl MeasureHeader {
+    pub(crate) fn ticks(&self) -> u64 {
+        self.numerator * QUARTER_TICKS * 4 / self.denominator
+    }
+}
+
+pub(crate) struct Track {
+    pub(crate) name: String,
+    pub(crate) percussion: bool,
+    /// The MIDI note of each open string, from the lowest string
+    pub(crate) tuning: Vec<i32>,
+    pub(crate) capo: u8,
+    /// The beats of each voice of each measure
+    pub(crate) measures: Vec<Vec<Vec<TabBeat>>>,
+}
+
+pub(crate) struct TabBeat {
+    pub(crate) ticks: u64,
+    pub(crate) notes: Vec<TabNote>,
+}
+
+pub(crate) struct TabNote {
+    /// Numbered from 0 for the lowest string
+    pub(crate) string: usize,
+    pub(crate) fret: u8,
+    /// Continues the previous note on the string
+    pub(crate) tie: bool,
+    pub(crate) dead: bool,
+    pub(crate) finger: Option<u8>,
+    pub(crate) effects: NoteEffects,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Harmonic {
+    Natural,
+    Artificial,
+    Tapped,
+    Pinch,
+    Semi,
+}
+
+/// The effects of a note, including the ones set for all notes of its beat
+#[derive(Clone, Default)]
+pub(crate) struct NoteEffects {
+    /// The bend points, as the position within the note from 0 to 1 and the bend in cents
+    pub(crate) bend: Vec<(f64, i16)>,
+    /// The next note on the string is hammered on or pulled off
+    pub(crate) hammer: bool,
+    /// Slides to the next note on the string
+    pub(crate) slide: bool,
+    pub(crate) slide_in_out: bool,
+    pub(crate) palm_mute: bool,
+    pub(crate) vibrato: bool,
+    pub(crate) harmonic: Option<Harmonic>,
+    pub(crate) tremolo_picking: bool,
+    pub(crate) tap: bool,
+    pub(crate) slap: bool,
+    pub(crate) pop: bool,
+    pub(crate) grace: bool,
+    pub(crate) trill: bool,
+    pub(crate) let_ring: bool,
+    pub(crate) tremolo_bar: bool,
+}
+
+impl TabSong {
+
+    /// Turns the tab into a song. Every track except for drum tracks becomes a guitar part, repeats
+    /// and alternate endings are played out, and the tempo changes are turned into a beat grid.
+    pub(crate) fn into_song(self, warnings: &mut Warnings) -> Song {
+        let order = playing_order(&self.headers);
+
+        // The tick each measure starts at, every time it's played
+        let mut starts = Vec::with_capacity(order.len());
+        let mut end = 0;
+
+        for idx in &order {
+            starts.push(end);
+            end += self.headers[*idx].ticks();
+        }
+
+        let mut tempo = TempoMap::new(if self.tempo > 0.0 { self.tempo } else { 120.0 });
+
+        for (play, idx) in order.iter().enumerate() {
+            for (offset, bpm) in &self.headers[*idx].tempo {
+                tempo.add(starts[play] + offset, *bpm);
+            }
+        }
+
+        tempo.changes.sort_by_key(|(position, _)| *position);
+
+        let mut instrument_parts = vec![];
+        let mut guitar_parts = 0;
+
+        for (idx, track) in self.tracks.iter().enumerate() {
+            let name = if track.name.is_empty() { format!("Track {}", idx + 1) } else { track.name.clone() };
+
+            if track.percussion {
+                warnings.add(format!("{}: drum tracks skipped", name));
+                continue;
+            }
+
+            let guitar_part = guitar_part(track, &order, &starts, &tempo, &name, warnings);
+
+            if guitar_part.notes.is_empty() {
+                warnings.add(format!("{}: no notes, skipped", name));
+                continue;
+            }
+
+            let instrument_part_type = guitar_part_type(name.as_str(), guitar_part, &mut guitar_parts);
+            instrument_parts.push(InstrumentPart { name, instrument_part_type });
+        }
+
+        let mut beats = vec![];
+        let mut sections = vec![];
+
+        for (play, idx) in order.iter().enumerate() {
+            let header = &self.headers[*idx];
+            let beat_ticks = QUARTER_TICKS * 4 / header.denominator;
+
+            if let Some(marker) = &header.marker {
+                sections.push(Section { name: marker.clone(), time: seconds(tempo.seconds(starts[play])) });
+            }
+
+            for beat in 0..header.numerator {
+                beats.push(Beat {
+                    time: seconds(tempo.seconds(starts[play] + beat * beat_ticks)),
+                    measure: play + 1,
+                    beat_in_measure: (beat + 1).min(u8::MAX as u64) as u8,
+                });
+            }
+        }
+
+        let notes_end = instrument_parts.iter()
+            .filter_map(|part| match &part.instrument_part_type {
+                InstrumentPartType::LeadGuitar(guitar_part) |
+                InstrumentPartType::RhythmGuitar(guitar_part) |
+                InstrumentPartType::BassGuitar(guitar_part) => Some(guitar_part),
+                _ => None,
+            })
+            .flat_map(|guitar_part| guitar_part.notes.iter().map(|note| note.time + note.length))
+            .max()
+            .unwrap_or_default();
+
+        Song {
+            metadata: Metadata {
+                title: self.title,
+                artist: self.artist,
+                album: self.album,
+                year: 0,
+                length: notes_end.max(seconds(tempo.seconds(end))),
+                key: None,
+                preview_start: None,
+            },
+            instrument_parts,
+            beats,
+            sections,
+            a440_offset_cents: 0.0,
+        }
+    }
+}
+
+/// The order the measures are played in, with repeats and alternate endings played out
+fn playing_order(headers: &[MeasureHeader]) -> Vec<usize> {
+    let mut order = vec![];
+    let mut repeats_left: Vec<Option<u32>> = vec![None; headers.len()];
+    let mut repeat_start = 0;
+    let mut pass = 0;
+    let mut repeating = false;
+    let mut idx = 0;
+
+    while idx < headers.len() && order.len() < MAX_MEASURES {
+        let header = &headers[idx];
+
+        if header.repeat_open && !repeating {
+            repeat_start = idx;
+            pass = 0;
+        }
+
+        repeating = false;
+
+        if header.alternatives != 0 && pass < 8 && header.alternatives & (1 << pass) == 0 {
+            idx += 1;
+            continue;
+        }
+
+        order.push(idx);
+
+        if header.repeat_close > 0 {
+            let left = repeats_left[idx].get_or_insert(header.repeat_close);
+
+            if *left > 0 {
+                *left -= 1;
+                pass += 1;
+                idx = repeat_start;
+                repeating = true;
+                continue;
+            }
+
+            // Nested in another repeat, the measures are repeated again the next time around
+            repeats_left[idx] = None;
+        }
+
+        idx += 1;
+    }
+
+    order
+}
+
+/// Tempo changes, as the tick and the new tempo in quarter notes per minute
+struct TempoMap {
+    initial: f64,
+    changes: Vec<(u64, f64)>,
+}
+
+impl TempoMap {
+
+    fn new(initial: f64) -> Self {
+        Self { initial, changes: vec![] }
+    }
+
+    fn add(&mut self, position: u64, bpm: f64) {
+        if bpm > 0.0 && !self.changes.iter().any(|(existing, _)| *existing == position) {
+            self.changes.push((position, bpm));
+        }
+    }
+
+    /// Converts a position in ticks into seconds. The changes must be sorted.
+    fn seconds(&self, position: u64) -> f64 {
+        let mut seconds = 0.0;
+        let mut current = (0, self.initial);
+
+        for (change_position, bpm) in &self.changes {
+            if *change_position >= position {
+                break;
+            }
+
+            seconds += ticks_to_seconds(change_position - current.0, current.1);
+            current = (*change_position, *bpm);
+        }
+
+        seconds + ticks_to_seconds(position - current.0, current.1)
+    }
+}
+
+fn ticks_to_seconds(ticks: u64, bpm: f64) -> f64 {
+    ticks as f64 / QUARTER_TICKS as f64 * 60.0 / bpm
+}
+
+/// Converts seconds to a duration. Times that don't fit in a duration, which only come from tempos
+/// too slow to play, are zero.
+fn seconds(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or_default()
+}
+
+/// A note of a track, placed on the timeline of the song
+struct PlacedNote<'a> {
+    start: u64,
+    end: u64,
+    note: &'a TabNote,
+}
+
+/// Places the notes of a track on the timeline of the song, merges tied notes into the notes they
+/// continue and turns the effects into techniques
+fn guitar_part(track: &Track, order: &[usize], starts: &[u64], tempo: &TempoMap, name: &str, warnings: &mut Warnings) -> GuitarPart {
+    let mut placed: Vec<PlacedNote> = vec![];
+
+    for (play, idx) in order.iter().enumerate() {
+        for voice in &track.measures[*idx] {
+            let mut position = starts[play];
+
+            for beat in voice {
+                for note in &beat.notes {
+                    let end = position + beat.ticks;
+
+                    if note.tie && let Some(tied) = placed.iter_mut().rev().find(|other| other.note.string == note.string) {
+                        tied.end = tied.end.max(end);
+                        continue;
+                    }
+
+                    placed.push(PlacedNote { start: position, end, note });
+                }
+
+                position += beat.ticks;
+            }
+        }
+    }
+
+    placed.sort_by_key(|placed_note| (placed_note.start, placed_note.note.string));
+
+    let next_on_string: Vec<Option<usize>> = (0..placed.len())
+        .map(|idx| placed[idx + 1..].iter()
+            .position(|other| other.note.string == placed[idx].note.string && other.start > placed[idx].start)
+            .map(|offset| idx + 1 + offset))
+        .collect();
+
+    let mut techniques: Vec<Vec<GuitarTechnique>> = vec![vec![]; placed.len()];
+
+    for (idx, placed_note) in placed.iter().enumerate() {
+        let note = placed_note.note;
+        let effects = &note.effects;
+
+        if effects.hammer && let Some(next) = next_on_string[idx] {
+            let next_fret = placed[next].note.fret;
+
+            if next_fret > note.fret {
+                techniques[next].push(GuitarTechnique::HammerOn);
+            } else if next_fret < note.fret {
+                techniques[next].push(GuitarTechnique::PullOff);
+            }
+        }
+
+        if effects.slide {
+            match next_on_string[idx] {
+                Some(next) => techniques[idx].push(GuitarTechnique::Slide { to_fret: placed[next].note.fret }),
+                None => warnings.add(format!("{}: slides without a note to slide to dropped", name)),
+            }
+        }
+
+        let flags = [
+            (effects.palm_mute, GuitarTechnique::PalmMute),
+            (note.dead, GuitarTechnique::FretHandMute),
+            (effects.vibrato, GuitarTechnique::Vibrato),
+            (effects.tremolo_picking, GuitarTechnique::Tremolo),
+            (effects.tap, GuitarTechnique::Tap),
+            (effects.slap, GuitarTechnique::Slap),
+            (effects.pop, GuitarTechnique::Pop),
+        ];
+
+        techniques[idx].extend(flags.into_iter().filter(|(set, _)| *set).map(|(_, technique)| technique));
+
+        match effects.harmonic {
+            Some(Harmonic::Natural | Harmonic::Tapped) => techniques[idx].push(GuitarTechnique::Harmonic),
+            Some(Harmonic::Artificial | Harmonic::Pinch | Harmonic::Semi) => techniques[idx].push(GuitarTechnique::PinchHarmonic),
+            None => {}
+        }
+
+        let dropped = [
+            (effects.slide_in_out, "slides into and out of notes"),
+            (effects.grace, "grace notes"),
+            (effects.trill, "trills"),
+            (effects.let_ring, "let ring"),
+            (effects.tremolo_bar, "tremolo bar"),
+        ];
+
+        for (_, effect) in dropped.iter().filter(|(set, _)| *set) {
+            warnings.add(format!("{}: {} dropped", name, effect));
+        }
+    }
+
+    let notes = placed.iter().zip(techniques)
+        .map(|(placed_note, mut technique)| {
+            let time = tempo.seconds(placed_note.start);
+            let length = tempo.seconds(placed_note.end) - time;
+
+            if !placed_note.note.effects.bend.is_empty() {
+                technique.push(GuitarTechnique::Bend {
+                    points: placed_note.note.effects.bend.iter()
+                        .map(|(position, cents)| BendPoint {
+                            time_offset: seconds(length * position.clamp(0.0, 1.0)),
+                            cents: *cents,
+                        })
+                        .collect(),
+                });
+            }
+
+            GuitarNote {
+                string: placed_note.note.string as u8,
+                fret: placed_note.note.fret,
+                finger: placed_note.note.finger,
+                time: seconds(time),
+                length: seconds(length),
+                technique,
+            }
+        })
+        .collect();
+
+    GuitarPart {
+        notes,
+        tuning: GuitarTuning::from(track.tuning.iter()
+            .map(|note| note.saturating_sub(40).clamp(i8::MIN as i32, i8::MAX as i32) as i8)
+            .collect::<Vec<i8>>()),
+        capo: track.capo,
+        anchors: vec![],
+    }
+}
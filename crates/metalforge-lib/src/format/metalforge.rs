@@ -0,0 +1,288 @@
+use crate::format::registry::{Confidence, SongFormat};
+use crate::format::{path_string, same_file, ChartFiles, LoadError, SongDir};
+use crate::library::songfile::{Format, PartSummary, SongFile, SongIdBuilder};
+use crate::song::instrument_part::{InstrumentPart, InstrumentPartType};
+use crate::song::Song;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Error;
+use std::path::Path;
+
+/// The name of the file a song is stored in, in the native format
+pub const SONG_FILE_NAME: &str = "metalforge.json";
+
+/// The name the audio of a song is written with by `write_metalforge_song`
+pub const AUDIO_FILE_NAME: &str = "song.ogg";
+
+/// The version of the format written by `write_metalforge_song`. Songs written with an older
+/// version are migrated when they are read, songs with a newer version are rejected.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Upgrades a song document by a single version. The migration at index 0 upgrades version 1 to 2,
+/// the one at index 1 upgrades version 2 to 3, etc. Migrations work on the raw JSON, so the model
+/// only ever has to describe the current version.
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+const MIGRATIONS: &[Migration] = &[];
+
+const _: () = assert!(MIGRATIONS.len() + 1 == FORMAT_VERSION as usize, "every format version needs a migration");
+
+/// A song in the native format, along with the version of the format it was written with. Tempo is
+/// stored as the beats of the song, i.e. the time each beat starts at.
+#[derive(Serialize, Deserialize)]
+struct SongDocument<S> {
+    version: u32,
+    /// The name of the song's audio file, relative to the directory the song is in
+    audio: String,
+    song: S,
+}
+
+/// A song directory with a `metalforge.json` and the song's audio
+pub struct MetalforgeFormat;
+
+impl SongFormat for MetalforgeFormat {
+    fn format(&self) -> Format {
+        Format::Metalforge
+    }
+
+    fn probe(&self, path: &Path) -> Confidence {
+        if path.join(SONG_FILE_NAME).is_file() {
+            Confidence::Certain
+        } else {
+            Confidence::None
+        }
+    }
+
+    fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError> {
+        load_metalforge(path)
+    }
+
+    fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError> {
+        load_metalforge_song(song_file.song_dir.as_str())
+    }
+
+    fn save(&self, song: &Song, song_path: &str, dir: &Path) -> Result<(), LoadError> {
+        Ok(write_metalforge_song(song, song_path, dir)?)
+    }
+}
+
+/// Checks if the directory contains a song in the native format and creates a library entry for it.
+/// Returns `None` if the directory doesn't contain a `metalforge.json`.
+pub fn load_metalforge<P: AsRef<Path>>(dir: P) -> Result<Option<SongFile>, LoadError> {
+    if !dir.as_ref().is_dir() {
+        return Ok(None);
+    }
+
+    let mut files = SongDir(dir.as_ref());
+
+    let Some(document) = read_document(&mut files)? else {
+        return Ok(None);
+    };
+
+    let mut id = SongIdBuilder::new();
+
+    for file_name in [SONG_FILE_NAME, document.audio.as_str()] {
+        let contents = files.open(file_name)?
+            .ok_or_else(|| LoadError::MissingFile(file_name.to_string()))?;
+
+        id.add_file(file_name, contents)?;
+    }
+
+    Ok(Some(SongFile {
+        id: id.build(),
+        format: Format::Metalforge,
+        song_dir: path_string(dir.as_ref())?,
+        song_path: files.path(document.audio.as_str()),
+        metadata: document.song.metadata.clone(),
+        parts: document.song.instrument_parts.iter().map(PartSummary::from).collect(),
+    }))
+}
+
+/// Parses the song stored in the native format in the specified directory
+pub fn load_metalforge_song<P: AsRef<Path>>(dir: P) -> Result<Song, LoadError> {
+    read_document(&mut SongDir(dir.as_ref()))?
+        .map(|document| document.song)
+        .ok_or_else(|| LoadError::MissingFile(SONG_FILE_NAME.to_string()))
+}
+
+/// Writes a song in the native format into the specified directory, creating the directory if
+/// needed. The audio is copied from `song_path` unless it's already there or isn't a file.
+pub fn write_metalforge_song<P: AsRef<Path>>(song: &Song, song_path: &str, dir: P) -> Result<(), Error> {
+    debug!("Writing song to {:?}", dir.as_ref());
+
+    std::fs::create_dir_all(dir.as_ref())?;
+
+    let document = SongDocument {
+        version: FORMAT_VERSION,
+        audio: AUDIO_FILE_NAME.to_string(),
+        song,
+    };
+
+    let json = serde_json::to_vec_pretty(&document).map_err(Error::other)?;
+    std::fs::write(dir.as_ref().join(SONG_FILE_NAME), json)?;
+
+    let audio_path = Path::new(song_path);
+    let target_path = dir.as_ref().join(AUDIO_FILE_NAME);
+
+    if audio_path.is_file() && !same_file(audio_path, target_path.as_path()) {
+        std::fs::copy(audio_path, target_path)?;
+    }
+
+    Ok(())
+}
+
+/// Parses `metalforge.json`, migrating it to the current version first if it's older. Returns
+/// `None` if there is no `metalforge.json`.
+fn read_document(files: &mut impl ChartFiles) -> Result<Option<SongDocument<Song>>, LoadError> {
+    read_migrated_document(files, MIGRATIONS)
+}
+
+/// Parses `metalforge.json` with the specified migrations, the version after the last migration
+/// being the current one
+fn read_migrated_document(files: &mut impl ChartFiles, migrations: &[Migration]) -> Result<Option<SongDocument<Song>>, LoadError> {
+    let current_version = migrations.len() as u64 + 1;
+    let path = files.path(SONG_FILE_NAME);
+
+    let Some(reader) = files.open(SONG_FILE_NAME)? else {
+        return Ok(None);
+    };
+
+    let parse_error = |json_path: &str, message: String| LoadError::Parse {
+        file: path.clone(),
+        json_path: json_path.to_string(),
+        message,
+    };
+
+    let mut value: Value = serde_json::from_reader(reader)
+        .map_err(|error| parse_error(".", error.to_string()))?;
+
+    let version = value.get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| parse_error("version", "missing format version".to_string()))?;
+
+    if version == 0 || version > current_version {
+        return Err(parse_error("version", format!("unsupported format version {}, the newest supported version is {}", version, current_version)));
+    }
+
+    for (idx, migration) in migrations.iter().enumerate().skip(version as usize - 1) {
+        debug!("Migrating {} from version {} to {}", path, idx + 1, idx + 2);
+
+        migration(&mut value).map_err(|message| parse_error(".", message))?;
+        value["version"] = Value::from(idx + 2);
+    }
+
+    serde_path_to_error::deserialize(value)
+        .map(Some)
+        .map_err(|error| parse_error(error.path().to_string().as_str(), error.inner().to_string()))
+}
+
+impl From<&InstrumentPart> for PartSummary {
+    fn from(part: &InstrumentPart) -> Self {
+        let guitar_part = match &part.instrument_part_type {
+            InstrumentPartType::LeadGuitar(guitar_part) |
+            InstrumentPartType::RhythmGuitar(guitar_part) |
+            InstrumentPartType::BassGuitar(guitar_part) => Some(guitar_part),
+            InstrumentPartType::Keyboard | InstrumentPartType::Drums | InstrumentPartType::Vocals => None,
+        };
+
+        PartSummary {
+            name: part.name.clone(),
+            kind: part.instrument_part_type.kind(),
+            tuning: guitar_part.map(|guitar_part| guitar_part.tuning.clone()),
+            capo: guitar_part.map(|guitar_part| guitar_part.capo).unwrap_or(0),
+            difficulty: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::guitarpro::import_guitar_pro;
+    use crate::format::testing::fixture;
+    use crate::library::testing::{copy_fixture_song, temp_dir};
+
+    fn fixture_song() -> Song {
+        import_guitar_pro(fixture("guitarpro/gp5.gp5").as_slice()).unwrap().song
+    }
+
+    #[test]
+    fn round_trips_songs() {
+        let dir = temp_dir("metalforge-round-trip");
+        let song = fixture_song();
+        std::fs::write(dir.join("audio.ogg"), b"audio").unwrap();
+
+        write_metalforge_song(&song, dir.join("audio.ogg").to_str().unwrap(), dir.join("song")).unwrap();
+
+        let song_file = load_metalforge(dir.join("song")).unwrap().unwrap();
+        assert_eq!(song_file.metadata.title, "Test Song");
+        assert_eq!(Path::new(song_file.song_path.as_str()), dir.join("song").join(AUDIO_FILE_NAME));
+        assert_eq!(std::fs::read(song_file.song_path.as_str()).unwrap(), b"audio");
+
+        let loaded = load_metalforge_song(dir.join("song")).unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&song).unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let dir = temp_dir("metalforge-newer");
+        write_metalforge_song(&fixture_song(), "", dir.as_path()).unwrap();
+
+        let mut document: Value = serde_json::from_slice(std::fs::read(dir.join(SONG_FILE_NAME)).unwrap().as_slice()).unwrap();
+        document["version"] = Value::from(FORMAT_VERSION + 1);
+        std::fs::write(dir.join(SONG_FILE_NAME), serde_json::to_vec(&document).unwrap()).unwrap();
+
+        assert!(matches!(load_metalforge_song(dir.as_path()), Err(LoadError::Parse { json_path, .. }) if json_path == "version"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_other_directories() {
+        let dir = temp_dir("metalforge-other");
+        copy_fixture_song(dir.join("song").as_path());
+
+        assert!(load_metalforge(dir.join("song")).unwrap().is_none());
+        assert!(load_metalforge(dir.join("song").join("song.json")).unwrap().is_none());
+        assert_eq!(MetalforgeFormat.probe(dir.join("song").as_path()), Confidence::None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A migration as a future version might need, moving the audio into a list of files
+    fn move_audio(document: &mut Value) -> Result<(), String> {
+        let audio = document.get("files")
+            .and_then(|files| files.get("audio"))
+            .cloned()
+            .ok_or("missing files.audio")?;
+
+        document["audio"] = audio;
+        document.as_object_mut().ok_or("not an object")?.remove("files");
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrates_older_versions() {
+        let dir = temp_dir("metalforge-migration");
+        let song = serde_json::to_value(fixture_song()).unwrap();
+
+        // Written by hand the way an older version would have, with the audio in a list of files
+        let document = serde_json::json!({ "version": 1, "files": { "audio": "backing.ogg" }, "song": song });
+        std::fs::write(dir.join(SONG_FILE_NAME), serde_json::to_vec(&document).unwrap()).unwrap();
+
+        let migrations: &[Migration] = &[move_audio];
+        let migrated = read_migrated_document(&mut SongDir(dir.as_path()), migrations).unwrap().unwrap();
+        assert_eq!(migrated.version, 2);
+        assert_eq!(migrated.audio, "backing.ogg");
+        assert_eq!(serde_json::to_value(&migrated.song).unwrap(), song);
+
+        // The current version doesn't know about the list of files yet
+        assert!(read_document(&mut SongDir(dir.as_path())).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
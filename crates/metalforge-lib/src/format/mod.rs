@@ -1,10 +1,191 @@
-use std::io::Error;
+use crate::song::guitar::GuitarPart;
+use crate::song::instrument_part::InstrumentPartType;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Error, ErrorKind, Read, Seek};
 use std::path::Path;
-use crate::format::opensongchart::load_open_song_chart;
-use crate::library::songfile::SongFile;
+use std::time::Duration;
 
+pub mod asciitab;
+pub mod guitarpro;
+pub mod metalforge;
+pub mod musicxml;
 pub mod opensongchart;
+pub mod package;
+pub mod registry;
+pub mod rocksmith;
+#[cfg(test)]
+mod testing;
 
-pub fn load_dir<P: AsRef<Path>>(path: P) -> Result<Option<SongFile>, Error> {
-    load_open_song_chart(path)
-}
\ No newline at end of file
+/// The reason a directory could not be loaded as a song
+#[derive(Debug)]
+pub enum LoadError {
+    /// The directory contains a song, but a file required by its format is missing
+    MissingFile(String),
+    /// A chart file could not be parsed. `json_path` points to the element that failed to parse
+    Parse { file: String, json_path: String, message: String },
+    /// A song package is damaged or doesn't match its manifest, the reason is included
+    InvalidPackage(String),
+    /// The song's format isn't registered or doesn't support what was asked of it
+    UnsupportedFormat(String),
+    Io(Error),
+}
+
+impl From<Error> for LoadError {
+    fn from(error: Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::MissingFile(file_name) => write!(f, "missing {}", file_name),
+            LoadError::Parse { file, json_path, message } => write!(f, "{} at {}: {}", file, json_path, message),
+            LoadError::InvalidPackage(reason) => write!(f, "invalid song package: {}", reason),
+            LoadError::UnsupportedFormat(reason) => write!(f, "unsupported format: {}", reason),
+            LoadError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// A path as it's stored in a library entry. Paths that aren't valid UTF-8 can't be stored, so the
+/// song in them can't be added to the library.
+pub(crate) fn path_string(path: &Path) -> Result<String, LoadError> {
+    path.to_str()
+        .map(str::to_string)
+        .ok_or_else(|| LoadError::Io(Error::new(ErrorKind::InvalidData, format!("{:?} is not valid UTF-8", path))))
+}
+
+/// The files of a song, read either from a song directory or from a song package
+pub(crate) trait ChartFiles {
+    /// Opens the file of the song with the specified name, or returns `None` if the song doesn't have it
+    fn open(&mut self, file_name: &str) -> Result<Option<Box<dyn Read + '_>>, LoadError>;
+
+    /// The path of the file with the specified name, as shown in the library and in errors
+    fn path(&self, file_name: &str) -> String;
+}
+
+/// The files of a song stored in a directory
+pub(crate) struct SongDir<'a>(pub(crate) &'a Path);
+
+impl ChartFiles for SongDir<'_> {
+    fn open(&mut self, file_name: &str) -> Result<Option<Box<dyn Read + '_>>, LoadError> {
+        let path = self.0.join(file_name);
+
+        if !std::fs::exists(path.as_path())? {
+            return Ok(None);
+        }
+
+        Ok(Some(Box::new(BufReader::new(File::open(path)?))))
+    }
+
+    fn path(&self, file_name: &str) -> String {
+        self.0.join(file_name).display().to_string()
+    }
+}
+
+/// Checks if both paths refer to the same file, e.g. before copying a song's audio onto itself
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Checks the extension of a file, ignoring case
+pub(crate) fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.iter().any(|candidate| extension.eq_ignore_ascii_case(candidate)))
+}
+
+/// Counts the things dropped while importing a song from another format, so each kind is only
+/// reported once
+#[derive(Default)]
+pub(crate) struct Warnings(Vec<(String, usize)>);
+
+impl Warnings {
+
+    pub(crate) fn add(&mut self, message: String) {
+        match self.0.iter_mut().find(|(existing, _)| *existing == message) {
+            Some((_, count)) => *count += 1,
+            None => self.0.push((message, 1)),
+        }
+    }
+
+    pub(crate) fn into_messages(self) -> Vec<String> {
+        self.0.into_iter()
+            .map(|(message, count)| if count > 1 { format!("{} ({} times)", message, count) } else { message })
+            .collect()
+    }
+}
+
+/// Decides what an imported guitar part is played on, for formats that don't say. Parts named or
+/// tuned like a bass are bass parts. Of the other parts, the first one is the lead part unless it's
+/// named "rhythm", the others are rhythm parts unless they're named "lead". `guitar_parts` counts the
+/// guitar parts decided on so far.
+pub(crate) fn guitar_part_type(name: &str, guitar_part: GuitarPart, guitar_parts: &mut usize) -> InstrumentPartType {
+    let lower_name = name.to_lowercase();
+
+    if lower_name.contains("bass") || guitar_part.tuning.string_offsets.first().is_some_and(|offset| *offset < -5) {
+        return InstrumentPartType::BassGuitar(guitar_part);
+    }
+
+    *guitar_parts += 1;
+
+    if lower_name.contains("rhythm") || (*guitar_parts > 1 && !lower_name.contains("lead")) {
+        InstrumentPartType::RhythmGuitar(guitar_part)
+    } else {
+        InstrumentPartType::LeadGuitar(guitar_part)
+    }
+}
+
+/// Audio data that can be decoded and seeked in while the song is played
+pub trait AudioData: Read + Seek + Send + Sync {}
+
+impl<T: Read + Seek + Send + Sync> AudioData for T {}
+
+/// The audio of a song, opened for playing
+pub struct SongAudio {
+    pub data: Box<dyn AudioData>,
+    /// The size of the audio file, which lets the decoder seek more accurately
+    pub byte_len: u64,
+}
+
+/// Opens an audio file for playing
+pub(crate) fn open_audio_file(path: &str) -> Result<SongAudio, LoadError> {
+    let file = File::open(path)?;
+    let byte_len = file.metadata()?.len();
+
+    Ok(SongAudio { data: Box::new(file), byte_len })
+}
+
+/// The sample rate of the silence played along with songs that don't have any audio
+const SILENCE_SAMPLE_RATE: u32 = 8000;
+
+/// Creates a WAV file of silence, for songs that only have a chart. Playing along with silence lets
+/// the song be played, seeked and slowed down like any other.
+pub(crate) fn silent_audio(length: Duration) -> SongAudio {
+    let samples = (length.as_secs_f64() * SILENCE_SAMPLE_RATE as f64).ceil() as u32;
+    let data_len = samples * 2;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // Uncompressed mono with 16 bits per sample
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SILENCE_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SILENCE_SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.resize(44 + data_len as usize, 0);
+
+    let byte_len = wav.len() as u64;
+    SongAudio { data: Box::new(Cursor::new(wav)), byte_len }
+}
@@ -0,0 +1,853 @@
+use crate::song::guitar::{BendPoint, GuitarNote, GuitarPart, GuitarTechnique};
+use crate::song::instrument_part::InstrumentPartType;
+use crate::song::Song;
+use std::fmt::{Result as FmtResult, Write};
+use std::time::Duration;
+
+/// Divisions per beat, i.e. notes are quantized to sixteenths
+const DIVISIONS: usize = 4;
+
+/// The tempo of songs without beats, in beats per minute
+const DEFAULT_TEMPO: f64 = 120.0;
+
+/// The number of beats per measure of songs without beats
+const DEFAULT_BEATS_PER_MEASURE: usize = 4;
+
+/// Tempo changes smaller than this aren't written at all, changes at least this large are shown
+/// with a metronome mark as well
+const TEMPO_TOLERANCE: f64 = 0.001;
+const VISIBLE_TEMPO_CHANGE: f64 = 1.0;
+
+/// Songs whose first beat is later than this start with a pickup measure, so the notes stay in
+/// sync with the audio
+const MIN_PICKUP_SECONDS: f64 = 0.001;
+
+/// The lengths in divisions that can be written as a single note or rest, longest first, with the
+/// note type and whether it's dotted
+const NOTE_VALUES: [(usize, &str, bool); 8] = [
+    (16, "whole", false),
+    (12, "half", true),
+    (8, "half", false),
+    (6, "quarter", true),
+    (4, "quarter", false),
+    (3, "eighth", true),
+    (2, "eighth", false),
+    (1, "16th", false),
+];
+
+const PITCH_NAMES: [(&str, i32); 12] = [
+    ("C", 0), ("C", 1), ("D", 0), ("D", 1), ("E", 0), ("F", 0),
+    ("F", 1), ("G", 0), ("G", 1), ("A", 0), ("A", 1), ("B", 0),
+];
+
+/// Writes the guitar and bass parts of a song as a MusicXML score, each part with a standard
+/// notation staff and a tablature staff. The measures and the tempo are taken from the beats of the
+/// song, with every beat written as a quarter note, and the notes are quantized to sixteenths. Notes
+/// starting together are written as chords, held until the next notes start at the latest.
+pub fn export_musicxml(song: &Song) -> Result<String, String> {
+    let parts: Vec<(&str, &GuitarPart, bool)> = song.instrument_parts.iter()
+        .filter_map(|part| match &part.instrument_part_type {
+            InstrumentPartType::LeadGuitar(guitar_part) |
+            InstrumentPartType::RhythmGuitar(guitar_part) => Some((part.name.as_str(), guitar_part, false)),
+            InstrumentPartType::BassGuitar(guitar_part) => Some((part.name.as_str(), guitar_part, true)),
+            _ => None,
+        })
+        .collect();
+
+    if parts.is_empty() {
+        return Err("the song has no guitar or bass parts".to_string());
+    }
+
+    let end = parts.iter()
+        .flat_map(|(_, guitar_part, _)| guitar_part.notes.iter().map(|note| note.time + note.length))
+        .chain(song.sections.iter().map(|section| section.time))
+        .fold(song.metadata.length, Duration::max);
+
+    let grid = Grid::new(song, end);
+    let mut xml = String::new();
+
+    write_score(&mut xml, song, &parts, &grid).map_err(|error| error.to_string())?;
+
+    Ok(xml)
+}
+
+/// The measures and beats the score is written in
+struct Grid {
+    /// The time each beat starts at in seconds, followed by the time the last beat ends at
+    beats: Vec<f64>,
+    measures: Vec<GridMeasure>,
+    /// Whether the first measure is a pickup leading up to the first beat of the song
+    pickup: bool,
+}
+
+struct GridMeasure {
+    first_beat: usize,
+    beat_count: usize,
+}
+
+impl Grid {
+
+    /// Takes the measures from the beats of the song, adding measures until `end`
+    fn new(song: &Song, end: Duration) -> Self {
+        let mut beats: Vec<f64> = vec![];
+        let mut measures: Vec<GridMeasure> = vec![];
+        let mut pickup = false;
+
+        if song.beats.first().is_some_and(|beat| beat.time.as_secs_f64() > MIN_PICKUP_SECONDS) {
+            beats.push(0.0);
+            measures.push(GridMeasure { first_beat: 0, beat_count: 1 });
+            pickup = true;
+        }
+
+        let mut previous_measure = None;
+
+        for beat in &song.beats {
+            let time = beat.time.as_secs_f64();
+
+            if beats.last().is_some_and(|last| time <= *last) {
+                continue;
+            }
+
+            if beat.beat_in_measure == 1 || previous_measure != Some(beat.measure) {
+                measures.push(GridMeasure { first_beat: beats.len(), beat_count: 0 });
+            }
+
+            if let Some(measure) = measures.last_mut() {
+                measure.beat_count += 1;
+            }
+
+            previous_measure = Some(beat.measure);
+            beats.push(time);
+        }
+
+        let beats_per_measure = measures.last()
+            .filter(|_| !song.beats.is_empty())
+            .map(|measure| measure.beat_count)
+            .unwrap_or(DEFAULT_BEATS_PER_MEASURE);
+
+        // Continues with the last tempo and time signature until every note is in a measure
+        loop {
+            let beat_length = match beats.as_slice() {
+                [.., previous, last] => last - previous,
+                _ => 60.0 / DEFAULT_TEMPO,
+            };
+            let beats_end = beats.last().map(|last| last + beat_length).unwrap_or(0.0);
+
+            if !measures.is_empty() && beats_end >= end.as_secs_f64() - MIN_PICKUP_SECONDS {
+                beats.push(beats_end);
+                break;
+            }
+
+            measures.push(GridMeasure { first_beat: beats.len(), beat_count: beats_per_measure });
+            beats.extend((0..beats_per_measure).map(|idx| beats_end + idx as f64 * beat_length));
+        }
+
+        Self { beats, measures, pickup }
+    }
+
+    /// The position of a point in time, in divisions from the start of the score
+    fn position(&self, time: Duration) -> usize {
+        let time = time.as_secs_f64();
+        let idx = self.beats.partition_point(|beat| *beat <= time)
+            .saturating_sub(1)
+            .min(self.beats.len() - 2);
+        let fraction = (time - self.beats[idx]) / (self.beats[idx + 1] - self.beats[idx]);
+
+        ((idx as f64 + fraction) * DIVISIONS as f64).round().max(0.0) as usize
+    }
+
+    /// The tempo of a beat, in beats per minute
+    fn tempo(&self, beat: usize) -> f64 {
+        60.0 / (self.beats[beat + 1] - self.beats[beat])
+    }
+}
+
+/// Notes starting at the same position, written as a chord
+struct NoteGroup {
+    start: usize,
+    end: usize,
+    /// Indices into the notes of the part, from the lowest string
+    notes: Vec<usize>,
+}
+
+/// What a note has to show because of the note before or after it on the same string
+#[derive(Clone, Default)]
+struct NoteMarks {
+    /// Set if the next note is hammered on or pulled off from this one
+    legato_start: Option<&'static str>,
+    /// Set if the previous note slides to this one
+    slide_stop: bool,
+}
+
+/// A part ready to be written, with its notes grouped into chords
+struct ScorePart<'a> {
+    guitar_part: &'a GuitarPart,
+    bass: bool,
+    notes: Vec<&'a GuitarNote>,
+    marks: Vec<NoteMarks>,
+    groups: Vec<NoteGroup>,
+}
+
+impl<'a> ScorePart<'a> {
+
+    fn new(guitar_part: &'a GuitarPart, bass: bool, grid: &Grid) -> Self {
+        let string_count = guitar_part.tuning.string_offsets.len();
+
+        let mut notes: Vec<&GuitarNote> = guitar_part.notes.iter()
+            .filter(|note| (note.string as usize) < string_count)
+            .collect();
+
+        notes.sort_by_key(|note| (note.time, note.string));
+
+        let mut marks = vec![NoteMarks::default(); notes.len()];
+
+        for (idx, note) in notes.iter().enumerate() {
+            for technique in &note.technique {
+                match technique {
+                    GuitarTechnique::HammerOn | GuitarTechnique::PullOff => {
+                        if let Some(previous) = notes[..idx].iter().rposition(|other| other.string == note.string) {
+                            marks[previous].legato_start = Some(legato_name(technique));
+                        }
+                    }
+                    GuitarTechnique::Slide { .. } => {
+                        if let Some(next) = notes[idx + 1..].iter().position(|other| other.string == note.string) {
+                            marks[idx + 1 + next].slide_stop = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut groups: Vec<NoteGroup> = vec![];
+
+        for (idx, note) in notes.iter().enumerate() {
+            let start = grid.position(note.time);
+            let end = grid.position(note.time + note.length).max(start + 1);
+
+            match groups.last_mut() {
+                Some(group) if group.start == start => {
+                    // A chord can only have a single note on each string
+                    if !group.notes.iter().any(|other| notes[*other].string == note.string) {
+                        group.notes.push(idx);
+                        group.end = group.end.max(end);
+                    }
+                }
+                _ => groups.push(NoteGroup { start, end, notes: vec![idx] }),
+            }
+        }
+
+        for idx in 1..groups.len() {
+            let next_start = groups[idx].start;
+            groups[idx - 1].end = groups[idx - 1].end.min(next_start);
+        }
+
+        for group in &mut groups {
+            group.notes.sort_by_key(|idx| notes[*idx].string);
+        }
+
+        Self { guitar_part, bass, notes, marks, groups }
+    }
+
+    fn string_count(&self) -> usize {
+        self.guitar_part.tuning.string_offsets.len()
+    }
+}
+
+fn legato_name(technique: &GuitarTechnique) -> &'static str {
+    match technique {
+        GuitarTechnique::PullOff => "pull-off",
+        _ => "hammer-on",
+    }
+}
+
+fn write_score(xml: &mut String, song: &Song, parts: &[(&str, &GuitarPart, bool)], grid: &Grid) -> FmtResult {
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#)?;
+    writeln!(xml, r#"<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">"#)?;
+    writeln!(xml, r#"<score-partwise version="4.0">"#)?;
+    writeln!(xml, "  <work><work-title>{}</work-title></work>", escape(song.metadata.title.as_str()))?;
+    writeln!(xml, "  <identification>")?;
+    writeln!(xml, r#"    <creator type="composer">{}</creator>"#, escape(song.metadata.artist.as_str()))?;
+    writeln!(xml, "    <encoding><software>Metalforge</software></encoding>")?;
+    writeln!(xml, "  </identification>")?;
+    writeln!(xml, "  <part-list>")?;
+
+    for (idx, (name, _, _)) in parts.iter().enumerate() {
+        writeln!(xml, r#"    <score-part id="P{}"><part-name>{}</part-name></score-part>"#, idx + 1, escape(name))?;
+    }
+
+    writeln!(xml, "  </part-list>")?;
+
+    for (idx, (_, guitar_part, bass)) in parts.iter().enumerate() {
+        let part = ScorePart::new(guitar_part, *bass, grid);
+
+        // Tempo and sections apply to the whole score, so they're only written once
+        let directions = if idx == 0 { directions(song, grid) } else { vec![] };
+
+        writeln!(xml, r#"  <part id="P{}">"#, idx + 1)?;
+
+        for measure_idx in 0..grid.measures.len() {
+            write_measure(xml, &part, grid, measure_idx, &directions)?;
+        }
+
+        writeln!(xml, "  </part>")?;
+    }
+
+    writeln!(xml, "</score-partwise>")
+}
+
+/// A tempo change or the start of a section, at a beat
+struct BeatDirection<'a> {
+    beat: usize,
+    tempo: Option<f64>,
+    show_tempo: bool,
+    rehearsal: Option<&'a str>,
+}
+
+fn directions<'a>(song: &'a Song, grid: &Grid) -> Vec<BeatDirection<'a>> {
+    let mut directions: Vec<BeatDirection> = vec![];
+    let mut current_tempo: Option<f64> = None;
+    let mut shown_tempo: Option<f64> = None;
+
+    for beat in 0..grid.beats.len() - 1 {
+        let tempo = grid.tempo(beat);
+
+        if current_tempo.is_none_or(|current| (current - tempo).abs() >= TEMPO_TOLERANCE) {
+            let show_tempo = shown_tempo.is_none_or(|shown| (shown - tempo).abs() >= VISIBLE_TEMPO_CHANGE);
+
+            if show_tempo {
+                shown_tempo = Some(tempo);
+            }
+
+            current_tempo = Some(tempo);
+            directions.push(BeatDirection { beat, tempo: Some(tempo), show_tempo, rehearsal: None });
+        }
+    }
+
+    for section in &song.sections {
+        let beat = (grid.position(section.time) + DIVISIONS / 2) / DIVISIONS;
+
+        match directions.iter_mut().find(|direction| direction.beat == beat && direction.rehearsal.is_none()) {
+            Some(direction) => direction.rehearsal = Some(section.name.as_str()),
+            None => directions.push(BeatDirection { beat, tempo: None, show_tempo: false, rehearsal: Some(section.name.as_str()) }),
+        }
+    }
+
+    directions.sort_by_key(|direction| direction.beat);
+    directions
+}
+
+fn write_measure(xml: &mut String, part: &ScorePart, grid: &Grid, measure_idx: usize, directions: &[BeatDirection]) -> FmtResult {
+    let measure = &grid.measures[measure_idx];
+    let start = measure.first_beat * DIVISIONS;
+    let end = (measure.first_beat + measure.beat_count) * DIVISIONS;
+
+    // The pickup is measure 0, so the first full measure is always measure 1
+    if grid.pickup && measure_idx == 0 {
+        writeln!(xml, r#"    <measure number="0" implicit="yes">"#)?;
+    } else {
+        writeln!(xml, r#"    <measure number="{}">"#, if grid.pickup { measure_idx } else { measure_idx + 1 })?;
+    }
+
+    // A pickup is written in the time signature of the measure after it
+    let signature_idx = if grid.pickup && measure_idx == 0 { 1.min(grid.measures.len() - 1) } else { measure_idx };
+    let beat_count = grid.measures[signature_idx].beat_count;
+    let after_pickup = grid.pickup && measure_idx == 1;
+
+    if measure_idx == 0 {
+        write_attributes(xml, part, beat_count)?;
+    } else if !after_pickup && grid.measures[measure_idx - 1].beat_count != beat_count {
+        writeln!(xml, "      <attributes>")?;
+        writeln!(xml, "        <time><beats>{}</beats><beat-type>4</beat-type></time>", beat_count)?;
+        writeln!(xml, "      </attributes>")?;
+    }
+
+    let mut cursor = start;
+
+    for direction in directions.iter().filter(|direction| (measure.first_beat..measure.first_beat + measure.beat_count).contains(&direction.beat)) {
+        let position = direction.beat * DIVISIONS;
+
+        if position > cursor {
+            writeln!(xml, "      <forward><duration>{}</duration></forward>", position - cursor)?;
+            cursor = position;
+        }
+
+        write_direction(xml, direction)?;
+    }
+
+    if cursor > start {
+        writeln!(xml, "      <backup><duration>{}</duration></backup>", cursor - start)?;
+    }
+
+    write_staff(xml, part, start, end, 1)?;
+    writeln!(xml, "      <backup><duration>{}</duration></backup>", end - start)?;
+    write_staff(xml, part, start, end, 2)?;
+
+    writeln!(xml, "    </measure>")
+}
+
+fn write_attributes(xml: &mut String, part: &ScorePart, beat_count: usize) -> FmtResult {
+    let tuning = &part.guitar_part.tuning.string_offsets;
+
+    writeln!(xml, "      <attributes>")?;
+    writeln!(xml, "        <divisions>{}</divisions>", DIVISIONS)?;
+
+    writeln!(xml, "        <time><beats>{}</beats><beat-type>4</beat-type></time>", beat_count)?;
+    writeln!(xml, "        <staves>2</staves>")?;
+
+    // Guitar and bass are written an octave higher than they sound
+    if part.bass {
+        writeln!(xml, r#"        <clef number="1"><sign>F</sign><line>4</line><clef-octave-change>-1</clef-octave-change></clef>"#)?;
+    } else {
+        writeln!(xml, r#"        <clef number="1"><sign>G</sign><line>2</line><clef-octave-change>-1</clef-octave-change></clef>"#)?;
+    }
+
+    writeln!(xml, r#"        <clef number="2"><sign>TAB</sign><line>5</line></clef>"#)?;
+    writeln!(xml, r#"        <staff-details number="2">"#)?;
+    writeln!(xml, "          <staff-lines>{}</staff-lines>", tuning.len())?;
+
+    for (idx, offset) in tuning.iter().enumerate() {
+        let (step, alter, octave) = pitch(40 + i32::from(*offset));
+
+        write!(xml, r#"          <staff-tuning line="{}"><tuning-step>{}</tuning-step>"#, idx + 1, step)?;
+
+        if alter != 0 {
+            write!(xml, "<tuning-alter>{}</tuning-alter>", alter)?;
+        }
+
+        writeln!(xml, "<tuning-octave>{}</tuning-octave></staff-tuning>", octave)?;
+    }
+
+    if part.guitar_part.capo > 0 {
+        writeln!(xml, "          <capo>{}</capo>", part.guitar_part.capo)?;
+    }
+
+    writeln!(xml, "        </staff-details>")?;
+    writeln!(xml, "      </attributes>")
+}
+
+fn write_direction(xml: &mut String, direction: &BeatDirection) -> FmtResult {
+    // Small tempo changes only keep the score in sync with the audio, so nothing is shown for them
+    if direction.rehearsal.is_none() && !direction.show_tempo {
+        if let Some(tempo) = direction.tempo {
+            writeln!(xml, r#"      <sound tempo="{:.3}"/>"#, tempo)?;
+        }
+
+        return Ok(());
+    }
+
+    writeln!(xml, r#"      <direction placement="above">"#)?;
+
+    if let Some(rehearsal) = direction.rehearsal {
+        writeln!(xml, "        <direction-type><rehearsal>{}</rehearsal></direction-type>", escape(rehearsal))?;
+    }
+
+    if let Some(tempo) = direction.tempo.filter(|_| direction.show_tempo) {
+        writeln!(xml, "        <direction-type><metronome><beat-unit>quarter</beat-unit><per-minute>{}</per-minute></metronome></direction-type>", tempo.round())?;
+    }
+
+    if let Some(tempo) = direction.tempo {
+        writeln!(xml, r#"        <sound tempo="{:.3}"/>"#, tempo)?;
+    }
+
+    writeln!(xml, "      </direction>")
+}
+
+/// Writes the notes of a measure on one of the staves. Notes are split into tied notes where they
+/// cross the end of the measure, or aren't as long as a single, possibly dotted, note.
+fn write_staff(xml: &mut String, part: &ScorePart, start: usize, end: usize, staff: usize) -> FmtResult {
+    let groups: Vec<&NoteGroup> = part.groups.iter()
+        .filter(|group| group.start < end && group.end > start)
+        .collect();
+
+    if groups.is_empty() {
+        writeln!(xml, "      <note>")?;
+        writeln!(xml, r#"        <rest measure="yes"/>"#)?;
+        writeln!(xml, "        <duration>{}</duration>", end - start)?;
+        writeln!(xml, "        <voice>{}</voice>", voice(staff))?;
+        writeln!(xml, "        <staff>{}</staff>", staff)?;
+        return writeln!(xml, "      </note>");
+    }
+
+    let mut cursor = start;
+
+    for group in groups {
+        let group_start = group.start.max(start);
+        let group_end = group.end.min(end);
+
+        write_rests(xml, cursor, group_start, staff)?;
+
+        let mut position = group_start;
+
+        for (length, note_type, dotted) in note_values(group_end - group_start) {
+            let segment = Segment {
+                length,
+                note_type,
+                dotted,
+                first: position == group.start,
+                tie_stop: position > group.start,
+                tie_start: position + length < group.end,
+            };
+
+            for (idx, note_idx) in group.notes.iter().enumerate() {
+                write_note(xml, part, *note_idx, idx > 0, &segment, staff)?;
+            }
+
+            position += length;
+        }
+
+        cursor = group_end;
+    }
+
+    write_rests(xml, cursor, end, staff)
+}
+
+fn write_rests(xml: &mut String, start: usize, end: usize, staff: usize) -> FmtResult {
+    for (length, note_type, dotted) in note_values(end.saturating_sub(start)) {
+        writeln!(xml, "      <note>")?;
+        writeln!(xml, "        <rest/>")?;
+        writeln!(xml, "        <duration>{}</duration>", length)?;
+        writeln!(xml, "        <voice>{}</voice>", voice(staff))?;
+        writeln!(xml, "        <type>{}</type>", note_type)?;
+
+        if dotted {
+            writeln!(xml, "        <dot/>")?;
+        }
+
+        writeln!(xml, "        <staff>{}</staff>", staff)?;
+        writeln!(xml, "      </note>")?;
+    }
+
+    Ok(())
+}
+
+/// Splits a length into the lengths of notes that can be written without tuplets
+fn note_values(mut length: usize) -> Vec<(usize, &'static str, bool)> {
+    let mut values = vec![];
+
+    while let Some(value) = NOTE_VALUES.iter().find(|(value_length, _, _)| *value_length <= length) {
+        values.push(*value);
+        length -= value.0;
+    }
+
+    values
+}
+
+/// The notes on the tablature staff are in their own voice, like notation software writes them
+fn voice(staff: usize) -> usize {
+    if staff == 1 { 1 } else { 5 }
+}
+
+/// A part of a note, which is tied to the other parts of the note
+struct Segment {
+    length: usize,
+    note_type: &'static str,
+    dotted: bool,
+    /// Set for the part the note starts with, which is the only one showing the techniques
+    first: bool,
+    tie_stop: bool,
+    tie_start: bool,
+}
+
+fn write_note(xml: &mut String, part: &ScorePart, note_idx: usize, chord: bool, segment: &Segment, staff: usize) -> FmtResult {
+    let note = part.notes[note_idx];
+    let marks = &part.marks[note_idx];
+    let open_string = i32::from(part.guitar_part.tuning.string_offsets[note.string as usize]);
+    let (step, alter, octave) = pitch(40 + open_string + i32::from(part.guitar_part.capo) + i32::from(note.fret));
+    let techniques: &[GuitarTechnique] = if segment.first { note.technique.as_slice() } else { &[] };
+    let last = !segment.tie_start;
+
+    writeln!(xml, "      <note>")?;
+
+    if chord {
+        writeln!(xml, "        <chord/>")?;
+    }
+
+    write!(xml, "        <pitch><step>{}</step>", step)?;
+
+    if alter != 0 {
+        write!(xml, "<alter>{}</alter>", alter)?;
+    }
+
+    writeln!(xml, "<octave>{}</octave></pitch>", octave)?;
+    writeln!(xml, "        <duration>{}</duration>", segment.length)?;
+
+    if segment.tie_stop {
+        writeln!(xml, r#"        <tie type="stop"/>"#)?;
+    }
+
+    if segment.tie_start {
+        writeln!(xml, r#"        <tie type="start"/>"#)?;
+    }
+
+    writeln!(xml, "        <voice>{}</voice>", voice(staff))?;
+    writeln!(xml, "        <type>{}</type>", segment.note_type)?;
+
+    if segment.dotted {
+        writeln!(xml, "        <dot/>")?;
+    }
+
+    if note.technique.iter().any(|technique| matches!(technique, GuitarTechnique::FretHandMute)) {
+        writeln!(xml, "        <notehead>x</notehead>")?;
+    }
+
+    writeln!(xml, "        <staff>{}</staff>", staff)?;
+    writeln!(xml, "        <notations>")?;
+
+    if segment.tie_stop {
+        writeln!(xml, r#"          <tied type="stop"/>"#)?;
+    }
+
+    if segment.tie_start {
+        writeln!(xml, r#"          <tied type="start"/>"#)?;
+    }
+
+    if segment.first && marks.slide_stop {
+        writeln!(xml, r#"          <slide type="stop"/>"#)?;
+    }
+
+    if last && note.technique.iter().any(|technique| matches!(technique, GuitarTechnique::Slide { .. })) {
+        writeln!(xml, r#"          <slide type="start" line-type="solid"/>"#)?;
+    }
+
+    writeln!(xml, "          <technical>")?;
+
+    if let Some(finger) = note.finger.filter(|_| segment.first) {
+        writeln!(xml, "            <fingering>{}</fingering>", if finger == 0 { "T".to_string() } else { finger.to_string() })?;
+    }
+
+    writeln!(xml, "            <string>{}</string>", part.string_count() - note.string as usize)?;
+    writeln!(xml, "            <fret>{}</fret>", note.fret)?;
+
+    for technique in techniques {
+        match technique {
+            GuitarTechnique::HammerOn | GuitarTechnique::PullOff => {
+                writeln!(xml, r#"            <{} type="stop"/>"#, legato_name(technique))?;
+            }
+            GuitarTechnique::Bend { points } => write_bend(xml, points)?,
+            GuitarTechnique::Harmonic => writeln!(xml, "            <harmonic><natural/></harmonic>")?,
+            GuitarTechnique::PinchHarmonic => writeln!(xml, "            <harmonic><artificial/></harmonic>")?,
+            GuitarTechnique::Tap => writeln!(xml, "            <tap>T</tap>")?,
+            GuitarTechnique::PalmMute => writeln!(xml, "            <other-technical>palm mute</other-technical>")?,
+            _ => {}
+        }
+    }
+
+    if let Some(legato) = marks.legato_start.filter(|_| last) {
+        writeln!(xml, r#"            <{0} type="start">{1}</{0}>"#, legato, if legato == "pull-off" { "P" } else { "H" })?;
+    }
+
+    writeln!(xml, "          </technical>")?;
+
+    let ornaments: Vec<&str> = techniques.iter()
+        .filter_map(|technique| match technique {
+            GuitarTechnique::Vibrato => Some(r#"<wavy-line type="start"/><wavy-line type="stop"/>"#),
+            GuitarTechnique::Tremolo => Some(r#"<tremolo type="single">3</tremolo>"#),
+            _ => None,
+        })
+        .collect();
+
+    if !ornaments.is_empty() {
+        writeln!(xml, "          <ornaments>{}</ornaments>", ornaments.concat())?;
+    }
+
+    let articulations: Vec<&str> = techniques.iter()
+        .filter_map(|technique| match technique {
+            GuitarTechnique::Slap => Some("<other-articulation>slap</other-articulation>"),
+            GuitarTechnique::Pop => Some("<other-articulation>pop</other-articulation>"),
+            _ => None,
+        })
+        .collect();
+
+    if !articulations.is_empty() {
+        writeln!(xml, "          <articulations>{}</articulations>", articulations.concat())?;
+    }
+
+    writeln!(xml, "        </notations>")?;
+    writeln!(xml, "      </note>")
+}
+
+/// Writes the largest bend of a note in whole semitones. A bend starting with the note is written as
+/// a pre-bend, a bend going back to the note's pitch as a release.
+fn write_bend(xml: &mut String, points: &[BendPoint]) -> FmtResult {
+    let Some(peak) = points.iter().map(|point| point.cents).max_by_key(|cents| cents.unsigned_abs()).filter(|cents| *cents != 0) else {
+        return Ok(());
+    };
+
+    let semitones = match (f32::from(peak) / 100.0).round() as i16 {
+        0 => peak.signum(),
+        semitones => semitones,
+    };
+
+    let pre_bend = points.first().is_some_and(|point| point.time_offset.is_zero() && point.cents != 0);
+    let release = points.len() > 1 && points.last().is_some_and(|point| point.cents == 0);
+
+    write!(xml, "            <bend><bend-alter>{}</bend-alter>", semitones)?;
+
+    if pre_bend {
+        write!(xml, "<pre-bend/>")?;
+    } else if release {
+        write!(xml, "<release/>")?;
+    }
+
+    writeln!(xml, "</bend>")
+}
+
+/// The step, alteration and octave of a MIDI note, spelled with sharps
+fn pitch(midi: i32) -> (&'static str, i32, i32) {
+    let (step, alter) = PITCH_NAMES[midi.rem_euclid(12) as usize];
+    (step, alter, midi.div_euclid(12) - 1)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::musicxml::import_musicxml;
+    use crate::format::testing::{self, beats, bend, describe_notes, guitar_part, guitar_parts, millis, part};
+    use crate::song::Section;
+    use musicxml::datatypes::Step;
+    use musicxml::elements::{MeasureElement, NotationContentTypes, PartElement, TechnicalContents};
+
+    /// A song at 120 bpm whose first beat is half a second in, with a note before it
+    fn song() -> Song {
+        let note = |time_millis, length_millis, string, fret, technique| testing::note(time_millis, length_millis, string, fret, None, technique);
+
+        let lead = guitar_part(vec![-2, 5, 10, 15, 19, 24], 2, vec![
+            note(0, 500, 0, 3, vec![GuitarTechnique::PalmMute]),
+            note(500, 250, 3, 5, vec![]),
+            note(750, 250, 3, 7, vec![GuitarTechnique::HammerOn]),
+            note(1000, 250, 3, 5, vec![GuitarTechnique::PullOff]),
+            note(1250, 250, 2, 5, vec![GuitarTechnique::Slide { to_fret: 7 }]),
+            note(1500, 500, 2, 7, vec![GuitarTechnique::Vibrato]),
+            note(2000, 1000, 4, 8, vec![]),
+            note(3000, 500, 0, 0, vec![GuitarTechnique::FretHandMute]),
+            note(3000, 500, 1, 2, vec![GuitarTechnique::FretHandMute]),
+            note(3000, 500, 2, 2, vec![GuitarTechnique::FretHandMute]),
+            note(3500, 500, 1, 5, vec![GuitarTechnique::Harmonic, bend(&[(0, 0), (250, 200)])]),
+        ]);
+
+        let bass = guitar_part(vec![-12, -7, -2, 3], 0, vec![
+            note(500, 500, 0, 0, vec![GuitarTechnique::Slap]),
+            note(1000, 500, 3, 2, vec![GuitarTechnique::Pop]),
+        ]);
+
+        let mut song = testing::song(vec![
+            part("Lead", InstrumentPartType::LeadGuitar(lead)),
+            part("Bass", InstrumentPartType::BassGuitar(bass)),
+        ]);
+
+        song.metadata.length = Duration::from_millis(4500);
+        song.beats = beats(500, 8);
+        song.sections = vec![Section { name: "Verse".to_string(), time: Duration::from_millis(500) }];
+        song
+    }
+
+    #[test]
+    fn round_trips_through_import() {
+        let song = song();
+        let score = export_musicxml(&song).unwrap();
+        let import = import_musicxml(score.into_bytes()).unwrap();
+        let parts = guitar_parts(&import.song);
+
+        assert_eq!(parts.len(), 2);
+
+        for ((name, part), (original_name, original)) in parts.iter().zip(guitar_parts(&song)) {
+            assert_eq!(*name, original_name);
+            assert_eq!(part.tuning, original.tuning, "{}", name);
+            assert_eq!(part.capo, original.capo, "{}", name);
+            assert_eq!(describe_notes(part), describe_notes(original), "{}", name);
+        }
+
+        assert!(import.warnings.is_empty(), "{:?}", import.warnings);
+    }
+
+    #[test]
+    fn starts_with_pickup_measure() {
+        let import = import_musicxml(export_musicxml(&song()).unwrap().into_bytes()).unwrap();
+        let beats: Vec<(u64, usize, u8)> = import.song.beats.iter()
+            .map(|beat| (millis(beat.time), beat.measure, beat.beat_in_measure))
+            .collect();
+
+        assert_eq!(beats, vec![
+            (0, 1, 1),
+            (500, 2, 1), (1000, 2, 2), (1500, 2, 3), (2000, 2, 4),
+            (2500, 3, 1), (3000, 3, 2), (3500, 3, 3), (4000, 3, 4),
+        ]);
+
+        let sections: Vec<(&str, u64)> = import.song.sections.iter().map(|section| (section.name.as_str(), millis(section.time))).collect();
+        assert_eq!(sections, vec![("Verse", 500)]);
+    }
+
+    #[test]
+    fn writes_ties_and_legato_pairs() {
+        let score = export_musicxml(&song()).unwrap();
+        let count = |text: &str| score.matches(text).count();
+
+        // The note held over the bar line is tied on both staves
+        assert_eq!(count(r#"<tied type="start"/>"#), 2);
+        assert_eq!(count(r#"<tied type="stop"/>"#), 2);
+        assert_eq!(count(r#"<hammer-on type="start">"#), count(r#"<hammer-on type="stop"/>"#));
+        assert_eq!(count(r#"<pull-off type="start">"#), count(r#"<pull-off type="stop"/>"#));
+        assert_eq!(count(r#"<slide type="start""#), count(r#"<slide type="stop"/>"#));
+        assert!(count(r#"<hammer-on type="start">"#) > 0 && count(r#"<slide type="start""#) > 0);
+    }
+
+    #[test]
+    fn writes_strings_from_the_highest() {
+        let score = musicxml::read_score_data_partwise(export_musicxml(&song()).unwrap().into_bytes()).unwrap();
+
+        let measure_elements = |part: usize| score.content.part[part].content.iter()
+            .filter_map(|element| match element {
+                PartElement::Measure(measure) => Some(measure.content.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+
+        let first_string_and_fret = |part: usize| measure_elements(part).into_iter()
+            .filter_map(|element| match element {
+                MeasureElement::Note(note) => note.content.notations.first(),
+                _ => None,
+            })
+            .flat_map(|notations| notations.content.notations.iter())
+            .find_map(|notation| match notation {
+                NotationContentTypes::Technical(technical) => {
+                    let string = technical.content.iter().find_map(|element| match element {
+                        TechnicalContents::StringNumber(number) => Some(*number.content),
+                        _ => None,
+                    });
+                    let fret = technical.content.iter().find_map(|element| match element {
+                        TechnicalContents::Fret(number) => Some(*number.content),
+                        _ => None,
+                    });
+                    string.zip(fret)
+                }
+                _ => None,
+            });
+
+        // The lowest string of the six-string lead is string 6, of the four-string bass string 4
+        assert_eq!(first_string_and_fret(0), Some((6, 3)));
+        assert_eq!(first_string_and_fret(1), Some((4, 0)));
+
+        let staff_details = measure_elements(0).into_iter()
+            .filter_map(|element| match element {
+                MeasureElement::Attributes(attributes) => attributes.content.staff_details.iter()
+                    .find(|details| !details.content.staff_tuning.is_empty()),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+
+        // Line 1 of the staff is the lowest string, tuned down to D
+        let lowest = staff_details.content.staff_tuning.iter().find(|tuning| *tuning.attributes.line == 1).unwrap();
+        assert!(matches!(lowest.content.tuning_step.content, Step::D));
+        assert_eq!(staff_details.content.capo.as_ref().map(|capo| *capo.content), Some(2));
+    }
+}
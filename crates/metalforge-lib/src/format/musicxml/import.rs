@@ -0,0 +1,796 @@
+use crate::format::{guitar_part_type, Warnings};
+use crate::song::guitar::{BendPoint, GuitarNote, GuitarPart, GuitarTechnique, GuitarTuning};
+use crate::song::instrument_part::{InstrumentPart, InstrumentPartType};
+use crate::song::metadata::Metadata;
+use crate::song::{Beat, Section, Song};
+use musicxml::datatypes::{NoteTypeValue, NoteheadValue, StartStop, StartStopContinue, Step, YesNo};
+use musicxml::elements::{
+    ArticulationsType, Attributes, AudibleType, BeatEquation, Direction, DirectionTypeContents, MeasureElement,
+    MetronomeContents, NotationContentTypes, Note, NoteType, OrnamentType, Part, PartElement, PartListElement,
+    ScorePartwise, StaffDetails, TechnicalContents,
+};
+use std::time::Duration;
+
+/// The tempo of a score without any tempo directions, in quarter notes per minute
+const DEFAULT_TEMPO: f64 = 120.0;
+
+/// Positions closer than this, in quarter notes, are considered the same
+const EPSILON: f64 = 1e-6;
+
+/// The pitch of each open string of the instruments usually written as tablature, for scores without
+/// a staff tuning, in semitones from E2
+const DEFAULT_TUNINGS: [&[i8]; 4] = [
+    &[-12, -7, -2, 3],
+    &[-17, -12, -7, -2, 3],
+    &[0, 5, 10, 15, 19, 24],
+    &[-5, 0, 5, 10, 15, 19, 24],
+];
+
+/// A song read from a MusicXML score, along with a description of everything in the score that
+/// couldn't be represented in the song
+pub struct MusicXmlImport {
+    pub song: Song,
+    pub warnings: Vec<String>,
+}
+
+/// Reads a MusicXML score into a song. Only parts written as tablature are imported, i.e. parts whose
+/// notes have a `<string>` and a `<fret>`. Tempo and time signature directions are turned into a beat
+/// grid, ties are merged into a single note, and slides, hammer-ons, pull-offs, bends, harmonics,
+/// taps, vibrato, tremolo picking, dead notes, slaps and pops are read as techniques. Compressed
+/// `.mxl` scores are read as well.
+pub fn import_musicxml(data: Vec<u8>) -> Result<MusicXmlImport, String> {
+    let score = musicxml::read_score_data_partwise(data)?;
+    let mut warnings = Warnings::default();
+
+    let layout = measure_layout(&score);
+    let mut tempo = TempoMap::default();
+    let mut sections = vec![];
+    let mut parts = vec![];
+
+    for part in &score.content.part {
+        let name = part_name(&score, part);
+        parts.push(read_part(part, name, &layout, &mut tempo, &mut sections, &mut warnings));
+    }
+
+    tempo.changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    sections.sort_by(|a: &(f64, String), b| a.0.total_cmp(&b.0));
+    sections.dedup_by(|a, b| (a.0 - b.0).abs() < EPSILON && a.1 == b.1);
+
+    let mut instrument_parts = vec![];
+    let mut guitar_parts = 0;
+
+    for part in parts {
+        if part.notes.is_empty() && part.tuning.is_none() {
+            warnings.add(format!("{}: not written as tablature, skipped", part.name));
+            continue;
+        }
+
+        let name = part.name.clone();
+        let guitar_part = guitar_part(part, &tempo, &mut warnings);
+        let instrument_part_type = guitar_part_type(name.as_str(), guitar_part, &mut guitar_parts);
+
+        instrument_parts.push(InstrumentPart { name, instrument_part_type });
+    }
+
+    let beats = beat_grid(&layout, &tempo);
+
+    let notes_end = instrument_parts.iter()
+        .filter_map(|part| match &part.instrument_part_type {
+            InstrumentPartType::LeadGuitar(guitar_part) |
+            InstrumentPartType::RhythmGuitar(guitar_part) |
+            InstrumentPartType::BassGuitar(guitar_part) => Some(guitar_part),
+            _ => None,
+        })
+        .flat_map(|guitar_part| guitar_part.notes.iter().map(|note| note.time + note.length))
+        .max()
+        .unwrap_or_default();
+
+    let measures_end = layout.last()
+        .map(|measure| seconds(tempo.seconds(measure.start + measure.length)))
+        .unwrap_or_default();
+
+    let song = Song {
+        metadata: metadata(&score, notes_end.max(measures_end)),
+        instrument_parts,
+        beats,
+        sections: sections.into_iter()
+            .map(|(position, name)| Section { name, time: seconds(tempo.seconds(position)) })
+            .collect(),
+        a440_offset_cents: 0.0,
+    };
+
+    Ok(MusicXmlImport { song, warnings: warnings.into_messages() })
+}
+
+/// Where a measure starts and how long it is, in quarter notes from the start of the score
+struct MeasureLayout {
+    start: f64,
+    length: f64,
+    beat_type: u32,
+}
+
+/// Tempo changes, as the position in quarter notes and the new tempo in quarter notes per minute
+#[derive(Default)]
+struct TempoMap {
+    changes: Vec<(f64, f64)>,
+}
+
+impl TempoMap {
+
+    fn add(&mut self, position: f64, bpm: f64) {
+        if bpm > 0.0 && !self.changes.iter().any(|(existing, _)| (existing - position).abs() < EPSILON) {
+            self.changes.push((position, bpm));
+        }
+    }
+
+    /// Converts a position in quarter notes into seconds. The changes must be sorted.
+    fn seconds(&self, position: f64) -> f64 {
+        let mut seconds = 0.0;
+        let mut current = (0.0, DEFAULT_TEMPO);
+
+        for (change_position, bpm) in &self.changes {
+            if *change_position >= position {
+                break;
+            }
+
+            seconds += (change_position - current.0) * 60.0 / current.1;
+            current = (*change_position, *bpm);
+        }
+
+        seconds + (position - current.0) * 60.0 / current.1
+    }
+}
+
+/// Converts seconds to a duration, clamping negative times to zero. Times that don't fit in a duration
+/// can only come from a broken score and are zero as well.
+fn seconds(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or_default()
+}
+
+fn measures(part: &Part) -> impl Iterator<Item = &musicxml::elements::Measure> {
+    part.content.iter().filter_map(|element| match element {
+        PartElement::Measure(measure) => Some(measure),
+        _ => None,
+    })
+}
+
+/// The measures of the score, taken from its first part. Measures are as long as their time
+/// signature, except for pickup measures, which are as long as their notes.
+fn measure_layout(score: &ScorePartwise) -> Vec<MeasureLayout> {
+    let Some(first_part) = score.content.part.first() else {
+        return vec![];
+    };
+
+    let mut layout = vec![];
+    let mut divisions = 1.0;
+    let (mut beats, mut beat_type) = (4, 4);
+    let mut start = 0.0;
+
+    for measure in measures(first_part) {
+        let mut cursor: f64 = 0.0;
+        let mut end: f64 = 0.0;
+
+        for element in &measure.content {
+            match element {
+                MeasureElement::Attributes(attributes) => {
+                    if let Some(value) = &attributes.content.divisions && f64::from(*value.content) > 0.0 {
+                        divisions = f64::from(*value.content);
+                    }
+
+                    if let Some(signature) = attributes.content.time.first().and_then(|time| time.content.beats.first()) {
+                        beats = parse_beats(signature.beats.content.as_str()).unwrap_or(beats);
+                        beat_type = signature.beat_type.content.trim().parse().unwrap_or(beat_type).max(1);
+                    }
+                }
+                MeasureElement::Note(note) => {
+                    if !is_chord(note) && let Some(duration) = note_duration(note) {
+                        cursor += duration / divisions;
+                    }
+                }
+                MeasureElement::Backup(backup) => cursor -= f64::from(*backup.content.duration.content) / divisions,
+                MeasureElement::Forward(forward) => cursor += f64::from(*forward.content.duration.content) / divisions,
+                _ => {}
+            }
+
+            end = end.max(cursor);
+        }
+
+        let signature_length = f64::from(beats) * 4.0 / f64::from(beat_type);
+        let length = if matches!(measure.attributes.implicit, Some(YesNo::Yes)) && end > EPSILON { end } else { signature_length };
+
+        layout.push(MeasureLayout { start, length, beat_type });
+        start += length;
+    }
+
+    layout
+}
+
+/// Parses the number of beats of a time signature, adding up composite signatures like "3+2"
+fn parse_beats(beats: &str) -> Option<u32> {
+    beats.split('+')
+        .map(|part| part.trim().parse::<u32>().ok())
+        .sum::<Option<u32>>()
+        .filter(|beats| *beats > 0)
+}
+
+fn beat_grid(layout: &[MeasureLayout], tempo: &TempoMap) -> Vec<Beat> {
+    let mut beats = vec![];
+
+    for (idx, measure) in layout.iter().enumerate() {
+        let beat_length = 4.0 / f64::from(measure.beat_type);
+        let count = ((measure.length - EPSILON) / beat_length).ceil().max(1.0) as usize;
+
+        for beat in 0..count {
+            beats.push(Beat {
+                time: seconds(tempo.seconds(measure.start + beat as f64 * beat_length)),
+                measure: idx + 1,
+                beat_in_measure: (beat + 1).min(u8::MAX as usize) as u8,
+            });
+        }
+    }
+
+    beats
+}
+
+fn part_name(score: &ScorePartwise, part: &Part) -> String {
+    score.content.part_list.content.content.iter()
+        .find_map(|element| match element {
+            PartListElement::ScorePart(score_part) if *score_part.attributes.id == *part.attributes.id => {
+                Some(score_part.content.part_name.content.trim().to_string())
+            }
+            _ => None,
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| part.attributes.id.to_string())
+}
+
+fn metadata(score: &ScorePartwise, length: Duration) -> Metadata {
+    let title = score.content.work.as_ref()
+        .and_then(|work| work.content.work_title.as_ref())
+        .map(|title| title.content.clone())
+        .or_else(|| score.content.movement_title.as_ref().map(|title| title.content.clone()))
+        .unwrap_or_default();
+
+    let creators = score.content.identification.as_ref()
+        .map(|identification| identification.content.creator.as_slice())
+        .unwrap_or_default();
+
+    let artist = ["artist", "composer"].iter()
+        .find_map(|creator_type| creators.iter().find(|creator| creator.attributes.r#type.as_ref().is_some_and(|t| t.as_str() == *creator_type)))
+        .or(creators.first())
+        .map(|creator| creator.content.clone())
+        .unwrap_or_default();
+
+    Metadata {
+        title: title.trim().to_string(),
+        artist: artist.trim().to_string(),
+        album: String::new(),
+        year: 0,
+        length,
+        key: None,
+        preview_start: None,
+    }
+}
+
+/// A part as it's read from the score, with positions in quarter notes and strings numbered the way
+/// MusicXML does, from the highest string
+struct ScorePart {
+    name: String,
+    notes: Vec<ScoreNote>,
+    tuning: Option<Vec<i8>>,
+    string_count: Option<usize>,
+    capo: u8,
+}
+
+struct ScoreNote {
+    string: u8,
+    fret: u8,
+    finger: Option<u8>,
+    start: f64,
+    length: f64,
+    technique: Vec<GuitarTechnique>,
+    bend: Option<ScoreBend>,
+    slide: bool,
+    tie_stop: bool,
+}
+
+struct ScoreBend {
+    cents: i16,
+    pre_bend: bool,
+    release: bool,
+}
+
+fn read_part(
+    part: &Part,
+    name: String,
+    layout: &[MeasureLayout],
+    tempo: &mut TempoMap,
+    sections: &mut Vec<(f64, String)>,
+    warnings: &mut Warnings,
+) -> ScorePart {
+    let mut score_part = ScorePart { name, notes: vec![], tuning: None, string_count: None, capo: 0 };
+    let mut divisions = 1.0;
+
+    for (idx, measure) in measures(part).enumerate() {
+        let Some(measure_layout) = layout.get(idx) else {
+            warnings.add(format!("{}: measures beyond the end of the score skipped", score_part.name));
+            break;
+        };
+
+        let mut cursor = 0.0;
+        let mut chord_start = 0.0;
+
+        for element in &measure.content {
+            let position = measure_layout.start + cursor;
+
+            match element {
+                MeasureElement::Attributes(attributes) => {
+                    if let Some(value) = &attributes.content.divisions {
+                        match f64::from(*value.content) {
+                            value if value > 0.0 => divisions = value,
+                            _ => warnings.add(format!("{}: divisions that aren't positive ignored", score_part.name)),
+                        }
+                    }
+
+                    read_staff_details(attributes, &mut score_part);
+                }
+                MeasureElement::Direction(direction) => read_direction(direction, position, tempo, sections),
+                MeasureElement::Sound(sound) => {
+                    if let Some(bpm) = &sound.attributes.tempo {
+                        tempo.add(position, **bpm);
+                    }
+                }
+                MeasureElement::Backup(backup) => cursor -= f64::from(*backup.content.duration.content) / divisions,
+                MeasureElement::Forward(forward) => cursor += f64::from(*forward.content.duration.content) / divisions,
+                MeasureElement::Note(note) => {
+                    let Some(duration) = note_duration(note).map(|duration| duration / divisions) else {
+                        warnings.add(format!("{}: grace notes dropped", score_part.name));
+                        continue;
+                    };
+
+                    if !is_chord(note) {
+                        chord_start = cursor;
+                        cursor += duration;
+                    }
+
+                    if matches!(note.content.info, NoteType::Cue(_)) {
+                        warnings.add(format!("{}: cue notes dropped", score_part.name));
+                        continue;
+                    }
+
+                    let start = measure_layout.start + chord_start;
+
+                    if let Some(score_note) = read_note(note, start, duration, &score_part.name, warnings) {
+                        score_part.notes.push(score_note);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    score_part
+}
+
+fn read_staff_details(attributes: &Attributes, score_part: &mut ScorePart) {
+    // Scores with both a notation and a tablature staff only tune the tablature staff
+    let Some(staff_details) = attributes.content.staff_details.iter()
+        .find(|details| !details.content.staff_tuning.is_empty())
+        .or(attributes.content.staff_details.first()) else {
+        return;
+    };
+
+    if let Some(capo) = &staff_details.content.capo {
+        score_part.capo = (*capo.content).min(u8::MAX as u32) as u8;
+    }
+
+    if let Some(lines) = &staff_details.content.staff_lines {
+        score_part.string_count = Some(*lines.content as usize);
+    }
+
+    if let Some(tuning) = staff_tuning(staff_details) {
+        score_part.string_count.get_or_insert(tuning.len());
+        score_part.tuning = Some(tuning);
+    }
+}
+
+/// The pitch of each string from the lowest, in semitones from E2
+fn staff_tuning(staff_details: &StaffDetails) -> Option<Vec<i8>> {
+    let mut lines: Vec<(u8, i8)> = staff_details.content.staff_tuning.iter()
+        .map(|tuning| {
+            let step = match tuning.content.tuning_step.content {
+                Step::C => 0,
+                Step::D => 2,
+                Step::E => 4,
+                Step::F => 5,
+                Step::G => 7,
+                Step::A => 9,
+                Step::B => 11,
+            };
+            let alter = tuning.content.tuning_alter.as_ref().map(|alter| *alter.content).unwrap_or(0);
+            let midi = (i16::from(*tuning.content.tuning_octave.content) + 1) * 12 + step + alter;
+
+            (*tuning.attributes.line, (midi - 40) as i8)
+        })
+        .collect();
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    lines.sort_by_key(|(line, _)| *line);
+    Some(lines.into_iter().map(|(_, offset)| offset).collect())
+}
+
+fn read_direction(direction: &Direction, position: f64, tempo: &mut TempoMap, sections: &mut Vec<(f64, String)>) {
+    let sound_tempo = direction.content.sound.as_ref().and_then(|sound| sound.attributes.tempo.as_ref());
+
+    if let Some(bpm) = sound_tempo {
+        tempo.add(position, **bpm);
+    }
+
+    for direction_type in &direction.content.direction_type {
+        match &direction_type.content {
+            DirectionTypeContents::Rehearsal(rehearsals) => {
+                for rehearsal in rehearsals {
+                    sections.push((position, rehearsal.content.trim().to_string()));
+                }
+            }
+            // The sound of a direction is what's played, the metronome mark is only shown
+            DirectionTypeContents::Metronome(metronome) if sound_tempo.is_none() => {
+                if let MetronomeContents::BeatBased(beat_based) = &metronome.content
+                    && let BeatEquation::BPM(per_minute) = &beat_based.equals
+                    && let Ok(bpm) = per_minute.content.trim().parse::<f64>() {
+                    let dots = beat_based.beat_unit_dot.len() as i32;
+                    let quarters = quarters(&beat_based.beat_unit.content) * (2.0 - 0.5f64.powi(dots));
+                    tempo.add(position, bpm * quarters);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The length of a note value in quarter notes
+fn quarters(note_type: &NoteTypeValue) -> f64 {
+    match note_type {
+        NoteTypeValue::Maxima => 32.0,
+        NoteTypeValue::Long => 16.0,
+        NoteTypeValue::Breve => 8.0,
+        NoteTypeValue::Whole => 4.0,
+        NoteTypeValue::Half => 2.0,
+        NoteTypeValue::Quarter => 1.0,
+        NoteTypeValue::Eighth => 0.5,
+        NoteTypeValue::Sixteenth => 0.25,
+        NoteTypeValue::ThirtySecond => 0.125,
+        NoteTypeValue::SixtyFourth => 0.0625,
+        NoteTypeValue::OneHundredTwentyEighth => 0.03125,
+        NoteTypeValue::TwoHundredFiftySixth => 0.015625,
+        NoteTypeValue::FiveHundredTwelfth => 0.0078125,
+        NoteTypeValue::OneThousandTwentyFourth => 0.00390625,
+    }
+}
+
+/// The duration of a note in divisions, or `None` for grace notes, which take no time
+fn note_duration(note: &Note) -> Option<f64> {
+    match &note.content.info {
+        NoteType::Normal(info) => Some(f64::from(*info.duration.content)),
+        NoteType::Cue(info) => Some(f64::from(*info.duration.content)),
+        NoteType::Grace(_) => None,
+    }
+}
+
+fn is_chord(note: &Note) -> bool {
+    match &note.content.info {
+        NoteType::Normal(info) => info.chord.is_some(),
+        NoteType::Cue(info) => info.chord.is_some(),
+        NoteType::Grace(_) => false,
+    }
+}
+
+fn read_note(note: &Note, start: f64, length: f64, part_name: &str, warnings: &mut Warnings) -> Option<ScoreNote> {
+    let NoteType::Normal(info) = &note.content.info else {
+        return None;
+    };
+
+    match &info.audible {
+        AudibleType::Rest(_) => return None,
+        AudibleType::Unpitched(_) => {
+            warnings.add(format!("{}: unpitched notes dropped", part_name));
+            return None;
+        }
+        AudibleType::Pitch(_) => {}
+    }
+
+    let mut string = None;
+    let mut fret = None;
+    let mut finger = None;
+    let mut technique = vec![];
+    let mut bend = None;
+    let mut slide = false;
+    let mut tie_stop = info.tie.iter().any(|tie| matches!(tie.attributes.r#type, StartStop::Stop));
+
+    for notation in note.content.notations.iter().flat_map(|notations| notations.content.notations.iter()) {
+        match notation {
+            NotationContentTypes::Technical(technical) => {
+                for element in &technical.content {
+                    match element {
+                        TechnicalContents::StringNumber(number) => string = Some(*number.content),
+                        TechnicalContents::Fret(number) => fret = Some((*number.content).min(u8::MAX as u32) as u8),
+                        TechnicalContents::Fingering(fingering) => finger = parse_finger(fingering.content.as_str()),
+                        TechnicalContents::HammerOn(hammer_on) => {
+                            // The technique belongs to the note being hammered, which ends the hammer-on
+                            if matches!(hammer_on.attributes.r#type, StartStop::Stop) {
+                                technique.push(GuitarTechnique::HammerOn);
+                            }
+                        }
+                        TechnicalContents::PullOff(pull_off) => {
+                            if matches!(pull_off.attributes.r#type, StartStop::Stop) {
+                                technique.push(GuitarTechnique::PullOff);
+                            }
+                        }
+                        TechnicalContents::Bend(score_bend) => bend = Some(ScoreBend {
+                            cents: score_bend.content.bend_alter.content.saturating_mul(100),
+                            pre_bend: score_bend.content.pre_bend.is_some(),
+                            release: score_bend.content.release.is_some(),
+                        }),
+                        TechnicalContents::Harmonic(harmonic) => technique.push(if harmonic.content.artificial.is_some() {
+                            GuitarTechnique::PinchHarmonic
+                        } else {
+                            GuitarTechnique::Harmonic
+                        }),
+                        TechnicalContents::Tap(_) => technique.push(GuitarTechnique::Tap),
+                        TechnicalContents::OtherTechnical(other) if other.content.to_lowercase().contains("palm") => {
+                            technique.push(GuitarTechnique::PalmMute);
+                        }
+                        TechnicalContents::OpenString(_) => {}
+                        other => warnings.add(format!("{}: {} dropped", part_name, technical_name(other))),
+                    }
+                }
+            }
+            NotationContentTypes::Tied(tied) => tie_stop |= !matches!(tied.attributes.r#type, StartStopContinue::Start),
+            NotationContentTypes::Slide(score_slide) => slide |= matches!(score_slide.attributes.r#type, StartStop::Start),
+            NotationContentTypes::Glissando(glissando) => slide |= matches!(glissando.attributes.r#type, StartStop::Start),
+            NotationContentTypes::Ornaments(ornaments) => {
+                for ornament in &ornaments.content.ornaments {
+                    match ornament {
+                        OrnamentType::WavyLine(wavy_line) => {
+                            if !matches!(wavy_line.attributes.r#type, StartStopContinue::Stop) {
+                                technique.push(GuitarTechnique::Vibrato);
+                            }
+                        }
+                        OrnamentType::Tremolo(_) => technique.push(GuitarTechnique::Tremolo),
+                        _ => warnings.add(format!("{}: ornaments dropped", part_name)),
+                    }
+                }
+            }
+            NotationContentTypes::Articulations(articulations) => {
+                for articulation in &articulations.content {
+                    match articulation {
+                        ArticulationsType::OtherArticulation(other) if other.content.trim().eq_ignore_ascii_case("slap") => {
+                            technique.push(GuitarTechnique::Slap);
+                        }
+                        ArticulationsType::OtherArticulation(other) if other.content.trim().eq_ignore_ascii_case("pop") => {
+                            technique.push(GuitarTechnique::Pop);
+                        }
+                        _ => warnings.add(format!("{}: articulations dropped", part_name)),
+                    }
+                }
+            }
+            NotationContentTypes::Arpeggiate(_) => warnings.add(format!("{}: arpeggios dropped", part_name)),
+            _ => {}
+        }
+    }
+
+    if note.content.notehead.as_ref().is_some_and(|notehead| matches!(notehead.content, NoteheadValue::X)) {
+        technique.push(GuitarTechnique::FretHandMute);
+    }
+
+    let (Some(string), Some(fret)) = (string, fret) else {
+        warnings.add(format!("{}: notes without a string and fret dropped", part_name));
+        return None;
+    };
+
+    Some(ScoreNote { string, fret, finger, start, length, technique, bend, slide, tie_stop })
+}
+
+/// Reads a fingering of the fretting hand, where the thumb is written as "T" or "0"
+fn parse_finger(fingering: &str) -> Option<u8> {
+    match fingering.trim() {
+        "T" | "t" | "0" => Some(0),
+        "1" => Some(1),
+        "2" => Some(2),
+        "3" => Some(3),
+        "4" => Some(4),
+        _ => None,
+    }
+}
+
+fn technical_name(technical: &TechnicalContents) -> &'static str {
+    match technical {
+        TechnicalContents::UpBow(_) | TechnicalContents::DownBow(_) => "bowing marks",
+        TechnicalContents::Pluck(_) => "picking hand fingerings",
+        TechnicalContents::Stopped(_) | TechnicalContents::SnapPizzicato(_) => "pizzicato marks",
+        TechnicalContents::OtherTechnical(_) => "unknown techniques",
+        _ => "techniques of other instruments",
+    }
+}
+
+/// Turns the notes of a part into the notes of a guitar part: ties are merged into the note they
+/// continue, copies of notes on a second staff are removed, and slides get the fret of the note they
+/// slide to.
+fn guitar_part(part: ScorePart, tempo: &TempoMap, warnings: &mut Warnings) -> GuitarPart {
+    let string_count = part.string_count
+        .or(part.tuning.as_ref().map(Vec::len))
+        .unwrap_or(6);
+
+    let tuning = match part.tuning {
+        Some(tuning) => tuning,
+        None => DEFAULT_TUNINGS.iter()
+            .find(|tuning| tuning.len() == string_count)
+            .map(|tuning| tuning.to_vec())
+            .unwrap_or_else(|| {
+                warnings.add(format!("{}: no tuning for {} strings, using E standard", part.name, string_count));
+                DEFAULT_TUNINGS[2].to_vec()
+            }),
+    };
+
+    let mut score_notes = part.notes;
+    score_notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut merged: Vec<ScoreNote> = vec![];
+
+    for note in score_notes {
+        if merged.iter().any(|other| other.string == note.string && other.fret == note.fret && (other.start - note.start).abs() < EPSILON) {
+            continue;
+        }
+
+        // The note a tie continues may already have been extended by the same tie on another staff
+        if note.tie_stop && let Some(tied) = merged.iter_mut().rev()
+            .find(|other| other.string == note.string && other.fret == note.fret
+                && other.start < note.start - EPSILON && other.start + other.length > note.start - EPSILON) {
+            tied.length = tied.length.max(note.start + note.length - tied.start);
+            tied.slide |= note.slide;
+            continue;
+        }
+
+        merged.push(note);
+    }
+
+    let mut notes = vec![];
+
+    for (idx, note) in merged.iter().enumerate() {
+        if note.string == 0 || note.string as usize > string_count {
+            warnings.add(format!("{}: notes on strings the instrument doesn't have dropped", part.name));
+            continue;
+        }
+
+        let time = tempo.seconds(note.start);
+        let length = tempo.seconds(note.start + note.length) - time;
+        let mut technique = note.technique.clone();
+
+        if note.slide {
+            match merged[idx + 1..].iter().find(|next| next.string == note.string) {
+                Some(target) => technique.push(GuitarTechnique::Slide { to_fret: target.fret }),
+                None => warnings.add(format!("{}: slides without a note to slide to dropped", part.name)),
+            }
+        }
+
+        if let Some(bend) = &note.bend {
+            // Bends that aren't pre-bent start from the unbent pitch
+            let mut points = match bend.pre_bend {
+                true => vec![BendPoint { time_offset: Duration::ZERO, cents: bend.cents }],
+                false => vec![
+                    BendPoint { time_offset: Duration::ZERO, cents: 0 },
+                    BendPoint { time_offset: seconds(length / 2.0), cents: bend.cents },
+                ],
+            };
+
+            if bend.release {
+                points.push(BendPoint { time_offset: seconds(length * 0.75), cents: 0 });
+            }
+
+            technique.push(GuitarTechnique::Bend { points });
+        }
+
+        notes.push(GuitarNote {
+            string: (string_count - note.string as usize) as u8,
+            fret: note.fret,
+            finger: note.finger,
+            time: seconds(time),
+            length: seconds(length),
+            technique,
+        });
+    }
+
+    GuitarPart {
+        notes,
+        tuning: GuitarTuning::from(tuning),
+        capo: part.capo,
+        anchors: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::testing::{describe_notes, fixture, guitar_parts, millis};
+
+    /// A score exported by notation software: a guitar with a notation and a tablature staff, a bass
+    /// with only a tablature staff and a piano
+    fn import() -> MusicXmlImport {
+        import_musicxml(fixture("musicxml/tab.musicxml")).unwrap()
+    }
+
+    #[test]
+    fn reads_tablature_parts() {
+        let import = import();
+        let parts = guitar_parts(&import.song);
+
+        assert_eq!(parts.iter().map(|(name, _)| *name).collect::<Vec<_>>(), vec!["Electric Guitar", "Bass"]);
+        assert!(matches!(import.song.instrument_parts[0].instrument_part_type, InstrumentPartType::LeadGuitar(_)));
+        assert!(matches!(import.song.instrument_parts[1].instrument_part_type, InstrumentPartType::BassGuitar(_)));
+
+        assert_eq!(import.song.metadata.title, "Fixture Riff");
+        assert_eq!(import.song.metadata.artist, "Metalforge");
+        assert_eq!(millis(import.song.metadata.length), 4667);
+    }
+
+    #[test]
+    fn reads_staff_tuning_and_capo() {
+        let import = import();
+        let parts = guitar_parts(&import.song);
+
+        // Drop D on the tablature staff of the guitar, standard tuning on the bass
+        assert_eq!(parts[0].1.tuning.string_offsets, vec![-2, 5, 10, 15, 19, 24]);
+        assert_eq!(parts[0].1.capo, 2);
+        assert_eq!(parts[1].1.tuning.string_offsets, vec![-12, -7, -2, 3]);
+        assert_eq!(parts[1].1.capo, 0);
+    }
+
+    #[test]
+    fn merges_ties_and_staff_copies() {
+        let import = import();
+        let parts = guitar_parts(&import.song);
+
+        // Every note is written on both staves of the guitar, the half note is tied over the bar line
+        assert_eq!(describe_notes(parts[0].1), vec![
+            "0+667 0:0",
+            "667+333 3:2",
+            "1000+333 3:4 [hammer-ons]",
+            "1333+1833 4:5",
+            "3167+500 5:7",
+        ]);
+        assert_eq!(describe_notes(parts[1].1), vec!["0+2667 1:0", "2667+2000 0:5"]);
+    }
+
+    #[test]
+    fn follows_tempo_directions() {
+        let import = import();
+
+        // 90 bpm from the sound of the first direction, then a dotted quarter at 80, i.e. 120 bpm
+        let beats: Vec<(u64, usize, u8)> = import.song.beats.iter()
+            .map(|beat| (millis(beat.time), beat.measure, beat.beat_in_measure))
+            .collect();
+
+        assert_eq!(beats, vec![
+            (0, 1, 1), (667, 1, 2), (1333, 1, 3), (2000, 1, 4),
+            (2667, 2, 1), (3167, 2, 2), (3667, 2, 3), (4167, 2, 4),
+        ]);
+
+        let sections: Vec<(&str, u64)> = import.song.sections.iter().map(|section| (section.name.as_str(), millis(section.time))).collect();
+        assert_eq!(sections, vec![("Intro", 0)]);
+    }
+
+    #[test]
+    fn warns_about_dropped_content() {
+        assert_eq!(import().warnings, vec![
+            "Electric Guitar: articulations dropped (2 times)",
+            "Electric Guitar: grace notes dropped",
+            "Electric Guitar: picking hand fingerings dropped",
+            "Electric Guitar: ornaments dropped",
+            "Piano: notes without a string and fret dropped",
+            "Piano: not written as tablature, skipped",
+        ]);
+    }
+}
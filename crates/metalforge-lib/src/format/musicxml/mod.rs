@@ -0,0 +1,162 @@
+use crate::format::registry::{Confidence, SongFormat};
+use crate::format::{has_extension, path_string, same_file, ChartFiles, LoadError, SongDir};
+use crate::library::songfile::{Format, PartSummary, SongFile, SongIdBuilder};
+use crate::song::Song;
+use log::{debug, warn};
+use std::io::{Error, Read};
+use std::path::{Path, PathBuf};
+
+pub mod export;
+pub mod import;
+
+pub use export::export_musicxml;
+pub use import::{import_musicxml, MusicXmlImport};
+
+/// The extensions of MusicXML scores, uncompressed and compressed
+pub const MUSICXML_EXTENSIONS: [&str; 2] = ["musicxml", "mxl"];
+
+/// The name of the audio file played along with a score
+pub const AUDIO_FILE_NAME: &str = "song.ogg";
+
+/// The name a score is written with by `write_musicxml_song`
+pub const SCORE_FILE_NAME: &str = "score.musicxml";
+
+/// A song directory with a MusicXML score written as tablature and the song's audio. Plain `.xml`
+/// scores are recognised too, but other formats use that extension as well.
+pub struct MusicXmlFormat;
+
+impl SongFormat for MusicXmlFormat {
+    fn format(&self) -> Format {
+        Format::MusicXml
+    }
+
+    fn probe(&self, path: &Path) -> Confidence {
+        match score_path(path) {
+            Some(score) if has_extension(score.as_path(), &MUSICXML_EXTENSIONS) => Confidence::Likely,
+            Some(_) => Confidence::Possible,
+            None => Confidence::None,
+        }
+    }
+
+    fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError> {
+        load_musicxml(path)
+    }
+
+    fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError> {
+        load_musicxml_song(song_file.song_dir.as_str())
+    }
+
+    fn save(&self, song: &Song, song_path: &str, dir: &Path) -> Result<(), LoadError> {
+        Ok(write_musicxml_song(song, song_path, dir)?)
+    }
+}
+
+/// Imports the score in the specified directory and creates a library entry for it. Returns `None`
+/// if the directory doesn't contain a score, or the score has no tablature.
+pub fn load_musicxml<P: AsRef<Path>>(dir: P) -> Result<Option<SongFile>, LoadError> {
+    let Some(score) = score_path(dir.as_ref()) else {
+        return Ok(None);
+    };
+
+    let Some(song) = read_score(score.as_path())? else {
+        debug!("{:?} is not a MusicXML score", score);
+        return Ok(None);
+    };
+
+    if song.instrument_parts.is_empty() {
+        debug!("No tablature in {:?}", score);
+        return Ok(None);
+    }
+
+    let mut files = SongDir(dir.as_ref());
+    let score_name = score.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let mut id = SongIdBuilder::new();
+
+    for file_name in [score_name, AUDIO_FILE_NAME] {
+        let contents = files.open(file_name)?
+            .ok_or_else(|| LoadError::MissingFile(file_name.to_string()))?;
+
+        id.add_file(file_name, contents)?;
+    }
+
+    Ok(Some(SongFile {
+        id: id.build(),
+        format: Format::MusicXml,
+        song_dir: path_string(dir.as_ref())?,
+        song_path: files.path(AUDIO_FILE_NAME),
+        parts: song.instrument_parts.iter().map(PartSummary::from).collect(),
+        metadata: song.metadata,
+    }))
+}
+
+/// Imports the score in the specified directory
+pub fn load_musicxml_song<P: AsRef<Path>>(dir: P) -> Result<Song, LoadError> {
+    let score = score_path(dir.as_ref())
+        .ok_or_else(|| LoadError::MissingFile(format!("*.{}", MUSICXML_EXTENSIONS[0])))?;
+
+    read_score(score.as_path())?
+        .ok_or_else(|| LoadError::MissingFile(format!("*.{}", MUSICXML_EXTENSIONS[0])))
+}
+
+/// Writes the guitar and bass parts of a song as a MusicXML score into the specified directory,
+/// creating the directory if needed. The audio is copied from `song_path` unless it's already there
+/// or isn't a file.
+pub fn write_musicxml_song<P: AsRef<Path>>(song: &Song, song_path: &str, dir: P) -> Result<(), Error> {
+    debug!("Writing score to {:?}", dir.as_ref());
+
+    let score = export_musicxml(song).map_err(Error::other)?;
+
+    std::fs::create_dir_all(dir.as_ref())?;
+    std::fs::write(dir.as_ref().join(SCORE_FILE_NAME), score)?;
+
+    let audio_path = Path::new(song_path);
+    let target_path = dir.as_ref().join(AUDIO_FILE_NAME);
+
+    if audio_path.is_file() && !same_file(audio_path, target_path.as_path()) {
+        std::fs::copy(audio_path, target_path)?;
+    }
+
+    Ok(())
+}
+
+/// Imports a score, logging everything that couldn't be imported. Returns `None` for `.xml` files
+/// that aren't MusicXML at all.
+fn read_score(path: &Path) -> Result<Option<Song>, LoadError> {
+    let mut data = vec![];
+    std::fs::File::open(path)?.read_to_end(&mut data)?;
+
+    if !has_extension(path, &MUSICXML_EXTENSIONS) && !is_score(data.as_slice()) {
+        return Ok(None);
+    }
+
+    let import = import_musicxml(data).map_err(|message| LoadError::Parse {
+        file: path.display().to_string(),
+        json_path: ".".to_string(),
+        message,
+    })?;
+
+    for warning in &import.warnings {
+        warn!("{:?}: {}", path, warning);
+    }
+
+    Ok(Some(import.song))
+}
+
+/// Checks the root element of an uncompressed score
+fn is_score(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(4096)]);
+    head.contains("<score-partwise") || head.contains("<score-timewise")
+}
+
+/// The score in a song directory. Scores with a MusicXML extension are preferred over `.xml` files,
+/// and the first one in alphabetical order is used if there are several.
+fn score_path(dir: &Path) -> Option<PathBuf> {
+    let mut scores: Vec<PathBuf> = std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && has_extension(path, &[MUSICXML_EXTENSIONS[0], MUSICXML_EXTENSIONS[1], "xml"]))
+        .collect();
+
+    scores.sort_by_key(|path| (!has_extension(path, &MUSICXML_EXTENSIONS), path.clone()));
+    scores.into_iter().next()
+}
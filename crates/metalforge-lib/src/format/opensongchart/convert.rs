@@ -0,0 +1,504 @@
+use crate::format::opensongchart::arrangement::{SongBeat, SongSection, SongStructure};
+use crate::format::opensongchart::drum_part::DrumSongNotes;
+use crate::format::opensongchart::instrument_part::{CentsOffset, InstrumentType, SongChord, SongInstrumentNotes, SongNote, SongNoteTechniques, StringTuning};
+use crate::format::opensongchart::instrument_part::InstrumentPart as ChartInstrumentPart;
+use crate::format::opensongchart::keyboard_part::SongKeyboardNotes;
+use crate::format::opensongchart::song::Song as ChartSong;
+use crate::format::opensongchart::vocal_part::SongVocals;
+use crate::format::opensongchart::{OpenSongChart, Part};
+use crate::library::songfile::PartSummary;
+use crate::song::guitar::{BendPoint, GuitarNote, GuitarPart, GuitarTechnique, GuitarTuning};
+use crate::song::instrument_part::{InstrumentKind, InstrumentPart, InstrumentPartType};
+use crate::song::metadata::Metadata;
+use crate::song::{Beat, Section, Song};
+use std::time::Duration;
+
+impl From<OpenSongChart> for Song {
+    fn from(chart: OpenSongChart) -> Self {
+        // Convert beats, tracking measure and beat-within-measure. Beats before the first measure,
+        // i.e. a pickup, are counted as part of the first measure
+        let mut measure = 0usize;
+        let mut beat_in_measure = 1u8;
+        let beats: Vec<Beat> = chart.arrangement.beats.iter().map(|b| {
+            if b.is_measure {
+                measure += 1;
+                beat_in_measure = 1;
+            } else {
+                beat_in_measure = beat_in_measure.saturating_add(1);
+            }
+            Beat {
+                time: Duration::from_secs_f32(b.time_offset),
+                measure: measure.max(1),
+                beat_in_measure,
+            }
+        }).collect();
+
+        let sections = chart.arrangement.sections.iter()
+            .map(|section| Section {
+                name: section.name.clone(),
+                time: Duration::from_secs_f32(section.start_time.unwrap_or(0.0).max(0.0)),
+            })
+            .collect();
+
+        let mut instrument_parts: Vec<InstrumentPart> = vec![];
+
+        for part_def in chart.song.instrument_parts.iter() {
+            let matched_part = chart.instrument_parts.iter()
+                .find(|p| p.has_part_id(part_def.instrument_name.as_str()));
+
+            let instrument_type = match &part_def.instrument_type {
+                guitar_type @ (InstrumentType::LeadGuitar | InstrumentType::RhythmGuitar | InstrumentType::BassGuitar) => {
+                    let notes_data = matched_part.and_then(|p| {
+                        if let Part::InstrumentPart(_, notes) = p { Some(notes) } else { None }
+                    });
+
+                    let tuning = guitar_tuning(part_def);
+
+                    let guitar_notes = notes_data.map(|nd| {
+                        nd.notes.iter().filter_map(|note| {
+                            let string = note.string? as u8;
+                            let fret_raw = note.fret?;
+                            if fret_raw < 0 { return None; }
+                            let fret = fret_raw as u8;
+                            let time = Duration::from_secs_f32(note.time_offset.unwrap_or(0.0));
+                            let length = Duration::from_secs_f32(note.time_length.unwrap_or(0.1));
+
+                            // Resolve finger: finger_id indexes into chords array, then index by string.
+                            // Strings without a finger are -1
+                            let finger = note.finger_id
+                                .and_then(|fid| nd.chords.get(fid as usize))
+                                .and_then(|chord| chord.fingers.get(string as usize))
+                                .and_then(|finger| u8::try_from(*finger).ok());
+
+                            let technique = note.techniques.iter()
+                                .filter_map(|t| map_technique(t, note))
+                                .collect();
+
+                            Some(GuitarNote { string, fret, finger, time, length, technique })
+                        }).collect::<Vec<_>>()
+                    }).unwrap_or_default();
+
+                    let guitar_part = GuitarPart {
+                        notes: guitar_notes,
+                        tuning,
+                        capo: part_def.capo_fret.max(0) as u8,
+                        anchors: vec![],
+                    };
+
+                    match guitar_type {
+                        InstrumentType::LeadGuitar => Some(InstrumentPartType::LeadGuitar(guitar_part)),
+                        InstrumentType::RhythmGuitar => Some(InstrumentPartType::RhythmGuitar(guitar_part)),
+                        InstrumentType::BassGuitar => Some(InstrumentPartType::BassGuitar(guitar_part)),
+                        _ => unreachable!()
+                    }
+                }
+                InstrumentType::Keys | InstrumentType::Drums | InstrumentType::Vocals => None,
+            };
+
+            if let Some(t) = instrument_type {
+                instrument_parts.push(InstrumentPart {
+                    name: part_def.instrument_name.clone(),
+                    instrument_part_type: t,
+                });
+            }
+        }
+
+        Song {
+            metadata: metadata(&chart.song),
+            instrument_parts,
+            beats,
+            sections,
+            a440_offset_cents: chart.song.a440_cent_offset,
+        }
+    }
+}
+
+/// Converts a song back into an OpenSongChart, e.g. to save a song imported from another format. Notes
+/// starting together are stored as chords, and fingerings are kept in the chords of the part as
+/// well. The chart doesn't refer to any audio, `song_path` is left empty.
+impl From<&Song> for OpenSongChart {
+    fn from(song: &Song) -> Self {
+        let sections: Vec<SongSection> = song.sections.iter().enumerate()
+            .map(|(idx, section)| SongSection {
+                name: section.name.clone(),
+                start_time: Some(section.time.as_secs_f32()),
+                end_time: Some(song.sections.get(idx + 1)
+                    .map(|next| next.time)
+                    .unwrap_or(song.metadata.length)
+                    .as_secs_f32()),
+            })
+            .collect();
+
+        let arrangement = SongStructure {
+            sections: sections.iter().map(copy_section).collect(),
+            beats: song.beats.iter()
+                .map(|beat| SongBeat {
+                    time_offset: beat.time.as_secs_f32(),
+                    is_measure: beat.beat_in_measure == 1,
+                    end_time: None,
+                })
+                .collect(),
+        };
+
+        let mut part_defs = vec![];
+        let mut parts = vec![];
+
+        for part in &song.instrument_parts {
+            let part_id = part.name.clone();
+
+            let (instrument_type, guitar_part) = match &part.instrument_part_type {
+                InstrumentPartType::LeadGuitar(guitar_part) => (InstrumentType::LeadGuitar, Some(guitar_part)),
+                InstrumentPartType::RhythmGuitar(guitar_part) => (InstrumentType::RhythmGuitar, Some(guitar_part)),
+                InstrumentPartType::BassGuitar(guitar_part) => (InstrumentType::BassGuitar, Some(guitar_part)),
+                InstrumentPartType::Keyboard => (InstrumentType::Keys, None),
+                InstrumentPartType::Drums => (InstrumentType::Drums, None),
+                InstrumentPartType::Vocals => (InstrumentType::Vocals, None),
+            };
+
+            part_defs.push(ChartInstrumentPart {
+                instrument_name: part_id.clone(),
+                instrument_type,
+                arrangement_name: None,
+                song_audio: None,
+                song_stem: None,
+                tuning: guitar_part.map(|guitar_part| StringTuning {
+                    string_semitone_offsets: guitar_part.tuning.string_offsets.iter().map(|&offset| offset as i16).collect(),
+                }),
+                capo_fret: guitar_part.map(|guitar_part| guitar_part.capo as i16).unwrap_or(0),
+                song_difficulty: 0.0,
+            });
+
+            let sections = sections.iter().map(copy_section).collect();
+
+            parts.push(match &part.instrument_part_type {
+                InstrumentPartType::LeadGuitar(guitar_part) |
+                InstrumentPartType::RhythmGuitar(guitar_part) |
+                InstrumentPartType::BassGuitar(guitar_part) => Part::InstrumentPart(part_id, guitar_notes(guitar_part, sections)),
+                InstrumentPartType::Keyboard => Part::KeyboardPart(part_id, SongKeyboardNotes { sections, notes: vec![] }),
+                InstrumentPartType::Drums => Part::DrumPart(part_id, DrumSongNotes { sections, notes: vec![] }),
+                InstrumentPartType::Vocals => Part::VocalPart(part_id, SongVocals { vocals: vec![] }),
+            });
+        }
+
+        OpenSongChart {
+            song: ChartSong {
+                song_name: song.metadata.title.clone(),
+                artist_name: song.metadata.artist.clone(),
+                album_name: song.metadata.album.clone(),
+                song_year: song.metadata.year as i16,
+                song_length_seconds: song.metadata.length.as_secs_f32(),
+                a440_cent_offset: song.a440_offset_cents,
+                preview_start_seconds: song.metadata.preview_start.map(|start| start.as_secs_f32()),
+                instrument_parts: part_defs,
+            },
+            arrangement,
+            instrument_parts: parts,
+            song_path: String::new(),
+        }
+    }
+}
+
+fn copy_section(section: &SongSection) -> SongSection {
+    SongSection {
+        name: section.name.clone(),
+        start_time: section.start_time,
+        end_time: section.end_time,
+    }
+}
+
+/// Converts the notes of a guitar part. Notes starting at the same time share a chord listing the
+/// fret and finger of each string, which is how the chart records fingerings. A single note only
+/// gets a chord of its own if it says which finger it's played with.
+fn guitar_notes(part: &GuitarPart, sections: Vec<SongSection>) -> SongInstrumentNotes {
+    let string_count = part.notes.iter()
+        .map(|note| note.string as usize + 1)
+        .chain(std::iter::once(part.tuning.string_offsets.len()))
+        .max()
+        .unwrap_or(0);
+
+    let mut chords: Vec<SongChord> = vec![];
+    let mut notes = vec![];
+
+    for group in part.notes.chunk_by(|a, b| a.time == b.time) {
+        let is_chord = group.len() > 1;
+        let has_fingering = group.iter().any(|note| note.finger.is_some());
+
+        let chord_idx = (is_chord || has_fingering).then(|| {
+            let mut chord = SongChord {
+                name: String::new(),
+                fingers: vec![-1; string_count],
+                frets: vec![-1; string_count],
+            };
+
+            for note in group {
+                chord.frets[note.string as usize] = note.fret as i8;
+                chord.fingers[note.string as usize] = note.finger.map(|finger| finger as i8).unwrap_or(-1);
+            }
+
+            match chords.iter().position(|existing| existing.frets == chord.frets && existing.fingers == chord.fingers) {
+                Some(idx) => idx as i16,
+                None => {
+                    chords.push(chord);
+                    (chords.len() - 1) as i16
+                }
+            }
+        });
+
+        for note in group {
+            let mut techniques: Vec<SongNoteTechniques> = note.technique.iter().map(chart_technique).collect();
+
+            if is_chord {
+                techniques.push(SongNoteTechniques::ChordNote);
+            }
+
+            let slide_fret = note.technique.iter().find_map(|technique| match technique {
+                GuitarTechnique::Slide { to_fret } => Some(*to_fret as i8),
+                _ => None,
+            });
+
+            let cents_offset = note.technique.iter()
+                .find_map(|technique| match technique {
+                    GuitarTechnique::Bend { points } => Some(points.iter()
+                        .map(|point| CentsOffset { time_offset: point.time_offset.as_secs_f32(), cents: point.cents })
+                        .collect()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+
+            notes.push(SongNote {
+                time_offset: Some(note.time.as_secs_f32()),
+                time_length: Some(note.length.as_secs_f32()),
+                fret: Some(note.fret as i8),
+                string: Some(note.string as i8),
+                cents_offset,
+                techniques,
+                hand_fret: None,
+                slide_fret,
+                chord_id: chord_idx.filter(|_| is_chord),
+                finger_id: chord_idx,
+            });
+        }
+    }
+
+    SongInstrumentNotes { sections, chords, notes }
+}
+
+fn chart_technique(technique: &GuitarTechnique) -> SongNoteTechniques {
+    match technique {
+        GuitarTechnique::HammerOn => SongNoteTechniques::HammerOn,
+        GuitarTechnique::PullOff => SongNoteTechniques::PullOff,
+        GuitarTechnique::PalmMute => SongNoteTechniques::PalmMute,
+        GuitarTechnique::FretHandMute => SongNoteTechniques::FretHandMute,
+        GuitarTechnique::Slide { .. } => SongNoteTechniques::Slide,
+        GuitarTechnique::Bend { .. } => SongNoteTechniques::Bend,
+        GuitarTechnique::Tremolo => SongNoteTechniques::Tremolo,
+        GuitarTechnique::Vibrato => SongNoteTechniques::Vibrato,
+        GuitarTechnique::Harmonic => SongNoteTechniques::Harmonic,
+        GuitarTechnique::PinchHarmonic => SongNoteTechniques::PinchHarmonic,
+        GuitarTechnique::Tap => SongNoteTechniques::Tap,
+        GuitarTechnique::Slap => SongNoteTechniques::Slap,
+        GuitarTechnique::Pop => SongNoteTechniques::Pop,
+    }
+}
+
+impl From<&ChartInstrumentPart> for PartSummary {
+    fn from(part_def: &ChartInstrumentPart) -> Self {
+        let kind = instrument_kind(&part_def.instrument_type);
+
+        let tuning = match kind {
+            InstrumentKind::LeadGuitar | InstrumentKind::RhythmGuitar | InstrumentKind::BassGuitar => Some(guitar_tuning(part_def)),
+            InstrumentKind::Keyboard | InstrumentKind::Drums | InstrumentKind::Vocals => None,
+        };
+
+        PartSummary {
+            name: part_def.instrument_name.clone(),
+            kind,
+            tuning,
+            capo: part_def.capo_fret.max(0) as u8,
+            difficulty: part_def.song_difficulty,
+        }
+    }
+}
+
+pub(crate) fn metadata(song: &ChartSong) -> Metadata {
+    Metadata {
+        title: song.song_name.clone(),
+        artist: song.artist_name.clone(),
+        album: song.album_name.clone(),
+        year: song.song_year as u16,
+        length: Duration::from_secs_f32(song.song_length_seconds),
+        key: None,
+        preview_start: song.preview_start_seconds
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+            .map(Duration::from_secs_f32)
+    }
+}
+
+fn instrument_kind(instrument_type: &InstrumentType) -> InstrumentKind {
+    match instrument_type {
+        InstrumentType::LeadGuitar => InstrumentKind::LeadGuitar,
+        InstrumentType::RhythmGuitar => InstrumentKind::RhythmGuitar,
+        InstrumentType::BassGuitar => InstrumentKind::BassGuitar,
+        InstrumentType::Keys => InstrumentKind::Keyboard,
+        InstrumentType::Drums => InstrumentKind::Drums,
+        InstrumentType::Vocals => InstrumentKind::Vocals,
+    }
+}
+
+fn guitar_tuning(part_def: &ChartInstrumentPart) -> GuitarTuning {
+    part_def.tuning.as_ref()
+        .map(|t| GuitarTuning {
+            string_offsets: t.string_semitone_offsets.iter().map(|&v| v as i8).collect()
+        })
+        .unwrap_or_else(|| GuitarTuning { string_offsets: vec![0, 5, 10, 15, 19, 24] })
+}
+
+fn map_technique(t: &SongNoteTechniques, note: &SongNote) -> Option<GuitarTechnique> {
+    match t {
+        SongNoteTechniques::HammerOn => Some(GuitarTechnique::HammerOn),
+        SongNoteTechniques::PullOff => Some(GuitarTechnique::PullOff),
+        SongNoteTechniques::PalmMute => Some(GuitarTechnique::PalmMute),
+        SongNoteTechniques::FretHandMute => Some(GuitarTechnique::FretHandMute),
+        SongNoteTechniques::Slide => {
+            let to_fret = note.slide_fret.unwrap_or(0).max(0) as u8;
+            Some(GuitarTechnique::Slide { to_fret })
+        }
+        SongNoteTechniques::Bend => {
+            let points = note.cents_offset.iter().map(|co| BendPoint {
+                time_offset: Duration::from_secs_f32(co.time_offset),
+                cents: co.cents,
+            }).collect();
+            Some(GuitarTechnique::Bend { points })
+        }
+        SongNoteTechniques::Tremolo => Some(GuitarTechnique::Tremolo),
+        SongNoteTechniques::Vibrato => Some(GuitarTechnique::Vibrato),
+        SongNoteTechniques::Harmonic => Some(GuitarTechnique::Harmonic),
+        SongNoteTechniques::PinchHarmonic => Some(GuitarTechnique::PinchHarmonic),
+        SongNoteTechniques::Tap => Some(GuitarTechnique::Tap),
+        SongNoteTechniques::Slap => Some(GuitarTechnique::Slap),
+        SongNoteTechniques::Pop => Some(GuitarTechnique::Pop),
+        // These no longer exist in the native format — drop them
+        SongNoteTechniques::Chord
+        | SongNoteTechniques::ChordNote
+        | SongNoteTechniques::Continued
+        | SongNoteTechniques::Arpeggio
+        | SongNoteTechniques::Accent => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::opensongchart::read_chart;
+    use crate::format::testing::{self, bend, describe_notes, guitar_part, guitar_parts, part};
+    use crate::format::SongDir;
+    use std::path::Path;
+
+    /// A song using every technique, with chords and a fingering on some of the notes
+    fn song() -> Song {
+        let note = |time_millis, string, fret, finger, technique| testing::note(time_millis, 250, string, fret, finger, technique);
+        let bend = bend(&[(0, 0), (125, 200), (250, 0)]);
+
+        let lead = guitar_part(vec![-2, 5, 10, 15, 19, 24], 2, vec![
+            note(500, 0, 3, None, vec![GuitarTechnique::HammerOn, GuitarTechnique::PalmMute]),
+            note(1000, 0, 0, Some(1), vec![GuitarTechnique::FretHandMute]),
+            note(1000, 1, 2, Some(3), vec![]),
+            note(1000, 2, 2, None, vec![]),
+            note(1500, 3, 7, Some(2), vec![GuitarTechnique::Slide { to_fret: 9 }, GuitarTechnique::Vibrato]),
+            note(2000, 2, 5, None, vec![bend, GuitarTechnique::Tremolo, GuitarTechnique::PinchHarmonic]),
+            note(2500, 1, 12, Some(0), vec![GuitarTechnique::PullOff, GuitarTechnique::Harmonic, GuitarTechnique::Tap]),
+            note(3000, 0, 0, Some(1), vec![]),
+            note(3000, 1, 2, Some(3), vec![]),
+            note(3000, 2, 2, None, vec![]),
+        ]);
+
+        let bass = guitar_part(vec![-12, -7, -2, 3], 0, vec![
+            note(500, 0, 0, None, vec![GuitarTechnique::Slap]),
+            note(1000, 1, 2, None, vec![GuitarTechnique::Pop]),
+        ]);
+
+        let mut song = testing::song(vec![
+            part("lead", InstrumentPartType::LeadGuitar(lead)),
+            part("rhythm", InstrumentPartType::RhythmGuitar(guitar_part(vec![0, 5, 10, 15, 19, 24], 0, vec![]))),
+            part("bass", InstrumentPartType::BassGuitar(bass)),
+        ]);
+
+        song.metadata.album = "Fixtures".to_string();
+        song.metadata.year = 2024;
+        song.metadata.preview_start = Some(Duration::from_secs(2));
+        song.sections = vec![
+            Section { name: "intro".to_string(), time: Duration::ZERO },
+            Section { name: "verse".to_string(), time: Duration::from_secs(2) },
+        ];
+        song.a440_offset_cents = -12.5;
+        song
+    }
+
+    #[test]
+    fn song_round_trips_through_chart() {
+        let song = song();
+        let chart = OpenSongChart::from(&song);
+
+        let Part::InstrumentPart(_, lead) = &chart.instrument_parts[0] else {
+            panic!("lead part isn't a guitar part");
+        };
+
+        // The two chords with the same fingering share it, the other fingered notes have their own
+        assert_eq!(lead.chords.iter().map(|chord| chord.fingers.clone()).collect::<Vec<_>>(), vec![
+            vec![1, 3, -1, -1, -1, -1],
+            vec![-1, -1, -1, 2, -1, -1],
+            vec![-1, 0, -1, -1, -1, -1],
+        ]);
+
+        let measures: Vec<bool> = chart.arrangement.beats.iter().map(|beat| beat.is_measure).collect();
+        assert_eq!(measures, vec![true, false, false, false, true, false, false, false]);
+
+        let round_trip = Song::from(chart);
+
+        assert_eq!(serde_json::to_value(&round_trip).unwrap(), serde_json::to_value(&song).unwrap());
+    }
+
+    #[test]
+    fn chart_round_trips_through_song() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/opensongchart");
+        let read = || read_chart(&mut SongDir(fixture.as_path())).unwrap().unwrap();
+
+        let song = Song::from(read());
+        let (_, lead) = guitar_parts(&song)[0];
+
+        assert_eq!(lead.tuning.string_offsets, vec![-2, 5, 10, 15, 19, 24]);
+        assert_eq!(lead.capo, 2);
+        assert_eq!(describe_notes(lead), vec![
+            "250+500 0:3",
+            "1000+1000 3:7 [hammer-ons, palm mutes, slide to 9, bend 0@0 200@500, vibrato]",
+            "2000+0 0:0 finger 1 [palm mutes]",
+            "3000+100 1:12 [pull-offs, fret-hand mutes, tremolo, harmonics, pinch harmonics, taps, slaps, pops]",
+        ]);
+
+        let measures: Vec<(usize, u8)> = song.beats.iter().map(|beat| (beat.measure, beat.beat_in_measure)).collect();
+        assert_eq!(measures, vec![(1, 1), (1, 2), (2, 1)]);
+
+        let chart = OpenSongChart::from(&song);
+        let original = read();
+
+        assert_eq!(chart.song.song_name, original.song.song_name);
+        assert_eq!(chart.song.song_length_seconds, original.song.song_length_seconds);
+        assert_eq!(chart.song.a440_cent_offset, original.song.a440_cent_offset);
+        assert_eq!(chart.song.preview_start_seconds, original.song.preview_start_seconds);
+
+        let beats = |chart: &OpenSongChart| chart.arrangement.beats.iter().map(|beat| (beat.time_offset, beat.is_measure)).collect::<Vec<_>>();
+        assert_eq!(beats(&chart), beats(&original));
+
+        let lead_notes = |chart: &OpenSongChart| match &chart.instrument_parts[0] {
+            Part::InstrumentPart(_, notes) => notes.notes.iter()
+                .map(|note| (note.time_offset, note.string, note.fret, note.slide_fret, note.cents_offset.iter().map(|offset| offset.cents).collect::<Vec<_>>()))
+                .collect::<Vec<_>>(),
+            _ => panic!("lead part isn't a guitar part"),
+        };
+
+        assert_eq!(lead_notes(&chart), lead_notes(&original));
+        assert_eq!(chart.song.instrument_parts[0].tuning.as_ref().map(|tuning| tuning.string_semitone_offsets.clone()), Some(vec![-2, 5, 10, 15, 19, 24]));
+        assert_eq!(chart.song.instrument_parts[0].capo_fret, 2);
+    }
+}
@@ -99,7 +99,7 @@ pub struct SongNote {
     pub cents_offset: Vec<CentsOffset>,
 
     /// Song technique flags
-    #[serde(rename = "Techniques", with = "song_techniques", default)]
+    #[serde(rename = "Techniques", with = "song_techniques", default, skip_serializing_if = "Vec::is_empty")]
     pub techniques: Vec<SongNoteTechniques>,
 
     /// Bottom fret of hand position
@@ -125,22 +125,34 @@ impl SongNote {
     //public float EndTime => TimeOffset + TimeLength;
 }
 
+/// Techniques are stored as a single string listing the names of the technique flags, separated by
+/// commas, e.g. "HammerOn, PalmMute"
 mod song_techniques {
     use log::warn;
+    use serde::ser::Error;
     use serde::{Deserialize, Deserializer, Serializer};
     use crate::format::opensongchart::instrument_part::SongNoteTechniques;
 
+    /// The separator written between technique names
+    const SEPARATOR: &str = ", ";
+
     pub fn serialize<S>(
-        _techniques: &Vec<SongNoteTechniques>,
-        _serializer: S,
+        techniques: &[SongNoteTechniques],
+        serializer: S,
     ) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        // let s = format!("{}", date.format(FORMAT));
-        // let s = techniques.iter().map(|technique| serde_json::to_string(technique)).collect().join(",");
-        // serializer.serialize_str(&s)
-        unimplemented!()
+        let mut names = vec![];
+
+        for technique in techniques {
+            match serde_json::to_value(technique).map_err(S::Error::custom)? {
+                serde_json::Value::String(name) => names.push(name),
+                value => return Err(S::Error::custom(format!("technique is not a name: {}", value))),
+            }
+        }
+
+        serializer.serialize_str(names.join(SEPARATOR).as_str())
     }
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<SongNoteTechniques>, D::Error> where D: Deserializer<'de> {
@@ -148,8 +160,8 @@ mod song_techniques {
 
         let mut techniques = vec![];
 
-        for part in s.split(",") {
-            let quoted_part = format!("\"{}\"", part.trim());
+        for part in s.split(",").map(str::trim).filter(|part| !part.is_empty() && *part != "None") {
+            let quoted_part = format!("\"{}\"", part);
             let tq_result: serde_json::Result<SongNoteTechniques> = serde_json::from_str(quoted_part.as_str());
 
             if let Ok(technique) = tq_result {
@@ -3,12 +3,16 @@ use crate::format::opensongchart::drum_part::DrumSongNotes;
 use crate::format::opensongchart::instrument_part::{InstrumentType, SongInstrumentNotes};
 use crate::format::opensongchart::keyboard_part::SongKeyboardNotes;
 use crate::format::opensongchart::song::Song;
-use crate::format::opensongchart::vocal_part::{SongVocal, SongVocals};
-use crate::library::songfile::SongFile;
+use crate::format::opensongchart::vocal_part::SongVocals;
+use crate::format::opensongchart::convert::metadata;
+use crate::format::registry::{Confidence, SongFormat};
+use crate::format::{path_string, same_file, ChartFiles, LoadError, SongDir};
+use crate::library::songfile::{Format, PartSummary, SongFile, SongId, SongIdBuilder};
 use log::debug;
-use std::fs::File;
-use std::io::{BufReader, Error};
+use serde::de::DeserializeOwned;
+use std::io::{Error, ErrorKind};
 use std::path::Path;
+use crate::song::Song as NativeSong;
 
 pub mod song;
 pub mod arrangement;
@@ -16,6 +20,7 @@ pub mod instrument_part;
 pub mod vocal_part;
 pub mod keyboard_part;
 pub mod drum_part;
+pub mod convert;
 
 pub trait SongEvent {}
 
@@ -35,98 +40,323 @@ pub enum Part {
 
 impl Part {
     pub fn has_part_id(&self, id: &str) -> bool {
-        let part_id = match self {
+        self.part_id() == id
+    }
+
+    pub fn part_id(&self) -> &str {
+        match self {
             Part::InstrumentPart(part_id, _) => part_id.as_str(),
             Part::KeyboardPart(part_id, _) => part_id.as_str(),
             Part::DrumPart(part_id, _) => part_id.as_str(),
             Part::VocalPart(part_id, _) => part_id.as_str(),
-        };
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Result<Vec<u8>> {
+        match self {
+            Part::InstrumentPart(_, notes) => serde_json::to_vec_pretty(notes),
+            Part::KeyboardPart(_, notes) => serde_json::to_vec_pretty(notes),
+            Part::DrumPart(_, notes) => serde_json::to_vec_pretty(notes),
+            Part::VocalPart(_, vocals) => serde_json::to_vec_pretty(vocals),
+        }
+    }
+}
+
+impl OpenSongChart {
+
+    /// Serializes the chart into the files of an OpenSongChart directory: `song.json`,
+    /// `arrangement.json` and a file for each part, named after the part. Parsing the files and
+    /// serializing the chart again gives exactly the same files.
+    pub fn to_files(&self) -> serde_json::Result<Vec<(String, Vec<u8>)>> {
+        let mut files = vec![
+            ("song.json".to_string(), serde_json::to_vec_pretty(&self.song)?),
+            ("arrangement.json".to_string(), serde_json::to_vec_pretty(&self.arrangement)?),
+        ];
+
+        for part in &self.instrument_parts {
+            files.push((format!("{}.json", part.part_id()), part.to_json()?));
+        }
+
+        Ok(files)
+    }
+}
+
+/// Writes a chart into the specified directory, creating the directory if needed. The audio is copied
+/// from the chart's `song_path` into `song.ogg`, unless it's already there or isn't a file of its own,
+/// e.g. when the chart was read from a song package.
+pub fn write_open_song_chart<P: AsRef<Path>>(chart: &OpenSongChart, dir: P) -> Result<(), Error> {
+    debug!("Writing OpenSongChart to {:?}", dir.as_ref());
+
+    std::fs::create_dir_all(dir.as_ref())?;
+
+    for (file_name, contents) in chart.to_files().map_err(Error::other)? {
+        std::fs::write(dir.as_ref().join(file_name), contents)?;
+    }
+
+    let audio_path = Path::new(chart.song_path.as_str());
+    let target_path = dir.as_ref().join("song.ogg");
+
+    if audio_path.is_file() && !same_file(audio_path, target_path.as_path()) {
+        std::fs::copy(audio_path, target_path)?;
+    }
+
+    Ok(())
+}
+
+/// A song directory with a `song.json`, an `arrangement.json` and a file for each part
+pub struct OpenSongChartFormat;
+
+impl SongFormat for OpenSongChartFormat {
+    fn format(&self) -> Format {
+        Format::OpenSongChart
+    }
 
-        part_id == id
+    fn probe(&self, path: &Path) -> Confidence {
+        if path.join("song.json").is_file() {
+            Confidence::Likely
+        } else {
+            Confidence::None
+        }
+    }
+
+    fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError> {
+        load_open_song_chart(path)
+    }
+
+    fn load_song(&self, song_file: &SongFile) -> Result<NativeSong, LoadError> {
+        load_open_song_chart_song(song_file.song_dir.as_str())
+    }
+
+    fn save(&self, song: &NativeSong, song_path: &str, dir: &Path) -> Result<(), LoadError> {
+        let mut chart = OpenSongChart::from(song);
+        chart.song_path = song_path.to_string();
+
+        Ok(write_open_song_chart(&chart, dir)?)
+    }
+}
+
+/// Reads only `song.json` from the specified directory and creates a library entry from it, if the
+/// directory contains a complete OpenSongChart. The individual part files are not parsed.
+pub fn load_open_song_chart<P: AsRef<Path>>(dir: P) -> Result<Option<SongFile>, LoadError> {
+    debug!("Summarising name=\"{:?}\"", dir.as_ref());
+
+    if !dir.as_ref().is_dir() {
+        return Ok(None);
+    }
+
+    let mut files = SongDir(dir.as_ref());
+
+    let Some((song, id)) = summarize_chart(&mut files)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(SongFile {
+        id,
+        format: Format::OpenSongChart,
+        song_dir: path_string(dir.as_ref())?,
+        song_path: files.path("song.ogg"),
+        metadata: metadata(&song),
+        parts: song.instrument_parts.iter().map(PartSummary::from).collect(),
+    }))
+}
+
+/// Reads `song.json` and checks that the files required by the chart are present. Returns the song
+/// along with its ID, which is derived from every file of the chart, or `None` if there is no
+/// `song.json` at all.
+pub(crate) fn summarize_chart(files: &mut impl ChartFiles) -> Result<Option<(Song, SongId)>, LoadError> {
+    if files.open("song.json")?.is_none() {
+        return Ok(None);
+    }
+
+    let song: Song = read_json(files, "song.json")?;
+    require_file(files, "arrangement.json")?;
+    require_file(files, "song.ogg")?;
+
+    // Part files are optional, only the ones present contribute to the ID
+    let mut file_names = vec!["song.json".to_string(), "arrangement.json".to_string()];
+    file_names.extend(song.instrument_parts.iter().map(|part| format!("{}.json", part.instrument_name.as_str())));
+    file_names.push("song.ogg".to_string());
+
+    let mut id = SongIdBuilder::new();
+
+    for file_name in file_names {
+        if let Some(contents) = files.open(file_name.as_str())? {
+            id.add_file(file_name.as_str(), contents)?;
+        }
     }
+
+    Ok(Some((song, id.build())))
 }
 
-pub fn load_open_song_chart<P: AsRef<Path>>(dir: P) -> Result<Option<SongFile>, Error> {
-    scan_directory(dir).map(|maybe_chart|
-        maybe_chart.map(|chart| {
-            SongFile::from(chart)
-        })
-    )
+/// Parses the complete chart in the specified directory, including every instrument part
+pub fn load_open_song_chart_song<P: AsRef<Path>>(dir: P) -> Result<crate::song::Song, LoadError> {
+    scan_directory(dir.as_ref())?
+        .map(crate::song::Song::from)
+        .ok_or_else(|| LoadError::Io(Error::new(ErrorKind::NotFound, format!("No OpenSongChart found at {:?}", dir.as_ref()))))
 }
 
-pub fn scan_directory<P: AsRef<Path>>(path: P) -> Result<Option<OpenSongChart>, Error> {
+pub fn scan_directory<P: AsRef<Path>>(path: P) -> Result<Option<OpenSongChart>, LoadError> {
     debug!("Scanning name=\"{:?}\" exists={:?}", path.as_ref(), path.as_ref().exists());
 
     if path.as_ref().is_dir() {
-        let mut song_json_path = Path::to_path_buf(path.as_ref());
-        song_json_path.push("song.json");
-
-        debug!("Reading song.json");
-
-        if std::fs::exists(song_json_path.as_path())? {
-            let song_reader = BufReader::new(File::open(song_json_path)?);
-            let song: Song = serde_json::from_reader(song_reader)?;
-
-            // song.json has been read and parsed, look for arrangement.json
-            let mut arrangement_json_path = Path::to_path_buf(path.as_ref());
-            arrangement_json_path.push("arrangement.json");
-
-            debug!("Reading arrangement.json");
-
-            if std::fs::exists(arrangement_json_path.as_path())? {
-                let arrangement_reader = BufReader::new(File::open(arrangement_json_path)?);
-                let arrangement: SongStructure = serde_json::from_reader(arrangement_reader)?;
-
-                let mut parts = vec![];
-
-                for part in &song.instrument_parts {
-                    let mut part_path = Path::to_path_buf(path.as_ref());
-                    part_path.push(format!("{}.json", part.instrument_name.as_str()));
-
-                    debug!("Reading {}.json", part.instrument_name.as_str());
-
-                    if std::fs::exists(part_path.as_path())? {
-                        let part_reader = BufReader::new(File::open(part_path)?);
-                        let part_id = part.instrument_name.clone();
-
-                        let part = match part.instrument_type {
-                            InstrumentType::LeadGuitar | InstrumentType::RhythmGuitar | InstrumentType::BassGuitar => {
-                                Part::InstrumentPart(part_id, serde_json::from_reader(part_reader)?)
-                            }
-                            InstrumentType::Keys => {
-                                Part::KeyboardPart(part_id, serde_json::from_reader(part_reader)?)
-                            }
-                            InstrumentType::Drums => {
-                                Part::DrumPart(part_id, serde_json::from_reader(part_reader)?)
-                            }
-                            InstrumentType::Vocals => {
-                                let vocals: Vec<SongVocal> = serde_json::from_reader(part_reader)?;
-                                Part::VocalPart(part_id, SongVocals { vocals })
-                            }
-                        };
-
-                        parts.push(part);
-                    }
-                }
+        read_chart(&mut SongDir(path.as_ref()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parses the complete chart from the files of a song, or returns `None` if there is no `song.json`
+pub(crate) fn read_chart(files: &mut impl ChartFiles) -> Result<Option<OpenSongChart>, LoadError> {
+    debug!("Reading song.json");
+
+    if files.open("song.json")?.is_none() {
+        return Ok(None);
+    }
+
+    let song: Song = read_json(files, "song.json")?;
+
+    // song.json has been read and parsed, look for arrangement.json
+    debug!("Reading arrangement.json");
 
-                debug!("Reading song.ogg");
+    require_file(files, "arrangement.json")?;
+    let arrangement: SongStructure = read_json(files, "arrangement.json")?;
 
-                let mut ogg_path = Path::to_path_buf(path.as_ref());
-                ogg_path.push("song.ogg");
+    let mut parts = vec![];
 
-                if std::fs::exists(ogg_path.as_path())? {
-                    return Ok(Some(OpenSongChart {
-                        song,
-                        arrangement,
-                        instrument_parts: parts,
-                        song_path: ogg_path.to_str().unwrap().to_string(),
-                    }));
+    for part in &song.instrument_parts {
+        let part_file = format!("{}.json", part.instrument_name.as_str());
+
+        debug!("Reading {}", part_file);
+
+        if files.open(part_file.as_str())?.is_some() {
+            let part_id = part.instrument_name.clone();
+
+            let part = match part.instrument_type {
+                InstrumentType::LeadGuitar | InstrumentType::RhythmGuitar | InstrumentType::BassGuitar => {
+                    Part::InstrumentPart(part_id, read_json(files, part_file.as_str())?)
+                }
+                InstrumentType::Keys => {
+                    Part::KeyboardPart(part_id, read_json(files, part_file.as_str())?)
                 }
-            }
+                InstrumentType::Drums => {
+                    Part::DrumPart(part_id, read_json(files, part_file.as_str())?)
+                }
+                InstrumentType::Vocals => {
+                    Part::VocalPart(part_id, read_json(files, part_file.as_str())?)
+                }
+            };
+
+            parts.push(part);
         }
+    }
 
-        Ok(None)
-    } else {
-        Ok(None)
+    debug!("Reading song.ogg");
+
+    require_file(files, "song.ogg")?;
+
+    Ok(Some(OpenSongChart {
+        song,
+        arrangement,
+        instrument_parts: parts,
+        song_path: files.path("song.ogg"),
+    }))
+}
+
+/// Parses a JSON chart file, keeping track of where in the document parsing failed
+fn read_json<T: DeserializeOwned>(files: &mut impl ChartFiles, file_name: &str) -> Result<T, LoadError> {
+    let file = files.path(file_name);
+    let reader = files.open(file_name)?.ok_or_else(|| LoadError::MissingFile(file_name.to_string()))?;
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|error| LoadError::Parse {
+        file,
+        json_path: error.path().to_string(),
+        message: error.inner().to_string(),
+    })
+}
+
+/// Checks that a file required by the chart is one of the song's files
+fn require_file(files: &mut impl ChartFiles, file_name: &str) -> Result<(), LoadError> {
+    match files.open(file_name)? {
+        Some(_) => Ok(()),
+        None => Err(LoadError::MissingFile(file_name.to_string())),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/opensongchart");
+
+    /// The files of a chart written by `to_files`
+    struct WrittenFiles(Vec<(String, Vec<u8>)>);
+
+    impl ChartFiles for WrittenFiles {
+        fn open(&mut self, file_name: &str) -> Result<Option<Box<dyn Read + '_>>, LoadError> {
+            let contents = match file_name {
+                "song.ogg" => Some(&[][..]),
+                _ => self.0.iter().find(|(name, _)| name == file_name).map(|(_, contents)| contents.as_slice()),
+            };
+
+            Ok(contents.map(|contents| Box::new(contents) as Box<dyn Read>))
+        }
+
+        fn path(&self, file_name: &str) -> String {
+            file_name.to_string()
+        }
+    }
+
+    fn read_fixture() -> OpenSongChart {
+        read_chart(&mut SongDir(Path::new(FIXTURE))).unwrap().unwrap()
+    }
+
+    #[test]
+    fn writes_files_as_read() {
+        let files = read_fixture().to_files().unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+
+        assert_eq!(names, vec!["song.json", "arrangement.json", "lead.json", "bass.json", "keys.json", "drums.json", "vocals.json"]);
+
+        for (name, contents) in &files {
+            let fixture = std::fs::read(Path::new(FIXTURE).join(name)).unwrap();
+            assert_eq!(String::from_utf8_lossy(contents), String::from_utf8_lossy(fixture.as_slice()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn reads_written_files_back() {
+        let chart = read_fixture();
+        let files = chart.to_files().unwrap();
+        let read_back = read_chart(&mut WrittenFiles(files.clone())).unwrap().unwrap();
+
+        assert_eq!(read_back.to_files().unwrap(), files);
+        assert_eq!(serde_json::to_value(&read_back.song).unwrap(), serde_json::to_value(&chart.song).unwrap());
+        assert_eq!(serde_json::to_value(&read_back.arrangement).unwrap(), serde_json::to_value(&chart.arrangement).unwrap());
+
+        let Some(Part::InstrumentPart(_, lead)) = read_back.instrument_parts.iter().find(|part| part.has_part_id("lead")) else {
+            panic!("lead part missing");
+        };
+
+        assert_eq!(lead.notes[1].techniques.len(), 5);
+        assert_eq!(lead.notes[3].techniques.len(), 12);
+        assert!(lead.notes[0].techniques.is_empty());
+
+        let Some(Part::VocalPart(_, vocals)) = read_back.instrument_parts.iter().find(|part| part.has_part_id("vocals")) else {
+            panic!("vocal part missing");
+        };
+
+        assert_eq!(vocals.vocals.len(), 2);
+        assert_eq!(read_back.song.preview_start_seconds, Some(2.0));
+    }
+
+    #[test]
+    fn skips_empty_techniques() {
+        let json = r#"{"TimeOffset": 1.0, "TimeLength": null, "Fret": 0, "String": 0, "Techniques": "None", "HandFret": null, "SlideFret": null, "ChordID": null, "FingerID": null}"#;
+        let note: instrument_part::SongNote = serde_json::from_str(json).unwrap();
+
+        assert!(note.techniques.is_empty());
+        assert!(!String::from_utf8(serde_json::to_vec(&note).unwrap()).unwrap().contains("Techniques"));
+    }
+}
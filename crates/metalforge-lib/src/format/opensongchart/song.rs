@@ -21,6 +21,9 @@ pub struct Song {
     #[serde(rename = "A440CentsOffset", default)]
     pub a440_cent_offset: f32,
 
+    #[serde(rename = "PreviewStartSeconds", default, skip_serializing_if = "Option::is_none")]
+    pub preview_start_seconds: Option<f32>,
+
     #[serde(rename = "InstrumentParts")]
     pub instrument_parts: Vec<InstrumentPart>
 }
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct SongVocals {
-    // Note: this struct does not exist in the original spec, I invented it to make it consistent with the rest of the spec
+    // Note: this struct does not exist in the original spec, I invented it to make it consistent with the rest of the spec.
+    // On disk the vocals are a plain array, hence the transparent representation
     pub vocals: Vec<SongVocal>
 }
 
@@ -0,0 +1,535 @@
+use crate::format::opensongchart::convert::metadata;
+use crate::format::opensongchart::{read_chart, summarize_chart, OpenSongChart};
+use crate::format::registry::{Confidence, FormatRegistry, SongFormat};
+use crate::format::{path_string, ChartFiles, LoadError, SongAudio, SongDir};
+use crate::library::songfile::{Format, PartSummary, SongFile};
+use crate::song::Song;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// The extension of song package files
+pub const PACKAGE_EXTENSION: &str = "zip";
+
+/// The name of the manifest describing the files of a song package
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// The version of the manifest written by `export_package`. Packages with a newer manifest are rejected
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Files larger than this are not read from song packages, so a damaged or malicious package can't
+/// use up all memory when it's loaded
+const MAX_FILE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Lists every file of a song package along with its checksum, so damaged packages are noticed
+/// before the song is added to the library
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackageManifest {
+    pub version: u32,
+    pub title: String,
+    pub artist: String,
+    pub files: Vec<PackageFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackageFile {
+    pub name: String,
+    pub size: u64,
+    /// The BLAKE3 hash of the contents of the file, as a hex string
+    pub checksum: String,
+}
+
+/// A song packed into a single zip archive, containing the same files as an OpenSongChart song
+/// directory. The files may also be in a single directory within the archive, as created by zipping
+/// a song directory by hand.
+pub struct SongPackage {
+    path: PathBuf,
+    archive: ZipArchive<BufReader<File>>,
+    /// The directory within the archive the song's files are in, either empty or ending with "/"
+    root: String,
+}
+
+impl SongPackage {
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let archive = ZipArchive::new(BufReader::new(File::open(path.as_ref())?))
+            .map_err(package_error)?;
+
+        let root = if archive.index_for_name("song.json").is_some() {
+            String::new()
+        } else {
+            let mut song_dirs = archive.file_names()
+                .filter_map(|name| name.strip_suffix("song.json"))
+                .filter(|dir| dir.ends_with('/') && dir.matches('/').count() == 1);
+
+            match (song_dirs.next(), song_dirs.next()) {
+                (Some(dir), None) => dir.to_string(),
+                (Some(_), Some(_)) => return Err(LoadError::InvalidPackage("more than one song.json in the package".to_string())),
+                (None, _) => return Err(LoadError::MissingFile("song.json".to_string())),
+            }
+        };
+
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            archive,
+            root,
+        })
+    }
+
+    /// Checks the package before its song is added to the library: every file name must be a plain
+    /// relative path, the files listed in the manifest must match their checksums, and the complete
+    /// chart must parse.
+    pub fn validate(&mut self) -> Result<(), LoadError> {
+        for name in self.archive.file_names() {
+            if !is_safe_name(name) {
+                return Err(LoadError::InvalidPackage(format!("unsafe file name {:?}", name)));
+            }
+        }
+
+        for idx in 0..self.archive.len() {
+            let file = self.archive.by_index(idx).map_err(package_error)?;
+
+            if file.size() > MAX_FILE_SIZE {
+                return Err(LoadError::InvalidPackage(format!("{} is too large", file.name())));
+            }
+        }
+
+        if let Some(manifest) = self.manifest()? {
+            self.verify_manifest(&manifest)?;
+        }
+
+        read_chart(self)?.ok_or_else(|| LoadError::MissingFile("song.json".to_string()))?;
+
+        Ok(())
+    }
+
+    /// The manifest of the package, if it has one
+    pub fn manifest(&mut self) -> Result<Option<PackageManifest>, LoadError> {
+        let path = self.path(MANIFEST_NAME);
+
+        let Some(reader) = self.open(MANIFEST_NAME)? else {
+            return Ok(None);
+        };
+
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+
+        serde_path_to_error::deserialize(&mut deserializer)
+            .map(Some)
+            .map_err(|error| LoadError::Parse {
+                file: path,
+                json_path: error.path().to_string(),
+                message: error.inner().to_string(),
+            })
+    }
+
+    fn verify_manifest(&mut self, manifest: &PackageManifest) -> Result<(), LoadError> {
+        if manifest.version > MANIFEST_VERSION {
+            return Err(LoadError::InvalidPackage(format!("unsupported manifest version {}", manifest.version)));
+        }
+
+        for expected in &manifest.files {
+            let reader = self.open(expected.name.as_str())?
+                .ok_or_else(|| LoadError::MissingFile(expected.name.clone()))?;
+
+            let (size, checksum) = checksum(reader)?;
+
+            if size != expected.size || checksum != expected.checksum {
+                return Err(LoadError::InvalidPackage(format!("{} doesn't match its checksum", expected.name)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The names of the song's files in the package, relative to the directory they are in
+    fn file_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.archive.file_names()
+            .filter_map(|name| name.strip_prefix(self.root.as_str()))
+            .filter(|name| !name.is_empty() && !name.ends_with('/') && *name != MANIFEST_NAME)
+            .map(str::to_string)
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    /// Reads an entire file of the package into memory
+    fn read(&mut self, file_name: &str) -> Result<Vec<u8>, LoadError> {
+        let mut reader = self.open(file_name)?
+            .ok_or_else(|| LoadError::MissingFile(file_name.to_string()))?;
+
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents)?;
+
+        Ok(contents)
+    }
+}
+
+impl ChartFiles for SongPackage {
+    fn open(&mut self, file_name: &str) -> Result<Option<Box<dyn Read + '_>>, LoadError> {
+        // The size in the header of a damaged package may not match the contents, so reading stops
+        // at the limit either way
+        match self.archive.by_name(format!("{}{}", self.root, file_name).as_str()) {
+            Ok(file) => Ok(Some(Box::new(file.take(MAX_FILE_SIZE)))),
+            Err(ZipError::FileNotFound) => Ok(None),
+            Err(error) => Err(package_error(error)),
+        }
+    }
+
+    fn path(&self, file_name: &str) -> String {
+        self.path.join(format!("{}{}", self.root, file_name)).display().to_string()
+    }
+}
+
+/// An OpenSongChart packed into a zip archive, see `SongPackage`
+pub struct SongPackageFormat;
+
+impl SongFormat for SongPackageFormat {
+    fn format(&self) -> Format {
+        Format::SongPackage
+    }
+
+    fn probe(&self, path: &Path) -> Confidence {
+        // Zip archives are used for all sorts of things, only the contents tell a song package apart
+        if is_package(path) {
+            Confidence::Likely
+        } else {
+            Confidence::None
+        }
+    }
+
+    fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError> {
+        load_package(path).map(Some)
+    }
+
+    fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError> {
+        load_package_song(song_file.song_dir.as_str())
+    }
+
+    fn open_audio(&self, song_file: &SongFile) -> Result<SongAudio, LoadError> {
+        open_package_audio(song_file.song_dir.as_str())
+    }
+}
+
+/// Checks if the path looks like a song package, without opening it
+pub fn is_package(path: &Path) -> bool {
+    path.is_file() && path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(PACKAGE_EXTENSION))
+}
+
+/// Validates a song package and creates a library entry for the song in it
+pub fn load_package<P: AsRef<Path>>(path: P) -> Result<SongFile, LoadError> {
+    debug!("Loading song package {:?}", path.as_ref());
+
+    let mut package = SongPackage::open(path.as_ref())?;
+    package.validate()?;
+
+    let (song, id) = summarize_chart(&mut package)?
+        .ok_or_else(|| LoadError::MissingFile("song.json".to_string()))?;
+
+    Ok(SongFile {
+        id,
+        format: Format::SongPackage,
+        song_dir: path_string(path.as_ref())?,
+        song_path: package.path("song.ogg"),
+        metadata: metadata(&song),
+        parts: song.instrument_parts.iter().map(PartSummary::from).collect(),
+    })
+}
+
+/// Parses the complete chart of the song in a song package
+pub fn load_package_song<P: AsRef<Path>>(path: P) -> Result<Song, LoadError> {
+    read_chart(&mut SongPackage::open(path)?)?
+        .map(Song::from)
+        .ok_or_else(|| LoadError::MissingFile("song.json".to_string()))
+}
+
+/// Reads the audio of the song in a song package into memory, so it can be played without
+/// extracting the package
+pub fn open_package_audio<P: AsRef<Path>>(path: P) -> Result<SongAudio, LoadError> {
+    let contents = SongPackage::open(path)?.read("song.ogg")?;
+    let byte_len = contents.len() as u64;
+
+    Ok(SongAudio { data: Box::new(Cursor::new(contents)), byte_len })
+}
+
+/// Packs a song into a song package at the specified path, along with a manifest listing the size
+/// and checksum of each file. OpenSongCharts and song packages are packed as they are, songs of any
+/// other format are converted to an OpenSongChart first. The package is written to a temporary file
+/// first, so an existing package is only replaced once the new one is complete.
+pub fn export_package<P: AsRef<Path>>(song_file: &SongFile, formats: &FormatRegistry, path: P) -> Result<(), LoadError> {
+    info!("Exporting song {:?} to {:?}", song_file.song_dir, path.as_ref());
+
+    let temp_path = path.as_ref().with_extension("tmp");
+
+    match write_package(song_file, formats, temp_path.as_path()) {
+        Ok(()) => std::fs::rename(temp_path, path.as_ref())?,
+        Err(error) => {
+            let _ = std::fs::remove_file(temp_path);
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_package(song_file: &SongFile, formats: &FormatRegistry, path: &Path) -> Result<(), LoadError> {
+    let mut writer = ZipWriter::new(File::create(path)?);
+
+    let files = match song_file.format {
+        Format::OpenSongChart => {
+            let dir = Path::new(song_file.song_dir.as_str());
+            write_files(&mut writer, &mut SongDir(dir), dir_file_names(dir)?)?
+        }
+        Format::SongPackage => {
+            let mut package = SongPackage::open(song_file.song_dir.as_str())?;
+            let file_names = package.file_names();
+            write_files(&mut writer, &mut package, file_names)?
+        }
+        Format::Metalforge | Format::MusicXml | Format::GuitarPro | Format::Rocksmith | Format::Custom(_) => {
+            let song = formats.load_song(song_file)?;
+            let mut files = OpenSongChart::from(&song).to_files().map_err(std::io::Error::other)?;
+
+            // The audio is stored as song.ogg whatever its format, the decoder tells formats apart
+            // by their contents
+            let mut audio = vec![];
+            formats.open_audio(song_file)?.data.read_to_end(&mut audio)?;
+            files.push(("song.ogg".to_string(), audio));
+
+            if let Some(album_art) = song_file.album_art()
+                && let Some(name) = album_art.file_name().and_then(|name| name.to_str()) {
+                files.push((name.to_string(), std::fs::read(album_art.as_path())?));
+            }
+
+            files.into_iter()
+                .map(|(name, contents)| write_file(&mut writer, name, contents.as_slice()))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let manifest = PackageManifest {
+        version: MANIFEST_VERSION,
+        title: song_file.metadata.title.clone(),
+        artist: song_file.metadata.artist.clone(),
+        files,
+    };
+
+    writer.start_file(MANIFEST_NAME, SimpleFileOptions::default()).map_err(package_error)?;
+    serde_json::to_writer_pretty(&mut writer, &manifest).map_err(std::io::Error::other)?;
+    writer.finish().map_err(package_error)?;
+
+    Ok(())
+}
+
+/// Copies the files of a song into a package, returning the manifest entry of each file
+fn write_files<W, F>(writer: &mut ZipWriter<W>, files: &mut F, file_names: Vec<String>) -> Result<Vec<PackageFile>, LoadError>
+where
+    W: Write + std::io::Seek,
+    F: ChartFiles
+{
+    let mut entries = vec![];
+
+    for name in file_names {
+        let mut contents = vec![];
+        files.open(name.as_str())?
+            .ok_or_else(|| LoadError::MissingFile(name.clone()))?
+            .read_to_end(&mut contents)?;
+
+        entries.push(write_file(writer, name, contents.as_slice())?);
+    }
+
+    Ok(entries)
+}
+
+/// Adds a file to a package, returning its manifest entry
+fn write_file<W: Write + std::io::Seek>(writer: &mut ZipWriter<W>, name: String, contents: &[u8]) -> Result<PackageFile, LoadError> {
+    // Audio and images are already compressed, only the charts are worth compressing
+    let compression = if name.ends_with(".json") { CompressionMethod::Deflated } else { CompressionMethod::Stored };
+
+    writer.start_file(name.as_str(), SimpleFileOptions::default().compression_method(compression))
+        .map_err(package_error)?;
+    writer.write_all(contents)?;
+
+    let (size, checksum) = checksum(contents)?;
+    Ok(PackageFile { name, size, checksum })
+}
+
+/// The names of the files directly in a song directory. Hidden files and any manifest left over from
+/// an extracted package are skipped.
+fn dir_file_names(dir: &Path) -> Result<Vec<String>, LoadError> {
+    let mut names = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if let Some(name) = entry.file_name().to_str()
+            && entry.file_type()?.is_file()
+            && !name.starts_with('.')
+            && name != MANIFEST_NAME {
+            names.push(name.to_string());
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+fn checksum<R: Read>(contents: R) -> Result<(u64, String), LoadError> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(contents)?;
+
+    Ok((hasher.count(), hasher.finalize().to_hex().to_string()))
+}
+
+/// File names in a package must stay within the package, even though packages are never extracted
+fn is_safe_name(name: &str) -> bool {
+    !name.contains('\\') && Path::new(name).components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn package_error(error: ZipError) -> LoadError {
+    match error {
+        ZipError::Io(error) => LoadError::Io(error),
+        error => LoadError::InvalidPackage(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::guitarpro::load_guitar_pro;
+    use crate::format::opensongchart::load_open_song_chart;
+    use crate::format::testing::{describe_notes, fixture, guitar_parts};
+    use crate::library::testing::{copy_fixture_song, temp_dir};
+
+    /// Rewrites a package with some of its files replaced or added, keeping the manifest as it was
+    fn repack(path: &Path, changed: &[(&str, &[u8])]) {
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut files: Vec<(String, Vec<u8>)> = vec![];
+
+        for idx in 0..archive.len() {
+            let mut file = archive.by_index(idx).unwrap();
+            let mut contents = vec![];
+            file.read_to_end(&mut contents).unwrap();
+            files.push((file.name().to_string(), contents));
+        }
+
+        for (name, contents) in changed {
+            match files.iter_mut().find(|(file_name, _)| file_name == name) {
+                Some((_, file)) => *file = contents.to_vec(),
+                None => files.push((name.to_string(), contents.to_vec())),
+            }
+        }
+
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+
+        for (name, contents) in files {
+            writer.start_file(name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents.as_slice()).unwrap();
+        }
+
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trips_open_song_charts() {
+        let dir = temp_dir("package-open-song-chart");
+        copy_fixture_song(dir.join("song").as_path());
+        let song_file = load_open_song_chart(dir.join("song")).unwrap().unwrap();
+
+        let path = dir.join("song.zip");
+        export_package(&song_file, &FormatRegistry::default(), path.as_path()).unwrap();
+
+        let mut package = SongPackage::open(path.as_path()).unwrap();
+        package.validate().unwrap();
+        let manifest = package.manifest().unwrap().unwrap();
+        assert_eq!(manifest.files.len(), dir_file_names(dir.join("song").as_path()).unwrap().len());
+
+        // The files are packed as they are, so the song keeps its ID
+        let packed = load_package(path.as_path()).unwrap();
+        assert_eq!(packed.id, song_file.id);
+        assert_eq!(packed.metadata.title, song_file.metadata.title);
+        assert_eq!(packed.parts.len(), song_file.parts.len());
+        assert!(!dir.join("song.tmp").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn converts_other_formats_to_open_song_charts() {
+        let dir = temp_dir("package-guitar-pro");
+        std::fs::write(dir.join("tab.gp5"), fixture("guitarpro/gp5.gp5")).unwrap();
+        let song_file = load_guitar_pro(dir.join("tab.gp5")).unwrap().unwrap();
+        let song = FormatRegistry::default().load_song(&song_file).unwrap();
+
+        let path = dir.join("tab.zip");
+        export_package(&song_file, &FormatRegistry::default(), path.as_path()).unwrap();
+
+        let packed = load_package(path.as_path()).unwrap();
+        assert_eq!(packed.metadata.title, "Test Song");
+
+        let packed_song = load_package_song(path.as_path()).unwrap();
+        let parts: Vec<(&str, Vec<String>)> = guitar_parts(&packed_song).into_iter().map(|(name, part)| (name, describe_notes(part))).collect();
+        let expected: Vec<(&str, Vec<String>)> = guitar_parts(&song).into_iter().map(|(name, part)| (name, describe_notes(part))).collect();
+        assert_eq!(parts, expected);
+
+        // The tab has no audio, so the silence it's played along with is packed instead
+        assert!(open_package_audio(path.as_path()).unwrap().byte_len > 0);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_files_not_matching_checksums() {
+        let dir = temp_dir("package-checksum");
+        copy_fixture_song(dir.join("song").as_path());
+        let song_file = load_open_song_chart(dir.join("song")).unwrap().unwrap();
+
+        let path = dir.join("song.zip");
+        export_package(&song_file, &FormatRegistry::default(), path.as_path()).unwrap();
+
+        let mut lead = std::fs::read(dir.join("song/lead.json")).unwrap();
+        lead.push(b'\n');
+        repack(path.as_path(), &[("lead.json", lead.as_slice())]);
+
+        assert!(matches!(load_package(path.as_path()), Err(LoadError::InvalidPackage(reason)) if reason == "lead.json doesn't match its checksum"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unsafe_file_names() {
+        let dir = temp_dir("package-unsafe");
+        copy_fixture_song(dir.join("song").as_path());
+        let song_file = load_open_song_chart(dir.join("song")).unwrap().unwrap();
+
+        let path = dir.join("song.zip");
+        export_package(&song_file, &FormatRegistry::default(), path.as_path()).unwrap();
+        repack(path.as_path(), &[("../song.ogg", b"audio")]);
+
+        assert!(matches!(load_package(path.as_path()), Err(LoadError::InvalidPackage(reason)) if reason == "unsafe file name \"../song.ogg\""));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removes_temporary_file_when_export_fails() {
+        let dir = temp_dir("package-failed");
+        copy_fixture_song(dir.join("song").as_path());
+
+        let mut song_file = load_open_song_chart(dir.join("song")).unwrap().unwrap();
+        song_file.song_dir = dir.join("missing").display().to_string();
+
+        let path = dir.join("song.zip");
+        assert!(export_package(&song_file, &FormatRegistry::default(), path.as_path()).is_err());
+        assert!(!path.exists());
+        assert!(!dir.join("song.tmp").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+use crate::format::guitarpro::GuitarProFormat;
+use crate::format::metalforge::MetalforgeFormat;
+use crate::format::musicxml::MusicXmlFormat;
+use crate::format::opensongchart::OpenSongChartFormat;
+use crate::format::package::SongPackageFormat;
+use crate::format::rocksmith::RocksmithFormat;
+use crate::format::{open_audio_file, LoadError, SongAudio};
+use crate::library::songfile::{Format, SongFile};
+use crate::song::Song;
+use log::debug;
+use std::cmp::Reverse;
+use std::path::Path;
+
+/// How sure a format is that a path holds a song it can load, judging only by the names of the
+/// files. When several formats recognise a path, the most confident one is tried first.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Confidence {
+    /// The path doesn't look like a song of the format at all
+    None,
+    /// The path has a file commonly used by the format, but also by other formats
+    Possible,
+    /// The path has the files the format requires
+    Likely,
+    /// The path has a file only the format uses
+    Certain,
+}
+
+/// A song format the library can find songs in. The built-in formats are registered by
+/// `FormatRegistry::default`, applications can register their own formats as well.
+pub trait SongFormat: Send + Sync {
+
+    /// Identifies the format in the library entries of its songs
+    fn format(&self) -> Format;
+
+    /// Checks how likely it is that the path holds a song of this format. This is called for every
+    /// file and directory in the library, so it must only look at the names of files and not read
+    /// any of them. Checking the contents is left to `load_entry`.
+    fn probe(&self, path: &Path) -> Confidence;
+
+    /// Creates a library entry for the song at the path, or returns `None` if there is no song of
+    /// this format after all. Only as much of the song as the library needs should be parsed.
+    fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError>;
+
+    /// Parses the full song of a library entry created by `load_entry`
+    fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError>;
+
+    /// Opens the audio of a library entry created by `load_entry`. By default, `song_path` is
+    /// opened as a file.
+    fn open_audio(&self, song_file: &SongFile) -> Result<SongAudio, LoadError> {
+        open_audio_file(song_file.song_path.as_str())
+    }
+
+    /// Writes a song in this format into the specified directory, copying the audio from
+    /// `song_path`. Formats that can only be read don't override this.
+    fn save(&self, _song: &Song, _song_path: &str, _dir: &Path) -> Result<(), LoadError> {
+        Err(LoadError::UnsupportedFormat(format!("songs can't be saved as {}", self.format())))
+    }
+}
+
+/// The song formats the library is scanned for
+pub struct FormatRegistry {
+    formats: Vec<Box<dyn SongFormat>>,
+}
+
+impl FormatRegistry {
+
+    /// A registry without any formats, not even the built-in ones
+    pub fn empty() -> Self {
+        Self { formats: vec![] }
+    }
+
+    /// Adds a format to the registry. If several formats are equally confident about a path, the one
+    /// registered first is tried first.
+    pub fn register<F: SongFormat + 'static>(&mut self, format: F) {
+        self.formats.push(Box::new(format));
+    }
+
+    /// The registered implementation of a format
+    pub fn get(&self, format: Format) -> Option<&dyn SongFormat> {
+        self.formats.iter()
+            .find(|registered| registered.format() == format)
+            .map(|registered| registered.as_ref())
+    }
+
+    /// How confident the most confident format is about the path
+    pub fn probe(&self, path: &Path) -> Confidence {
+        self.formats.iter()
+            .map(|format| format.probe(path))
+            .max()
+            .unwrap_or(Confidence::None)
+    }
+
+    /// Checks if the path holds a song of any registered format and creates a library entry for it.
+    /// The formats recognising the path are tried from the most to the least confident, until one of
+    /// them loads the song. Returns `None` if the path doesn't look like a song at all, or the error
+    /// of the most confident format if none of them could load it.
+    pub fn load_dir<P: AsRef<Path>>(&self, path: P) -> Result<Option<SongFile>, LoadError> {
+        let mut candidates: Vec<(Confidence, &dyn SongFormat)> = self.formats.iter()
+            .map(|format| (format.probe(path.as_ref()), format.as_ref()))
+            .filter(|(confidence, _)| *confidence > Confidence::None)
+            .collect();
+
+        // Stable, so equally confident formats stay in the order they were registered
+        candidates.sort_by_key(|(confidence, _)| Reverse(*confidence));
+
+        let mut first_error = None;
+
+        for (confidence, format) in candidates {
+            debug!("Trying {} at {:?}, confidence {:?}", format.format(), path.as_ref(), confidence);
+
+            match format.load_entry(path.as_ref()) {
+                Ok(Some(song_file)) => return Ok(Some(song_file)),
+                Ok(None) => {}
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the full song of a library entry created by `load_dir`
+    pub fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError> {
+        self.format_of(song_file)?.load_song(song_file)
+    }
+
+    /// Opens the audio of a library entry created by `load_dir`
+    pub fn open_audio(&self, song_file: &SongFile) -> Result<SongAudio, LoadError> {
+        self.format_of(song_file)?.open_audio(song_file)
+    }
+
+    /// Writes a song in the specified format into a directory, copying the audio from `song_path`
+    pub fn save<P: AsRef<Path>>(&self, format: Format, song: &Song, song_path: &str, dir: P) -> Result<(), LoadError> {
+        self.get(format)
+            .ok_or_else(|| LoadError::UnsupportedFormat(format!("{} is not registered", format)))?
+            .save(song, song_path, dir.as_ref())
+    }
+
+    fn format_of(&self, song_file: &SongFile) -> Result<&dyn SongFormat, LoadError> {
+        self.get(song_file.format)
+            .ok_or_else(|| LoadError::UnsupportedFormat(format!("{} is not registered", song_file.format)))
+    }
+}
+
+/// A registry with every built-in format. A directory with a song in the native format is loaded as
+/// such, even if it also has an OpenSongChart.
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(SongPackageFormat);
+        registry.register(MetalforgeFormat);
+        registry.register(OpenSongChartFormat);
+        registry.register(MusicXmlFormat);
+        registry.register(GuitarProFormat);
+        registry.register(RocksmithFormat);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::testing::song_file;
+    use std::sync::{Arc, Mutex};
+
+    /// What a stub format does when asked to load a path
+    #[derive(Copy, Clone)]
+    enum Outcome {
+        Loads,
+        NotFound,
+        Fails,
+    }
+
+    /// A format that's as confident about every path as it's told to be, and remembers the order it
+    /// was tried in
+    struct StubFormat {
+        name: &'static str,
+        confidence: Confidence,
+        outcome: Outcome,
+        tried: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl SongFormat for StubFormat {
+        fn format(&self) -> Format {
+            Format::Custom(self.name)
+        }
+
+        fn probe(&self, _path: &Path) -> Confidence {
+            self.confidence
+        }
+
+        fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError> {
+            self.tried.lock().unwrap().push(self.name);
+
+            match self.outcome {
+                Outcome::Loads => {
+                    let mut song_file = song_file(self.name, path.to_str().unwrap());
+                    song_file.format = self.format();
+                    Ok(Some(song_file))
+                }
+                Outcome::NotFound => Ok(None),
+                Outcome::Fails => Err(LoadError::MissingFile(format!("{}.json", self.name))),
+            }
+        }
+
+        fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError> {
+            let mut song = Song::empty();
+            song.metadata.title = song_file.id.0.clone();
+            Ok(song)
+        }
+    }
+
+    /// A registry of stub formats, along with the names of the formats in the order they're tried
+    fn registry(formats: &[(&'static str, Confidence, Outcome)]) -> (FormatRegistry, Arc<Mutex<Vec<&'static str>>>) {
+        let tried = Arc::new(Mutex::new(vec![]));
+        let mut registry = FormatRegistry::empty();
+
+        for (name, confidence, outcome) in formats {
+            registry.register(StubFormat { name, confidence: *confidence, outcome: *outcome, tried: tried.clone() });
+        }
+
+        (registry, tried)
+    }
+
+    fn loaded_by(result: Result<Option<SongFile>, LoadError>) -> Option<Format> {
+        result.unwrap().map(|song_file| song_file.format)
+    }
+
+    #[test]
+    fn tries_most_confident_format_first() {
+        let (registry, tried) = registry(&[
+            ("possible", Confidence::Possible, Outcome::Loads),
+            ("certain", Confidence::Certain, Outcome::Loads),
+            ("likely", Confidence::Likely, Outcome::Loads),
+        ]);
+
+        assert_eq!(loaded_by(registry.load_dir("/songs/a")), Some(Format::Custom("certain")));
+        assert_eq!(*tried.lock().unwrap(), vec!["certain"]);
+        assert_eq!(registry.probe(Path::new("/songs/a")), Confidence::Certain);
+    }
+
+    #[test]
+    fn tries_equally_confident_formats_in_registration_order() {
+        let (registry, tried) = registry(&[
+            ("first", Confidence::Likely, Outcome::NotFound),
+            ("second", Confidence::Likely, Outcome::Loads),
+            ("third", Confidence::Likely, Outcome::Loads),
+        ]);
+
+        assert_eq!(loaded_by(registry.load_dir("/songs/a")), Some(Format::Custom("second")));
+        assert_eq!(*tried.lock().unwrap(), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn falls_back_to_less_confident_formats() {
+        let (registry, tried) = registry(&[
+            ("fails", Confidence::Certain, Outcome::Fails),
+            ("not found", Confidence::Likely, Outcome::NotFound),
+            ("loads", Confidence::Possible, Outcome::Loads),
+            ("ignored", Confidence::None, Outcome::Loads),
+        ]);
+
+        assert_eq!(loaded_by(registry.load_dir("/songs/a")), Some(Format::Custom("loads")));
+        assert_eq!(*tried.lock().unwrap(), vec!["fails", "not found", "loads"]);
+    }
+
+    #[test]
+    fn returns_error_of_most_confident_format() {
+        let (registry, _) = registry(&[
+            ("second", Confidence::Possible, Outcome::Fails),
+            ("first", Confidence::Likely, Outcome::Fails),
+            ("not found", Confidence::Likely, Outcome::NotFound),
+        ]);
+
+        assert!(matches!(registry.load_dir("/songs/a"), Err(LoadError::MissingFile(file)) if file == "first.json"));
+    }
+
+    #[test]
+    fn ignores_paths_no_format_recognises() {
+        let (registry, tried) = registry(&[("ignored", Confidence::None, Outcome::Loads)]);
+
+        assert_eq!(loaded_by(registry.load_dir("/songs/a")), None);
+        assert!(tried.lock().unwrap().is_empty());
+        assert_eq!(FormatRegistry::empty().probe(Path::new("/songs/a")), Confidence::None);
+    }
+
+    #[test]
+    fn loads_songs_of_custom_formats() {
+        let (registry, _) = registry(&[("custom", Confidence::Likely, Outcome::Loads)]);
+        let song_file = registry.load_dir("/songs/a").unwrap().unwrap();
+
+        assert_eq!(registry.load_song(&song_file).unwrap().metadata.title, "custom");
+        assert!(matches!(registry.save(Format::Custom("custom"), &Song::empty(), "song.ogg", "/songs/b"), Err(LoadError::UnsupportedFormat(_))));
+
+        let mut unregistered = song_file.clone();
+        unregistered.format = Format::Custom("other");
+        assert!(matches!(registry.load_song(&unregistered), Err(LoadError::UnsupportedFormat(_))));
+    }
+}
@@ -0,0 +1,517 @@
+use crate::format::{guitar_part_type, Warnings};
+use crate::song::guitar::{BendPoint, FretAnchor, GuitarNote, GuitarPart, GuitarTechnique, GuitarTuning};
+use crate::song::instrument_part::InstrumentPart;
+use crate::song::metadata::Metadata;
+use crate::song::{Beat, Section, Song};
+use roxmltree::{Document, Node};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The pitch of each open string in standard tuning, which the tuning of an arrangement is given
+/// relative to, in semitones from E2
+const GUITAR_STANDARD: [i8; 6] = [0, 5, 10, 15, 19, 24];
+const BASS_STANDARD: [i8; 4] = [-12, -7, -2, 3];
+
+/// A song read from Rocksmith arrangements, along with a description of everything in them that
+/// couldn't be represented in the song
+pub struct RocksmithImport {
+    pub song: Song,
+    pub warnings: Vec<String>,
+}
+
+/// Reads Rocksmith 2014 arrangements, given with their file names, into a song with a part for each
+/// of them. Only the hardest level of every phrase is kept. The metadata, beats and sections of the
+/// song are taken from the first arrangement with beats.
+pub fn import_rocksmith<'a>(arrangements: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> Result<RocksmithImport, String> {
+    let mut warnings = Warnings::default();
+    let mut song: Option<Song> = None;
+    let mut guitar_parts = 0;
+    let mut notes_end = Duration::ZERO;
+
+    for (name, data) in arrangements {
+        let arrangement = read_arrangement(data, &mut warnings).map_err(|message| format!("{}: {}", name, message))?;
+        notes_end = arrangement.part.notes.iter().map(|note| note.time + note.length).fold(notes_end, Duration::max);
+
+        // Arrangements without beats, usually made by hand, only add their part
+        let song = match &mut song {
+            Some(song) if song.beats.is_empty() && !arrangement.song.beats.is_empty() => {
+                let instrument_parts = std::mem::take(&mut song.instrument_parts);
+                *song = Song { instrument_parts, ..arrangement.song };
+                song
+            }
+            song => song.get_or_insert(arrangement.song),
+        };
+
+        song.instrument_parts.push(InstrumentPart {
+            instrument_part_type: guitar_part_type(arrangement.name.as_str(), arrangement.part, &mut guitar_parts),
+            name: arrangement.name,
+        });
+    }
+
+    let mut song = song.ok_or_else(|| "no arrangements".to_string())?;
+    song.metadata.length = song.metadata.length.max(notes_end);
+
+    Ok(RocksmithImport { song, warnings: warnings.into_messages() })
+}
+
+/// Checks the root element of an arrangement, so other XML files next to it, like the vocals, can
+/// be told apart
+pub fn is_arrangement(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(4096)]);
+
+    head.match_indices("<song").any(|(idx, tag)| head[idx + tag.len()..].starts_with(|c: char| c == '>' || c.is_whitespace()))
+}
+
+struct Arrangement {
+    name: String,
+    part: GuitarPart,
+    /// The song as described by the arrangement, without any parts
+    song: Song,
+}
+
+fn read_arrangement(data: &[u8], warnings: &mut Warnings) -> Result<Arrangement, String> {
+    let xml = std::str::from_utf8(data).map_err(|error| error.to_string())?;
+    let document = Document::parse(xml.trim_start_matches('\u{feff}')).map_err(|error| error.to_string())?;
+    let root = document.root_element();
+
+    if !root.has_tag_name("song") {
+        return Err(format!("not a Rocksmith arrangement: <{}>", root.tag_name().name()));
+    }
+
+    let name = arrangement_name(root);
+    let text = |name| child(root, name).and_then(|child| child.text()).unwrap_or_default().trim();
+
+    let standard: &[i8] = match name.to_lowercase().contains("bass") {
+        true => &BASS_STANDARD,
+        false => &GUITAR_STANDARD,
+    };
+
+    let tuning: Vec<i8> = standard.iter()
+        .enumerate()
+        .map(|(string, offset)| {
+            let tuning = child(root, "tuning").and_then(|tuning| attribute::<i8>(tuning, format!("string{}", string).as_str()));
+            offset.saturating_add(tuning.unwrap_or(0))
+        })
+        .collect();
+
+    let templates: Vec<ChordTemplate> = children(root, "chordTemplates", "chordTemplate").map(ChordTemplate::read).collect();
+    let hardest = HardestLevels::read(root);
+
+    let mut notes = vec![];
+    let mut anchors = vec![];
+
+    for level in children(root, "levels", "level") {
+        let difficulty = attribute::<i32>(level, "difficulty").unwrap_or(0);
+        let is_hardest = |node: Node| seconds(node, "time").is_some_and(|time| hardest.difficulty(time) == difficulty);
+
+        for note in children(level, "notes", "note").filter(|note| is_hardest(*note)) {
+            notes.extend(read_note(note, None, warnings));
+        }
+
+        for chord in children(level, "chords", "chord").filter(|chord| is_hardest(*chord)) {
+            read_chord(chord, &templates, &mut notes, warnings);
+        }
+
+        anchors.extend(children(level, "anchors", "anchor")
+            .filter(|anchor| is_hardest(*anchor))
+            .filter_map(|anchor| Some(FretAnchor {
+                time: seconds(anchor, "time")?,
+                fret: attribute::<u8>(anchor, "fret")?,
+                width: attribute::<f64>(anchor, "width").map_or(4, |width| width.round().clamp(1.0, u8::MAX as f64) as u8),
+            })));
+    }
+
+    let string_count = tuning.len();
+
+    if notes.iter().any(|note| note.string as usize >= string_count) {
+        warnings.add(format!("{}: notes on strings past the {} strings of the arrangement skipped", name, string_count));
+        notes.retain(|note| (note.string as usize) < string_count);
+    }
+
+    notes.sort_by_key(|note| (note.time, note.string));
+    anchors.sort_by_key(|anchor| anchor.time);
+
+    let part = GuitarPart {
+        notes,
+        tuning: GuitarTuning::from(tuning),
+        capo: text("capo").parse::<u8>().unwrap_or(0),
+        anchors,
+    };
+
+    let song = Song {
+        metadata: Metadata {
+            title: text("title").to_string(),
+            artist: text("artistName").to_string(),
+            album: text("albumName").to_string(),
+            year: text("albumYear").parse().unwrap_or(0),
+            length: text("songLength").parse().ok().and_then(|length| Duration::try_from_secs_f64(length).ok()).unwrap_or_default(),
+            key: None,
+            preview_start: None,
+        },
+        instrument_parts: vec![],
+        beats: read_beats(root),
+        sections: children(root, "sections", "section")
+            .filter_map(|section| Some(Section {
+                name: section.attribute("name")?.to_string(),
+                time: seconds(section, "startTime")?,
+            }))
+            .collect(),
+        a440_offset_cents: text("centOffset").parse().unwrap_or(0.0),
+    };
+
+    Ok(Arrangement { name, part, song })
+}
+
+/// The name of the arrangement, e.g. "Lead" or "Bass". Older arrangements only say which path of the
+/// game they're played on.
+fn arrangement_name(root: Node) -> String {
+    let name = child(root, "arrangement").and_then(|child| child.text()).unwrap_or_default().trim();
+
+    if !name.is_empty() {
+        return name.to_string();
+    }
+
+    let path = child(root, "arrangementProperties").and_then(|properties| {
+        [("pathBass", "Bass"), ("pathRhythm", "Rhythm"), ("pathLead", "Lead")].into_iter()
+            .find(|(attribute_name, _)| attribute::<i32>(properties, attribute_name) == Some(1))
+    });
+
+    path.map_or("Guitar", |(_, name)| name).to_string()
+}
+
+/// Every beat starting a measure has the number of the measure, the others have -1
+fn read_beats(root: Node) -> Vec<Beat> {
+    let mut beats: Vec<Beat> = vec![];
+
+    for ebeat in children(root, "ebeats", "ebeat") {
+        let Some(time) = seconds(ebeat, "time") else {
+            continue;
+        };
+
+        let (measure, beat_in_measure) = match (attribute::<i32>(ebeat, "measure").unwrap_or(-1), beats.last()) {
+            (-1, Some(previous)) => (previous.measure, previous.beat_in_measure.saturating_add(1)),
+            (_, previous) => (previous.map_or(1, |previous| previous.measure + 1), 1),
+        };
+
+        beats.push(Beat { time, measure, beat_in_measure });
+    }
+
+    beats
+}
+
+/// The levels of an arrangement each hold every note of the arrangement at one difficulty, but a
+/// phrase may have fewer levels than the hardest phrase. The hardest level of a phrase is used for
+/// every note from the start of the phrase until the next one.
+struct HardestLevels {
+    /// The start of each phrase and its hardest level, in order
+    phrases: Vec<(Duration, i32)>,
+    /// The hardest level of the arrangement, used before the first phrase
+    hardest: i32,
+}
+
+impl HardestLevels {
+
+    fn read(root: Node) -> Self {
+        let levels: Vec<i32> = children(root, "levels", "level")
+            .map(|level| attribute::<i32>(level, "difficulty").unwrap_or(0))
+            .collect();
+
+        let hardest = levels.iter().copied().max().unwrap_or(0);
+
+        let max_difficulties: Vec<i32> = children(root, "phrases", "phrase")
+            .map(|phrase| attribute::<i32>(phrase, "maxDifficulty").unwrap_or(hardest))
+            .collect();
+
+        // A phrase's hardest level is the hardest one the arrangement has up to its maximum
+        let level_up_to = |max: i32| levels.iter().copied().filter(|level| *level <= max).max().unwrap_or(hardest);
+
+        let mut phrases: Vec<(Duration, i32)> = children(root, "phraseIterations", "phraseIteration")
+            .filter_map(|iteration| {
+                let max = max_difficulties.get(attribute::<usize>(iteration, "phraseId")?).copied().unwrap_or(hardest);
+                Some((seconds(iteration, "time")?, level_up_to(max)))
+            })
+            .collect();
+
+        phrases.sort_by_key(|(time, _)| *time);
+
+        HardestLevels { phrases, hardest }
+    }
+
+    fn difficulty(&self, time: Duration) -> i32 {
+        self.phrases.iter()
+            .take_while(|(start, _)| *start <= time)
+            .last()
+            .map_or(self.hardest, |(_, level)| *level)
+    }
+}
+
+/// The frets and fingers of a chord on each string, lowest string first, -1 for unused strings
+struct ChordTemplate {
+    frets: Vec<i32>,
+    fingers: Vec<i32>,
+}
+
+impl ChordTemplate {
+
+    fn read(template: Node) -> Self {
+        let strings = |name: &str| (0..GUITAR_STANDARD.len())
+            .map(|string| attribute::<i32>(template, format!("{}{}", name, string).as_str()).unwrap_or(-1))
+            .collect();
+
+        ChordTemplate { frets: strings("fret"), fingers: strings("finger") }
+    }
+}
+
+/// Reads a chord into its notes. Chords in lower levels or repeated quickly may leave out their
+/// notes, which are then taken from the chord template.
+fn read_chord(chord: Node, templates: &[ChordTemplate], notes: &mut Vec<GuitarNote>, warnings: &mut Warnings) {
+    let template = attribute::<usize>(chord, "chordId").and_then(|id| templates.get(id));
+
+    let mut chord_notes: Vec<GuitarNote> = chord.children()
+        .filter(|child| child.has_tag_name("chordNote"))
+        .filter_map(|chord_note| read_note(chord_note, template, warnings))
+        .collect();
+
+    if chord_notes.is_empty() {
+        let (Some(template), Some(time)) = (template, seconds(chord, "time")) else {
+            warnings.add("chords without notes or a template skipped".to_string());
+            return;
+        };
+
+        chord_notes = template.frets.iter()
+            .enumerate()
+            .filter(|(_, fret)| **fret >= 0)
+            .map(|(string, fret)| GuitarNote {
+                string: string as u8,
+                fret: (*fret).min(u8::MAX as i32) as u8,
+                finger: finger(template.fingers[string]),
+                time,
+                length: Duration::ZERO,
+                technique: vec![],
+            })
+            .collect();
+    }
+
+    for note in &mut chord_notes {
+        if flag(chord, "palmMute") && !note.technique.iter().any(|technique| matches!(technique, GuitarTechnique::PalmMute)) {
+            note.technique.push(GuitarTechnique::PalmMute);
+        }
+
+        if flag(chord, "fretHandMute") && !note.technique.iter().any(|technique| matches!(technique, GuitarTechnique::FretHandMute)) {
+            note.technique.push(GuitarTechnique::FretHandMute);
+        }
+    }
+
+    notes.extend(chord_notes);
+}
+
+/// Reads a note, or a note of a chord. Notes of chords without a finger of their own are played
+/// with the finger of the chord template.
+fn read_note(note: Node, template: Option<&ChordTemplate>, warnings: &mut Warnings) -> Option<GuitarNote> {
+    let string = attribute::<u8>(note, "string")?;
+    let fret = u8::try_from(attribute::<i32>(note, "fret")?).ok()?;
+    let time = seconds(note, "time")?;
+    let length = seconds(note, "sustain").unwrap_or_default();
+
+    let left_hand = finger(attribute::<i32>(note, "leftHand").unwrap_or(-1)).or_else(|| template
+        .and_then(|template| template.fingers.get(string as usize))
+        .and_then(|template_finger| finger(*template_finger)));
+
+    let mut technique = vec![];
+
+    let flags = [
+        ("hammerOn", GuitarTechnique::HammerOn),
+        ("pullOff", GuitarTechnique::PullOff),
+        ("palmMute", GuitarTechnique::PalmMute),
+        ("mute", GuitarTechnique::FretHandMute),
+        ("tremolo", GuitarTechnique::Tremolo),
+        ("vibrato", GuitarTechnique::Vibrato),
+        ("harmonic", GuitarTechnique::Harmonic),
+        ("harmonicPinch", GuitarTechnique::PinchHarmonic),
+        ("tap", GuitarTechnique::Tap),
+        ("slap", GuitarTechnique::Slap),
+        ("pluck", GuitarTechnique::Pop),
+    ];
+
+    for (name, flag_technique) in flags {
+        if flag(note, name) {
+            technique.push(flag_technique);
+        }
+    }
+
+    let slide_to = |name| attribute::<i32>(note, name).and_then(|to_fret| u8::try_from(to_fret).ok());
+
+    if let Some(to_fret) = slide_to("slideTo") {
+        technique.push(GuitarTechnique::Slide { to_fret });
+    } else if let Some(to_fret) = slide_to("slideUnpitchTo") {
+        warnings.add("unpitched slides imported as slides".to_string());
+        technique.push(GuitarTechnique::Slide { to_fret });
+    }
+
+    if let Some(points) = read_bend(note, time, length) {
+        technique.push(GuitarTechnique::Bend { points });
+    }
+
+    if flag(note, "accent") {
+        warnings.add("accents dropped".to_string());
+    }
+
+    Some(GuitarNote { string, fret, finger: left_hand, time, length, technique })
+}
+
+/// Bends are given in semitones, as the bend at each point in time. Bends without any points bend to
+/// their value over the first half of the note.
+fn read_bend(note: Node, time: Duration, length: Duration) -> Option<Vec<BendPoint>> {
+    let cents = |semitones: f64| (semitones * 100.0).round().clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+
+    let mut points: Vec<BendPoint> = children(note, "bendValues", "bendValue")
+        .filter_map(|value| Some(BendPoint {
+            time_offset: seconds(value, "time")?.saturating_sub(time),
+            cents: cents(attribute::<f64>(value, "step")?),
+        }))
+        .collect();
+
+    points.sort_by_key(|point| point.time_offset);
+
+    let bend = attribute::<f64>(note, "bend").unwrap_or(0.0);
+
+    if points.is_empty() {
+        if bend <= 0.0 {
+            return None;
+        }
+
+        points.push(BendPoint { time_offset: length / 2, cents: cents(bend) });
+    }
+
+    if points[0].time_offset > Duration::ZERO {
+        points.insert(0, BendPoint { time_offset: Duration::ZERO, cents: 0 });
+    }
+
+    Some(points)
+}
+
+/// Fingers are numbered from the thumb at 0 to the little finger at 4, with -1 for no finger
+fn finger(finger: i32) -> Option<u8> {
+    u8::try_from(finger).ok().filter(|finger| *finger <= 4)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+/// The elements with the specified name in the list with the specified name
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, list: &str, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    child(node, list).into_iter().flat_map(move |list| list.children().filter(move |child| child.has_tag_name(name)))
+}
+
+fn attribute<T: FromStr>(node: Node, name: &str) -> Option<T> {
+    node.attribute(name).and_then(|value| value.trim().parse().ok())
+}
+
+/// Techniques are flagged with 1 or, for vibrato, its strength. Unused flags are 0 or -1.
+fn flag(node: Node, name: &str) -> bool {
+    attribute::<f64>(node, name).is_some_and(|value| value > 0.0)
+}
+
+fn seconds(node: Node, name: &str) -> Option<Duration> {
+    attribute::<f64>(node, name).and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::testing::{describe_notes, fixture, guitar_parts, millis};
+    use crate::song::instrument_part::InstrumentKind;
+
+    fn import(names: &[&str]) -> RocksmithImport {
+        let files: Vec<(&str, Vec<u8>)> = names.iter()
+            .map(|name| (*name, fixture(format!("rocksmith/{}", name).as_str())))
+            .collect();
+
+        import_rocksmith(files.iter().map(|(name, data)| (*name, data.as_slice()))).unwrap()
+    }
+
+    #[test]
+    fn imports_hardest_level_of_each_phrase() {
+        let import = import(&["lead.xml"]);
+        let parts = guitar_parts(&import.song);
+        let (name, lead) = parts[0];
+
+        assert_eq!(name, "Lead");
+        assert_eq!(describe_notes(lead), vec![
+            "1000+500 0:3",
+            "2000+1000 3:7 finger 2 [hammer-ons, palm mutes, vibrato, slide to 9]",
+            "3000+1000 2:5 [tremolo, pinch harmonics, bend 0@0 100@500 0@750]",
+            "4000+0 1:12 [pull-offs, fret-hand mutes, harmonics, taps]",
+            "5000+0 0:0 finger 1 [palm mutes]",
+            "5000+0 1:0 finger 2 [palm mutes]",
+            "6000+0 0:0 finger 1 [fret-hand mutes]",
+            "6000+0 1:0 finger 3 [fret-hand mutes]",
+            "6000+0 2:0 finger 4 [fret-hand mutes]",
+        ]);
+
+        let anchors: Vec<(u64, u8, u8)> = lead.anchors.iter().map(|anchor| (millis(anchor.time), anchor.fret, anchor.width)).collect();
+        assert_eq!(anchors, vec![(1000, 1, 4), (2000, 5, 4), (4000, 12, 5)]);
+
+        assert_eq!(import.warnings, vec!["Lead: notes on strings past the 6 strings of the arrangement skipped"]);
+    }
+
+    #[test]
+    fn imports_tuning_capo_and_song() {
+        let import = import(&["lead.xml"]);
+        let (_, lead) = guitar_parts(&import.song)[0];
+        let song = &import.song;
+
+        assert_eq!(lead.tuning.string_offsets, vec![-2, 5, 10, 15, 19, 24]);
+        assert_eq!(lead.capo, 2);
+        assert_eq!(song.a440_offset_cents, -10.0);
+        assert_eq!(song.metadata.title, "Fixture");
+        assert_eq!(song.metadata.artist, "Metalforge");
+        assert_eq!(song.metadata.album, "Fixtures");
+        assert_eq!(song.metadata.year, 2024);
+        assert_eq!(millis(song.metadata.length), 8000);
+
+        let beats: Vec<(u64, usize, u8)> = song.beats.iter().map(|beat| (millis(beat.time), beat.measure, beat.beat_in_measure)).collect();
+        assert_eq!(beats, vec![(0, 1, 1), (500, 1, 2), (1000, 1, 3), (1500, 1, 4), (2000, 2, 1), (2500, 2, 2)]);
+
+        let sections: Vec<(&str, u64)> = song.sections.iter().map(|section| (section.name.as_str(), millis(section.time))).collect();
+        assert_eq!(sections, vec![("intro", 0), ("verse", 2000)]);
+    }
+
+    #[test]
+    fn takes_song_from_first_arrangement_with_beats() {
+        let import = import(&["bass.xml", "lead.xml"]);
+        let parts = guitar_parts(&import.song);
+        let kinds: Vec<InstrumentKind> = import.song.instrument_parts.iter().map(|part| part.instrument_part_type.kind()).collect();
+
+        assert_eq!(kinds, vec![InstrumentKind::BassGuitar, InstrumentKind::LeadGuitar]);
+        assert_eq!(parts[0].0, "Bass");
+        assert_eq!(parts[0].1.tuning.string_offsets, vec![-13, -8, -3, 2]);
+        assert_eq!(describe_notes(parts[0].1), vec![
+            "1000+0 3:2 [slaps]",
+            "9000+1000 2:2 [pops, bend 0@0 200@500]",
+        ]);
+
+        assert_eq!(import.song.metadata.title, "Fixture");
+        assert_eq!(import.song.beats.len(), 6);
+        assert_eq!(millis(import.song.metadata.length), 10000);
+    }
+
+    #[test]
+    fn tells_arrangements_from_other_files() {
+        assert!(is_arrangement(fixture("rocksmith/lead.xml").as_slice()));
+        assert!(is_arrangement(fixture("rocksmith/bass.xml").as_slice()));
+        assert!(!is_arrangement(fixture("rocksmith/vocals.xml").as_slice()));
+    }
+
+    #[test]
+    fn rejects_broken_arrangements() {
+        let lead = fixture("rocksmith/lead.xml");
+
+        for data in [&lead[..lead.len() / 2], b"<vocals/>", b"\xff\xfe"] {
+            assert!(import_rocksmith([("broken.xml", data)]).is_err());
+        }
+
+        assert!(import_rocksmith([]).is_err());
+    }
+}
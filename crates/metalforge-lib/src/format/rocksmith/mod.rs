@@ -0,0 +1,168 @@
+use crate::format::registry::{Confidence, SongFormat};
+use crate::format::{has_extension, path_string, ChartFiles, LoadError, SongDir};
+use crate::library::songfile::{Format, PartSummary, SongFile, SongIdBuilder};
+use crate::song::Song;
+use log::{debug, warn};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub mod import;
+
+pub use import::{import_rocksmith, is_arrangement, RocksmithImport};
+
+/// The extension of arrangement files
+pub const ARRANGEMENT_EXTENSION: &str = "xml";
+
+/// The extensions of the audio files played along with the arrangements, from the most preferred
+pub const AUDIO_EXTENSIONS: [&str; 2] = ["ogg", "wav"];
+
+/// A song directory with one or more Rocksmith 2014 arrangements and the song's audio, as found in
+/// unpacked custom songs. Other XML files, like the vocals and showlights, are ignored.
+pub struct RocksmithFormat;
+
+impl SongFormat for RocksmithFormat {
+    fn format(&self) -> Format {
+        Format::Rocksmith
+    }
+
+    fn probe(&self, path: &Path) -> Confidence {
+        // Which of the XML files are arrangements is only checked when loading. Without audio, the
+        // directory is more likely to hold a MusicXML score.
+        match (has_xml_file(path), audio_path(path).is_some()) {
+            (false, _) => Confidence::None,
+            (true, true) => Confidence::Likely,
+            (true, false) => Confidence::Possible,
+        }
+    }
+
+    fn load_entry(&self, path: &Path) -> Result<Option<SongFile>, LoadError> {
+        load_rocksmith(path)
+    }
+
+    fn load_song(&self, song_file: &SongFile) -> Result<Song, LoadError> {
+        load_rocksmith_song(song_file.song_dir.as_str())
+    }
+}
+
+/// Imports the arrangements in the specified directory and creates a library entry for them. Returns
+/// `None` if the directory doesn't contain any arrangements.
+pub fn load_rocksmith<P: AsRef<Path>>(dir: P) -> Result<Option<SongFile>, LoadError> {
+    let arrangements = arrangement_paths(dir.as_ref());
+
+    if arrangements.is_empty() {
+        debug!("No Rocksmith arrangements in {:?}", dir.as_ref());
+        return Ok(None);
+    }
+
+    let song = read_arrangements(dir.as_ref(), arrangements.as_slice())?;
+    let audio = audio_path(dir.as_ref())
+        .ok_or_else(|| LoadError::MissingFile(format!("*.{}", AUDIO_EXTENSIONS[0])))?;
+
+    let mut files = SongDir(dir.as_ref());
+    let mut id = SongIdBuilder::new();
+
+    for path in arrangements.iter().chain([&audio]) {
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let contents = files.open(file_name)?
+            .ok_or_else(|| LoadError::MissingFile(file_name.to_string()))?;
+
+        id.add_file(file_name, contents)?;
+    }
+
+    Ok(Some(SongFile {
+        id: id.build(),
+        format: Format::Rocksmith,
+        song_dir: path_string(dir.as_ref())?,
+        song_path: audio.display().to_string(),
+        parts: song.instrument_parts.iter().map(PartSummary::from).collect(),
+        metadata: song.metadata,
+    }))
+}
+
+/// Imports the arrangements in the specified directory
+pub fn load_rocksmith_song<P: AsRef<Path>>(dir: P) -> Result<Song, LoadError> {
+    let arrangements = arrangement_paths(dir.as_ref());
+
+    if arrangements.is_empty() {
+        return Err(LoadError::MissingFile(format!("*.{}", ARRANGEMENT_EXTENSION)));
+    }
+
+    read_arrangements(dir.as_ref(), arrangements.as_slice())
+}
+
+/// Imports arrangements into a single song, logging everything that couldn't be imported
+fn read_arrangements(dir: &Path, paths: &[PathBuf]) -> Result<Song, LoadError> {
+    let mut arrangements = vec![];
+
+    for path in paths {
+        let mut data = vec![];
+        std::fs::File::open(path)?.read_to_end(&mut data)?;
+
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default().to_string();
+        arrangements.push((name, data));
+    }
+
+    let import = import_rocksmith(arrangements.iter().map(|(name, data)| (name.as_str(), data.as_slice())))
+        .map_err(|message| LoadError::Parse {
+            file: dir.display().to_string(),
+            json_path: ".".to_string(),
+            message,
+        })?;
+
+    for warning in &import.warnings {
+        warn!("{:?}: {}", dir, warning);
+    }
+
+    Ok(import.song)
+}
+
+/// The arrangements in a song directory, in alphabetical order
+fn arrangement_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && has_extension(path, &[ARRANGEMENT_EXTENSION]))
+        .filter(|path| read_head(path).is_ok_and(|head| is_arrangement(head.as_slice())))
+        .collect();
+
+    paths.sort();
+    paths
+}
+
+/// Checks if a directory has any XML files, without reading them
+fn has_xml_file(dir: &Path) -> bool {
+    std::fs::read_dir(dir).is_ok_and(|entries| entries
+        .filter_map(|entry| entry.ok())
+        .any(|entry| has_extension(entry.path().as_path(), &[ARRANGEMENT_EXTENSION])))
+}
+
+/// Reads enough of a file to check its root element
+fn read_head(path: &Path) -> Result<Vec<u8>, std::io::Error> {
+    let mut head = vec![];
+    std::fs::File::open(path)?.take(4096).read_to_end(&mut head)?;
+
+    Ok(head)
+}
+
+/// The audio file in a song directory. Custom songs often come with a short preview as well, which
+/// is only used if there's no other audio.
+fn audio_path(dir: &Path) -> Option<PathBuf> {
+    let mut paths: Vec<(bool, usize, PathBuf)> = std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let extension = AUDIO_EXTENSIONS.iter().position(|extension| has_extension(path.as_path(), &[extension]))?;
+            let is_preview = path.file_stem()?.to_str()?.to_lowercase().contains("preview");
+
+            Some((is_preview, extension, path))
+        })
+        .collect();
+
+    paths.sort();
+    paths.into_iter().next().map(|(_, _, path)| path)
+}
@@ -0,0 +1,121 @@
+use crate::song::guitar::{BendPoint, GuitarNote, GuitarPart, GuitarTechnique, GuitarTuning};
+use crate::song::instrument_part::{InstrumentPart, InstrumentPartType};
+use crate::song::metadata::Metadata;
+use crate::song::{Beat, Song};
+use std::time::Duration;
+
+/// Reads a file from `tests/fixtures`
+pub(crate) fn fixture(path: &str) -> Vec<u8> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(path);
+    std::fs::read(path.as_path()).unwrap_or_else(|error| panic!("failed to read {:?}: {}", path, error))
+}
+
+pub(crate) fn note(time_millis: u64, length_millis: u64, string: u8, fret: u8, finger: Option<u8>, technique: Vec<GuitarTechnique>) -> GuitarNote {
+    GuitarNote {
+        string,
+        fret,
+        finger,
+        time: Duration::from_millis(time_millis),
+        length: Duration::from_millis(length_millis),
+        technique,
+    }
+}
+
+/// A bend through the specified points, given as the offset in milliseconds and the cents
+pub(crate) fn bend(points: &[(u64, i16)]) -> GuitarTechnique {
+    GuitarTechnique::Bend {
+        points: points.iter()
+            .map(|(offset_millis, cents)| BendPoint { time_offset: Duration::from_millis(*offset_millis), cents: *cents })
+            .collect(),
+    }
+}
+
+pub(crate) fn guitar_part(tuning: Vec<i8>, capo: u8, notes: Vec<GuitarNote>) -> GuitarPart {
+    GuitarPart { notes, tuning: GuitarTuning::from(tuning), capo, anchors: vec![] }
+}
+
+pub(crate) fn part(name: &str, instrument_part_type: InstrumentPartType) -> InstrumentPart {
+    InstrumentPart { name: name.to_string(), instrument_part_type }
+}
+
+/// Beats in 4/4 at 120 bpm, starting at the specified time
+pub(crate) fn beats(first_millis: u64, count: u64) -> Vec<Beat> {
+    (0..count)
+        .map(|idx| Beat {
+            time: Duration::from_millis(first_millis + idx * 500),
+            measure: idx as usize / 4 + 1,
+            beat_in_measure: (idx % 4 + 1) as u8,
+        })
+        .collect()
+}
+
+/// A four second song with the specified parts and two measures of beats, which tests change the
+/// other fields of as needed
+pub(crate) fn song(instrument_parts: Vec<InstrumentPart>) -> Song {
+    Song {
+        metadata: Metadata {
+            title: "Fixture".to_string(),
+            artist: "Metalforge".to_string(),
+            album: String::new(),
+            year: 0,
+            length: Duration::from_secs(4),
+            key: None,
+            preview_start: None,
+        },
+        instrument_parts,
+        beats: beats(0, 8),
+        sections: vec![],
+        a440_offset_cents: 0.0,
+    }
+}
+
+pub(crate) fn millis(duration: Duration) -> u64 {
+    (duration.as_secs_f64() * 1000.0).round() as u64
+}
+
+/// The guitar parts of a song with their names
+pub(crate) fn guitar_parts(song: &Song) -> Vec<(&str, &GuitarPart)> {
+    song.instrument_parts.iter()
+        .filter_map(|part| match &part.instrument_part_type {
+            InstrumentPartType::LeadGuitar(guitar_part)
+            | InstrumentPartType::RhythmGuitar(guitar_part)
+            | InstrumentPartType::BassGuitar(guitar_part) => Some((part.name.as_str(), guitar_part)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Describes a note as its time and length in milliseconds, string, fret, finger and techniques,
+/// e.g. "1000+500 3:7 finger 2 [hammer-ons, slide to 9]"
+pub(crate) fn describe(note: &GuitarNote) -> String {
+    let mut description = format!("{}+{} {}:{}", millis(note.time), millis(note.length), note.string, note.fret);
+
+    if let Some(finger) = note.finger {
+        description.push_str(format!(" finger {}", finger).as_str());
+    }
+
+    if !note.technique.is_empty() {
+        let techniques: Vec<String> = note.technique.iter().map(describe_technique).collect();
+        description.push_str(format!(" [{}]", techniques.join(", ")).as_str());
+    }
+
+    description
+}
+
+pub(crate) fn describe_technique(technique: &GuitarTechnique) -> String {
+    match technique {
+        GuitarTechnique::Slide { to_fret } => format!("slide to {}", to_fret),
+        GuitarTechnique::Bend { points } => {
+            let points: Vec<String> = points.iter()
+                .map(|point| format!("{}@{}", point.cents, millis(point.time_offset)))
+                .collect();
+
+            format!("bend {}", points.join(" "))
+        }
+        technique => technique.name().to_string(),
+    }
+}
+
+pub(crate) fn describe_notes(part: &GuitarPart) -> Vec<String> {
+    part.notes.iter().map(describe).collect()
+}
@@ -2,3 +2,6 @@ pub mod engine;
 pub mod library;
 pub mod song;
 pub mod format;
+pub mod playlist;
+pub mod practice;
+mod store;
@@ -0,0 +1,169 @@
+use crate::library::songfile::SongId;
+use crate::store::JsonFile;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::path::Path;
+
+/// The highest personal difficulty rating a song can be given
+pub const MAX_RATING: u8 = 5;
+
+/// What the user noted about a song on top of the metadata of the chart
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SongAnnotations {
+    pub favorite: bool,
+    /// Free-form labels for grouping songs, e.g. "warmup" or "gig"
+    pub tags: Vec<String>,
+    /// The user's own difficulty rating from 1 to `MAX_RATING`, `None` means the song isn't rated
+    pub rating: Option<u8>,
+    pub notes: String,
+}
+
+impl SongAnnotations {
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Checks if the song has the specified tag, ignoring case
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own_tag| own_tag.eq_ignore_ascii_case(tag))
+    }
+
+    /// Replaces the tags with the ones listed in the text, separated by commas
+    pub fn set_tags(&mut self, text: &str) {
+        self.tags.clear();
+
+        for tag in text.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+            if !self.has_tag(tag) {
+                self.tags.push(tag.to_string());
+            }
+        }
+    }
+}
+
+/// Keeps the annotations of every song in a local JSON file next to the other library data, so song
+/// directories are never written to. The file is rewritten whenever an annotation changes.
+pub struct AnnotationStore {
+    file: JsonFile,
+    pub songs: BTreeMap<SongId, SongAnnotations>,
+}
+
+impl AnnotationStore {
+
+    /// Reads the annotations from the specified file. A missing file is treated as if no songs had
+    /// been annotated yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = JsonFile::new(path);
+        let songs: BTreeMap<SongId, SongAnnotations> = file.read()?.unwrap_or_default();
+
+        info!("Loaded annotations of {} songs from {:?}", songs.len(), file.path());
+
+        Ok(Self { file, songs })
+    }
+
+    /// Creates an empty store, the file is created when the first song is annotated
+    pub fn empty<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            file: JsonFile::new(path),
+            songs: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, song_id: &SongId) -> Option<&SongAnnotations> {
+        self.songs.get(song_id)
+    }
+
+    pub fn is_favorite(&self, song_id: &SongId) -> bool {
+        self.get(song_id).is_some_and(|annotations| annotations.favorite)
+    }
+
+    /// Applies a change to the annotations of a song and saves them. Songs left without annotations
+    /// are removed from the file.
+    pub fn edit<F: FnOnce(&mut SongAnnotations)>(&mut self, song_id: &SongId, change: F) -> Result<(), Error> {
+        let mut annotations = self.songs.remove(song_id).unwrap_or_default();
+        change(&mut annotations);
+
+        if !annotations.is_empty() {
+            self.songs.insert(song_id.clone(), annotations);
+        }
+
+        self.save()
+    }
+
+    /// Moves the annotations of a song to its new ID after the song's files changed. Annotations
+    /// the song already has under the new ID, e.g. when an edit is reverted, are kept instead.
+    pub fn rekey(&mut self, previous_id: &SongId, song_id: &SongId) -> Result<(), Error> {
+        if previous_id == song_id {
+            return Ok(());
+        }
+
+        let Some(annotations) = self.songs.remove(previous_id) else {
+            return Ok(());
+        };
+
+        self.songs.entry(song_id.clone()).or_insert(annotations);
+        self.save()
+    }
+
+    /// The distinct tags given to songs, sorted alphabetically
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = vec![];
+
+        for tag in self.songs.values().flat_map(|annotations| annotations.tags.iter()) {
+            if !tags.iter().any(|known| known.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.clone());
+            }
+        }
+
+        tags.sort_by_key(|tag| tag.to_lowercase());
+        tags
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        self.file.write(&self.songs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rekeys_annotations_of_updated_songs() {
+        let dir = std::env::temp_dir().join(format!("metalforge-annotations-{}", std::process::id()));
+        let path = dir.join("annotations.json");
+        let _ = std::fs::remove_dir_all(dir.as_path());
+
+        let previous_id = SongId("previous".to_string());
+        let song_id = SongId("updated".to_string());
+
+        let mut store = AnnotationStore::empty(path.as_path());
+        store.edit(&previous_id, |annotations| {
+            annotations.favorite = true;
+            annotations.set_tags("warmup, gig");
+            annotations.notes = "Watch the bends".to_string();
+        }).unwrap();
+        let annotated = store.get(&previous_id).cloned();
+
+        store.rekey(&previous_id, &song_id).unwrap();
+
+        let reopened = AnnotationStore::open(path.as_path()).unwrap();
+        assert_eq!(reopened.get(&previous_id), None);
+        assert_eq!(reopened.get(&song_id).cloned(), annotated);
+
+        // Annotations already under the new ID win over the moved ones
+        let other_id = SongId("other".to_string());
+        store.edit(&other_id, |annotations| annotations.rating = Some(2)).unwrap();
+        store.rekey(&song_id, &other_id).unwrap();
+
+        let reopened = AnnotationStore::open(path.as_path()).unwrap();
+        assert_eq!(reopened.songs.len(), 1);
+        assert_eq!(reopened.get(&other_id).and_then(|annotations| annotations.rating), Some(2));
+        assert!(!reopened.is_favorite(&other_id));
+
+        std::fs::remove_dir_all(dir.as_path()).unwrap();
+    }
+}
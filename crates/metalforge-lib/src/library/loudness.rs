@@ -0,0 +1,416 @@
+use crate::format::registry::FormatRegistry;
+use crate::format::LoadError;
+use crate::library::songfile::{SongFile, SongId};
+use crate::store::JsonFile;
+use log::{debug, error, info};
+use rodio::decoder::DecoderBuilder;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::io::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The loudness songs are normalized to by default, in LUFS. This is the level most streaming
+/// services play music at.
+pub const DEFAULT_TARGET_LUFS: f32 = -14.0;
+
+/// Blocks quieter than this are silence and don't count towards the loudness of a song
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Blocks this much quieter than the average of the blocks above the absolute gate are quiet
+/// passages, which don't count towards the loudness either
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Loudness is measured over 400ms blocks overlapping by 75%, i.e. a new block starts every 100ms
+const STEP_MILLIS: u32 = 100;
+const STEPS_PER_BLOCK: usize = 4;
+
+/// The number of songs measured between saves of the loudness index while analyzing a library
+const SAVE_INTERVAL: usize = 25;
+
+/// How loud a song is, measured the way EBU R128 describes it
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct Loudness {
+    /// The integrated loudness of the whole song, in LUFS
+    pub integrated_lufs: f32,
+    /// The highest absolute sample value, 1.0 being full scale
+    pub peak: f32,
+}
+
+impl Loudness {
+
+    /// The volume the song should be played at to reach the target loudness. The song is never
+    /// amplified so much that its peak would clip.
+    pub fn gain(&self, target_lufs: f32) -> f32 {
+        let gain = 10f32.powf((target_lufs - self.integrated_lufs) / 20.0);
+
+        if self.peak > 0.0 {
+            gain.min(1.0 / self.peak)
+        } else {
+            gain
+        }
+    }
+}
+
+/// Decodes the entire audio of a song and measures its loudness. This takes a while, so songs are
+/// analyzed once and the results are kept in a `LoudnessStore`.
+pub fn analyze(song_file: &SongFile, formats: &FormatRegistry) -> Result<Loudness, LoadError> {
+    let audio = formats.open_audio(song_file)?;
+
+    let decoder = DecoderBuilder::new()
+        .with_data(audio.data)
+        .with_byte_len(audio.byte_len)
+        .build()
+        .map_err(|error| LoadError::Io(Error::other(error)))?;
+
+    let channels = decoder.channels().get() as usize;
+    let sample_rate = decoder.sample_rate().get();
+
+    Ok(measure_samples(decoder, channels, sample_rate))
+}
+
+/// Measures the loudness of interleaved samples with the specified number of channels
+fn measure_samples<I: IntoIterator<Item = f32>>(samples: I, channels: usize, sample_rate: u32) -> Loudness {
+    let frames_per_step = (sample_rate * STEP_MILLIS / 1000).max(1) as usize;
+
+    let mut filters = vec![KWeighting::new(sample_rate as f64); channels];
+    let mut step_energies = vec![];
+    let mut energy = 0.0;
+    let mut step_samples = 0;
+    let mut peak: f32 = 0.0;
+
+    for (idx, sample) in samples.into_iter().enumerate() {
+        peak = peak.max(sample.abs());
+
+        let weighted = filters[idx % channels].process(f64::from(sample));
+        energy += weighted * weighted;
+        step_samples += 1;
+
+        if step_samples == frames_per_step * channels {
+            // Mean square per channel, summed over the channels
+            step_energies.push(energy / frames_per_step as f64);
+            energy = 0.0;
+            step_samples = 0;
+        }
+    }
+
+    Loudness {
+        integrated_lufs: integrated_loudness(&step_energies) as f32,
+        peak,
+    }
+}
+
+/// Gates the 400ms blocks made of consecutive steps and averages the energy of the blocks left
+fn integrated_loudness(step_energies: &[f64]) -> f64 {
+    let blocks: Vec<f64> = step_energies.windows(STEPS_PER_BLOCK)
+        .map(|steps| steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64)
+        .filter(|energy| to_lufs(*energy) > ABSOLUTE_GATE_LUFS)
+        .collect();
+
+    if blocks.is_empty() {
+        return ABSOLUTE_GATE_LUFS;
+    }
+
+    let relative_gate = to_lufs(mean(&blocks)) + RELATIVE_GATE_LU;
+
+    let gated: Vec<f64> = blocks.into_iter()
+        .filter(|energy| to_lufs(*energy) > relative_gate)
+        .collect();
+
+    to_lufs(mean(&gated))
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+fn to_lufs(energy: f64) -> f64 {
+    if energy > 0.0 {
+        -0.691 + 10.0 * energy.log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+/// The K-weighting filter of ITU-R BS.1770, which models how loud the ear perceives each
+/// frequency: a high shelf boosting the treble followed by a high pass cutting the low end
+#[derive(Clone)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+
+    fn new(sample_rate: f64) -> Self {
+        // The coefficients are derived for any sample rate from the analog prototypes of the filters
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        let shelf = Biquad::new(
+            [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        let output = self.b[0] * sample + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+
+        self.x = [sample, self.x[0]];
+        self.y = [output, self.y[0]];
+
+        output
+    }
+}
+
+/// The loudness of every song analyzed so far. Songs are identified by the contents of their files,
+/// so a song only needs to be analyzed again if it changes.
+pub struct LoudnessStore {
+    file: JsonFile,
+    pub songs: BTreeMap<SongId, Loudness>,
+}
+
+impl LoudnessStore {
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = JsonFile::new(path);
+        let songs: BTreeMap<SongId, Loudness> = file.read()?.unwrap_or_default();
+
+        info!("Loaded loudness of {} songs from {:?}", songs.len(), file.path());
+
+        Ok(Self { file, songs })
+    }
+
+    pub fn empty<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            file: JsonFile::new(path),
+            songs: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, song_id: &SongId) -> Option<Loudness> {
+        self.songs.get(song_id).copied()
+    }
+
+    /// Adds the loudness of a song. The index is only written to disk by `save`.
+    pub fn insert(&mut self, song_id: SongId, loudness: Loudness) {
+        self.songs.insert(song_id, loudness);
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        self.file.write(&self.songs)
+    }
+}
+
+/// Measures the loudness of every song that isn't in the loudness index yet, on as many threads as
+/// there are cores. The index is saved every few songs and once at the end, so stopping early by
+/// setting `cancelled` loses little work.
+pub fn analyze_missing(songs: &[SongFile], index: &Mutex<LoudnessStore>, formats: &FormatRegistry, cancelled: &AtomicBool) {
+    let pending: Vec<&SongFile> = songs.iter()
+        .filter(|song| index.lock().is_ok_and(|index| index.get(&song.id).is_none()))
+        .collect();
+
+    if pending.is_empty() {
+        return;
+    }
+
+    info!("Analyzing the loudness of {} songs", pending.len());
+
+    let next = AtomicUsize::new(0);
+    let measured = AtomicUsize::new(0);
+    let workers = thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while !cancelled.load(Ordering::Relaxed) {
+                    let Some(song) = pending.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+
+                    if measure(song, index, formats)
+                        && (measured.fetch_add(1, Ordering::Relaxed) + 1).is_multiple_of(SAVE_INTERVAL) {
+                        save(index);
+                    }
+                }
+            });
+        }
+    });
+
+    save(index);
+}
+
+/// Measures the loudness of a song and saves it in the loudness index
+pub fn analyze_into(song: &SongFile, index: &Mutex<LoudnessStore>, formats: &FormatRegistry) {
+    if measure(song, index, formats) {
+        save(index);
+    }
+}
+
+/// Measures the loudness of a song and adds it to the loudness index without saving it. Returns
+/// whether the song could be measured.
+fn measure(song: &SongFile, index: &Mutex<LoudnessStore>, formats: &FormatRegistry) -> bool {
+    match analyze(song, formats) {
+        Ok(loudness) => {
+            debug!("Loudness of {:?}: {:?}", song.song_dir, loudness);
+
+            if let Ok(mut index) = index.lock() {
+                index.insert(song.id.clone(), loudness);
+            }
+
+            true
+        }
+        Err(error) => {
+            error!("Failed to analyze the loudness of {:?}: {}", song.song_dir, error);
+            false
+        }
+    }
+}
+
+fn save(index: &Mutex<LoudnessStore>) {
+    if let Ok(index) = index.lock() && let Err(error) = index.save() {
+        error!("Failed to save loudness index: {:?}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interleaved stereo samples of a sine wave at the same level on both channels
+    fn stereo_sine(frequency: f64, dbfs: f64, seconds: f64, sample_rate: u32) -> Vec<f32> {
+        let amplitude = 10f64.powf(dbfs / 20.0);
+        let frames = (seconds * sample_rate as f64) as usize;
+
+        (0..frames)
+            .map(|frame| (amplitude * (2.0 * PI * frequency * frame as f64 / sample_rate as f64).sin()) as f32)
+            .flat_map(|sample| [sample, sample])
+            .collect()
+    }
+
+    fn assert_lufs(loudness: Loudness, expected: f32, tolerance: f32) {
+        assert!((loudness.integrated_lufs - expected).abs() < tolerance,
+                "expected {} LUFS, measured {}", expected, loudness.integrated_lufs);
+    }
+
+    #[test]
+    fn measures_reference_sine() {
+        // BS.1770 calibrates the K-weighting so a 997 Hz sine at -20 dBFS on both channels of a
+        // stereo signal measures -20 LUFS, at any sample rate
+        for sample_rate in [44100, 48000, 96000] {
+            let loudness = measure_samples(stereo_sine(997.0, -20.0, 10.0, sample_rate), 2, sample_rate);
+
+            assert_lufs(loudness, -20.0, 0.05);
+            assert!((loudness.peak - 0.1).abs() < 0.001, "peak {}", loudness.peak);
+        }
+    }
+
+    #[test]
+    fn weights_frequencies() {
+        let sample_rate = 48000;
+        let measure = |frequency| measure_samples(stereo_sine(frequency, -20.0, 10.0, sample_rate), 2, sample_rate).integrated_lufs;
+
+        // The high pass cuts the low end. The shelf boosts the treble by about 4 dB, which is 3.35 dB
+        // more than the 997 Hz reference is boosted by.
+        assert!(measure(20.0) < -30.0);
+        assert!((measure(10000.0) + 16.65).abs() < 0.05);
+    }
+
+    #[test]
+    fn ignores_silence() {
+        let sample_rate = 48000;
+        let mut samples = stereo_sine(997.0, -20.0, 5.0, sample_rate);
+        samples.extend(std::iter::repeat_n(0.0, 2 * 5 * sample_rate as usize));
+
+        // The blocks overlapping the end of the sine are partly silent but still above the gates
+        assert_lufs(measure_samples(samples, 2, sample_rate), -20.0, 0.2);
+    }
+
+    #[test]
+    fn ignores_quiet_passages() {
+        // The passage at -40 dBFS is more than 10 LU below the average, so only the loud one counts.
+        // Averaging both passages would measure about -23 LUFS.
+        let sample_rate = 48000;
+        let mut samples = stereo_sine(997.0, -20.0, 5.0, sample_rate);
+        samples.extend(stereo_sine(997.0, -40.0, 5.0, sample_rate));
+
+        assert_lufs(measure_samples(samples, 2, sample_rate), -20.0, 0.2);
+    }
+
+    #[test]
+    fn measures_silence_at_absolute_gate() {
+        let loudness = measure_samples(vec![0.0; 2 * 48000], 2, 48000);
+
+        assert_eq!(loudness.integrated_lufs, ABSOLUTE_GATE_LUFS as f32);
+        assert_eq!(loudness.peak, 0.0);
+    }
+
+    #[test]
+    fn gains_towards_target() {
+        let loudness = Loudness { integrated_lufs: -20.0, peak: 0.1 };
+
+        assert!((loudness.gain(-14.0) - 10f32.powf(6.0 / 20.0)).abs() < 0.001);
+        assert!((loudness.gain(-26.0) - 10f32.powf(-6.0 / 20.0)).abs() < 0.001);
+    }
+
+    #[test]
+    fn limits_gain_to_peak() {
+        let loudness = Loudness { integrated_lufs: -20.0, peak: 0.8 };
+
+        assert_eq!(loudness.gain(-14.0), 1.25);
+        // Turning the song down is never limited
+        assert!(loudness.gain(-30.0) < 1.0);
+    }
+
+    #[test]
+    fn gains_silence_without_limit() {
+        let loudness = Loudness { integrated_lufs: -30.0, peak: 0.0 };
+
+        assert!((loudness.gain(-10.0) - 10.0).abs() < 0.001);
+    }
+}
@@ -1,64 +1,178 @@
-use crate::format::load_dir;
-use crate::library::songfile::{SongFile};
-use log::{error, warn};
-use std::io::Error;
-use std::path::{Path};
+use crate::format::registry::FormatRegistry;
+use crate::library::report::ScanReport;
+use crate::library::scanner::{LibraryScanner, ScanEvent};
+use crate::library::songfile::{SongFile, SongId};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
 
+pub mod annotations;
+pub mod loudness;
+pub mod query;
+pub mod report;
+pub mod scanner;
+pub mod song_dirs;
 pub mod songfile;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod watcher;
 
+#[derive(Clone)]
 pub struct Library {
-    pub songs: Vec<SongFile>
+    pub songs: Vec<SongFile>,
+    /// The outcome of scanning each song directory, including the ones that failed to load
+    pub report: ScanReport
 }
 
 impl Library {
 
     pub fn empty() -> Self {
         Self {
-            songs: vec![]
+            songs: vec![],
+            report: ScanReport::default()
         }
     }
 
+    /// Scans the library paths for songs in any of the built-in formats
     pub fn scan_directories<P: AsRef<Path>>(paths: Vec<P>) -> Library {
-        let mut songs = vec![];
-
-        for path in paths {
-            if let Ok(mut path_songs) = scan_directory(&path) {
-                songs.append(&mut path_songs);
-            } else {
-                warn!("Failed to read songs at {:?}", path.as_ref());
-            }
-        }
+        Self::scan_directories_with(paths, &FormatRegistry::default(), &AtomicBool::new(false), |_event| {})
+    }
+
+    /// Scans the library paths in parallel for songs in any of the registered formats, notifying
+    /// `listener` about the progress of the scan and the songs found. Setting `cancelled` stops the
+    /// scan and returns the songs found so far.
+    pub fn scan_directories_with<P, F>(paths: Vec<P>, formats: &FormatRegistry, cancelled: &AtomicBool, listener: F) -> Library
+    where
+        P: AsRef<Path>,
+        F: Fn(ScanEvent) + Sync
+    {
+        let (mut songs, report) = LibraryScanner::new(formats, cancelled, listener).scan(paths);
+
+        songs.sort_by(compare_songs);
 
         Library {
-            songs
+            songs,
+            report
         }
     }
-}
 
-pub fn scan_directory<P: AsRef<Path>>(path: P) -> Result<Vec<SongFile>, Error> {
-    let mut songs = vec![];
-
-    for maybe_entry in std::fs::read_dir(path)? {
-        let entry = maybe_entry?;
-
-        match load_dir(entry.path()) {
-            Ok(Some(songfile)) => songs.push(songfile),
-            Ok(None) => {
-                if entry.metadata()?.is_dir() {
-                    let mut sub_result = scan_directory(entry.path().as_path())?;
-                    songs.append(&mut sub_result);
-                }
-            },
-            Err(error) => error!("Failed to scan library {:?}: {:?}", entry.path(), error),
+    pub fn get(&self, id: &SongId) -> Option<&SongFile> {
+        self.songs.iter().find(|song| &song.id == id)
+    }
+
+    /// Adds a song to the library in sorted position, replacing the song with the same ID if there is
+    /// one. Returns `true` if an existing song was replaced.
+    pub fn upsert(&mut self, songfile: SongFile) -> bool {
+        let replaced = self.remove(&songfile.id).is_some();
+        let idx = self.songs.partition_point(|song| compare_songs(song, &songfile) != Ordering::Greater);
+        self.songs.insert(idx, songfile);
+
+        replaced
+    }
+
+    pub fn remove(&mut self, id: &SongId) -> Option<SongFile> {
+        self.songs.iter()
+            .position(|song| &song.id == id)
+            .map(|idx| self.songs.remove(idx))
+    }
+
+    /// Groups the songs that have more than one version in the library, i.e. songs with the same
+    /// artist and title but different contents. Letter case and surrounding whitespace are ignored.
+    pub fn versions(&self) -> Vec<Vec<&SongFile>> {
+        let mut groups: HashMap<(String, String), Vec<&SongFile>> = HashMap::new();
+
+        for song in &self.songs {
+            groups.entry(version_key(song)).or_default().push(song);
         }
+
+        let mut versions: Vec<Vec<&SongFile>> = groups.into_values()
+            .filter(|group| group.len() > 1)
+            .collect();
+
+        versions.sort_by(|a, b| compare_songs(a[0], b[0]));
+        versions
+    }
+
+    /// Lists every version of the specified song in the library, including the song itself
+    pub fn versions_of(&self, song: &SongFile) -> Vec<&SongFile> {
+        let key = version_key(song);
+
+        self.songs.iter()
+            .filter(|other| version_key(other) == key)
+            .collect()
+    }
+}
+
+fn version_key(song: &SongFile) -> (String, String) {
+    (song.metadata.artist.trim().to_lowercase(), song.metadata.title.trim().to_lowercase())
+}
+
+/// The default library order: by artist, then by title
+fn compare_songs(a: &SongFile, b: &SongFile) -> Ordering {
+    let artist_cmd = a.metadata.artist.as_str().cmp(b.metadata.artist.as_str());
+    let title_cmd = a.metadata.title.as_str().cmp(b.metadata.title.as_str());
+
+    artist_cmd.then(title_cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::testing::song_file;
+
+    fn song(id: &str, artist: &str, title: &str) -> SongFile {
+        let mut song = song_file(id, format!("/songs/{}", id).as_str());
+        song.metadata.artist = artist.to_string();
+        song.metadata.title = title.to_string();
+        song
     }
 
-    songs.sort_by(|a, b| {
-        let artist_cmd = a.song.metadata.artist.as_str().cmp(b.song.metadata.artist.as_str());
-        let title_cmd = a.song.metadata.title.as_str().cmp(b.song.metadata.title.as_str());
+    fn ids(songs: &[&SongFile]) -> Vec<String> {
+        songs.iter().map(|song| song.id.0.clone()).collect()
+    }
 
-        artist_cmd.then(title_cmd)
-    });
+    fn library_ids(library: &Library) -> Vec<String> {
+        ids(&library.songs.iter().collect::<Vec<&SongFile>>())
+    }
 
-    Ok(songs)
-}
\ No newline at end of file
+    #[test]
+    fn groups_versions_ignoring_case_and_whitespace() {
+        let library = Library {
+            songs: vec![
+                song("live", "Artist", "Song"),
+                song("other", "Artist", "Other Song"),
+                song("studio", " artist", "SONG "),
+                song("cover", "Cover Band", "Song"),
+            ],
+            ..Library::empty()
+        };
+
+        let versions: Vec<Vec<String>> = library.versions().iter().map(|group| ids(group)).collect();
+        assert_eq!(versions, vec![vec!["live".to_string(), "studio".to_string()]]);
+
+        assert_eq!(ids(&library.versions_of(&library.songs[2])), vec!["live", "studio"]);
+        assert_eq!(ids(&library.versions_of(&library.songs[3])), vec!["cover"]);
+    }
+
+    #[test]
+    fn upserts_songs_in_library_order() {
+        let mut library = Library::empty();
+
+        assert!(!library.upsert(song("c", "Charlie", "Song")));
+        assert!(!library.upsert(song("a", "Alpha", "Song")));
+        assert!(!library.upsert(song("b", "Alpha", "Another Song")));
+        assert_eq!(library_ids(&library), vec!["b", "a", "c"]);
+
+        // A song with the same ID replaces the existing one and moves to its new position
+        assert!(library.upsert(song("c", "Alpha", "Zebra")));
+        assert_eq!(library_ids(&library), vec!["b", "a", "c"]);
+        assert_eq!(library.get(&SongId("c".to_string())).unwrap().metadata.artist, "Alpha");
+        assert!(library.upsert(song("a", "Delta", "Song")));
+        assert_eq!(library_ids(&library), vec!["b", "c", "a"]);
+
+        assert_eq!(library.remove(&SongId("c".to_string())).map(|song| song.id.0), Some("c".to_string()));
+        assert!(library.remove(&SongId("c".to_string())).is_none());
+        assert_eq!(library_ids(&library), vec!["b", "a"]);
+    }
+}
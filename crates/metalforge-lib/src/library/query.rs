@@ -0,0 +1,309 @@
+use crate::library::annotations::{AnnotationStore, SongAnnotations};
+use crate::library::songfile::{PartSummary, SongFile};
+use crate::library::Library;
+use crate::song::guitar::GuitarTuning;
+use crate::song::instrument_part::InstrumentKind;
+use std::cmp::Ordering;
+
+/// Describes which songs to list from the library and in what order. The default query matches
+/// every song and sorts by artist, then title. Besides the chart metadata, songs can be filtered by
+/// the annotations the user gave them.
+#[derive(Clone, Default)]
+pub struct LibraryQuery {
+    /// Words that must all appear in the title, artist, album, tags or notes of the song, ignoring case
+    pub text: String,
+    /// Only list songs that have a part for this instrument
+    pub instrument: Option<InstrumentKind>,
+    /// Only list songs that have a part in this tuning
+    pub tuning: Option<GuitarTuning>,
+    /// Only list songs that have a part with the capo on this fret. 0 means no capo
+    pub capo: Option<u8>,
+    /// Only list songs released between these years, inclusive
+    pub year: Option<(u16, u16)>,
+    /// Only list songs that have a part with a difficulty between these values, inclusive
+    pub difficulty: Option<(f32, f32)>,
+    /// Only list songs marked as favorite
+    pub favorites: bool,
+    /// Only list songs with this tag, ignoring case
+    pub tag: Option<String>,
+    /// Only list songs the user rated this difficult
+    pub rating: Option<u8>,
+    pub sort: SortKey,
+    pub descending: bool,
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum SortKey {
+    /// Sort by artist, then by title
+    #[default]
+    Artist,
+    Title,
+    Album,
+    Year,
+    /// Sort by the difficulty of the easiest part
+    Difficulty,
+    Length,
+    /// Sort by the user's own difficulty rating, unrated songs first
+    Rating,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 7] = [SortKey::Artist, SortKey::Title, SortKey::Album, SortKey::Year, SortKey::Difficulty, SortKey::Length, SortKey::Rating];
+}
+
+impl LibraryQuery {
+
+    pub fn matches(&self, song: &SongFile, annotations: Option<&SongAnnotations>) -> bool {
+        self.matches_text(song, annotations)
+            && self.year.is_none_or(|(from, to)| (from..=to).contains(&song.metadata.year))
+            && (!self.filters_parts() || song.parts.iter().any(|part| self.matches_part(part)))
+            && self.matches_annotations(annotations)
+    }
+
+    fn matches_text(&self, song: &SongFile, annotations: Option<&SongAnnotations>) -> bool {
+        let mut searched = vec![
+            song.metadata.title.to_lowercase(),
+            song.metadata.artist.to_lowercase(),
+            song.metadata.album.to_lowercase(),
+        ];
+
+        if let Some(annotations) = annotations {
+            searched.extend(annotations.tags.iter().map(|tag| tag.to_lowercase()));
+            searched.push(annotations.notes.to_lowercase());
+        }
+
+        self.text.to_lowercase()
+            .split_whitespace()
+            .all(|word| searched.iter().any(|text| text.contains(word)))
+    }
+
+    fn matches_annotations(&self, annotations: Option<&SongAnnotations>) -> bool {
+        (!self.favorites || annotations.is_some_and(|annotations| annotations.favorite))
+            && self.tag.as_ref().is_none_or(|tag| annotations.is_some_and(|annotations| annotations.has_tag(tag)))
+            && self.rating.is_none_or(|rating| annotations.and_then(|annotations| annotations.rating) == Some(rating))
+    }
+
+    fn filters_parts(&self) -> bool {
+        self.instrument.is_some() || self.tuning.is_some() || self.capo.is_some() || self.difficulty.is_some()
+    }
+
+    /// Part filters have to match the same part, e.g. a song with a bass part in drop D and a lead
+    /// guitar part in E standard doesn't match a filter for lead guitar in drop D
+    fn matches_part(&self, part: &PartSummary) -> bool {
+        self.instrument.is_none_or(|instrument| part.kind == instrument)
+            && self.tuning.as_ref().is_none_or(|tuning| part.tuning.as_ref() == Some(tuning))
+            && self.capo.is_none_or(|capo| part.capo == capo)
+            && self.difficulty.is_none_or(|(from, to)| part.difficulty >= from && part.difficulty <= to)
+    }
+
+    pub fn compare(&self, a: &SongFile, b: &SongFile, annotations: &AnnotationStore) -> Ordering {
+        let rating = |song: &SongFile| annotations.get(&song.id).and_then(|annotations| annotations.rating);
+
+        let by_artist = || a.metadata.artist.cmp(&b.metadata.artist)
+            .then_with(|| a.metadata.title.cmp(&b.metadata.title));
+
+        let ordering = match self.sort {
+            SortKey::Artist => by_artist(),
+            SortKey::Title => a.metadata.title.cmp(&b.metadata.title),
+            SortKey::Album => a.metadata.album.cmp(&b.metadata.album),
+            SortKey::Year => a.metadata.year.cmp(&b.metadata.year),
+            SortKey::Difficulty => min_difficulty(a).total_cmp(&min_difficulty(b)),
+            SortKey::Length => a.metadata.length.cmp(&b.metadata.length),
+            SortKey::Rating => rating(a).cmp(&rating(b)),
+        }.then_with(by_artist);
+
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+fn min_difficulty(song: &SongFile) -> f32 {
+    song.parts.iter()
+        .map(|part| part.difficulty)
+        .min_by(f32::total_cmp)
+        .unwrap_or(0.0)
+}
+
+impl Library {
+
+    /// Lists the songs matching the query, in the order requested by the query. The annotations are
+    /// kept apart from the library, as they belong to the user rather than the song files.
+    pub fn query(&self, query: &LibraryQuery, annotations: &AnnotationStore) -> Vec<&SongFile> {
+        let mut songs: Vec<&SongFile> = self.songs.iter()
+            .filter(|song| query.matches(song, annotations.get(&song.id)))
+            .collect();
+
+        songs.sort_by(|a, b| query.compare(a, b, annotations));
+        songs
+    }
+
+    /// The distinct tunings used by the parts in the library, useful for offering tuning filters
+    pub fn tunings(&self) -> Vec<GuitarTuning> {
+        let mut tunings: Vec<GuitarTuning> = vec![];
+
+        for tuning in self.songs.iter().flat_map(|song| song.parts.iter()).filter_map(|part| part.tuning.as_ref()) {
+            if !tunings.contains(tuning) {
+                tunings.push(tuning.clone());
+            }
+        }
+
+        tunings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::songfile::SongId;
+    use crate::library::testing::{song_file, temp_dir};
+    use crate::song::guitar::CommonTunings;
+    use std::path::Path;
+    use std::time::Duration;
+
+    fn drop_d() -> GuitarTuning {
+        GuitarTuning::from(vec![-2, 5, 10, 15, 19, 24])
+    }
+
+    fn part(kind: InstrumentKind, tuning: GuitarTuning, capo: u8, difficulty: f32) -> PartSummary {
+        PartSummary { name: format!("{:?}", kind), kind, tuning: Some(tuning), capo, difficulty }
+    }
+
+    fn song(title: &str, artist: &str, album: &str, year: u16, length_secs: u64, parts: Vec<PartSummary>) -> SongFile {
+        let mut song = song_file(title, format!("/songs/{}", title).as_str());
+        song.metadata.artist = artist.to_string();
+        song.metadata.album = album.to_string();
+        song.metadata.year = year;
+        song.metadata.length = Duration::from_secs(length_secs);
+        song.parts = parts;
+        song
+    }
+
+    /// Songs whose titles sort the other way around than their artists
+    fn library() -> Library {
+        Library {
+            songs: vec![
+                song("Zulu", "Alpha", "First", 1990, 200, vec![
+                    part(InstrumentKind::LeadGuitar, CommonTunings::EStandard.to_tuning(), 0, 0.8),
+                    part(InstrumentKind::BassGuitar, GuitarTuning::from(vec![-2, 5, 10, 15]), 0, 0.3),
+                ]),
+                song("Yankee", "Bravo", "Second", 2005, 150, vec![
+                    part(InstrumentKind::RhythmGuitar, drop_d(), 2, 0.5),
+                ]),
+                song("Xray", "Charlie", "Third", 2020, 300, vec![]),
+            ],
+            ..Library::empty()
+        }
+    }
+
+    fn annotations(dir: &Path) -> AnnotationStore {
+        let mut annotations = AnnotationStore::empty(dir.join("annotations.json"));
+
+        annotations.edit(&SongId("Zulu".to_string()), |annotations| {
+            annotations.favorite = true;
+            annotations.set_tags("warmup");
+            annotations.rating = Some(3);
+        }).unwrap();
+
+        annotations.edit(&SongId("Yankee".to_string()), |annotations| {
+            annotations.set_tags("Gig");
+            annotations.notes = "Solo at the end".to_string();
+            annotations.rating = Some(1);
+        }).unwrap();
+
+        annotations
+    }
+
+    fn titles<'a>(library: &'a Library, query: &LibraryQuery, annotations: &AnnotationStore) -> Vec<&'a str> {
+        library.query(query, annotations).iter().map(|song| song.metadata.title.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_by_text() {
+        let dir = temp_dir("query-text");
+        let (library, annotations) = (library(), annotations(dir.as_path()));
+        let query = |text: &str| LibraryQuery { text: text.to_string(), ..LibraryQuery::default() };
+
+        assert_eq!(titles(&library, &query(""), &annotations), vec!["Zulu", "Yankee", "Xray"]);
+        assert_eq!(titles(&library, &query("ALPHA"), &annotations), vec!["Zulu"]);
+        assert_eq!(titles(&library, &query("third"), &annotations), vec!["Xray"]);
+        // Every word has to match, but not necessarily the same field
+        assert_eq!(titles(&library, &query("bravo second"), &annotations), vec!["Yankee"]);
+        assert!(titles(&library, &query("bravo first"), &annotations).is_empty());
+        // Tags and notes are searched too
+        assert_eq!(titles(&library, &query("warm"), &annotations), vec!["Zulu"]);
+        assert_eq!(titles(&library, &query("solo"), &annotations), vec!["Yankee"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filters_by_parts_and_year() {
+        let dir = temp_dir("query-parts");
+        let (library, annotations) = (library(), annotations(dir.as_path()));
+
+        let query = LibraryQuery { instrument: Some(InstrumentKind::BassGuitar), ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Zulu"]);
+
+        let query = LibraryQuery { tuning: Some(drop_d()), ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Yankee"]);
+
+        let query = LibraryQuery { capo: Some(0), ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Zulu"]);
+
+        let query = LibraryQuery { difficulty: Some((0.4, 0.6)), ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Yankee"]);
+
+        // The lead part is in E standard, the part in drop D is a rhythm part
+        let query = LibraryQuery { instrument: Some(InstrumentKind::LeadGuitar), tuning: Some(drop_d()), ..LibraryQuery::default() };
+        assert!(titles(&library, &query, &annotations).is_empty());
+
+        let query = LibraryQuery { year: Some((2005, 2020)), ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Yankee", "Xray"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filters_by_annotations() {
+        let dir = temp_dir("query-annotations");
+        let (library, annotations) = (library(), annotations(dir.as_path()));
+
+        let query = LibraryQuery { favorites: true, ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Zulu"]);
+
+        let query = LibraryQuery { tag: Some("gig".to_string()), ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Yankee"]);
+
+        let query = LibraryQuery { rating: Some(1), ..LibraryQuery::default() };
+        assert_eq!(titles(&library, &query, &annotations), vec!["Yankee"]);
+
+        let query = LibraryQuery { rating: Some(5), ..LibraryQuery::default() };
+        assert!(titles(&library, &query, &annotations).is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sorts_by_each_key() {
+        let dir = temp_dir("query-sort");
+        let (library, annotations) = (library(), annotations(dir.as_path()));
+        let sorted = |sort: SortKey, descending: bool| {
+            titles(&library, &LibraryQuery { sort, descending, ..LibraryQuery::default() }, &annotations)
+        };
+
+        assert_eq!(sorted(SortKey::Artist, false), vec!["Zulu", "Yankee", "Xray"]);
+        assert_eq!(sorted(SortKey::Artist, true), vec!["Xray", "Yankee", "Zulu"]);
+        assert_eq!(sorted(SortKey::Title, false), vec!["Xray", "Yankee", "Zulu"]);
+        assert_eq!(sorted(SortKey::Album, false), vec!["Zulu", "Yankee", "Xray"]);
+        assert_eq!(sorted(SortKey::Year, true), vec!["Xray", "Yankee", "Zulu"]);
+        // Songs are sorted by their easiest part, songs without parts count as the easiest
+        assert_eq!(sorted(SortKey::Difficulty, false), vec!["Xray", "Zulu", "Yankee"]);
+        assert_eq!(sorted(SortKey::Length, false), vec!["Yankee", "Zulu", "Xray"]);
+        assert_eq!(sorted(SortKey::Rating, false), vec!["Xray", "Yankee", "Zulu"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use crate::format::LoadError;
+
+/// Describes what happened to each song directory found while scanning the library, ordered by path
+#[derive(Clone, Default)]
+pub struct ScanReport {
+    pub entries: Vec<ScanEntry>
+}
+
+impl ScanReport {
+
+    /// Records the status of a directory, replacing any earlier status recorded for it
+    pub fn record(&mut self, entry: ScanEntry) {
+        match self.entries.binary_search_by(|existing| existing.path.cmp(&entry.path)) {
+            Ok(idx) => self.entries[idx] = entry,
+            Err(idx) => self.entries.insert(idx, entry),
+        }
+    }
+
+    /// Forgets about every directory under the specified path
+    pub fn remove_under(&mut self, path: &str) {
+        self.entries.retain(|entry| !std::path::Path::new(entry.path.as_str()).starts_with(path));
+    }
+
+    /// The directories that could not be loaded
+    pub fn problems(&self) -> impl Iterator<Item = &ScanEntry> {
+        self.entries.iter().filter(|entry| !matches!(entry.status, ScanStatus::Loaded))
+    }
+}
+
+#[derive(Clone)]
+pub struct ScanEntry {
+    pub path: String,
+    pub status: ScanStatus
+}
+
+impl ScanEntry {
+    pub fn from_error(path: String, error: &LoadError) -> Self {
+        let status = match error {
+            LoadError::MissingFile(file_name) => ScanStatus::Skipped(format!("missing {}", file_name)),
+            LoadError::Parse { file, json_path, message } => ScanStatus::Failed {
+                error: message.clone(),
+                file: Some(file.clone()),
+                json_path: Some(json_path.clone()),
+            },
+            LoadError::InvalidPackage(reason) => ScanStatus::Failed {
+                error: format!("invalid song package: {}", reason),
+                file: None,
+                json_path: None,
+            },
+            LoadError::UnsupportedFormat(reason) => ScanStatus::Failed {
+                error: format!("unsupported format: {}", reason),
+                file: None,
+                json_path: None,
+            },
+            LoadError::Io(error) => ScanStatus::Failed {
+                error: error.to_string(),
+                file: None,
+                json_path: None,
+            },
+        };
+
+        Self { path, status }
+    }
+}
+
+#[derive(Clone)]
+pub enum ScanStatus {
+    /// The song was loaded into the library
+    Loaded,
+    /// The directory looks like a song but is incomplete, the reason is included
+    Skipped(String),
+    /// The directory contains an identical copy of the song found in the directory included
+    Duplicate(String),
+    /// A chart file could not be read or parsed
+    Failed { error: String, file: Option<String>, json_path: Option<String> },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(report: &ScanReport) -> Vec<&str> {
+        report.entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn records_entries_in_path_order() {
+        let mut report = ScanReport::default();
+
+        for path in ["/songs/b", "/songs/a", "/songs/c"] {
+            report.record(ScanEntry { path: path.to_string(), status: ScanStatus::Loaded });
+        }
+        report.record(ScanEntry { path: "/songs/b".to_string(), status: ScanStatus::Skipped("missing song.ogg".to_string()) });
+
+        assert_eq!(paths(&report), vec!["/songs/a", "/songs/b", "/songs/c"]);
+
+        let problems: Vec<&str> = report.problems().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(problems, vec!["/songs/b"]);
+    }
+
+    #[test]
+    fn removes_entries_under_path() {
+        let mut report = ScanReport::default();
+
+        for path in ["/songs/a/one", "/songs/a/two", "/songs/ab", "/songs/b"] {
+            report.record(ScanEntry { path: path.to_string(), status: ScanStatus::Loaded });
+        }
+        report.remove_under("/songs/a");
+
+        assert_eq!(paths(&report), vec!["/songs/ab", "/songs/b"]);
+    }
+
+    #[test]
+    fn describes_load_errors() {
+        let skipped = ScanEntry::from_error("/songs/a".to_string(), &LoadError::MissingFile("song.ogg".to_string()));
+        assert!(matches!(skipped.status, ScanStatus::Skipped(reason) if reason == "missing song.ogg"));
+
+        let failed = ScanEntry::from_error("/songs/a".to_string(), &LoadError::InvalidPackage("no song.json".to_string()));
+        assert!(matches!(failed.status, ScanStatus::Failed { error, file: None, .. } if error == "invalid song package: no song.json"));
+
+        let parse_error = LoadError::Parse { file: "lead.json".to_string(), json_path: "notes[3].fret".to_string(), message: "invalid type".to_string() };
+        let failed = ScanEntry::from_error("/songs/a".to_string(), &parse_error);
+        assert!(matches!(failed.status, ScanStatus::Failed { error, file: Some(file), json_path: Some(json_path) }
+            if error == "invalid type" && file == "lead.json" && json_path == "notes[3].fret"));
+
+        let io_error = LoadError::Io(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "access denied"));
+        let failed = ScanEntry::from_error("/songs/a".to_string(), &io_error);
+        assert!(matches!(failed.status, ScanStatus::Failed { error, file: None, json_path: None } if error == "access denied"));
+    }
+}
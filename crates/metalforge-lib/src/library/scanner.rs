@@ -0,0 +1,285 @@
+use crate::format::registry::FormatRegistry;
+use crate::library::report::{ScanEntry, ScanReport, ScanStatus};
+use crate::library::songfile::{SongFile, SongId};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// How long an idle worker waits for a new directory before checking if the scan has finished
+const WORKER_POLL_MILLIS: u64 = 20;
+
+/// Notifications sent while a library scan is in progress
+pub enum ScanEvent {
+    /// A directory has been scanned
+    Progress(ScanProgress),
+    /// A song has been found. Songs are reported in the order they are found, not in library order
+    SongFound(SongFile),
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct ScanProgress {
+    /// The number of directories scanned so far
+    pub scanned: usize,
+    /// The number of songs found so far
+    pub found: usize,
+    /// The number of directories discovered so far, including the ones already scanned
+    pub total: usize,
+    /// The directory that was scanned most recently
+    pub current_path: String,
+}
+
+/// Scans song directories in parallel on a pool of worker threads. Each directory is checked for a
+/// song of any of the registered formats first and is only descended into if it doesn't contain one,
+/// or the song in it couldn't be loaded.
+pub struct LibraryScanner<'a, F> {
+    formats: &'a FormatRegistry,
+    cancelled: &'a AtomicBool,
+    listener: F,
+    queue_tx: Sender<PathBuf>,
+    queue_rx: Receiver<PathBuf>,
+    // Directories that have been queued but not finished scanning yet
+    pending: AtomicUsize,
+    total: AtomicUsize,
+    scanned: AtomicUsize,
+    songs: Mutex<Vec<SongFile>>,
+    report: Mutex<ScanReport>,
+}
+
+impl<'a, F> LibraryScanner<'a, F> where F: Fn(ScanEvent) + Sync {
+
+    pub fn new(formats: &'a FormatRegistry, cancelled: &'a AtomicBool, listener: F) -> Self {
+        let (queue_tx, queue_rx) = unbounded();
+
+        Self {
+            formats,
+            cancelled,
+            listener,
+            queue_tx,
+            queue_rx,
+            pending: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            scanned: AtomicUsize::new(0),
+            songs: Mutex::new(vec![]),
+            report: Mutex::new(ScanReport::default()),
+        }
+    }
+
+    /// Scans the entries of every library path and returns the songs found, along with a report
+    /// describing each song directory. Identical copies of a song are only returned once. If the scan
+    /// is cancelled, the songs found up to that point are returned.
+    pub fn scan<P: AsRef<Path>>(self, paths: Vec<P>) -> (Vec<SongFile>, ScanReport) {
+        for path in paths {
+            if let Err(error) = self.enqueue_entries(path.as_ref()) {
+                warn!("Failed to read songs at {:?}: {:?}", path.as_ref(), error);
+                self.record(ScanEntry::from_error(path.as_ref().display().to_string(), &error.into()));
+            }
+        }
+
+        let workers = thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1);
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| self.run_worker());
+            }
+        });
+
+        let mut songs = self.songs.into_inner().unwrap_or_default();
+        let mut report = self.report.into_inner().unwrap_or_default();
+        remove_duplicates(&mut songs, &mut report);
+
+        (songs, report)
+    }
+
+    fn run_worker(&self) {
+        while !self.cancelled.load(Ordering::Relaxed) && self.pending.load(Ordering::Acquire) > 0 {
+            if let Ok(path) = self.queue_rx.recv_timeout(Duration::from_millis(WORKER_POLL_MILLIS)) {
+                self.scan_path(path.as_path());
+                // Sub-directories have already been queued, so this can only reach zero once every directory is done
+                self.pending.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+    }
+
+    fn scan_path(&self, path: &Path) {
+        match self.formats.load_dir(path) {
+            Ok(Some(songfile)) => {
+                self.record(ScanEntry { path: songfile.song_dir.clone(), status: ScanStatus::Loaded });
+                self.songs.lock().unwrap().push(songfile.clone());
+                (self.listener)(ScanEvent::SongFound(songfile));
+            }
+            Ok(None) => self.descend(path),
+            Err(error) => {
+                error!("Failed to scan library {:?}: {}", path, error);
+                self.record(ScanEntry::from_error(path.display().to_string(), &error));
+
+                // A stray file can make a folder of songs look like a song, the songs below it are
+                // still scanned
+                self.descend(path);
+            }
+        }
+
+        let scanned = self.scanned.fetch_add(1, Ordering::Relaxed) + 1;
+        let found = self.songs.lock().unwrap().len();
+
+        (self.listener)(ScanEvent::Progress(ScanProgress {
+            scanned,
+            found,
+            total: self.total.load(Ordering::Relaxed),
+            current_path: path.display().to_string(),
+        }));
+    }
+
+    fn descend(&self, path: &Path) {
+        if path.is_dir() && let Err(error) = self.enqueue_entries(path) {
+            error!("Failed to scan library {:?}: {:?}", path, error);
+            self.record(ScanEntry::from_error(path.display().to_string(), &error.into()));
+        }
+    }
+
+    fn record(&self, entry: ScanEntry) {
+        if let Ok(mut report) = self.report.lock() {
+            report.record(entry);
+        }
+    }
+
+    fn enqueue_entries(&self, path: &Path) -> Result<(), std::io::Error> {
+        for maybe_entry in std::fs::read_dir(path)? {
+            let entry = maybe_entry?;
+
+            self.pending.fetch_add(1, Ordering::AcqRel);
+            self.total.fetch_add(1, Ordering::Relaxed);
+            let _ = self.queue_tx.send(entry.path());
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps a single copy of each song that was found in several directories. The copy in the directory
+/// that sorts first is kept, so the same copy is chosen on every scan.
+fn remove_duplicates(songs: &mut Vec<SongFile>, report: &mut ScanReport) {
+    songs.sort_by(|a, b| a.song_dir.cmp(&b.song_dir));
+
+    let mut originals: HashMap<SongId, String> = HashMap::new();
+
+    songs.retain(|song| match originals.get(&song.id) {
+        Some(original_dir) => {
+            info!("Song {:?} is a duplicate of {:?}", song.song_dir, original_dir);
+            report.record(ScanEntry { path: song.song_dir.clone(), status: ScanStatus::Duplicate(original_dir.clone()) });
+            false
+        }
+        None => {
+            originals.insert(song.id.clone(), song.song_dir.clone());
+            true
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::testing::{copy_fixture_song, song_file, temp_dir};
+
+    #[test]
+    fn scans_below_directories_that_fail_to_load() {
+        let library = temp_dir("scanner-descend");
+        let artist = library.join("artist");
+        copy_fixture_song(artist.join("album").as_path());
+        std::fs::write(artist.join("song.json"), "{").unwrap();
+
+        let formats = FormatRegistry::default();
+        let cancelled = AtomicBool::new(false);
+        let (songs, report) = LibraryScanner::new(&formats, &cancelled, |_| {}).scan(vec![library.as_path()]);
+
+        let dirs: Vec<&str> = songs.iter().map(|song| song.song_dir.as_str()).collect();
+        assert_eq!(dirs, vec![artist.join("album").to_str().unwrap()]);
+
+        let problems: Vec<&str> = report.problems().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(problems, vec![artist.to_str().unwrap()]);
+
+        std::fs::remove_dir_all(library).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_first_copy_of_duplicates() {
+        let mut songs = vec![
+            song_file("a", "/songs/b-copy"),
+            song_file("b", "/songs/c"),
+            song_file("a", "/songs/a"),
+            song_file("a", "/songs/c-copy"),
+        ];
+        let mut report = ScanReport::default();
+
+        remove_duplicates(&mut songs, &mut report);
+
+        let dirs: Vec<&str> = songs.iter().map(|song| song.song_dir.as_str()).collect();
+        assert_eq!(dirs, vec!["/songs/a", "/songs/c"]);
+
+        let duplicates: Vec<(&str, Option<&str>)> = report.problems()
+            .map(|entry| match &entry.status {
+                ScanStatus::Duplicate(original_dir) => (entry.path.as_str(), Some(original_dir.as_str())),
+                _ => (entry.path.as_str(), None),
+            })
+            .collect();
+        assert_eq!(duplicates, vec![("/songs/b-copy", Some("/songs/a")), ("/songs/c-copy", Some("/songs/a"))]);
+    }
+
+    #[test]
+    fn counts_directories_and_songs() {
+        let library = temp_dir("scanner-counts");
+        copy_fixture_song(library.join("a/song").as_path());
+        copy_fixture_song(library.join("b/song").as_path());
+        std::fs::create_dir_all(library.join("empty")).unwrap();
+        std::fs::write(library.join("notes.txt"), "").unwrap();
+
+        let formats = FormatRegistry::default();
+        let cancelled = AtomicBool::new(false);
+        let progress: Mutex<Vec<ScanProgress>> = Mutex::new(vec![]);
+        let found = AtomicUsize::new(0);
+        let (songs, report) = LibraryScanner::new(&formats, &cancelled, |event| match event {
+            ScanEvent::Progress(scan_progress) => progress.lock().unwrap().push(scan_progress),
+            ScanEvent::SongFound(_) => { found.fetch_add(1, Ordering::Relaxed); }
+        }).scan(vec![library.as_path()]);
+
+        // a, b, empty, notes.txt and the two song directories. Song directories aren't descended into.
+        let progress = progress.into_inner().unwrap();
+        let last = progress.iter().max_by_key(|progress| progress.scanned).unwrap();
+        assert_eq!(progress.len(), 6);
+        assert_eq!((last.scanned, last.total), (6, 6));
+        assert_eq!(progress.iter().map(|progress| progress.found).max(), Some(2));
+
+        // Both copies are found, but only the first is kept
+        assert_eq!(found.into_inner(), 2);
+        assert_eq!(songs.len(), 1);
+        assert_eq!(report.problems().count(), 1);
+
+        std::fs::remove_dir_all(library).unwrap();
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let library = temp_dir("scanner-cancel");
+        copy_fixture_song(library.join("1/2/3/4/song").as_path());
+
+        // Each directory only holds the next one, so at most one more directory is scanned after
+        // cancelling
+        let formats = FormatRegistry::default();
+        let cancelled = AtomicBool::new(false);
+        let (songs, _) = LibraryScanner::new(&formats, &cancelled, |event| {
+            if let ScanEvent::Progress(_) = event {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }).scan(vec![library.as_path()]);
+
+        assert!(songs.is_empty());
+
+        std::fs::remove_dir_all(library).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use crate::library::songfile::{SongFile, SongId};
+use crate::library::Library;
+use crate::store::JsonFile;
+use log::info;
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::path::Path;
+
+/// Remembers the ID of the song in each song directory, in a local JSON file. Song IDs are derived
+/// from the contents of the song's files, so editing a song gives it a new ID. Comparing a scan of
+/// the library with the remembered IDs finds the songs that changed while the app wasn't running,
+/// so whatever is kept under their previous ID can be moved to the new one.
+pub struct SongDirStore {
+    file: JsonFile,
+    pub songs: BTreeMap<String, SongId>,
+}
+
+impl SongDirStore {
+
+    /// Reads the song directories from the specified file. A missing file is treated as if the
+    /// library had never been scanned.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = JsonFile::new(path);
+        let songs: BTreeMap<String, SongId> = file.read()?.unwrap_or_default();
+
+        info!("Loaded the IDs of {} song directories from {:?}", songs.len(), file.path());
+
+        Ok(Self { file, songs })
+    }
+
+    /// Creates an empty store, the file is created when the first song is recorded
+    pub fn empty<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            file: JsonFile::new(path),
+            songs: BTreeMap::new(),
+        }
+    }
+
+    /// Remembers the IDs of the songs of a scanned library and returns the songs whose directory held
+    /// a song with another ID before, along with that previous ID. Songs whose previous ID is still
+    /// in the library, e.g. one of several copies of a song that was edited, keep their data where
+    /// it is. Directories that aren't in the library are remembered, since a cancelled scan or a
+    /// song that failed to load doesn't mean the song is gone.
+    pub fn reconcile(&mut self, library: &Library) -> Result<Vec<(SongId, SongFile)>, Error> {
+        let mut updated = vec![];
+        let mut changed = false;
+
+        for songfile in &library.songs {
+            let previous_id = self.songs.insert(songfile.song_dir.clone(), songfile.id.clone());
+
+            match previous_id {
+                Some(previous_id) if previous_id == songfile.id => {}
+                Some(previous_id) => {
+                    if library.get(&previous_id).is_none() {
+                        updated.push((previous_id, songfile.clone()));
+                    }
+                    changed = true;
+                }
+                None => changed = true,
+            }
+        }
+
+        if changed {
+            self.save()?;
+        }
+
+        Ok(updated)
+    }
+
+    /// Remembers the ID of a song added or updated while the library is watched
+    pub fn record(&mut self, songfile: &SongFile) -> Result<(), Error> {
+        if self.songs.get(&songfile.song_dir) == Some(&songfile.id) {
+            return Ok(());
+        }
+
+        self.songs.insert(songfile.song_dir.clone(), songfile.id.clone());
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        self.file.write(&self.songs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::testing::{song_file, temp_dir};
+
+    fn library(songs: Vec<SongFile>) -> Library {
+        Library { songs, ..Library::empty() }
+    }
+
+    #[test]
+    fn finds_songs_changed_between_scans() {
+        let dir = temp_dir("song-dirs");
+        let path = dir.join("song_dirs.json");
+
+        let mut store = SongDirStore::empty(path.as_path());
+        let first_scan = library(vec![song_file("a", "/songs/a"), song_file("b", "/songs/b"), song_file("c", "/songs/c")]);
+        assert!(store.reconcile(&first_scan).unwrap().is_empty());
+
+        // "a" was edited, "b" is unchanged and "c" wasn't found this time
+        let mut store = SongDirStore::open(path.as_path()).unwrap();
+        let second_scan = library(vec![song_file("a2", "/songs/a"), song_file("b", "/songs/b")]);
+        let updated = store.reconcile(&second_scan).unwrap();
+        let ids: Vec<(&str, &str)> = updated.iter()
+            .map(|(previous_id, songfile)| (previous_id.0.as_str(), songfile.id.0.as_str()))
+            .collect();
+        assert_eq!(ids, vec![("a", "a2")]);
+
+        let reopened = SongDirStore::open(path.as_path()).unwrap();
+        assert_eq!(reopened.songs.get("/songs/a"), Some(&SongId("a2".to_string())));
+        assert_eq!(reopened.songs.get("/songs/c"), Some(&SongId("c".to_string())));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_data_of_copies_still_in_library() {
+        let dir = temp_dir("song-dirs-copies");
+        let mut store = SongDirStore::empty(dir.join("song_dirs.json"));
+        store.record(&song_file("a", "/songs/a")).unwrap();
+        store.record(&song_file("a", "/songs/copy")).unwrap();
+
+        // Only the copy was edited, the original still has its data under the previous ID
+        let scan = library(vec![song_file("a", "/songs/a"), song_file("a2", "/songs/copy")]);
+        assert!(store.reconcile(&scan).unwrap().is_empty());
+        assert_eq!(store.songs.get("/songs/copy"), Some(&SongId("a2".to_string())));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
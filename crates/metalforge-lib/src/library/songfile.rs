@@ -1,159 +1,152 @@
-use crate::format::opensongchart::instrument_part::{InstrumentType, SongNote, SongNoteTechniques};
-use crate::format::opensongchart::{OpenSongChart, Part};
-use crate::song::guitar::{BendPoint, GuitarNote, GuitarPart, GuitarTechnique, GuitarTuning};
-use crate::song::instrument_part::{InstrumentPart, InstrumentPartType};
+use crate::song::guitar::GuitarTuning;
+use crate::song::instrument_part::InstrumentKind;
 use crate::song::metadata::Metadata;
-use crate::song::{Beat, Song};
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Error, Read};
+use std::path::{Path, PathBuf};
+
+/// Identifies a song in the library. The ID is derived from the contents of the song's files, so it
+/// stays the same across rescans and is shared by identical copies of a song in different directories.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Debug)]
+pub struct SongId(pub String);
+
+impl SongId {
+
+    /// Hashes the name and contents of every file of a song. The files must always be listed in the
+    /// same order for the ID to be stable.
+    pub fn from_files<P: AsRef<Path>>(files: &[P]) -> Result<SongId, Error> {
+        let mut builder = SongIdBuilder::new();
+
+        for file in files {
+            let file_name = file.as_ref().file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            builder.add_file(file_name, File::open(file.as_ref())?)?;
+        }
+
+        Ok(builder.build())
+    }
+}
+
+/// Builds a song ID one file at a time, for songs whose files aren't read from a directory. Gives
+/// the same ID as `SongId::from_files` for the same files added in the same order.
+pub struct SongIdBuilder(blake3::Hasher);
+
+impl SongIdBuilder {
+
+    pub fn new() -> Self {
+        Self(blake3::Hasher::new())
+    }
+
+    pub fn add_file<R: Read>(&mut self, file_name: &str, contents: R) -> Result<(), Error> {
+        self.0.update(file_name.as_bytes());
+        self.0.update_reader(contents)?;
+
+        Ok(())
+    }
+
+    pub fn build(self) -> SongId {
+        SongId(self.0.finalize().to_hex().to_string())
+    }
+}
+
+impl Default for SongIdBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+/// A lightweight library entry describing a song without holding any of its notes. The full chart
+/// is only parsed when the song is loaded for playing.
 #[derive(Clone)]
 pub struct SongFile {
+    pub id: SongId,
     pub format: Format,
+    /// The directory the song's chart files are in, or the song package or Guitar Pro file the song
+    /// is read from
+    pub song_dir: String,
+    /// The path of the song's audio file. For song packages, this is the path of the audio file
+    /// within the package and can't be opened directly, see `format::open_audio`. Empty for Guitar
+    /// Pro files without any audio.
     pub song_path: String,
-    pub song: Song
+    pub metadata: Metadata,
+    pub parts: Vec<PartSummary>
 }
 
-impl From<OpenSongChart> for SongFile {
-    fn from(chart: OpenSongChart) -> Self {
-        // Convert beats, tracking measure and beat-within-measure
-        let mut measure = 1usize;
-        let mut beat_in_measure = 1u8;
-        let beats: Vec<Beat> = chart.arrangement.beats.iter().map(|b| {
-            if b.is_measure {
-                measure += 1;
-                beat_in_measure = 1;
-            } else {
-                beat_in_measure += 1;
-            }
-            Beat {
-                time: Duration::from_secs_f32(b.time_offset),
-                measure,
-                beat_in_measure,
-            }
-        }).collect();
-
-        let mut instrument_parts: Vec<InstrumentPart> = vec![];
-
-        for part_def in chart.song.instrument_parts.iter() {
-            let matched_part = chart.instrument_parts.iter()
-                .find(|p| p.has_part_id(part_def.instrument_name.as_str()));
-
-            let instrument_type = match &part_def.instrument_type {
-                guitar_type @ (InstrumentType::LeadGuitar | InstrumentType::RhythmGuitar | InstrumentType::BassGuitar) => {
-                    let notes_data = matched_part.and_then(|p| {
-                        if let Part::InstrumentPart(_, notes) = p { Some(notes) } else { None }
-                    });
-
-                    let tuning = part_def.tuning.as_ref()
-                        .map(|t| GuitarTuning {
-                            string_offsets: t.string_semitone_offsets.iter().map(|&v| v as i8).collect()
-                        })
-                        .unwrap_or_else(|| GuitarTuning { string_offsets: vec![0, 5, 10, 15, 19, 24] });
-
-                    let guitar_notes = notes_data.map(|nd| {
-                        nd.notes.iter().filter_map(|note| {
-                            let string = note.string? as u8;
-                            let fret_raw = note.fret?;
-                            if fret_raw < 0 { return None; }
-                            let fret = fret_raw as u8;
-                            let time = Duration::from_secs_f32(note.time_offset.unwrap_or(0.0));
-                            let length = Duration::from_secs_f32(note.time_length.unwrap_or(0.1));
-
-                            // Resolve finger: finger_id indexes into chords array, then index by string
-                            let finger = note.finger_id
-                                .and_then(|fid| nd.chords.get(fid as usize))
-                                .and_then(|chord| chord.fingers.get(string as usize))
-                                .copied()
-                                .unwrap_or(0)
-                                .max(0) as u8;
-
-                            let technique = note.techniques.iter()
-                                .filter_map(|t| map_technique(t, note))
-                                .collect();
-
-                            Some(GuitarNote { string, fret, finger: Some(finger), time, length, technique })
-                        }).collect::<Vec<_>>()
-                    }).unwrap_or_default();
-
-                    let guitar_part = GuitarPart {
-                        notes: guitar_notes,
-                        tuning,
-                        capo: part_def.capo_fret.max(0) as u8,
-                    };
-
-                    match guitar_type {
-                        InstrumentType::LeadGuitar => Some(InstrumentPartType::LeadGuitar(guitar_part)),
-                        InstrumentType::RhythmGuitar => Some(InstrumentPartType::RhythmGuitar(guitar_part)),
-                        InstrumentType::BassGuitar => Some(InstrumentPartType::BassGuitar(guitar_part)),
-                        _ => unreachable!()
-                    }
-                }
-                InstrumentType::Keys | InstrumentType::Drums | InstrumentType::Vocals => None,
-            };
-
-            if let Some(t) = instrument_type {
-                instrument_parts.push(InstrumentPart {
-                    name: part_def.instrument_name.clone(),
-                    instrument_part_type: t,
-                });
-            }
-        }
+/// Describes a single instrument part of a song
+#[derive(Clone)]
+pub struct PartSummary {
+    pub name: String,
+    pub kind: InstrumentKind,
+    /// The tuning of the part, only present for stringed instruments
+    pub tuning: Option<GuitarTuning>,
+    /// The index of the fret the capo should be placed at. 0 means no capo
+    pub capo: u8,
+    pub difficulty: f32
+}
+
+/// The extensions of the images recognised as album art
+const ALBUM_ART_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// Album art with one of these names is preferred over other images in the song directory
+const ALBUM_ART_NAMES: [&str; 4] = ["cover", "album", "folder", "art"];
+
+impl SongFile {
+
+    /// Looks for an image in the song directory to show as the album art. An image named e.g.
+    /// "cover.jpg" is preferred, otherwise the first image in alphabetical order is used. Songs read
+    /// from a song package have no album art.
+    pub fn album_art(&self) -> Option<PathBuf> {
+        let mut images: Vec<PathBuf> = std::fs::read_dir(self.song_dir.as_str()).ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter(|path| path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| ALBUM_ART_EXTENSIONS.contains(&extension.to_lowercase().as_str())))
+            .collect();
 
-        SongFile {
-            format: Format::OpenSongChart,
-            song_path: chart.song_path,
-            song: Song {
-                metadata: Metadata {
-                    title: chart.song.song_name.clone(),
-                    artist: chart.song.artist_name.clone(),
-                    album: chart.song.album_name.clone(),
-                    year: chart.song.song_year as u16,
-                    length: Duration::from_secs_f32(chart.song.song_length_seconds),
-                    key: None
-                },
-                instrument_parts,
-                beats,
-                sections: vec![],
-                a440_offset_cents: 0.0,
-            }
+        images.sort();
+
+        let preferred = images.iter().position(|path| path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| ALBUM_ART_NAMES.contains(&stem.to_lowercase().as_str())));
+
+        match preferred {
+            Some(idx) => Some(images.swap_remove(idx)),
+            None => images.into_iter().next(),
         }
     }
 }
 
-fn map_technique(t: &SongNoteTechniques, note: &SongNote) -> Option<GuitarTechnique> {
-    match t {
-        SongNoteTechniques::HammerOn => Some(GuitarTechnique::HammerOn),
-        SongNoteTechniques::PullOff => Some(GuitarTechnique::PullOff),
-        SongNoteTechniques::PalmMute => Some(GuitarTechnique::PalmMute),
-        SongNoteTechniques::FretHandMute => Some(GuitarTechnique::FretHandMute),
-        SongNoteTechniques::Slide => {
-            let to_fret = note.slide_fret.unwrap_or(0).max(0) as u8;
-            Some(GuitarTechnique::Slide { to_fret })
-        }
-        SongNoteTechniques::Bend => {
-            let points = note.cents_offset.iter().map(|co| BendPoint {
-                time_offset: Duration::from_secs_f32(co.time_offset),
-                cents: co.cents,
-            }).collect();
-            Some(GuitarTechnique::Bend { points })
+/// The format a song is stored in, which is needed to load the song again
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    OpenSongChart,
+    /// An OpenSongChart packed into a single zip archive, read without extracting it
+    SongPackage,
+    /// Metalforge's own versioned format, a `metalforge.json` holding the entire song
+    Metalforge,
+    /// A MusicXML score written as tablature, imported whenever the song is loaded
+    MusicXml,
+    /// A Guitar Pro file, imported whenever the song is loaded
+    GuitarPro,
+    /// Rocksmith 2014 arrangements next to the song's audio, imported whenever the song is loaded
+    Rocksmith,
+    /// A format registered by the application, identified by its name
+    Custom(&'static str)
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::OpenSongChart => write!(f, "OpenSongChart"),
+            Format::SongPackage => write!(f, "song package"),
+            Format::Metalforge => write!(f, "Metalforge"),
+            Format::MusicXml => write!(f, "MusicXML"),
+            Format::GuitarPro => write!(f, "Guitar Pro"),
+            Format::Rocksmith => write!(f, "Rocksmith"),
+            Format::Custom(name) => write!(f, "{}", name),
         }
-        SongNoteTechniques::Tremolo => Some(GuitarTechnique::Tremolo),
-        SongNoteTechniques::Vibrato => Some(GuitarTechnique::Vibrato),
-        SongNoteTechniques::Harmonic => Some(GuitarTechnique::Harmonic),
-        SongNoteTechniques::PinchHarmonic => Some(GuitarTechnique::PinchHarmonic),
-        SongNoteTechniques::Tap => Some(GuitarTechnique::Tap),
-        SongNoteTechniques::Slap => Some(GuitarTechnique::Slap),
-        SongNoteTechniques::Pop => Some(GuitarTechnique::Pop),
-        // These no longer exist in the native format — drop them
-        SongNoteTechniques::Chord
-        | SongNoteTechniques::ChordNote
-        | SongNoteTechniques::Continued
-        | SongNoteTechniques::Arpeggio
-        | SongNoteTechniques::Accent => None,
     }
 }
-
-#[derive(Copy, Clone)]
-pub enum Format {
-    OpenSongChart
-}
\ No newline at end of file
@@ -0,0 +1,45 @@
+use crate::library::songfile::{Format, PartSummary, SongFile, SongId};
+use crate::song::metadata::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A library entry without any parts, for tests that only care about the entry itself
+pub(crate) fn song_file(id: &str, song_dir: &str) -> SongFile {
+    SongFile {
+        id: SongId(id.to_string()),
+        format: Format::OpenSongChart,
+        song_dir: song_dir.to_string(),
+        song_path: format!("{}/song.ogg", song_dir),
+        metadata: Metadata {
+            title: id.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            year: 2000,
+            length: Duration::from_secs(180),
+            key: None,
+            preview_start: None,
+        },
+        parts: Vec::<PartSummary>::new(),
+    }
+}
+
+/// Creates an empty directory for a test under the system's temporary directory, removing whatever
+/// an earlier run left behind
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("metalforge-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(dir.as_path());
+    std::fs::create_dir_all(dir.as_path()).unwrap_or_else(|error| panic!("failed to create {:?}: {}", dir, error));
+
+    dir
+}
+
+/// Copies the OpenSongChart song in `tests/fixtures/opensongchart` into a new song directory
+pub(crate) fn copy_fixture_song(dir: &Path) {
+    let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/opensongchart");
+    std::fs::create_dir_all(dir).unwrap();
+
+    for entry in std::fs::read_dir(fixture_dir).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(path.as_path(), dir.join(path.file_name().unwrap())).unwrap();
+    }
+}
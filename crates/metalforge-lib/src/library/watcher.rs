@@ -0,0 +1,304 @@
+use crate::format::registry::{Confidence, FormatRegistry};
+use crate::library::report::{ScanEntry, ScanReport, ScanStatus};
+use crate::library::scanner::LibraryScanner;
+use crate::library::songfile::{SongFile, SongId};
+use crate::library::Library;
+use crossbeam_channel::{unbounded, Receiver};
+use log::{debug, error, info, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long to wait for the file system to settle before re-parsing the changed songs. Copying a song
+/// folder produces a burst of events, which should only be processed once.
+const DEBOUNCE_MILLIS: u64 = 500;
+
+/// A change made to the library by the watcher
+pub enum LibraryChange {
+    SongAdded(SongFile),
+    /// A song's files have changed. The ID the song had before the change is included, as the ID is
+    /// derived from the song's contents.
+    SongUpdated(SongId, SongFile),
+    SongRemoved(SongId),
+    /// The scan report after the changes have been applied
+    ReportUpdated(ScanReport),
+}
+
+/// Watches the library paths for song directories being added, removed or modified. Only the
+/// affected directories are re-parsed, the shared library is updated in place and each change is
+/// reported to the listener. Watching stops when the watcher is dropped.
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl LibraryWatcher {
+
+    pub fn watch<P, F>(paths: &[P], library: Arc<Mutex<Library>>, formats: Arc<FormatRegistry>, listener: F) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+        F: Fn(LibraryChange) + Send + 'static
+    {
+        let (change_tx, change_rx) = unbounded();
+
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+            Ok(event) => {
+                if !matches!(event.kind, EventKind::Access(_)) {
+                    let _ = change_tx.send(event.paths);
+                }
+            }
+            Err(error) => warn!("Library watcher error: {:?}", error),
+        }).map_err(Error::other)?;
+
+        let mut roots = vec![];
+
+        for path in paths {
+            match path.as_ref().canonicalize() {
+                Ok(canonical_path) => {
+                    watcher.watch(canonical_path.as_path(), RecursiveMode::Recursive).map_err(Error::other)?;
+                    roots.push(LibraryRoot {
+                        path: path.as_ref().to_path_buf(),
+                        canonical_path,
+                    });
+                }
+                Err(error) => warn!("Cannot watch library path {:?}: {:?}", path.as_ref(), error),
+            }
+        }
+
+        let handler = ChangeHandler { roots, library, formats, listener };
+        std::thread::spawn(move || handler.run(change_rx));
+
+        Ok(Self {
+            _watcher: watcher,
+        })
+    }
+}
+
+struct LibraryRoot {
+    /// The library path as configured, songs found by scanning are relative to this path
+    path: PathBuf,
+    /// The absolute path reported by file system events
+    canonical_path: PathBuf,
+}
+
+struct ChangeHandler<F> {
+    roots: Vec<LibraryRoot>,
+    library: Arc<Mutex<Library>>,
+    formats: Arc<FormatRegistry>,
+    listener: F,
+}
+
+impl<F> ChangeHandler<F> where F: Fn(LibraryChange) {
+
+    fn run(&self, change_rx: Receiver<Vec<PathBuf>>) {
+        while let Ok(first_paths) = change_rx.recv() {
+            let mut paths: HashSet<PathBuf> = first_paths.into_iter().collect();
+
+            while let Ok(more_paths) = change_rx.recv_timeout(Duration::from_millis(DEBOUNCE_MILLIS)) {
+                paths.extend(more_paths);
+            }
+
+            let paths = paths.iter()
+                .filter_map(|path| self.to_library_path(path))
+                .collect();
+
+            self.handle_changes(paths);
+        }
+
+        debug!("Library watcher exited");
+    }
+
+    fn handle_changes(&self, paths: HashSet<PathBuf>) {
+        let mut song_dirs = HashSet::new();
+        let mut new_dirs = HashSet::new();
+        let mut removed_dirs = HashSet::new();
+
+        if let Ok(library) = self.library.lock() {
+            for path in paths {
+                if let Some(song_dir) = self.known_song_dir(&library, path.as_path()) {
+                    song_dirs.insert(song_dir);
+                } else if path.is_dir() || self.formats.probe(path.as_path()) > Confidence::None {
+                    new_dirs.insert(path);
+                } else if path.exists() {
+                    // A file was added to a directory that may have just become a complete song
+                    if let Some(parent) = path.parent().filter(|parent| !self.is_root(parent)) {
+                        song_dirs.insert(parent.to_path_buf());
+                    }
+                } else {
+                    removed_dirs.insert(path);
+                }
+            }
+        }
+
+        for song_dir in song_dirs {
+            self.reload_song(song_dir.as_path());
+        }
+
+        for new_dir in new_dirs {
+            self.scan_new_dir(new_dir);
+        }
+
+        for removed_dir in removed_dirs {
+            self.remove_songs_under(removed_dir.as_path());
+        }
+
+        if let Some(report) = self.library.lock().ok().map(|library| library.report.clone()) {
+            (self.listener)(LibraryChange::ReportUpdated(report));
+        }
+    }
+
+    fn reload_song(&self, song_dir: &Path) {
+        match self.formats.load_dir(song_dir) {
+            Ok(Some(songfile)) => self.add_song(songfile),
+            Ok(None) => self.remove_song(song_dir),
+            Err(error) => {
+                // A song that can no longer be loaded is removed until it's fixed
+                error!("Failed to reload song {:?}: {}", song_dir, error);
+                self.remove_song(song_dir);
+                self.record(ScanEntry::from_error(song_dir.display().to_string(), &error));
+            }
+        }
+    }
+
+    fn remove_song(&self, song_dir: &Path) {
+        let removed = self.library.lock().ok().and_then(|mut library| {
+            library.report.remove_under(song_dir.to_str()?);
+
+            let id = library.songs.iter()
+                .find(|song| Path::new(song.song_dir.as_str()) == song_dir)
+                .map(|song| song.id.clone())?;
+
+            library.remove(&id)
+        });
+
+        if let Some(songfile) = removed {
+            info!("Song removed from library: {:?}", song_dir);
+            (self.listener)(LibraryChange::SongRemoved(songfile.id));
+            self.promote_duplicates(songfile.song_dir.as_str());
+        }
+    }
+
+    /// Loads the copies of a song that were ignored as duplicates, once the original has been removed
+    fn promote_duplicates(&self, original_dir: &str) {
+        let duplicate_dirs: Vec<String> = self.library.lock()
+            .map(|library| library.report.entries.iter()
+                .filter(|entry| matches!(&entry.status, ScanStatus::Duplicate(dir) if dir == original_dir))
+                .map(|entry| entry.path.clone())
+                .collect())
+            .unwrap_or_default();
+
+        for duplicate_dir in duplicate_dirs {
+            self.reload_song(Path::new(duplicate_dir.as_str()));
+        }
+    }
+
+    fn scan_new_dir(&self, dir: PathBuf) {
+        match self.formats.load_dir(dir.as_path()) {
+            Ok(Some(songfile)) => self.add_song(songfile),
+            Ok(None) => {
+                // The directory may contain several songs, e.g. when an entire folder of songs is copied
+                let (songs, report) = LibraryScanner::new(&self.formats, &AtomicBool::new(false), |_event| {}).scan(vec![dir]);
+
+                for songfile in songs {
+                    self.add_song(songfile);
+                }
+
+                for entry in report.entries {
+                    self.record(entry);
+                }
+            }
+            Err(error) => {
+                error!("Failed to load song {:?}: {}", dir, error);
+                self.record(ScanEntry::from_error(dir.display().to_string(), &error));
+            }
+        }
+    }
+
+    fn remove_songs_under(&self, dir: &Path) {
+        let removed = self.library.lock()
+            .map(|mut library| {
+                if let Some(dir) = dir.to_str() {
+                    library.report.remove_under(dir);
+                }
+
+                let ids: Vec<SongId> = library.songs.iter()
+                    .filter(|song| Path::new(song.song_dir.as_str()).starts_with(dir))
+                    .map(|song| song.id.clone())
+                    .collect();
+
+                ids.iter().filter_map(|id| library.remove(id)).collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        for songfile in removed {
+            info!("Song removed from library: {:?}", songfile.song_dir);
+            (self.listener)(LibraryChange::SongRemoved(songfile.id));
+            self.promote_duplicates(songfile.song_dir.as_str());
+        }
+    }
+
+    fn add_song(&self, songfile: SongFile) {
+        let Ok(mut library) = self.library.lock() else {
+            return;
+        };
+
+        if let Some(original) = library.get(&songfile.id).filter(|original| original.song_dir != songfile.song_dir) {
+            info!("Song {:?} is a duplicate of {:?}", songfile.song_dir, original.song_dir);
+            let status = ScanStatus::Duplicate(original.song_dir.clone());
+            library.report.record(ScanEntry { path: songfile.song_dir, status });
+            return;
+        }
+
+        // Changing a song's files changes its ID, so the entry with the old ID has to be replaced
+        let previous_id = library.songs.iter()
+            .find(|song| song.song_dir == songfile.song_dir)
+            .map(|song| song.id.clone());
+
+        if let Some(previous_id) = previous_id.as_ref() {
+            library.remove(previous_id);
+        }
+
+        library.upsert(songfile.clone());
+        library.report.record(ScanEntry { path: songfile.song_dir.clone(), status: ScanStatus::Loaded });
+        drop(library);
+
+        if let Some(previous_id) = previous_id {
+            info!("Song updated in library: {:?}", songfile.song_dir);
+            (self.listener)(LibraryChange::SongUpdated(previous_id, songfile));
+        } else {
+            info!("Song added to library: {:?}", songfile.song_dir);
+            (self.listener)(LibraryChange::SongAdded(songfile));
+        }
+    }
+
+    fn record(&self, entry: ScanEntry) {
+        if let Ok(mut library) = self.library.lock() {
+            library.report.record(entry);
+        }
+    }
+
+    /// Finds the directory of an already known song that contains the specified path
+    fn known_song_dir(&self, library: &Library, path: &Path) -> Option<PathBuf> {
+        path.ancestors()
+            .take_while(|ancestor| !self.is_root(ancestor))
+            .find(|ancestor| library.songs.iter().any(|song| Path::new(song.song_dir.as_str()) == *ancestor))
+            .map(Path::to_path_buf)
+    }
+
+    fn is_root(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| root.path == path)
+    }
+
+    /// Translates an absolute path from a file system event to the form songs are identified by
+    fn to_library_path(&self, path: &Path) -> Option<PathBuf> {
+        self.roots.iter().find_map(|root| {
+            path.strip_prefix(root.canonical_path.as_path())
+                .ok()
+                .filter(|relative| relative.components().next().is_some())
+                .map(|relative| root.path.join(relative))
+        })
+    }
+}
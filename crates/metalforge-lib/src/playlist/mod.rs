@@ -0,0 +1,179 @@
+use crate::library::songfile::SongId;
+use crate::store::JsonFile;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::io::Error;
+use std::path::Path;
+use std::time::Duration;
+
+/// An ordered list of songs to be played one after the other, e.g. the setlist of a rehearsal
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Playlist {
+    pub name: String,
+    pub entries: Vec<PlaylistEntry>,
+    /// The silence between the end of a song and the start of the next one, in milliseconds
+    #[serde(default)]
+    pub gap_millis: u64,
+    /// The number of beats counted in before each song starts. 0 means no count-in
+    #[serde(default)]
+    pub count_in_beats: u8,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct PlaylistEntry {
+    pub song_id: SongId,
+    /// The name of the instrument part to play, `None` means the part the song was last played with
+    #[serde(default)]
+    pub part: Option<String>,
+}
+
+impl Playlist {
+
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            entries: vec![],
+            gap_millis: 0,
+            count_in_beats: 0,
+        }
+    }
+
+    pub fn gap(&self) -> Duration {
+        Duration::from_millis(self.gap_millis)
+    }
+
+    pub fn add(&mut self, song_id: SongId, part: Option<String>) {
+        self.entries.push(PlaylistEntry { song_id, part });
+    }
+
+    pub fn remove(&mut self, idx: usize) -> Option<PlaylistEntry> {
+        (idx < self.entries.len()).then(|| self.entries.remove(idx))
+    }
+
+    /// Moves an entry to a new position, shifting the entries in between. Returns `false` if either
+    /// position is out of range.
+    pub fn move_entry(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.entries.len() || to >= self.entries.len() {
+            return false;
+        }
+
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        true
+    }
+}
+
+/// Keeps every playlist in a local JSON file, which is rewritten whenever a playlist changes
+pub struct PlaylistStore {
+    file: JsonFile,
+    pub playlists: Vec<Playlist>,
+}
+
+impl PlaylistStore {
+
+    /// Reads the playlists from the specified file. A missing file is treated as if no playlists
+    /// had been created yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = JsonFile::new(path);
+        let playlists: Vec<Playlist> = file.read()?.unwrap_or_default();
+
+        info!("Loaded {} playlists from {:?}", playlists.len(), file.path());
+
+        Ok(Self { file, playlists })
+    }
+
+    /// Creates an empty store, the file is created when the first playlist is saved
+    pub fn empty<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            file: JsonFile::new(path),
+            playlists: vec![],
+        }
+    }
+
+    /// Adds an empty playlist and returns its index
+    pub fn create(&mut self, name: String) -> Result<usize, Error> {
+        self.playlists.push(Playlist::new(name));
+        self.save()?;
+
+        Ok(self.playlists.len() - 1)
+    }
+
+    pub fn delete(&mut self, idx: usize) -> Result<Option<Playlist>, Error> {
+        if idx >= self.playlists.len() {
+            return Ok(None);
+        }
+
+        let playlist = self.playlists.remove(idx);
+        self.save()?;
+
+        Ok(Some(playlist))
+    }
+
+    /// Applies a change to a playlist and saves it. Does nothing if there is no playlist at the index.
+    pub fn edit<F: FnOnce(&mut Playlist)>(&mut self, idx: usize, change: F) -> Result<(), Error> {
+        if let Some(playlist) = self.playlists.get_mut(idx) {
+            change(playlist);
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Points the entries of a song at its new ID after its files changed, in every playlist
+    pub fn rekey(&mut self, previous_id: &SongId, song_id: &SongId) -> Result<(), Error> {
+        let mut entries = self.playlists.iter_mut()
+            .flat_map(|playlist| playlist.entries.iter_mut())
+            .filter(|entry| &entry.song_id == previous_id)
+            .peekable();
+
+        if previous_id == song_id || entries.peek().is_none() {
+            return Ok(());
+        }
+
+        for entry in entries {
+            entry.song_id = song_id.clone();
+        }
+
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        self.file.write(&self.playlists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::testing::temp_dir;
+
+    #[test]
+    fn rekeys_entries_of_updated_songs() {
+        let dir = temp_dir("playlist-rekey");
+        let path = dir.join("setlists.json");
+        let previous_id = SongId("previous".to_string());
+        let song_id = SongId("updated".to_string());
+        let other_id = SongId("other".to_string());
+
+        let mut store = PlaylistStore::empty(path.as_path());
+        for name in ["Gig", "Warmup"] {
+            let idx = store.create(name.to_string()).unwrap();
+            store.edit(idx, |playlist| {
+                playlist.add(previous_id.clone(), Some("Lead".to_string()));
+                playlist.add(other_id.clone(), None);
+            }).unwrap();
+        }
+
+        store.rekey(&previous_id, &song_id).unwrap();
+
+        let reopened = PlaylistStore::open(path.as_path()).unwrap();
+        for playlist in &reopened.playlists {
+            let entries: Vec<(&str, Option<&str>)> = playlist.entries.iter()
+                .map(|entry| (entry.song_id.0.as_str(), entry.part.as_deref()))
+                .collect();
+            assert_eq!(entries, vec![("updated", Some("Lead")), ("other", None)]);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+use crate::library::songfile::SongId;
+use crate::store::JsonFile;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::io::Error;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub mod settings;
+pub mod stats;
+
+/// A single sitting with one part of a song, from loading the song until leaving it
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PracticeSession {
+    pub song_id: SongId,
+    /// The name of the instrument part that was practiced
+    pub part: String,
+    /// When the session started, in seconds since the Unix epoch
+    pub started_at: u64,
+    /// How long the song was playing during the session, in milliseconds
+    pub practiced_millis: u64,
+    /// How long the song was playing at each speed
+    pub speeds: Vec<SpeedUsage>,
+    /// The loops set up during the session, in the order they were set up
+    pub loops: Vec<LoopRange>,
+    /// The score achieved, if the session was scored
+    #[serde(default)]
+    pub score: Option<f32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SpeedUsage {
+    /// The playback speed, 1.0 being the original speed
+    pub speed: f32,
+    pub millis: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct LoopRange {
+    pub start_millis: u64,
+    pub end_millis: u64,
+}
+
+impl PracticeSession {
+
+    pub fn new(song_id: SongId, part: String) -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0);
+
+        Self {
+            song_id,
+            part,
+            started_at,
+            practiced_millis: 0,
+            speeds: vec![],
+            loops: vec![],
+            score: None,
+        }
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.started_at)
+    }
+
+    pub fn practiced(&self) -> Duration {
+        Duration::from_millis(self.practiced_millis)
+    }
+
+    /// Adds time spent playing the song at the specified speed
+    pub fn add_time(&mut self, speed: f32, elapsed: Duration) {
+        let millis = elapsed.as_millis() as u64;
+        self.practiced_millis += millis;
+
+        // Speeds are changed in steps, so comparing them with a small tolerance is enough
+        match self.speeds.iter_mut().find(|usage| (usage.speed - speed).abs() < 0.001) {
+            Some(usage) => usage.millis += millis,
+            None => self.speeds.push(SpeedUsage { speed, millis }),
+        }
+    }
+
+    /// Records a loop, unless it's the same as the loop recorded last
+    pub fn add_loop(&mut self, start: Duration, end: Duration) {
+        let range = LoopRange {
+            start_millis: start.as_millis() as u64,
+            end_millis: end.as_millis() as u64,
+        };
+
+        if self.loops.last() != Some(&range) {
+            self.loops.push(range);
+        }
+    }
+}
+
+/// Keeps the practice history of every song in a local file. Each session is appended to the file
+/// as a line of JSON, so recording a session never rewrites the existing history.
+pub struct PracticeStore {
+    file: JsonFile,
+    pub sessions: Vec<PracticeSession>,
+}
+
+impl PracticeStore {
+
+    /// Reads the practice history from the specified file. A missing file is treated as an empty
+    /// history, and lines that cannot be parsed are skipped.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = JsonFile::new(path);
+        let sessions: Vec<PracticeSession> = file.read_lines()?;
+
+        info!("Loaded {} practice sessions from {:?}", sessions.len(), file.path());
+
+        Ok(Self { file, sessions })
+    }
+
+    /// Creates an empty store that isn't backed by a file yet, the file is created when the first
+    /// session is recorded
+    pub fn empty<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            file: JsonFile::new(path),
+            sessions: vec![],
+        }
+    }
+
+    /// Appends the session to the practice history
+    pub fn record(&mut self, session: PracticeSession) -> Result<(), Error> {
+        self.file.append_line(&session)?;
+        self.sessions.push(session);
+
+        Ok(())
+    }
+
+    /// Moves the history of a song to its new ID after the song's files changed. The whole history
+    /// is rewritten, which only happens when the song has been practiced before.
+    pub fn rekey(&mut self, previous_id: &SongId, song_id: &SongId) -> Result<(), Error> {
+        if previous_id == song_id || !self.sessions.iter().any(|session| &session.song_id == previous_id) {
+            return Ok(());
+        }
+
+        for session in self.sessions.iter_mut().filter(|session| &session.song_id == previous_id) {
+            session.song_id = song_id.clone();
+        }
+
+        self.save()
+    }
+
+    /// The sessions of a song, optionally only the ones where the specified part was practiced
+    pub fn sessions_for<'a>(&'a self, song_id: &'a SongId, part: Option<&'a str>) -> impl Iterator<Item = &'a PracticeSession> {
+        self.sessions.iter()
+            .filter(move |session| &session.song_id == song_id)
+            .filter(move |session| part.is_none_or(|part| session.part == part))
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        self.file.write_lines(&self.sessions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rekeys_sessions_of_updated_songs() {
+        let dir = std::env::temp_dir().join(format!("metalforge-practice-{}", std::process::id()));
+        let path = dir.join("history.jsonl");
+        let _ = std::fs::remove_dir_all(dir.as_path());
+
+        let previous_id = SongId("previous".to_string());
+        let song_id = SongId("updated".to_string());
+        let other_id = SongId("other".to_string());
+
+        let mut store = PracticeStore::empty(path.as_path());
+        let mut session = PracticeSession::new(previous_id.clone(), "Lead".to_string());
+        session.add_loop(Duration::from_secs(5), Duration::from_secs(10));
+        store.record(session).unwrap();
+        store.record(PracticeSession::new(other_id.clone(), "Bass".to_string())).unwrap();
+        store.record(PracticeSession::new(previous_id.clone(), "Rhythm".to_string())).unwrap();
+
+        store.rekey(&previous_id, &song_id).unwrap();
+
+        // The history is rewritten in the same order, with the loops kept
+        let reopened = PracticeStore::open(path.as_path()).unwrap();
+        let ids: Vec<&str> = reopened.sessions.iter().map(|session| session.song_id.0.as_str()).collect();
+        assert_eq!(ids, vec!["updated", "other", "updated"]);
+        assert_eq!(reopened.sessions_for(&previous_id, None).count(), 0);
+        assert_eq!(reopened.sessions_for(&song_id, Some("Lead")).next().unwrap().loops, vec![LoopRange { start_millis: 5000, end_millis: 10000 }]);
+
+        // Songs without a history are left alone
+        store.rekey(&SongId("unknown".to_string()), &other_id).unwrap();
+        assert_eq!(PracticeStore::open(path.as_path()).unwrap().sessions_for(&other_id, None).count(), 1);
+
+        std::fs::remove_dir_all(dir.as_path()).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use crate::library::songfile::SongId;
+use crate::store::JsonFile;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::path::Path;
+
+/// The state of the player when a song was last left, restored when the song is opened again
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SongSettings {
+    pub position_millis: u64,
+    /// The playback speed, 1.0 being the original speed
+    pub speed: f32,
+    pub loop_start_millis: u64,
+    /// Where the loop ends, `None` means the loop ends with the song
+    pub loop_end_millis: Option<u64>,
+    /// The name of the instrument part shown in the player, `None` means the first part
+    pub arrangement: Option<String>,
+    pub zoom: f32,
+    /// Shifts the chart relative to the audio, positive values delay the chart
+    pub audio_offset_millis: i32,
+    /// The loops saved for the song, in the order they were saved
+    pub loops: Vec<NamedLoop>,
+    /// What the edges of loops are moved to when they are marked
+    pub loop_snap: LoopSnap,
+}
+
+/// A loop saved for practicing a specific part of a song, e.g. "intro riff"
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct NamedLoop {
+    pub name: String,
+    pub start_millis: u64,
+    pub end_millis: u64,
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub enum LoopSnap {
+    /// Loop edges are placed exactly where they are marked
+    #[default]
+    Off,
+    /// Loop edges are moved to the nearest beat
+    Beat,
+    /// Loop edges are moved to the nearest start of a measure
+    Measure,
+}
+
+impl Default for SongSettings {
+    fn default() -> Self {
+        Self {
+            position_millis: 0,
+            speed: 1.0,
+            loop_start_millis: 0,
+            loop_end_millis: None,
+            arrangement: None,
+            zoom: 1.0,
+            audio_offset_millis: 0,
+            loops: vec![],
+            loop_snap: LoopSnap::Off,
+        }
+    }
+}
+
+/// Keeps the settings of every song in a local JSON file, which is rewritten whenever the settings
+/// of a song change
+pub struct SongSettingsStore {
+    file: JsonFile,
+    pub songs: BTreeMap<SongId, SongSettings>,
+}
+
+impl SongSettingsStore {
+
+    /// Reads the song settings from the specified file. A missing file is treated as if no settings
+    /// had been saved yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = JsonFile::new(path);
+        let songs: BTreeMap<SongId, SongSettings> = file.read()?.unwrap_or_default();
+
+        info!("Loaded settings of {} songs from {:?}", songs.len(), file.path());
+
+        Ok(Self { file, songs })
+    }
+
+    /// Creates an empty store, the file is created when the first settings are saved
+    pub fn empty<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            file: JsonFile::new(path),
+            songs: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, song_id: &SongId) -> Option<&SongSettings> {
+        self.songs.get(song_id)
+    }
+
+    pub fn set(&mut self, song_id: SongId, settings: SongSettings) -> Result<(), Error> {
+        self.songs.insert(song_id, settings);
+        self.save()
+    }
+
+    /// Replaces the saved loops of a song and how their edges snap, keeping the rest of its settings.
+    /// Loops are saved as soon as they change, while the rest is only saved when the song is left.
+    pub fn set_loops(&mut self, song_id: &SongId, loops: Vec<NamedLoop>, loop_snap: LoopSnap) -> Result<(), Error> {
+        let settings = self.songs.entry(song_id.clone()).or_default();
+        settings.loops = loops;
+        settings.loop_snap = loop_snap;
+
+        self.save()
+    }
+
+    /// Forgets the settings of a song, so it opens with the default settings next time
+    pub fn remove(&mut self, song_id: &SongId) -> Result<(), Error> {
+        if self.songs.remove(song_id).is_some() {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moves the settings and saved loops of a song to its new ID after its files changed. Settings
+    /// already saved under the new ID are kept.
+    pub fn rekey(&mut self, previous_id: &SongId, song_id: &SongId) -> Result<(), Error> {
+        if previous_id == song_id {
+            return Ok(());
+        }
+
+        let Some(settings) = self.songs.remove(previous_id) else {
+            return Ok(());
+        };
+
+        self.songs.entry(song_id.clone()).or_insert(settings);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        self.file.write(&self.songs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::testing::temp_dir;
+
+    #[test]
+    fn saves_loops_separately() {
+        let dir = temp_dir("settings-loops");
+        let path = dir.join("settings.json");
+        let song_id = SongId("song".to_string());
+        let intro = NamedLoop { name: "Intro".to_string(), start_millis: 0, end_millis: 5000 };
+
+        // Loops of a song without settings start from the defaults
+        let mut store = SongSettingsStore::empty(path.as_path());
+        store.set_loops(&song_id, vec![intro.clone()], LoopSnap::Beat).unwrap();
+
+        let reopened = SongSettingsStore::open(path.as_path()).unwrap();
+        let settings = reopened.get(&song_id).unwrap();
+        assert_eq!(settings.loops, vec![intro.clone()]);
+        assert_eq!(settings.loop_snap, LoopSnap::Beat);
+        assert_eq!(settings.speed, 1.0);
+
+        // The settings saved when leaving the song are kept
+        store.set(song_id.clone(), SongSettings { position_millis: 1234, speed: 0.5, ..SongSettings::default() }).unwrap();
+        store.set_loops(&song_id, vec![], LoopSnap::Off).unwrap();
+
+        let reopened = SongSettingsStore::open(path.as_path()).unwrap();
+        let settings = reopened.get(&song_id).unwrap();
+        assert!(settings.loops.is_empty());
+        assert_eq!((settings.position_millis, settings.speed), (1234, 0.5));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rekeys_settings_of_updated_songs() {
+        let dir = temp_dir("settings-rekey");
+        let path = dir.join("settings.json");
+        let previous_id = SongId("previous".to_string());
+        let song_id = SongId("updated".to_string());
+        let intro = NamedLoop { name: "Intro".to_string(), start_millis: 0, end_millis: 5000 };
+
+        let mut store = SongSettingsStore::empty(path.as_path());
+        store.set(previous_id.clone(), SongSettings { position_millis: 1234, ..SongSettings::default() }).unwrap();
+        store.set_loops(&previous_id, vec![intro.clone()], LoopSnap::Measure).unwrap();
+
+        store.rekey(&previous_id, &song_id).unwrap();
+
+        let reopened = SongSettingsStore::open(path.as_path()).unwrap();
+        assert!(reopened.get(&previous_id).is_none());
+        let settings = reopened.get(&song_id).unwrap();
+        assert_eq!((settings.position_millis, settings.loops.clone(), settings.loop_snap), (1234, vec![intro], LoopSnap::Measure));
+
+        // Settings already under the new ID win over the moved ones
+        let other_id = SongId("other".to_string());
+        store.set(other_id.clone(), SongSettings { position_millis: 99, ..SongSettings::default() }).unwrap();
+        store.rekey(&song_id, &other_id).unwrap();
+        assert_eq!(store.get(&other_id).unwrap().position_millis, 99);
+        assert!(store.get(&song_id).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use crate::library::songfile::SongId;
+use crate::practice::{PracticeSession, PracticeStore};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A summary of the practice history of a song or one of its parts
+#[derive(Clone, Default, Debug)]
+pub struct PracticeStats {
+    pub sessions: usize,
+    /// The total time the song was playing
+    pub practiced: Duration,
+    pub last_practiced: Option<SystemTime>,
+    /// The fastest speed the song was played at
+    pub top_speed: Option<f32>,
+    pub best_score: Option<f32>,
+}
+
+/// The practice done within one period of a trend
+#[derive(Clone, Debug)]
+pub struct TrendPoint {
+    /// The start of the period
+    pub start: SystemTime,
+    pub sessions: usize,
+    pub practiced: Duration,
+    /// The average speed played at during the period, weighted by the time spent at each speed
+    pub average_speed: Option<f32>,
+    pub best_score: Option<f32>,
+}
+
+impl PracticeStore {
+
+    pub fn stats(&self, song_id: &SongId, part: Option<&str>) -> PracticeStats {
+        summarise(self.sessions_for(song_id, part))
+    }
+
+    /// The names of the parts of a song that have been practiced, in the order they were first practiced
+    pub fn practiced_parts(&self, song_id: &SongId) -> Vec<String> {
+        let mut parts: Vec<String> = vec![];
+
+        for session in self.sessions_for(song_id, None) {
+            if !parts.contains(&session.part) {
+                parts.push(session.part.clone());
+            }
+        }
+
+        parts
+    }
+
+    /// Groups the sessions of a song into periods of the specified length, e.g. days or weeks, to
+    /// show how practice has progressed over time. Periods without any practice are left out.
+    pub fn trend(&self, song_id: &SongId, part: Option<&str>, period: Duration) -> Vec<TrendPoint> {
+        let period_secs = period.as_secs().max(1);
+        let mut sessions: Vec<&PracticeSession> = self.sessions_for(song_id, part).collect();
+        sessions.sort_by_key(|session| session.started_at);
+
+        sessions.chunk_by(|a, b| a.started_at / period_secs == b.started_at / period_secs)
+            .map(|period_sessions| {
+                let stats = summarise(period_sessions.iter().copied());
+                let start = period_sessions[0].started_at / period_secs * period_secs;
+
+                TrendPoint {
+                    start: UNIX_EPOCH + Duration::from_secs(start),
+                    sessions: stats.sessions,
+                    practiced: stats.practiced,
+                    average_speed: average_speed(period_sessions),
+                    best_score: stats.best_score,
+                }
+            })
+            .collect()
+    }
+}
+
+fn summarise<'a, I: Iterator<Item = &'a PracticeSession>>(sessions: I) -> PracticeStats {
+    let mut stats = PracticeStats::default();
+
+    for session in sessions {
+        stats.sessions += 1;
+        stats.practiced += session.practiced();
+        stats.last_practiced = stats.last_practiced.max(Some(session.started_at()));
+
+        let top_speed = session.speeds.iter()
+            .filter(|usage| usage.millis > 0)
+            .map(|usage| usage.speed)
+            .reduce(f32::max);
+
+        stats.top_speed = max_option(stats.top_speed, top_speed);
+        stats.best_score = max_option(stats.best_score, session.score);
+    }
+
+    stats
+}
+
+fn average_speed(sessions: &[&PracticeSession]) -> Option<f32> {
+    let usages = || sessions.iter().flat_map(|session| session.speeds.iter());
+    let total_millis: u64 = usages().map(|usage| usage.millis).sum();
+
+    if total_millis == 0 {
+        return None;
+    }
+
+    let weighted: f32 = usages().map(|usage| usage.speed * usage.millis as f32).sum();
+    Some(weighted / total_millis as f32)
+}
+
+fn max_option(a: Option<f32>, b: Option<f32>) -> Option<f32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
@@ -1,6 +1,10 @@
+use crate::song::seconds;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 use std::time::Duration;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(transparent)]
 pub struct GuitarTuning {
     /// Represents the number of strings the guitar part was written for and their tunings, expressed
     /// as the number of semitones from E2. I.e. low E would be 0, A2 would be 5, D3 would be 10, etc.
@@ -21,6 +25,19 @@ impl From<CommonTunings> for GuitarTuning {
     }
 }
 
+/// Lists the note each string is tuned to from the lowest string to the highest, e.g. "DADGBE"
+impl Display for GuitarTuning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        const NOTE_NAMES: [&str; 12] = [ "E", "F", "F#", "G", "G#", "A", "A#", "B", "C", "C#", "D", "D#" ];
+
+        for offset in &self.string_offsets {
+            write!(f, "{}", NOTE_NAMES[(*offset as i32).rem_euclid(12) as usize])?;
+        }
+
+        Ok(())
+    }
+}
+
 pub enum CommonTunings {
     EStandard
 }
@@ -35,17 +52,71 @@ impl CommonTunings {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GuitarPart {
     /// The notes and chords to be played during this part
     pub notes: Vec<GuitarNote>,
     /// The tuning that should be used for this guitar part
     pub tuning: GuitarTuning,
     /// The index of the fret the capo should be placed at. 0 means open strings, i.e. no capo
-    pub capo: u8
+    pub capo: u8,
+    /// Where the fretting hand is placed over the course of the part, ordered by time. Empty if the
+    /// chart doesn't say.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anchors: Vec<FretAnchor>,
+}
+
+/// Places the fretting hand over a range of frets, from a point in a part until the next anchor
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct FretAnchor {
+    #[serde(with = "seconds")]
+    pub time: Duration,
+    /// The lowest fret the hand covers
+    pub fret: u8,
+    /// The number of frets the hand covers
+    pub width: u8,
+}
+
+/// How many notes, chords and techniques a guitar part has, describing the part before it's played
+#[derive(Clone, Debug)]
+pub struct PartStatistics {
+    pub notes: usize,
+    /// The number of times two or more notes start together
+    pub chords: usize,
+    /// The number of notes played with each technique, in the order the techniques first occur
+    pub techniques: Vec<(&'static str, usize)>,
+}
+
+impl GuitarPart {
+
+    pub fn statistics(&self) -> PartStatistics {
+        let mut techniques: Vec<(&'static str, usize)> = vec![];
+        let mut chords = 0;
+
+        for (idx, note) in self.notes.iter().enumerate() {
+            for technique in &note.technique {
+                match techniques.iter_mut().find(|(name, _count)| *name == technique.name()) {
+                    Some((_name, count)) => *count += 1,
+                    None => techniques.push((technique.name(), 1)),
+                }
+            }
+
+            // Counts each chord once, on its second note
+            if idx > 0 && self.notes[idx - 1].time == note.time
+                && (idx < 2 || self.notes[idx - 2].time != note.time) {
+                chords += 1;
+            }
+        }
+
+        PartStatistics {
+            notes: self.notes.len(),
+            chords,
+            techniques,
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GuitarNote {
     /// The index of the string the note is played on. 0 means the lowest string on the current instrument
     pub string: u8,
@@ -59,14 +130,16 @@ pub struct GuitarNote {
     /// - 4 = little
     pub finger: Option<u8>,
     /// The amount of time since the start of the song to play this note.
+    #[serde(with = "seconds")]
     pub time: Duration,
     /// The duration for which the note should be held
+    #[serde(with = "seconds")]
     pub length: Duration,
     /// The techniques that should be used when playing this note
     pub technique: Vec<GuitarTechnique>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum GuitarTechnique {
     HammerOn,
     PullOff,
@@ -84,9 +157,31 @@ pub enum GuitarTechnique {
     Pop,
 }
 
-#[derive(Copy, Clone)]
+impl GuitarTechnique {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GuitarTechnique::HammerOn => "hammer-ons",
+            GuitarTechnique::PullOff => "pull-offs",
+            GuitarTechnique::PalmMute => "palm mutes",
+            GuitarTechnique::FretHandMute => "fret-hand mutes",
+            GuitarTechnique::Slide { .. } => "slides",
+            GuitarTechnique::Bend { .. } => "bends",
+            GuitarTechnique::Tremolo => "tremolo",
+            GuitarTechnique::Vibrato => "vibrato",
+            GuitarTechnique::Harmonic => "harmonics",
+            GuitarTechnique::PinchHarmonic => "pinch harmonics",
+            GuitarTechnique::Tap => "taps",
+            GuitarTechnique::Slap => "slaps",
+            GuitarTechnique::Pop => "pops",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct BendPoint {
     // Time offset from the start of the note
+    #[serde(with = "seconds")]
     pub time_offset: Duration,
     // Directional bend, positive values bend up, negative values bend down
     pub cents: i16
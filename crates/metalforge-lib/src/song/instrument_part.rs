@@ -1,12 +1,14 @@
 use crate::song::guitar::GuitarPart;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct InstrumentPart {
     pub name: String,
     pub instrument_part_type: InstrumentPartType
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum InstrumentPartType {
     LeadGuitar(GuitarPart),
     RhythmGuitar(GuitarPart),
@@ -14,4 +16,51 @@ pub enum InstrumentPartType {
     Keyboard,
     Drums,
     Vocals
-}
\ No newline at end of file
+}
+
+impl InstrumentPartType {
+    pub fn kind(&self) -> InstrumentKind {
+        match self {
+            InstrumentPartType::LeadGuitar(_) => InstrumentKind::LeadGuitar,
+            InstrumentPartType::RhythmGuitar(_) => InstrumentKind::RhythmGuitar,
+            InstrumentPartType::BassGuitar(_) => InstrumentKind::BassGuitar,
+            InstrumentPartType::Keyboard => InstrumentKind::Keyboard,
+            InstrumentPartType::Drums => InstrumentKind::Drums,
+            InstrumentPartType::Vocals => InstrumentKind::Vocals,
+        }
+    }
+}
+
+/// The kind of instrument a part was written for, without any of the part's notes
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum InstrumentKind {
+    LeadGuitar,
+    RhythmGuitar,
+    BassGuitar,
+    Keyboard,
+    Drums,
+    Vocals
+}
+
+impl InstrumentKind {
+
+    /// Checks if the part is played on a guitar or a bass, the only parts the player can show
+    pub fn is_guitar(&self) -> bool {
+        matches!(self, InstrumentKind::LeadGuitar | InstrumentKind::RhythmGuitar | InstrumentKind::BassGuitar)
+    }
+}
+
+impl Display for InstrumentKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            InstrumentKind::LeadGuitar => "Lead Guitar",
+            InstrumentKind::RhythmGuitar => "Rhythm Guitar",
+            InstrumentKind::BassGuitar => "Bass Guitar",
+            InstrumentKind::Keyboard => "Keyboard",
+            InstrumentKind::Drums => "Drums",
+            InstrumentKind::Vocals => "Vocals",
+        };
+
+        write!(f, "{}", name)
+    }
+}
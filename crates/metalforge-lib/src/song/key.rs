@@ -1,23 +1,25 @@
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Key {
     pub root: NoteClass,
     pub accidental: Accidental,
     pub mode: Mode
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum Accidental {
     Natural,
     Sharp,
     Flat
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum NoteClass {
     C, D, E, F, G, A, B
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum Mode {
     Major,
     Minor,
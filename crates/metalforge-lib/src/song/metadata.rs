@@ -1,12 +1,18 @@
 use std::time::Duration;
+use serde::{Deserialize, Serialize};
 use crate::song::key::Key;
+use crate::song::seconds;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Metadata {
     pub title: String,
     pub artist: String,
     pub album: String,
     pub year: u16,
+    #[serde(with = "seconds")]
     pub length: Duration,
-    pub key: Option<Key>
+    pub key: Option<Key>,
+    /// Where the preview played while browsing the library starts, if the chart specifies it
+    #[serde(with = "seconds::option", default)]
+    pub preview_start: Option<Duration>
 }
\ No newline at end of file
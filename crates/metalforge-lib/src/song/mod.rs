@@ -1,14 +1,16 @@
-use crate::song::instrument_part::InstrumentPart;
+use crate::song::instrument_part::{InstrumentPart, InstrumentPartType};
 use crate::song::key::{Accidental, Key, Mode, NoteClass};
 use crate::song::metadata::Metadata;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
 pub mod guitar;
 pub mod instrument_part;
 pub mod key;
 pub mod metadata;
+pub(crate) mod seconds;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Song {
     pub metadata: Metadata,
     pub instrument_parts: Vec<InstrumentPart>,
@@ -27,6 +29,7 @@ impl Song {
                 year: 1970,
                 length: Default::default(),
                 key: None,
+                preview_start: None,
             },
             instrument_parts: vec![],
             beats: vec![],
@@ -34,11 +37,90 @@ impl Song {
             a440_offset_cents: 0.0,
         }
     }
+
+    /// Finds the beat closest to the specified position. If `measures_only` is set, only the first
+    /// beat of each measure is considered.
+    pub fn nearest_beat(&self, position: Duration, measures_only: bool) -> Option<&Beat> {
+        self.beats.iter()
+            .filter(|beat| !measures_only || beat.beat_in_measure == 1)
+            .min_by_key(|beat| beat.time.abs_diff(position))
+    }
+
+    /// The number of the measure playing at the specified position, indexed from 1
+    pub fn measure_at(&self, position: Duration) -> Option<usize> {
+        self.beats.iter()
+            .take_while(|beat| beat.time <= position)
+            .last()
+            .map(|beat| beat.measure)
+    }
+
+    /// Where a preview of the specified length should start. Uses the preview start of the chart if
+    /// it has one, otherwise the start of the window with the most notes in any of the guitar parts.
+    pub fn preview_start(&self, length: Duration) -> Duration {
+        if let Some(preview_start) = self.metadata.preview_start {
+            return preview_start;
+        }
+
+        let mut note_times: Vec<Duration> = self.instrument_parts.iter()
+            .filter_map(|part| match &part.instrument_part_type {
+                InstrumentPartType::LeadGuitar(guitar_part) |
+                InstrumentPartType::RhythmGuitar(guitar_part) |
+                InstrumentPartType::BassGuitar(guitar_part) => Some(guitar_part),
+                _ => None,
+            })
+            .flat_map(|guitar_part| guitar_part.notes.iter().map(|note| note.time))
+            .collect();
+
+        note_times.sort();
+
+        let mut densest = (0, Duration::ZERO);
+        let mut window_end = 0;
+
+        for (idx, start) in note_times.iter().enumerate() {
+            while window_end < note_times.len() && note_times[window_end] < *start + length {
+                window_end += 1;
+            }
+
+            if window_end - idx > densest.0 {
+                densest = (window_end - idx, *start);
+            }
+        }
+
+        // Starting on the measure the densest window begins in sounds less abrupt than starting on a note
+        self.beats.iter()
+            .filter(|beat| beat.beat_in_measure == 1)
+            .take_while(|beat| beat.time <= densest.1)
+            .last()
+            .map(|beat| beat.time)
+            .unwrap_or(densest.1)
+    }
+
+    /// The tempo at the specified position in beats per minute, from the length of the beat playing
+    /// at the position. The last beat is as long as the one before it.
+    pub fn tempo_at(&self, position: Duration) -> Option<f32> {
+        let idx = self.beats.iter()
+            .take_while(|beat| beat.time <= position)
+            .count()
+            .saturating_sub(1)
+            .min(self.beats.len().saturating_sub(2));
+
+        let beat_length = self.beats.get(idx + 1)?.time.checked_sub(self.beats[idx].time)?;
+
+        (!beat_length.is_zero()).then(|| 60.0 / beat_length.as_secs_f32())
+    }
+
+    /// The section playing at the specified position
+    pub fn section_at(&self, position: Duration) -> Option<&Section> {
+        self.sections.iter()
+            .take_while(|section| section.time <= position)
+            .last()
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Beat {
     /// The start time of this section
+    #[serde(with = "seconds")]
     pub time: Duration,
     /// Which measure this beat belongs to, indexed from 1
     pub measure: usize,
@@ -46,9 +128,10 @@ pub struct Beat {
     pub beat_in_measure: u8
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Section {
     pub name: String,
+    #[serde(with = "seconds")]
     pub time: Duration,
     // pub length: Duration,
 }
\ No newline at end of file
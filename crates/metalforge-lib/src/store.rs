@@ -0,0 +1,195 @@
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, Write};
+use std::path::{Path, PathBuf};
+
+/// The local file a store keeps its data in, e.g. the song settings or the setlists. Files are
+/// replaced through a temporary file, so a failed write never leaves a truncated file behind. A file
+/// that can't be read is moved aside to `<file name>.bak` rather than being overwritten by the next
+/// write, so it can still be recovered by hand.
+pub(crate) struct JsonFile {
+    path: PathBuf,
+}
+
+impl JsonFile {
+
+    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf() }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Reads the file as a single JSON value. Returns `None` if there is no file yet, or if it
+    /// couldn't be read and was moved aside.
+    pub(crate) fn read<T: DeserializeOwned>(&self) -> Result<Option<T>, Error> {
+        if !std::fs::exists(self.path.as_path())? {
+            return Ok(None);
+        }
+
+        let value = File::open(self.path.as_path())
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).map_err(Error::other));
+
+        match value {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => self.move_aside(error).map(|_| None),
+        }
+    }
+
+    /// Reads a file with a JSON value on each line. Lines that can't be parsed are skipped, so a
+    /// damaged line only loses that one value.
+    pub(crate) fn read_lines<T: DeserializeOwned>(&self) -> Result<Vec<T>, Error> {
+        if !std::fs::exists(self.path.as_path())? {
+            return Ok(vec![]);
+        }
+
+        let lines = File::open(self.path.as_path())
+            .and_then(|file| BufReader::new(file).lines().collect::<Result<Vec<String>, Error>>());
+
+        let lines = match lines {
+            Ok(lines) => lines,
+            Err(error) => return self.move_aside(error).map(|_| vec![]),
+        };
+
+        let values = lines.iter()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(line_idx, line)| match serde_json::from_str(line.as_str()) {
+                Ok(value) => Some(value),
+                Err(error) => {
+                    warn!("Skipping {:?} line {}: {}", self.path, line_idx + 1, error);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(values)
+    }
+
+    pub(crate) fn write<T: Serialize + ?Sized>(&self, value: &T) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(value).map_err(Error::other)?;
+        self.replace(json.as_bytes())
+    }
+
+    /// Replaces the file with one JSON value on each line
+    pub(crate) fn write_lines<'a, T: Serialize + 'a, I: IntoIterator<Item = &'a T>>(&self, values: I) -> Result<(), Error> {
+        let mut contents = String::new();
+
+        for value in values {
+            contents.push_str(serde_json::to_string(value).map_err(Error::other)?.as_str());
+            contents.push('\n');
+        }
+
+        self.replace(contents.as_bytes())
+    }
+
+    /// Adds a line with a JSON value to the end of the file, without rewriting the lines before it
+    pub(crate) fn append_line<T: Serialize>(&self, value: &T) -> Result<(), Error> {
+        self.create_parent()?;
+
+        let line = serde_json::to_string(value).map_err(Error::other)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.as_path())?;
+
+        writeln!(file, "{}", line)
+    }
+
+    fn replace(&self, contents: &[u8]) -> Result<(), Error> {
+        self.create_parent()?;
+
+        let temp_path = self.sibling("tmp");
+        std::fs::write(temp_path.as_path(), contents)?;
+        std::fs::rename(temp_path.as_path(), self.path.as_path())
+    }
+
+    fn create_parent(&self) -> Result<(), Error> {
+        match self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            Some(parent) => std::fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+    }
+
+    /// Renames a file that couldn't be read. The read error is returned if the file can't be renamed
+    /// either, since the file would be overwritten otherwise.
+    fn move_aside(&self, error: Error) -> Result<(), Error> {
+        let backup_path = self.sibling("bak");
+
+        match std::fs::rename(self.path.as_path(), backup_path.as_path()) {
+            Ok(()) => {
+                warn!("Failed to read {:?}, moved it to {:?}: {}", self.path, backup_path, error);
+                Ok(())
+            }
+            Err(_) => Err(error),
+        }
+    }
+
+    /// The path of the file with another extension added, e.g. `settings.json.tmp`
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(".");
+        path.push(extension);
+        PathBuf::from(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::testing::temp_dir;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn round_trips_values() {
+        let dir = temp_dir("store-values");
+        let file = JsonFile::new(dir.join("nested/values.json"));
+
+        assert_eq!(file.read::<BTreeMap<String, u32>>().unwrap(), None);
+
+        let values = BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 2)]);
+        file.write(&values).unwrap();
+
+        assert_eq!(file.read::<BTreeMap<String, u32>>().unwrap(), Some(values));
+        assert!(!dir.join("nested/values.json.tmp").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn moves_unreadable_files_aside() {
+        let dir = temp_dir("store-unreadable");
+        let path = dir.join("values.json");
+        std::fs::write(path.as_path(), "{\"a\": 1,").unwrap();
+
+        let file = JsonFile::new(path.as_path());
+        assert_eq!(file.read::<BTreeMap<String, u32>>().unwrap(), None);
+
+        // Writing afterwards leaves the damaged file for recovering by hand
+        file.write(&BTreeMap::from([("b".to_string(), 2)])).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("values.json.bak")).unwrap(), "{\"a\": 1,");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn appends_and_skips_damaged_lines() {
+        let dir = temp_dir("store-lines");
+        let file = JsonFile::new(dir.join("values.jsonl"));
+
+        file.append_line(&1).unwrap();
+        file.append_line(&2).unwrap();
+        std::fs::write(file.path(), format!("{}not json\n\n3\n", std::fs::read_to_string(file.path()).unwrap())).unwrap();
+
+        assert_eq!(file.read_lines::<u32>().unwrap(), vec![1, 2, 3]);
+
+        file.write_lines(&[4, 5]).unwrap();
+        assert_eq!(file.read_lines::<u32>().unwrap(), vec![4, 5]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}